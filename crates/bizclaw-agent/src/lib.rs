@@ -20,7 +20,11 @@ use bizclaw_core::traits::Provider;
 use bizclaw_core::traits::SecurityPolicy;
 use bizclaw_core::traits::memory::MemoryBackend;
 use bizclaw_core::traits::provider::GenerateParams;
-use bizclaw_core::types::{Message, OutgoingMessage};
+use bizclaw_core::types::{
+    Message, OutgoingMessage, ProviderResponse, StreamAccumulator, StreamEvent, ToolDefinition,
+};
+use futures::StreamExt;
+use tokio::sync::mpsc;
 
 /// Prompt cache — caches serialized system prompt + tool definitions to avoid
 /// re-serializing on every request.
@@ -80,11 +84,24 @@ pub struct ContextStats {
     pub session_id: String,
 }
 
+/// The configured embedder; an unknown `memory.embedding_provider` only
+/// disables semantic search.
+fn create_embedder(
+    config: &BizClawConfig,
+) -> Option<std::sync::Arc<dyn bizclaw_core::traits::Embedder>> {
+    bizclaw_providers::create_embedder(config).unwrap_or_else(|e| {
+        tracing::warn!("Semantic search disabled: {e}");
+        None
+    })
+}
+
 /// The BizClaw agent — processes messages using LLM providers and tools.
 pub struct Agent {
     config: BizClawConfig,
     provider: Box<dyn Provider>,
     memory: Box<dyn MemoryBackend>,
+    /// Embeddings for semantic memory/knowledge search (`memory.embedding_provider`)
+    embedder: Option<std::sync::Arc<dyn bizclaw_core::traits::Embedder>>,
     tools: bizclaw_tools::ToolRegistry,
     security: bizclaw_security::DefaultSecurityPolicy,
     conversation: Vec<Message>,
@@ -104,7 +121,8 @@ impl Agent {
     /// Create a new agent from configuration (sync, no MCP).
     pub fn new(config: BizClawConfig) -> Result<Self> {
         let provider = bizclaw_providers::create_provider(&config)?;
-        let memory = bizclaw_memory::create_memory(&config.memory)?;
+        let embedder = create_embedder(&config);
+        let memory = bizclaw_memory::create_memory_with_embedder(&config.memory, embedder.clone())?;
         let tools = bizclaw_tools::ToolRegistry::with_defaults();
         let security = bizclaw_security::DefaultSecurityPolicy::new(config.autonomy.clone());
 
@@ -129,6 +147,7 @@ impl Agent {
             config,
             provider,
             memory,
+            embedder,
             tools,
             security,
             conversation,
@@ -151,7 +170,8 @@ impl Agent {
     /// Create a new agent with MCP server support (async).
     pub async fn new_with_mcp(config: BizClawConfig) -> Result<Self> {
         let provider = bizclaw_providers::create_provider(&config)?;
-        let memory = bizclaw_memory::create_memory(&config.memory)?;
+        let embedder = create_embedder(&config);
+        let memory = bizclaw_memory::create_memory_with_embedder(&config.memory, embedder.clone())?;
         let mut tools = bizclaw_tools::ToolRegistry::with_defaults();
         let security = bizclaw_security::DefaultSecurityPolicy::new(config.autonomy.clone());
 
@@ -204,6 +224,7 @@ impl Agent {
             config,
             provider,
             memory,
+            embedder,
             tools,
             security,
             conversation,
@@ -245,6 +266,24 @@ impl Agent {
     /// Process a user message and generate a response.
     /// Features: knowledge RAG, memory retrieval, multi-round tool calling, auto-compaction.
     pub async fn process(&mut self, user_message: &str) -> Result<String> {
+        self.process_with(user_message, None).await
+    }
+
+    /// Like [`Agent::process`], but streams the provider's text to `deltas`
+    /// as it is generated, including text sent alongside tool calls.
+    pub async fn process_stream(
+        &mut self,
+        user_message: &str,
+        deltas: mpsc::UnboundedSender<String>,
+    ) -> Result<String> {
+        self.process_with(user_message, Some(&deltas)).await
+    }
+
+    async fn process_with(
+        &mut self,
+        user_message: &str,
+        deltas: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<String> {
         let mut compacted = false;
 
         // ═══════════════════════════════════════
@@ -307,6 +346,10 @@ impl Agent {
             max_tokens: self.config.brain.max_tokens,
             top_p: 0.9,
             stop: vec![],
+            session_id: Some(self.session_id.clone()),
+            lora_adapter: Some(self.config.brain.lora_path.clone()).filter(|path| !path.is_empty()),
+            lora_scale: Some(self.config.brain.lora_scale),
+            ..Default::default()
         };
 
         // ═══════════════════════════════════════
@@ -322,10 +365,14 @@ impl Agent {
             } else {
                 &vec![]
             };
-            let response = self
-                .provider
-                .chat(&self.conversation, current_tools, &params)
-                .await?;
+            let response = match deltas {
+                Some(deltas) => self.chat_streamed(current_tools, &params, deltas).await?,
+                None => {
+                    self.provider
+                        .chat(&self.conversation, current_tools, &params)
+                        .await?
+                }
+            };
 
             // No tool calls → this is the final text response
             if response.tool_calls.is_empty() {
@@ -436,13 +483,43 @@ impl Agent {
         Ok(final_content)
     }
 
+    /// One provider call, forwarding text deltas as they arrive.
+    async fn chat_streamed(
+        &self,
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+        deltas: &mpsc::UnboundedSender<String>,
+    ) -> Result<ProviderResponse> {
+        let mut stream = self
+            .provider
+            .chat_stream(&self.conversation, tools, params)
+            .await?;
+        let mut response = StreamAccumulator::new();
+        while let Some(event) = stream.next().await {
+            let event = event?;
+            if let StreamEvent::Delta(text) = &event {
+                // The receiver going away only means nobody is watching
+                let _ = deltas.send(text.clone());
+            }
+            response.push(event);
+        }
+        Ok(response.finish())
+    }
+
     /// Search the knowledge base for relevant context.
     async fn search_knowledge(&self, query: &str) -> Option<String> {
         let kb_arc = self.knowledge.as_ref()?;
+        let query_vec = self.embed_query(query).await;
+        if query_vec.is_some() {
+            self.embed_pending_chunks(kb_arc).await;
+        }
         let kb_lock = kb_arc.lock().await;
         let kb = kb_lock.as_ref()?;
 
-        let results = kb.search(query, 3);
+        let results = match &query_vec {
+            Some(query_vec) => kb.search_hybrid(query, query_vec, 3),
+            None => kb.search(query, 3),
+        };
         if results.is_empty() {
             return None;
         }
@@ -464,6 +541,51 @@ impl Agent {
         Some(context)
     }
 
+    /// Embed `query` for semantic search (`None` without an embedder or on error).
+    async fn embed_query(&self, query: &str) -> Option<Vec<f32>> {
+        let embedder = self.embedder.as_ref()?;
+        match embedder.embed(&[query.to_string()]).await {
+            Ok(mut vectors) => vectors.pop(),
+            Err(e) => {
+                tracing::debug!("Query embedding failed: {e}");
+                None
+            }
+        }
+    }
+
+    /// Embed knowledge chunks added since the last search, a batch at a time.
+    /// The store is unlocked while embedding.
+    async fn embed_pending_chunks(
+        &self,
+        kb_arc: &tokio::sync::Mutex<Option<bizclaw_knowledge::KnowledgeStore>>,
+    ) {
+        let Some(embedder) = &self.embedder else {
+            return;
+        };
+        let pending = match kb_arc.lock().await.as_ref() {
+            Some(kb) => kb.pending_embeddings(32),
+            None => return,
+        };
+        if pending.is_empty() {
+            return;
+        }
+        let texts: Vec<String> = pending.iter().map(|(_, text)| text.clone()).collect();
+        match embedder.embed(&texts).await {
+            Ok(vectors) => {
+                let kb_lock = kb_arc.lock().await;
+                let Some(kb) = kb_lock.as_ref() else {
+                    return;
+                };
+                for ((rowid, _), vector) in pending.iter().zip(&vectors) {
+                    if let Err(e) = kb.set_embedding(*rowid, vector) {
+                        tracing::warn!("Failed to store chunk embedding: {e}");
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("Knowledge embedding failed: {e}"),
+        }
+    }
+
     /// Retrieve relevant past conversations from memory (FTS5-powered).
     async fn retrieve_memory(&self, user_message: &str) -> Option<String> {
         if !self.config.memory.auto_save {
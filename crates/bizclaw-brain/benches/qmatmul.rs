@@ -0,0 +1,116 @@
+//! Fused quantized matmul vs. dequantize-then-matmul.
+//!
+//! Runs the per-token matmuls of a TinyLlama-1.1B-shaped model (22 layers,
+//! dim 2048, FFN 5632, 4 KV heads, 32k vocab) with random weights and
+//! reports the matmul-bound tokens/s for both paths.
+//!
+//! Run with: `cargo bench -p bizclaw-brain --bench qmatmul`
+
+use bizclaw_brain::gguf::GgmlType;
+use bizclaw_brain::{qmatmul, quant, tensor};
+use rand::{Rng, SeedableRng};
+use std::time::{Duration, Instant};
+
+const DIM: usize = 2048;
+const HIDDEN: usize = 5632;
+const KV_DIM: usize = 256;
+const VOCAB: usize = 32000;
+const LAYERS: usize = 22;
+
+/// (rows, cols) of every matmul in one transformer layer.
+const LAYER_SHAPES: [(usize, usize); 7] = [
+    (DIM, DIM),    // attn_q
+    (KV_DIM, DIM), // attn_k
+    (KV_DIM, DIM), // attn_v
+    (DIM, DIM),    // attn_output
+    (HIDDEN, DIM), // ffn_gate
+    (HIDDEN, DIM), // ffn_up
+    (DIM, HIDDEN), // ffn_down
+];
+
+fn random_weight(rng: &mut rand::rngs::StdRng, ty: GgmlType, rows: usize, cols: usize) -> Vec<u8> {
+    let ts = ty.type_size();
+    let n_blocks = rows * cols / ty.block_size();
+    let mut data: Vec<u8> = (0..n_blocks * ts).map(|_| rng.r#gen()).collect();
+    // Keep every f16 scale finite and small.
+    let scale_offsets: &[usize] = match ty {
+        GgmlType::Q4K | GgmlType::Q5K => &[0, 2],
+        GgmlType::Q6K => &[208],
+        _ => &[0],
+    };
+    for b in 0..n_blocks {
+        for &off in scale_offsets {
+            let v = half::f16::from_f32(0.01).to_le_bytes();
+            data[b * ts + off..b * ts + off + 2].copy_from_slice(&v);
+        }
+    }
+    data
+}
+
+fn time_it(iters: u32, mut f: impl FnMut()) -> Duration {
+    f(); // warm-up
+    let start = Instant::now();
+    for _ in 0..iters {
+        f();
+    }
+    start.elapsed() / iters
+}
+
+fn bench_type(ty: GgmlType) {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    let layer: Vec<(usize, usize, Vec<u8>)> = LAYER_SHAPES
+        .iter()
+        .map(|&(r, c)| (r, c, random_weight(&mut rng, ty, r, c)))
+        .collect();
+    let head = random_weight(&mut rng, ty, VOCAB, DIM);
+    let x_dim: Vec<f32> = (0..DIM).map(|_| rng.gen_range(-1.0..1.0)).collect();
+    let x_hidden: Vec<f32> = (0..HIDDEN).map(|_| rng.gen_range(-1.0..1.0)).collect();
+    let input_for = |cols: usize| if cols == DIM { &x_dim } else { &x_hidden };
+    let mut out = vec![0.0f32; VOCAB];
+
+    // Before: dequantize the whole matrix into a fresh Vec, then matmul.
+    let dequant = |rows: usize, cols: usize, w: &[u8], out: &mut [f32]| {
+        let mut dense = vec![0.0f32; rows * cols];
+        quant::dequantize_row(w, &mut dense, rows * cols, ty).unwrap();
+        tensor::matmul(&mut out[..rows], &dense, input_for(cols), rows, cols);
+    };
+    let before_layer = time_it(3, || {
+        for (r, c, w) in &layer {
+            dequant(*r, *c, w, &mut out);
+        }
+    });
+    let before_head = time_it(1, || dequant(VOCAB, DIM, &head, &mut out));
+
+    // After: fused quantized matmul.
+    let after_layer = time_it(10, || {
+        for (r, c, w) in &layer {
+            qmatmul::matmul(&mut out[..*r], w, ty, input_for(*c), *r, *c).unwrap();
+        }
+    });
+    let after_head = time_it(5, || {
+        qmatmul::matmul(&mut out, &head, ty, &x_dim, VOCAB, DIM).unwrap();
+    });
+
+    let per_token = |layer: Duration, head: Duration| layer * LAYERS as u32 + head;
+    let before = per_token(before_layer, before_head);
+    let after = per_token(after_layer, after_head);
+    println!(
+        "{:<6} before: {:>8.1} ms/token ({:>6.2} tok/s)   after: {:>8.1} ms/token ({:>6.2} tok/s)   speedup {:.1}x",
+        format!("{ty:?}"),
+        before.as_secs_f64() * 1e3,
+        1.0 / before.as_secs_f64(),
+        after.as_secs_f64() * 1e3,
+        1.0 / after.as_secs_f64(),
+        before.as_secs_f64() / after.as_secs_f64(),
+    );
+}
+
+fn main() {
+    println!(
+        "TinyLlama-1.1B-shaped matmuls, {} threads",
+        bizclaw_brain::thread_pool::num_threads()
+    );
+    for ty in [GgmlType::Q4_0, GgmlType::Q8_0, GgmlType::Q4K, GgmlType::Q6K] {
+        bench_type(ty);
+    }
+}
@@ -0,0 +1,541 @@
+//! Throughput benchmark and perplexity evaluation.
+//!
+//! Both load the model on their own (no sampler, chat template or session
+//! cache) and run the same forward passes as generation, inside a rayon
+//! pool with the requested number of threads. Reports serialize to JSON so
+//! results can be tracked across builds, quant types and machines.
+
+use crate::kv_cache::{KvPrecision, KvStorage};
+use crate::{forward, mmap, model, tokenizer};
+use bizclaw_core::error::{BizClawError, Result};
+use serde::Serialize;
+use std::path::Path;
+use std::time::Instant;
+
+/// Text the benchmark prompt is cut from.
+const BENCH_TEXT: &str = "The quick brown fox jumps over the lazy dog while the \
+    farmer counts his sheep and the baker sells fresh bread at the market. ";
+
+/// Settings for [`run_bench`].
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Prompt length processed by the prefill phase
+    pub prompt_tokens: usize,
+    /// Tokens generated one at a time after the prompt
+    pub gen_tokens: usize,
+    /// Measured runs (after one warm-up pass)
+    pub repetitions: usize,
+    pub batch_size: usize,
+    pub threads: usize,
+    pub kv_precision: KvPrecision,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            prompt_tokens: 128,
+            gen_tokens: 64,
+            repetitions: 3,
+            batch_size: 64,
+            threads: 4,
+            kv_precision: KvPrecision::F32,
+        }
+    }
+}
+
+/// Result of [`run_bench`]. Rates are means over the repetitions.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub model: String,
+    pub architecture: String,
+    /// Storage type holding most of the weight bytes
+    pub weight_type: String,
+    pub file_size_bytes: u64,
+    pub threads: usize,
+    pub batch_size: usize,
+    pub kv_precision: String,
+    pub prompt_tokens: usize,
+    pub gen_tokens: usize,
+    pub repetitions: usize,
+    pub load_ms: f64,
+    pub prefill_tokens_per_sec: f64,
+    pub prefill_tokens_per_sec_stddev: f64,
+    pub decode_tokens_per_sec: f64,
+    pub decode_tokens_per_sec_stddev: f64,
+    /// Allocated KV cache (the model's full context)
+    pub kv_cache_bytes: u64,
+    /// Part of the KV cache holding the benchmark's tokens
+    pub kv_used_bytes: u64,
+    /// Peak resident set size of the process (Linux only)
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// Settings for [`perplexity`].
+#[derive(Debug, Clone)]
+pub struct PerplexityOptions {
+    /// Window length in tokens (0 = the model's context length)
+    pub context: usize,
+    /// Tokens the window advances by (0 = half the window)
+    pub stride: usize,
+    pub batch_size: usize,
+    pub threads: usize,
+    pub kv_precision: KvPrecision,
+}
+
+impl Default for PerplexityOptions {
+    fn default() -> Self {
+        Self {
+            context: 512,
+            stride: 0,
+            batch_size: 64,
+            threads: 4,
+            kv_precision: KvPrecision::F32,
+        }
+    }
+}
+
+/// Result of [`perplexity`].
+#[derive(Debug, Clone, Serialize)]
+pub struct PerplexityReport {
+    pub model: String,
+    /// Corpus length including the leading BOS
+    pub tokens: usize,
+    /// Tokens whose log-likelihood was counted (each exactly once)
+    pub scored_tokens: usize,
+    pub windows: usize,
+    pub context: usize,
+    pub stride: usize,
+    /// Mean negative log-likelihood in nats
+    pub nll: f64,
+    pub perplexity: f64,
+    pub seconds: f64,
+    /// Tokens run through the model per second, overlap included
+    pub tokens_per_sec: f64,
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// A model loaded for evaluation only.
+struct EvalModel {
+    mmap_model: mmap::MmapModel,
+    params: model::ModelParams,
+    weights: forward::TransformerWeights,
+    tokenizer: tokenizer::BpeTokenizer,
+    kv_cache: KvStorage,
+}
+
+impl EvalModel {
+    fn load(path: &Path, precision: KvPrecision) -> Result<Self> {
+        let mmap_model = mmap::MmapModel::load(path)?;
+        let params = model::ModelParams::from_gguf(&mmap_model.gguf)?;
+        if params.arch.is_encoder() {
+            return Err(BizClawError::Brain(format!(
+                "{} is an embedding model ({:?})",
+                path.display(),
+                params.arch
+            )));
+        }
+        let weights = forward::TransformerWeights::from_gguf(&mmap_model, &params)?;
+        let tokenizer = tokenizer::BpeTokenizer::from_gguf(&mmap_model.gguf.metadata)?;
+        let kv_cache = KvStorage::new(
+            precision,
+            params.n_layers as usize,
+            params.max_seq_len as usize,
+            params.n_kv_heads as usize,
+            params.head_dim as usize,
+        );
+        Ok(Self {
+            mmap_model,
+            params,
+            weights,
+            tokenizer,
+            kv_cache,
+        })
+    }
+
+    fn n_ctx(&self) -> usize {
+        self.params.max_seq_len as usize
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.params.vocab_size as usize
+    }
+
+    /// Prefill `tokens` from position 0; returns the last token's logits.
+    fn prefill(&mut self, tokens: &[u32], batch_size: usize) -> Result<Vec<f32>> {
+        let mut logits = vec![0.0f32; self.vocab_size()];
+        let mut pos = 0;
+        for chunk in tokens.chunks(batch_size.max(1)) {
+            forward::forward_batch(
+                &self.mmap_model,
+                &self.weights,
+                &self.params,
+                &mut self.kv_cache,
+                chunk,
+                pos,
+                &mut logits,
+            )?;
+            pos += chunk.len();
+        }
+        Ok(logits)
+    }
+
+    fn decode(&mut self, token: u32, pos: usize, logits: &mut [f32]) -> Result<()> {
+        forward::forward(
+            &self.mmap_model,
+            &self.weights,
+            &self.params,
+            &mut self.kv_cache,
+            token,
+            pos,
+            logits,
+        )
+    }
+
+    /// The [`GgmlType`](crate::gguf::GgmlType) holding the most bytes.
+    fn weight_type(&self) -> String {
+        crate::inspect::weight_type(&self.mmap_model.gguf)
+            .map(|ty| format!("{ty:?}"))
+            .unwrap_or_default()
+    }
+}
+
+/// Measure prefill and decode throughput.
+pub fn run_bench(path: &Path, options: &BenchOptions) -> Result<BenchReport> {
+    let start = Instant::now();
+    let mut model = EvalModel::load(path, options.kv_precision)?;
+    let load_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    let total = options.prompt_tokens + options.gen_tokens;
+    if options.prompt_tokens == 0 || total > model.n_ctx() {
+        return Err(BizClawError::Brain(format!(
+            "Benchmark needs 1..={} prompt + generated tokens, got {} + {}",
+            model.n_ctx(),
+            options.prompt_tokens,
+            options.gen_tokens
+        )));
+    }
+    let prompt = bench_prompt(&model.tokenizer, options.prompt_tokens);
+
+    let mut prefill_rates = Vec::new();
+    let mut decode_rates = Vec::new();
+    with_threads(options.threads, || -> Result<()> {
+        // Warm-up: fault in the mmapped weights before timing anything
+        model.prefill(
+            &prompt[..prompt.len().min(options.batch_size.max(1))],
+            options.batch_size,
+        )?;
+
+        for _ in 0..options.repetitions.max(1) {
+            let start = Instant::now();
+            let mut logits = model.prefill(&prompt, options.batch_size)?;
+            prefill_rates.push(prompt.len() as f64 / start.elapsed().as_secs_f64());
+
+            if options.gen_tokens > 0 {
+                let start = Instant::now();
+                for pos in prompt.len()..total {
+                    let token = argmax(&logits);
+                    model.decode(token, pos, &mut logits)?;
+                }
+                decode_rates.push(options.gen_tokens as f64 / start.elapsed().as_secs_f64());
+            }
+        }
+        Ok(())
+    })??;
+
+    let kv_cache_bytes = model.kv_cache.memory_usage() as u64;
+    let (prefill_mean, prefill_stddev) = mean_stddev(&prefill_rates);
+    let (decode_mean, decode_stddev) = mean_stddev(&decode_rates);
+    Ok(BenchReport {
+        model: path.display().to_string(),
+        architecture: model.mmap_model.architecture().to_string(),
+        weight_type: model.weight_type(),
+        file_size_bytes: model.mmap_model.file_size() as u64,
+        threads: options.threads,
+        batch_size: options.batch_size,
+        kv_precision: options.kv_precision.as_str().to_string(),
+        prompt_tokens: prompt.len(),
+        gen_tokens: options.gen_tokens,
+        repetitions: prefill_rates.len(),
+        load_ms,
+        prefill_tokens_per_sec: prefill_mean,
+        prefill_tokens_per_sec_stddev: prefill_stddev,
+        decode_tokens_per_sec: decode_mean,
+        decode_tokens_per_sec_stddev: decode_stddev,
+        kv_cache_bytes,
+        kv_used_bytes: kv_cache_bytes * total as u64 / model.n_ctx() as u64,
+        peak_rss_bytes: peak_rss_bytes(),
+    })
+}
+
+/// Perplexity of `text` with a sliding window.
+///
+/// The window advances by `stride` tokens and only scores tokens no earlier
+/// window scored, so every token after the leading BOS counts exactly once
+/// while (after the first window) still seeing `context - stride` tokens of
+/// history. Each window is evaluated from an empty KV cache.
+pub fn perplexity(
+    path: &Path,
+    text: &str,
+    options: &PerplexityOptions,
+) -> Result<PerplexityReport> {
+    let mut model = EvalModel::load(path, options.kv_precision)?;
+    let mut tokens = Vec::new();
+    if model.tokenizer.add_bos {
+        tokens.push(model.tokenizer.bos_id);
+    }
+    tokens.extend(model.tokenizer.encode(text));
+    if tokens.len() < 2 {
+        return Err(BizClawError::Brain(
+            "Perplexity needs at least two tokens of text".into(),
+        ));
+    }
+
+    let context = match options.context {
+        0 => model.n_ctx(),
+        n => n.min(model.n_ctx()),
+    }
+    .max(2);
+    let stride = match options.stride {
+        0 => context / 2,
+        n => n.min(context),
+    };
+    let batch_size = options.batch_size.max(1);
+    let vocab = model.vocab_size();
+
+    let start = Instant::now();
+    let mut nll = 0.0f64;
+    let mut scored = 0usize;
+    let mut windows = 0usize;
+    let mut processed = 0usize;
+    with_threads(options.threads, || -> Result<()> {
+        let mut logits = vec![0.0f32; batch_size * vocab];
+        let mut begin = 0;
+        let mut scored_end = 1;
+        loop {
+            let end = (begin + context).min(tokens.len());
+            let window = &tokens[begin..end];
+            for (c, chunk) in window.chunks(batch_size).enumerate() {
+                let chunk_start = c * batch_size;
+                forward::forward_batch_all_logits(
+                    &model.mmap_model,
+                    &model.weights,
+                    &model.params,
+                    &mut model.kv_cache,
+                    chunk,
+                    chunk_start,
+                    &mut logits[..chunk.len() * vocab],
+                )?;
+                // Row t predicts the token after window[chunk_start + t]
+                for t in 0..chunk.len() {
+                    let target = begin + chunk_start + t + 1;
+                    if target < scored_end || target >= end {
+                        continue;
+                    }
+                    let row = &logits[t * vocab..(t + 1) * vocab];
+                    nll += neg_log_prob(row, tokens[target] as usize);
+                    scored += 1;
+                }
+            }
+            windows += 1;
+            processed += window.len();
+            scored_end = end;
+            if end == tokens.len() {
+                return Ok(());
+            }
+            begin += stride;
+        }
+    })??;
+
+    let seconds = start.elapsed().as_secs_f64();
+    let mean = nll / scored as f64;
+    Ok(PerplexityReport {
+        model: path.display().to_string(),
+        tokens: tokens.len(),
+        scored_tokens: scored,
+        windows,
+        context,
+        stride,
+        nll: mean,
+        perplexity: mean.exp(),
+        seconds,
+        tokens_per_sec: processed as f64 / seconds,
+        peak_rss_bytes: peak_rss_bytes(),
+    })
+}
+
+/// Peak resident set size (`VmHWM`) of this process; `None` off Linux.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// `prompt_tokens` tokens: BOS (if the model uses one) plus repeated text.
+fn bench_prompt(tokenizer: &tokenizer::BpeTokenizer, prompt_tokens: usize) -> Vec<u32> {
+    let mut prompt = Vec::with_capacity(prompt_tokens);
+    if tokenizer.add_bos {
+        prompt.push(tokenizer.bos_id);
+    }
+    let text = tokenizer.encode(BENCH_TEXT);
+    if text.is_empty() {
+        prompt.resize(prompt_tokens, tokenizer.bos_id);
+        return prompt;
+    }
+    prompt.extend(
+        text.iter()
+            .cycle()
+            .take(prompt_tokens.saturating_sub(prompt.len())),
+    );
+    prompt.truncate(prompt_tokens);
+    prompt
+}
+
+/// Run `f` on a rayon pool of `threads` workers (0 = rayon's default).
+fn with_threads<T: Send>(threads: usize, f: impl FnOnce() -> T + Send) -> Result<T> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|e| BizClawError::Brain(format!("Thread pool: {e}")))?;
+    Ok(pool.install(f))
+}
+
+/// `-log softmax(logits)[target]`, computed stably in f64.
+fn neg_log_prob(logits: &[f32], target: usize) -> f64 {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max) as f64;
+    let sum: f64 = logits.iter().map(|&x| (x as f64 - max).exp()).sum();
+    max + sum.ln() - logits[target] as f64
+}
+
+fn mean_stddev(values: &[f64]) -> (f64, f64) {
+    if values.is_empty() {
+        return (0.0, 0.0);
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    (mean, var.sqrt())
+}
+
+fn argmax(values: &[f32]) -> u32 {
+    values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i as u32)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_model::TinyLlama;
+
+    #[test]
+    fn test_bench() {
+        let file = TinyLlama::default().write();
+        let options = BenchOptions {
+            prompt_tokens: 20,
+            gen_tokens: 8,
+            repetitions: 2,
+            batch_size: 8,
+            threads: 2,
+            kv_precision: KvPrecision::F16,
+        };
+        let report = run_bench(&file.path, &options).unwrap();
+        assert_eq!(report.prompt_tokens, 20);
+        assert_eq!(report.repetitions, 2);
+        assert_eq!(report.weight_type, "F32");
+        assert!(report.prefill_tokens_per_sec > 0.0);
+        assert!(report.decode_tokens_per_sec > 0.0);
+        // 28 of the model's 128 positions
+        assert_eq!(report.kv_used_bytes, report.kv_cache_bytes * 28 / 128);
+        #[cfg(target_os = "linux")]
+        assert!(report.peak_rss_bytes.unwrap() > 0);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["kv_precision"], "f16");
+        assert!(json["decode_tokens_per_sec"].as_f64().unwrap() > 0.0);
+
+        let too_long = BenchOptions {
+            prompt_tokens: 100,
+            gen_tokens: 100,
+            ..options
+        };
+        assert!(run_bench(&file.path, &too_long).is_err());
+    }
+
+    #[test]
+    fn test_bench_prompt() {
+        let file = TinyLlama::default().write();
+        let model = EvalModel::load(&file.path, KvPrecision::F32).unwrap();
+        let tokenizer = &model.tokenizer;
+        let prompt = bench_prompt(tokenizer, 300);
+        assert_eq!(prompt.len(), 300);
+        assert_eq!(prompt[0], tokenizer.bos_id);
+        assert_eq!(bench_prompt(tokenizer, 1), [tokenizer.bos_id]);
+    }
+
+    #[test]
+    fn test_perplexity() {
+        let file = TinyLlama::default().write();
+        let text = "hello world, this is a perplexity test";
+        let whole = PerplexityOptions {
+            context: 0,
+            threads: 1,
+            ..Default::default()
+        };
+        let report = perplexity(&file.path, text, &whole).unwrap();
+        assert_eq!(report.windows, 1);
+        assert_eq!(report.scored_tokens, report.tokens - 1);
+
+        // Reference: one pass over the whole corpus, every target scored
+        let mut model = EvalModel::load(&file.path, KvPrecision::F32).unwrap();
+        let mut tokens = vec![model.tokenizer.bos_id];
+        tokens.extend(model.tokenizer.encode(text));
+        assert_eq!(tokens.len(), report.tokens);
+        let vocab = model.vocab_size();
+        let mut logits = vec![0.0f32; tokens.len() * vocab];
+        forward::forward_batch_all_logits(
+            &model.mmap_model,
+            &model.weights,
+            &model.params,
+            &mut model.kv_cache,
+            &tokens,
+            0,
+            &mut logits,
+        )
+        .unwrap();
+        let nll: f64 = (1..tokens.len())
+            .map(|t| neg_log_prob(&logits[(t - 1) * vocab..t * vocab], tokens[t] as usize))
+            .sum::<f64>()
+            / (tokens.len() - 1) as f64;
+        assert!((report.nll - nll).abs() < 1e-3, "{} vs {nll}", report.nll);
+        assert!((report.perplexity - nll.exp()).abs() < 1e-2);
+
+        // Sliding windows still score every token once
+        let sliding = PerplexityOptions {
+            context: 16,
+            stride: 8,
+            batch_size: 5,
+            threads: 2,
+            ..Default::default()
+        };
+        let report = perplexity(&file.path, text, &sliding).unwrap();
+        assert_eq!(report.scored_tokens, report.tokens - 1);
+        let expected_windows = (report.tokens - 16).div_ceil(8) + 1;
+        assert_eq!(report.windows, expected_windows);
+        assert!(report.perplexity.is_finite() && report.perplexity > 1.0);
+
+        assert!(perplexity(&file.path, "", &whole).is_err());
+    }
+
+    #[test]
+    fn test_neg_log_prob() {
+        let logits = [1.0f32, 2.0, 3.0];
+        let sum: f64 = logits.iter().map(|&x| (x as f64).exp()).sum();
+        let expected = -(2.0f64.exp() / sum).ln();
+        assert!((neg_log_prob(&logits, 1) - expected).abs() < 1e-9);
+        assert_eq!(mean_stddev(&[2.0, 4.0]), (3.0, 1.0));
+    }
+}
@@ -0,0 +1,758 @@
+//! Chat prompt formatting.
+//!
+//! Prefers the model's own `tokenizer.chat_template` (rendered with the Jinja
+//! subset in [`crate::jinja`]); when a model ships no template, or uses
+//! syntax the renderer does not support, falls back to a built-in format
+//! detected from the template text, the vocabulary, or the architecture.
+
+use crate::gguf::GgufFile;
+use crate::jinja::{Template, Value};
+use crate::tokenizer::BpeTokenizer;
+use crate::tool_calls::{self, ToolCallStyle};
+use bizclaw_core::types::{Message, Role, ToolDefinition};
+use std::collections::{BTreeMap, HashMap};
+
+/// Built-in chat prompt formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatFormat {
+    /// `<|im_start|>role\n...<|im_end|>` — Qwen, Hermes, Yi, many fine-tunes.
+    ChatMl,
+    /// `[INST] <<SYS>> ... [/INST]` — Llama-2 chat.
+    Llama2,
+    /// `<|start_header_id|>role<|end_header_id|>` — Llama-3.x.
+    Llama3,
+    /// `[INST] ... [/INST]` without `<<SYS>>` — Mistral / Mixtral.
+    Mistral,
+    /// `<start_of_turn>user ... <end_of_turn>` — Gemma.
+    Gemma,
+    /// `<|user|>\n...<|end|>` — Phi-3.
+    Phi3,
+}
+
+impl ChatFormat {
+    /// Parse a format name as used in config (`chatml`, `llama3`, ...).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "chatml" | "qwen" | "qwen2" => Some(Self::ChatMl),
+            "llama2" | "llama" => Some(Self::Llama2),
+            "llama3" => Some(Self::Llama3),
+            "mistral" | "mixtral" => Some(Self::Mistral),
+            "gemma" | "gemma2" => Some(Self::Gemma),
+            "phi3" | "phi" => Some(Self::Phi3),
+            _ => None,
+        }
+    }
+
+    /// Pick a format from (in order) the template text, the vocabulary, and
+    /// the `general.architecture` name.
+    pub fn detect(
+        template: Option<&str>,
+        architecture: &str,
+        has_token: impl Fn(&str) -> bool,
+    ) -> Self {
+        if let Some(t) = template {
+            if t.contains("<|im_start|>") {
+                return Self::ChatMl;
+            }
+            if t.contains("<|start_header_id|>") {
+                return Self::Llama3;
+            }
+            if t.contains("<start_of_turn>") {
+                return Self::Gemma;
+            }
+            if t.contains("<|user|>") && t.contains("<|end|>") {
+                return Self::Phi3;
+            }
+            if t.contains("<<SYS>>") {
+                return Self::Llama2;
+            }
+            if t.contains("[INST]") {
+                return Self::Mistral;
+            }
+        }
+
+        if has_token("<|im_start|>") {
+            return Self::ChatMl;
+        }
+        if has_token("<|start_header_id|>") {
+            return Self::Llama3;
+        }
+        if has_token("<start_of_turn>") {
+            return Self::Gemma;
+        }
+        if has_token("<|user|>") && has_token("<|end|>") {
+            return Self::Phi3;
+        }
+
+        match architecture {
+            a if a.starts_with("qwen") => Self::ChatMl,
+            a if a.starts_with("gemma") => Self::Gemma,
+            a if a.starts_with("phi") => Self::Phi3,
+            "mistral" => Self::Mistral,
+            _ => Self::Llama2,
+        }
+    }
+
+    /// End-of-turn markers this format closes assistant messages with.
+    pub fn stop_tokens(self) -> &'static [&'static str] {
+        match self {
+            Self::ChatMl => &["<|im_end|>", "<|endoftext|>"],
+            Self::Llama3 => &["<|eot_id|>", "<|eom_id|>"],
+            Self::Gemma => &["<end_of_turn>"],
+            Self::Phi3 => &["<|end|>", "<|endoftext|>"],
+            Self::Llama2 | Self::Mistral => &[],
+        }
+    }
+
+    /// Render messages with this built-in format.
+    pub fn render(
+        self,
+        messages: &[Message],
+        bos: &str,
+        eos: &str,
+        add_generation_prompt: bool,
+    ) -> String {
+        self.render_with_tools(messages, &[], bos, eos, add_generation_prompt)
+    }
+
+    /// Render messages, offering `tools` in the family's native style.
+    pub fn render_with_tools(
+        self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        bos: &str,
+        eos: &str,
+        add_generation_prompt: bool,
+    ) -> String {
+        let injected;
+        let messages = if tools.is_empty() || self == Self::Mistral {
+            messages
+        } else {
+            injected = ToolCallStyle::for_format(self).inject(messages, tools);
+            &injected
+        };
+        match self {
+            Self::ChatMl => render_chatml(messages, add_generation_prompt),
+            Self::Llama2 => render_llama2(messages, bos, eos),
+            Self::Llama3 => render_llama3(messages, bos, add_generation_prompt),
+            Self::Mistral => render_mistral(messages, tools, bos, eos),
+            Self::Gemma => render_gemma(messages, bos, add_generation_prompt),
+            Self::Phi3 => render_phi3(messages, add_generation_prompt),
+        }
+    }
+}
+
+fn tool_calls_text(msg: &Message) -> String {
+    msg.tool_calls
+        .iter()
+        .flatten()
+        .map(|call| {
+            let args: serde_json::Value = serde_json::from_str(&call.function.arguments)
+                .unwrap_or_else(|_| serde_json::Value::String(call.function.arguments.clone()));
+            format!(
+                "{{\"name\": {}, \"arguments\": {args}}}",
+                serde_json::Value::String(call.function.name.clone())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_chatml(messages: &[Message], add_generation_prompt: bool) -> String {
+    let mut out = String::new();
+    for msg in messages {
+        match msg.role {
+            Role::Tool => out.push_str(&format!(
+                "<|im_start|>user\n<tool_response>\n{}\n</tool_response><|im_end|>\n",
+                msg.content
+            )),
+            Role::Assistant if msg.tool_calls.as_ref().is_some_and(|c| !c.is_empty()) => {
+                out.push_str("<|im_start|>assistant\n");
+                if !msg.content.is_empty() {
+                    out.push_str(&msg.content);
+                    out.push('\n');
+                }
+                for call in tool_calls_text(msg).lines() {
+                    out.push_str(&format!("<tool_call>\n{call}\n</tool_call>\n"));
+                }
+                out.truncate(out.trim_end_matches('\n').len());
+                out.push_str("<|im_end|>\n");
+            }
+            _ => out.push_str(&format!(
+                "<|im_start|>{}\n{}<|im_end|>\n",
+                msg.role, msg.content
+            )),
+        }
+    }
+    if add_generation_prompt {
+        out.push_str("<|im_start|>assistant\n");
+    }
+    out
+}
+
+fn render_llama3(messages: &[Message], bos: &str, add_generation_prompt: bool) -> String {
+    let mut out = bos.to_string();
+    for msg in messages {
+        let role = match msg.role {
+            Role::Tool => "ipython".to_string(),
+            ref r => r.to_string(),
+        };
+        let mut content = msg.content.trim().to_string();
+        if msg.role == Role::Assistant && msg.tool_calls.is_some() {
+            let calls = tool_calls_text(msg);
+            if !calls.is_empty() {
+                content = calls;
+            }
+        }
+        out.push_str(&format!(
+            "<|start_header_id|>{role}<|end_header_id|>\n\n{content}<|eot_id|>"
+        ));
+    }
+    if add_generation_prompt {
+        out.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+    }
+    out
+}
+
+/// Concatenate system messages; Mistral/Gemma/Llama-2 fold them into the first user turn.
+fn system_prompt(messages: &[Message]) -> Option<String> {
+    let parts: Vec<&str> = messages
+        .iter()
+        .filter(|m| m.role == Role::System)
+        .map(|m| m.content.as_str())
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("\n\n"))
+}
+
+fn render_llama2(messages: &[Message], bos: &str, eos: &str) -> String {
+    let mut system = system_prompt(messages);
+    let mut out = String::new();
+    for msg in messages {
+        match msg.role {
+            Role::System => {}
+            Role::User | Role::Tool => {
+                let content = if msg.role == Role::Tool {
+                    format!("Tool result: {}", msg.content)
+                } else {
+                    msg.content.clone()
+                };
+                out.push_str(bos);
+                match system.take() {
+                    Some(sys) => out.push_str(&format!(
+                        "[INST] <<SYS>>\n{sys}\n<</SYS>>\n\n{} [/INST]",
+                        content.trim()
+                    )),
+                    None => out.push_str(&format!("[INST] {} [/INST]", content.trim())),
+                }
+            }
+            Role::Assistant => out.push_str(&format!(" {} {eos}", msg.content.trim())),
+        }
+    }
+    out
+}
+
+fn render_mistral(messages: &[Message], tools: &[ToolDefinition], bos: &str, eos: &str) -> String {
+    let mut system = system_prompt(messages);
+    // Tools are listed right before the last user turn
+    let last_user = messages.iter().rposition(|m| m.role == Role::User);
+    let mut out = bos.to_string();
+    for (i, msg) in messages.iter().enumerate() {
+        match msg.role {
+            Role::System => {}
+            Role::User => {
+                if Some(i) == last_user && !tools.is_empty() {
+                    out.push_str(&format!(
+                        "[AVAILABLE_TOOLS] {}[/AVAILABLE_TOOLS]",
+                        tool_calls::tools_list(tools)
+                    ));
+                }
+                let content = match system.take() {
+                    Some(sys) => format!("{sys}\n\n{}", msg.content),
+                    None => msg.content.clone(),
+                };
+                out.push_str(&format!("[INST] {} [/INST]", content.trim()));
+            }
+            Role::Tool => out.push_str(&format!(
+                "[TOOL_RESULTS] {}[/TOOL_RESULTS]",
+                msg.content.trim()
+            )),
+            Role::Assistant => {
+                let calls = tool_calls_text(msg);
+                if calls.is_empty() {
+                    out.push_str(&format!(" {}{eos}", msg.content.trim()));
+                } else {
+                    let list = calls.lines().collect::<Vec<_>>().join(", ");
+                    out.push_str(&format!("[TOOL_CALLS] [{list}]{eos}"));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn render_gemma(messages: &[Message], bos: &str, add_generation_prompt: bool) -> String {
+    let mut system = system_prompt(messages);
+    let mut out = bos.to_string();
+    for msg in messages {
+        let (role, content) = match msg.role {
+            Role::System => continue,
+            Role::Assistant => ("model", msg.content.clone()),
+            Role::Tool => ("user", format!("Tool result: {}", msg.content)),
+            Role::User => ("user", msg.content.clone()),
+        };
+        let content = match (role, system.take()) {
+            ("user", Some(sys)) => format!("{sys}\n\n{content}"),
+            (_, sys) => {
+                system = sys;
+                content
+            }
+        };
+        out.push_str(&format!(
+            "<start_of_turn>{role}\n{}<end_of_turn>\n",
+            content.trim()
+        ));
+    }
+    if add_generation_prompt {
+        out.push_str("<start_of_turn>model\n");
+    }
+    out
+}
+
+fn render_phi3(messages: &[Message], add_generation_prompt: bool) -> String {
+    let mut out = String::new();
+    for msg in messages {
+        let (role, content) = match msg.role {
+            Role::Tool => ("user", format!("Tool result: {}", msg.content)),
+            ref r => (
+                match r {
+                    Role::System => "system",
+                    Role::Assistant => "assistant",
+                    _ => "user",
+                },
+                msg.content.clone(),
+            ),
+        };
+        out.push_str(&format!("<|{role}|>\n{content}<|end|>\n"));
+    }
+    if add_generation_prompt {
+        out.push_str("<|assistant|>\n");
+    }
+    out
+}
+
+/// Chat template for a loaded model.
+#[derive(Debug, Clone)]
+pub struct ChatTemplate {
+    /// Parsed `tokenizer.chat_template`, if present and supported.
+    jinja: Option<Template>,
+    /// The Jinja template renders `tools` itself.
+    jinja_tools: bool,
+    /// Built-in format used when there is no Jinja template or it fails.
+    format: ChatFormat,
+    bos_token: String,
+    eos_token: String,
+}
+
+impl ChatTemplate {
+    /// Build from GGUF metadata and the model's tokenizer.
+    pub fn from_gguf(gguf: &GgufFile, tokenizer: &BpeTokenizer) -> Self {
+        let source = gguf
+            .metadata
+            .get("tokenizer.chat_template")
+            .and_then(|v| v.as_str());
+        let format = ChatFormat::detect(source, gguf.architecture().unwrap_or("llama"), |t| {
+            tokenizer.token_id(t).is_some()
+        });
+
+        let jinja = source.and_then(|src| match Template::parse(src) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                tracing::warn!("Unsupported chat template ({e}), using built-in {format:?}");
+                None
+            }
+        });
+
+        Self {
+            jinja_tools: jinja.is_some() && source.is_some_and(mentions_tools),
+            jinja,
+            format,
+            bos_token: tokenizer.decode_token(tokenizer.bos_id).to_string(),
+            eos_token: tokenizer.decode_token(tokenizer.eos_id).to_string(),
+        }
+    }
+
+    /// A built-in format with explicit BOS/EOS strings.
+    pub fn builtin(format: ChatFormat, bos_token: &str, eos_token: &str) -> Self {
+        Self {
+            jinja: None,
+            jinja_tools: false,
+            format,
+            bos_token: bos_token.into(),
+            eos_token: eos_token.into(),
+        }
+    }
+
+    /// Use a Jinja template source, keeping `fallback` for render errors.
+    pub fn from_jinja(
+        source: &str,
+        fallback: ChatFormat,
+        bos_token: &str,
+        eos_token: &str,
+    ) -> bizclaw_core::error::Result<Self> {
+        Ok(Self {
+            jinja: Some(Template::parse(source)?),
+            jinja_tools: mentions_tools(source),
+            ..Self::builtin(fallback, bos_token, eos_token)
+        })
+    }
+
+    /// The built-in format family (used for fallback and stop tokens).
+    pub fn format(&self) -> ChatFormat {
+        self.format
+    }
+
+    /// Whether the model's own Jinja template is in use.
+    pub fn has_jinja(&self) -> bool {
+        self.jinja.is_some()
+    }
+
+    /// How this model offers and calls tools.
+    pub fn tool_style(&self) -> ToolCallStyle {
+        ToolCallStyle::for_format(self.format)
+    }
+
+    /// Render a conversation into a prompt string.
+    ///
+    /// Tool definitions are exposed to Jinja templates as `tools` in the
+    /// OpenAI function format; templates that never mention `tools` get the
+    /// family's tool prompt injected into the messages instead. If the
+    /// template errors (e.g. it calls `raise_exception` on role order), the
+    /// built-in format is used.
+    pub fn render(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        add_generation_prompt: bool,
+    ) -> String {
+        if let Some(template) = &self.jinja {
+            let context = if tools.is_empty() || self.jinja_tools {
+                self.context(messages, tools, add_generation_prompt)
+            } else {
+                let injected = self.tool_style().inject(messages, tools);
+                self.context(&injected, &[], add_generation_prompt)
+            };
+            match template.render(context) {
+                Ok(prompt) => return prompt,
+                Err(e) => tracing::warn!(
+                    "Chat template failed ({e}), using built-in {:?}",
+                    self.format
+                ),
+            }
+        }
+        self.format.render_with_tools(
+            messages,
+            tools,
+            &self.bos_token,
+            &self.eos_token,
+            add_generation_prompt,
+        )
+    }
+
+    fn context(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        add_generation_prompt: bool,
+    ) -> HashMap<String, Value> {
+        let mut vars = HashMap::new();
+        vars.insert(
+            "messages".into(),
+            Value::List(messages.iter().map(message_value).collect()),
+        );
+        if !tools.is_empty() {
+            let tools = tools
+                .iter()
+                .map(|t| {
+                    Value::from(&serde_json::json!({
+                        "type": "function",
+                        "function": {
+                            "name": t.name,
+                            "description": t.description,
+                            "parameters": t.parameters,
+                        }
+                    }))
+                })
+                .collect();
+            vars.insert("tools".into(), Value::List(tools));
+        }
+        vars.insert("bos_token".into(), self.bos_token.as_str().into());
+        vars.insert("eos_token".into(), self.eos_token.as_str().into());
+        vars.insert("add_generation_prompt".into(), add_generation_prompt.into());
+        vars
+    }
+}
+
+fn mentions_tools(source: &str) -> bool {
+    source.contains("tools")
+}
+
+fn message_value(msg: &Message) -> Value {
+    let mut m = BTreeMap::new();
+    m.insert("role".to_string(), Value::Str(msg.role.to_string()));
+    m.insert("content".to_string(), Value::Str(msg.content.clone()));
+    if let Some(name) = &msg.name {
+        m.insert("name".to_string(), name.as_str().into());
+    }
+    if let Some(id) = &msg.tool_call_id {
+        m.insert("tool_call_id".to_string(), id.as_str().into());
+    }
+    if let Some(calls) = msg.tool_calls.as_ref().filter(|c| !c.is_empty()) {
+        let calls = calls
+            .iter()
+            .map(|c| {
+                // Templates expect arguments as a mapping (they `tojson` it)
+                let args: serde_json::Value = serde_json::from_str(&c.function.arguments)
+                    .unwrap_or_else(|_| serde_json::Value::String(c.function.arguments.clone()));
+                Value::from(&serde_json::json!({
+                    "id": c.id,
+                    "type": c.r#type,
+                    "function": {"name": c.function.name, "arguments": args},
+                }))
+            })
+            .collect();
+        m.insert("tool_calls".to_string(), Value::List(calls));
+    }
+    Value::Map(m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bizclaw_core::types::{FunctionCall, ToolCall};
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            Message::system("You are helpful."),
+            Message::user("Hi"),
+            Message::assistant("Hello!"),
+            Message::user("Weather?"),
+        ]
+    }
+
+    const CHATML_JINJA: &str = "{% for message in messages %}{{'<|im_start|>' + message['role'] + '\n' + message['content'] + '<|im_end|>' + '\n'}}{% endfor %}{% if add_generation_prompt %}{{ '<|im_start|>assistant\n' }}{% endif %}";
+
+    #[test]
+    fn test_jinja_chatml_matches_builtin() {
+        let t = ChatTemplate::from_jinja(CHATML_JINJA, ChatFormat::ChatMl, "", "").unwrap();
+        let msgs = conversation();
+        let rendered = t.render(&msgs, &[], true);
+        assert_eq!(rendered, ChatFormat::ChatMl.render(&msgs, "", "", true));
+        assert!(
+            rendered.ends_with("<|im_start|>user\nWeather?<|im_end|>\n<|im_start|>assistant\n")
+        );
+    }
+
+    #[test]
+    fn test_llama3_template() {
+        let src = "{% set loop_messages = messages %}{% for message in loop_messages %}{% set content = '<|start_header_id|>' + message['role'] + '<|end_header_id|>\n\n'+ message['content'] | trim + '<|eot_id|>' %}{% if loop.index0 == 0 %}{% set content = bos_token + content %}{% endif %}{{ content }}{% endfor %}{% if add_generation_prompt %}{{ '<|start_header_id|>assistant<|end_header_id|>\n\n' }}{% endif %}";
+        let t =
+            ChatTemplate::from_jinja(src, ChatFormat::Llama3, "<|begin_of_text|>", "<|eot_id|>")
+                .unwrap();
+        let msgs = conversation();
+        assert_eq!(
+            t.render(&msgs, &[], true),
+            ChatFormat::Llama3.render(&msgs, "<|begin_of_text|>", "<|eot_id|>", true)
+        );
+    }
+
+    #[test]
+    fn test_mistral_template_with_raise() {
+        // Mistral-style template that rejects system messages
+        let src = "{{ bos_token }}{% for message in messages %}{% if (message['role'] == 'user') != (loop.index0 % 2 == 0) %}{{ raise_exception('Conversation roles must alternate user/assistant/user/assistant/...') }}{% endif %}{% if message['role'] == 'user' %}{{ '[INST] ' + message['content'] + ' [/INST]' }}{% elif message['role'] == 'assistant' %}{{ message['content'] + eos_token}}{% endif %}{% endfor %}";
+        let t = ChatTemplate::from_jinja(src, ChatFormat::Mistral, "<s>", "</s>").unwrap();
+
+        let ok = [
+            Message::user("a"),
+            Message::assistant("b"),
+            Message::user("c"),
+        ];
+        assert_eq!(
+            t.render(&ok, &[], true),
+            "<s>[INST] a [/INST]b</s>[INST] c [/INST]"
+        );
+
+        // Template raises on the system message → built-in Mistral fallback
+        let rendered = t.render(&conversation(), &[], true);
+        assert_eq!(
+            rendered,
+            "<s>[INST] You are helpful.\n\nHi [/INST] Hello!</s>[INST] Weather? [/INST]"
+        );
+    }
+
+    #[test]
+    fn test_builtin_gemma_and_phi3() {
+        let msgs = conversation();
+        assert_eq!(
+            ChatFormat::Gemma.render(&msgs, "<bos>", "<eos>", true),
+            "<bos><start_of_turn>user\nYou are helpful.\n\nHi<end_of_turn>\n<start_of_turn>model\nHello!<end_of_turn>\n<start_of_turn>user\nWeather?<end_of_turn>\n<start_of_turn>model\n"
+        );
+        assert_eq!(
+            ChatFormat::Phi3.render(&msgs, "", "", true),
+            "<|system|>\nYou are helpful.<|end|>\n<|user|>\nHi<|end|>\n<|assistant|>\nHello!<|end|>\n<|user|>\nWeather?<|end|>\n<|assistant|>\n"
+        );
+    }
+
+    #[test]
+    fn test_builtin_llama2() {
+        let msgs = conversation();
+        assert_eq!(
+            ChatFormat::Llama2.render(&msgs, "<s>", "</s>", true),
+            "<s>[INST] <<SYS>>\nYou are helpful.\n<</SYS>>\n\nHi [/INST] Hello! </s><s>[INST] Weather? [/INST]"
+        );
+    }
+
+    #[test]
+    fn test_tool_roles() {
+        let mut call = Message::assistant("");
+        call.tool_calls = Some(vec![ToolCall {
+            id: "call_1".into(),
+            r#type: "function".into(),
+            function: FunctionCall {
+                name: "get_weather".into(),
+                arguments: r#"{"city":"Hanoi"}"#.into(),
+            },
+        }]);
+        let msgs = vec![
+            Message::user("Weather in Hanoi?"),
+            call,
+            Message::tool("31°C, sunny", "call_1"),
+        ];
+
+        let chatml = ChatFormat::ChatMl.render(&msgs, "", "", true);
+        assert!(chatml.contains(
+            "<|im_start|>assistant\n<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"city\":\"Hanoi\"}}\n</tool_call><|im_end|>"
+        ));
+        assert!(chatml.contains(
+            "<|im_start|>user\n<tool_response>\n31°C, sunny\n</tool_response><|im_end|>"
+        ));
+
+        let llama3 = ChatFormat::Llama3.render(&msgs, "", "", true);
+        assert!(
+            llama3.contains("<|start_header_id|>ipython<|end_header_id|>\n\n31°C, sunny<|eot_id|>")
+        );
+
+        // Jinja templates see tool_call_id and parsed arguments
+        let src = "{% for m in messages %}{% if m.role == 'tool' %}[{{ m.tool_call_id }}:{{ m.content }}]{% elif m.tool_calls %}{% for c in m.tool_calls %}{{ c.function.name }}{{ c.function.arguments | tojson }}{% endfor %}{% endif %}{% endfor %}{% if tools %}{{ tools[0].function.name }}{% endif %}";
+        let t = ChatTemplate::from_jinja(src, ChatFormat::ChatMl, "", "").unwrap();
+        let tools = [ToolDefinition {
+            name: "get_weather".into(),
+            description: "Weather lookup".into(),
+            parameters: serde_json::json!({"type": "object"}),
+        }];
+        assert_eq!(
+            t.render(&msgs, &tools, false),
+            "get_weather{\"city\": \"Hanoi\"}[call_1:31°C, sunny]get_weather"
+        );
+    }
+
+    #[test]
+    fn test_detect() {
+        let none = |_: &str| false;
+        assert_eq!(
+            ChatFormat::detect(Some(CHATML_JINJA), "llama", none),
+            ChatFormat::ChatMl
+        );
+        assert_eq!(ChatFormat::detect(None, "qwen2", none), ChatFormat::ChatMl);
+        assert_eq!(ChatFormat::detect(None, "gemma2", none), ChatFormat::Gemma);
+        assert_eq!(ChatFormat::detect(None, "llama", none), ChatFormat::Llama2);
+        assert_eq!(
+            ChatFormat::detect(None, "llama", |t| t == "<|start_header_id|>"),
+            ChatFormat::Llama3
+        );
+        assert_eq!(ChatFormat::from_name("ChatML"), Some(ChatFormat::ChatMl));
+        assert_eq!(ChatFormat::from_name("phi-3"), Some(ChatFormat::Phi3));
+    }
+
+    #[test]
+    fn test_qwen25_template_with_tools() {
+        let src = include_str!("../tests/fixtures/chat_templates/qwen2.5.jinja");
+        let t = ChatTemplate::from_jinja(src, ChatFormat::ChatMl, "", "<|im_end|>").unwrap();
+
+        let mut call = Message::assistant("");
+        call.tool_calls = Some(vec![ToolCall {
+            id: "call_1".into(),
+            r#type: "function".into(),
+            function: FunctionCall {
+                name: "get_weather".into(),
+                arguments: r#"{"city":"Hanoi"}"#.into(),
+            },
+        }]);
+        let msgs = vec![
+            Message::system("Be brief."),
+            Message::user("Weather in Hanoi?"),
+            call,
+            Message::tool("31°C", "call_1"),
+        ];
+        let tools = [ToolDefinition {
+            name: "get_weather".into(),
+            description: "Weather lookup".into(),
+            parameters: serde_json::json!({"type": "object"}),
+        }];
+
+        let out = t.render(&msgs, &tools, true);
+        assert!(out.starts_with("<|im_start|>system\nBe brief.\n\n# Tools\n"));
+        assert!(out.contains(
+            "<tools>\n{\"function\": {\"description\": \"Weather lookup\", \"name\": \"get_weather\", \"parameters\": {\"type\": \"object\"}}, \"type\": \"function\"}\n</tools>"
+        ));
+        assert!(out.contains("<|im_start|>user\nWeather in Hanoi?<|im_end|>\n"));
+        assert!(out.contains(
+            "<|im_start|>assistant\n<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Hanoi\"}}\n</tool_call><|im_end|>\n"
+        ));
+        assert!(out.ends_with(
+            "<|im_start|>user\n<tool_response>\n31°C\n</tool_response><|im_end|>\n<|im_start|>assistant\n"
+        ));
+
+        // Without tools or a system message the template's default system prompt is used
+        let plain = t.render(&[Message::user("Hi")], &[], true);
+        assert_eq!(
+            plain,
+            "<|im_start|>system\nYou are Qwen, created by Alibaba Cloud. You are a helpful assistant.<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n"
+        );
+    }
+
+    #[test]
+    fn test_tool_definitions_injected() {
+        let tools = [ToolDefinition {
+            name: "get_weather".into(),
+            description: "Weather lookup".into(),
+            parameters: serde_json::json!({"type": "object"}),
+        }];
+        let msgs = vec![Message::system("Be brief."), Message::user("Weather?")];
+
+        let chatml = ChatFormat::ChatMl.render_with_tools(&msgs, &tools, "", "", true);
+        assert!(chatml.starts_with("<|im_start|>system\nBe brief.\n\n# Tools\n"));
+        assert!(chatml.contains("<tools>\n{\"function\":{\"description\":\"Weather lookup\""));
+        assert!(chatml.ends_with("<|im_start|>user\nWeather?<|im_end|>\n<|im_start|>assistant\n"));
+
+        let llama3 = ChatFormat::Llama3.render_with_tools(&msgs, &tools, "", "", true);
+        assert!(
+            llama3.contains(
+                "<|start_header_id|>user<|end_header_id|>\n\nGiven the following functions"
+            )
+        );
+
+        let mistral = ChatFormat::Mistral.render_with_tools(&msgs, &tools, "<s>", "</s>", true);
+        assert!(mistral.starts_with("<s>[AVAILABLE_TOOLS] [{\"function\""));
+        assert!(mistral.ends_with("[/AVAILABLE_TOOLS][INST] Be brief.\n\nWeather? [/INST]"));
+
+        // A Jinja template that ignores `tools` gets them through the system prompt
+        let t = ChatTemplate::from_jinja(CHATML_JINJA, ChatFormat::ChatMl, "", "").unwrap();
+        assert!(!t.jinja_tools);
+        let out = t.render(&msgs, &tools, true);
+        assert!(out.contains("<|im_start|>system\nBe brief.\n\n# Tools\n"));
+        assert_eq!(
+            t.render(&msgs, &[], true),
+            ChatFormat::ChatMl.render(&msgs, "", "", true)
+        );
+    }
+}
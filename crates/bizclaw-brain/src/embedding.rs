@@ -0,0 +1,289 @@
+//! Sentence embeddings from GGUF models.
+//!
+//! Encoder models (`bert`, `nomic-bert`; e.g. bge, e5, nomic-embed-text) run
+//! bidirectionally through [`encoder`](crate::encoder); decoder models (e.g.
+//! e5-mistral, gte-Qwen2) run the causal forward pass. The per-token states
+//! are pooled as `{arch}.pooling_type` says (llama.cpp numbering: 1 = mean,
+//! 2 = CLS, 3 = last token) and L2-normalized, so cosine similarity is a dot
+//! product.
+
+use crate::kv_cache::{KvPrecision, KvStorage};
+use crate::{encoder, forward, mmap, model, tokenizer};
+use bizclaw_core::error::{BizClawError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// How token states become one vector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pooling {
+    /// Average of all tokens.
+    Mean,
+    /// The first token (BERT's `[CLS]`).
+    Cls,
+    /// The last token (decoder embedding models, which end with EOS).
+    Last,
+}
+
+impl Pooling {
+    /// Map GGUF `pooling_type`; 0 (none) and unknown values give `None`.
+    pub fn from_gguf(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(Self::Mean),
+            2 => Some(Self::Cls),
+            3 => Some(Self::Last),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Mean => "mean",
+            Self::Cls => "cls",
+            Self::Last => "last",
+        }
+    }
+
+    /// Pool `hidden` (`[n x dim]`, n > 0) into one `dim` vector.
+    pub fn pool(&self, hidden: &[f32], dim: usize) -> Vec<f32> {
+        let n = hidden.len() / dim;
+        match self {
+            Self::Cls => hidden[..dim].to_vec(),
+            Self::Last => hidden[(n - 1) * dim..n * dim].to_vec(),
+            Self::Mean => {
+                let mut sum = vec![0.0f32; dim];
+                for row in hidden.chunks_exact(dim) {
+                    crate::tensor::elementwise_add(&mut sum, row);
+                }
+                sum.iter_mut().for_each(|v| *v /= n as f32);
+                sum
+            }
+        }
+    }
+}
+
+/// Scale `v` to unit length (zero vectors stay zero).
+pub fn l2_normalize(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+enum Weights {
+    Encoder(encoder::EncoderWeights),
+    Decoder(forward::TransformerWeights),
+}
+
+/// A GGUF model loaded for embeddings only.
+pub struct EmbeddingModel {
+    mmap_model: mmap::MmapModel,
+    params: model::ModelParams,
+    tokenizer: tokenizer::BpeTokenizer,
+    weights: Weights,
+    pooling: Pooling,
+    normalize: bool,
+    path: PathBuf,
+}
+
+impl EmbeddingModel {
+    /// Load an embedding model. Pooling comes from the GGUF metadata,
+    /// defaulting to mean for encoders and last-token for decoders.
+    pub fn load(path: &Path, normalize: bool) -> Result<Self> {
+        let mmap_model = mmap::MmapModel::load(path)?;
+        let params = model::ModelParams::from_gguf(&mmap_model.gguf)?;
+        let tokenizer = tokenizer::BpeTokenizer::from_gguf(&mmap_model.gguf.metadata)?;
+
+        let arch_name = mmap_model.gguf.architecture().unwrap_or("llama");
+        let pooling_type = mmap_model
+            .gguf
+            .get_u32(&format!("{arch_name}.pooling_type"));
+        if pooling_type == Some(4) {
+            return Err(BizClawError::Brain(format!(
+                "{} is a reranker (rank pooling), not an embedding model",
+                path.display()
+            )));
+        }
+        let pooling =
+            pooling_type
+                .and_then(Pooling::from_gguf)
+                .unwrap_or(if params.arch.is_encoder() {
+                    Pooling::Mean
+                } else {
+                    Pooling::Last
+                });
+
+        let weights = if params.arch.is_encoder() {
+            Weights::Encoder(encoder::EncoderWeights::from_gguf(&mmap_model, &params)?)
+        } else {
+            Weights::Decoder(forward::TransformerWeights::from_gguf(
+                &mmap_model,
+                &params,
+            )?)
+        };
+        tracing::info!(
+            "Embedding model loaded: {} ({:?}, dim={}, pooling={})",
+            path.display(),
+            params.arch,
+            params.dim,
+            pooling.as_str()
+        );
+
+        Ok(Self {
+            mmap_model,
+            params,
+            tokenizer,
+            weights,
+            pooling,
+            normalize,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Length of the produced vectors.
+    pub fn dimensions(&self) -> usize {
+        self.params.dim as usize
+    }
+
+    pub fn pooling(&self) -> Pooling {
+        self.pooling
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Embed one text. Inputs longer than the model's context are truncated
+    /// (keeping the closing EOS/SEP token when the vocabulary adds one).
+    pub fn embed(&self, text: &str, batch_size: usize) -> Result<Vec<f32>> {
+        let mut tokens = self.tokenizer.encode_for_embedding(text);
+        let max = self.params.max_seq_len as usize;
+        if tokens.len() > max {
+            tokens.truncate(max);
+            if self.tokenizer.add_eos {
+                tokens[max - 1] = self.tokenizer.eos_id;
+            }
+        }
+        if tokens.is_empty() {
+            return Err(BizClawError::Brain(
+                "Nothing to embed: empty input and no BOS token".into(),
+            ));
+        }
+
+        let hidden = match &self.weights {
+            Weights::Encoder(weights) => {
+                encoder::encode(&self.mmap_model, weights, &self.params, &tokens)?
+            }
+            Weights::Decoder(weights) => {
+                let p = &self.params;
+                let mut kv_cache = KvStorage::new(
+                    KvPrecision::F32,
+                    p.n_layers as usize,
+                    tokens.len(),
+                    p.n_kv_heads as usize,
+                    p.head_dim as usize,
+                );
+                let mut hidden = Vec::with_capacity(tokens.len() * p.dim as usize);
+                let mut pos = 0;
+                for chunk in tokens.chunks(batch_size.max(1)) {
+                    let states = forward::hidden_states(
+                        &self.mmap_model,
+                        weights,
+                        p,
+                        &mut kv_cache,
+                        chunk,
+                        pos,
+                    )?;
+                    hidden.extend_from_slice(&states);
+                    pos += chunk.len();
+                }
+                hidden
+            }
+        };
+
+        let mut vector = self.pooling.pool(&hidden, self.dimensions());
+        if self.normalize {
+            l2_normalize(&mut vector);
+        }
+        Ok(vector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_model::TinyLlama;
+
+    fn dot(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    #[test]
+    fn test_pooling() {
+        let hidden = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        assert_eq!(Pooling::Cls.pool(&hidden, 2), vec![1.0, 2.0]);
+        assert_eq!(Pooling::Last.pool(&hidden, 2), vec![5.0, 6.0]);
+        assert_eq!(Pooling::Mean.pool(&hidden, 2), vec![3.0, 4.0]);
+        assert_eq!(Pooling::from_gguf(2), Some(Pooling::Cls));
+        assert_eq!(Pooling::from_gguf(0), None);
+
+        let mut v = [3.0, 4.0];
+        l2_normalize(&mut v);
+        assert_eq!(v, [0.6, 0.8]);
+    }
+
+    #[test]
+    fn test_embedding_architectures() {
+        for (architecture, pooling) in [
+            ("bert", Pooling::Cls),
+            ("nomic-bert", Pooling::Mean),
+            ("llama", Pooling::Last),
+        ] {
+            let file = TinyLlama {
+                architecture,
+                pooling: Some(pooling),
+                ..Default::default()
+            }
+            .write();
+            let model = EmbeddingModel::load(&file.path, true).unwrap();
+            assert_eq!(model.pooling(), pooling);
+
+            let a = model.embed("the cat sat", 4).unwrap();
+            let b = model.embed("the cat sat", 16).unwrap();
+            let c = model.embed("stock prices fell", 4).unwrap();
+            assert_eq!(a.len(), model.dimensions());
+            assert!((dot(&a, &a) - 1.0).abs() < 1e-4, "{architecture}");
+            // Batching the prefill does not change the result
+            assert!((dot(&a, &b) - 1.0).abs() < 1e-4, "{architecture}");
+            assert!(dot(&a, &c) < 0.9999, "{architecture}");
+        }
+    }
+
+    #[test]
+    fn test_encoder_is_bidirectional() {
+        // CLS pooling only sees later tokens through bidirectional attention
+        let file = TinyLlama {
+            architecture: "bert",
+            pooling: Some(Pooling::Cls),
+            ..Default::default()
+        }
+        .write();
+        let model = EmbeddingModel::load(&file.path, false).unwrap();
+        let a = model.embed("abc", 8).unwrap();
+        let b = model.embed("abd", 8).unwrap();
+        assert!(a.iter().zip(&b).any(|(x, y)| (x - y).abs() > 1e-4));
+    }
+
+    #[test]
+    fn test_long_input_is_truncated() {
+        let file = TinyLlama {
+            architecture: "bert",
+            context_length: 16,
+            ..Default::default()
+        }
+        .write();
+        let model = EmbeddingModel::load(&file.path, true).unwrap();
+        let long = "word ".repeat(40);
+        assert_eq!(model.embed(&long, 8).unwrap().len(), model.dimensions());
+    }
+}
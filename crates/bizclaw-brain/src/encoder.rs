@@ -0,0 +1,259 @@
+//! Encoder forward pass for BERT-style embedding models.
+//!
+//! Post-norm transformer with bidirectional attention: every token attends
+//! to the whole input, so there is no KV cache and the input runs in one
+//! pass. Covers `bert` (learned positions, biases, GELU) and `nomic-bert`
+//! (RoPE, fused QKV, SwiGLU).
+
+use crate::attention::multi_head_attention;
+use crate::forward::{attention_config, dequant_weight, embedding_row, project};
+use crate::model::{Architecture, ModelParams};
+use crate::{mmap::MmapModel, rope, tensor};
+use bizclaw_core::error::{BizClawError, Result};
+
+/// LayerNorm weight and optional bias.
+#[derive(Debug, Clone, Copy)]
+struct Norm {
+    weight: usize,
+    bias: Option<usize>,
+}
+
+/// A projection matrix and optional bias.
+#[derive(Debug, Clone, Copy)]
+struct Linear {
+    weight: usize,
+    bias: Option<usize>,
+}
+
+struct EncoderLayer {
+    /// Separate Q/K/V (BERT) or `None` when fused into `attn_qkv`
+    attn_q: Option<Linear>,
+    attn_k: Option<Linear>,
+    attn_v: Option<Linear>,
+    attn_qkv: Option<Linear>,
+    attn_output: Linear,
+    attn_output_norm: Norm,
+    ffn_up: Linear,
+    /// SwiGLU gate (nomic-bert); BERT uses a plain GELU FFN
+    ffn_gate: Option<Linear>,
+    ffn_down: Linear,
+    layer_output_norm: Norm,
+}
+
+/// Encoder weights — indices into the GGUF tensor list.
+pub struct EncoderWeights {
+    token_embd: usize,
+    token_types: Option<usize>,
+    position_embd: Option<usize>,
+    embd_norm: Norm,
+    layers: Vec<EncoderLayer>,
+}
+
+impl EncoderWeights {
+    /// Map the encoder tensors, failing on any the architecture needs.
+    pub fn from_gguf(model: &MmapModel, params: &ModelParams) -> Result<Self> {
+        let find = |name: &str| model.gguf.tensors.iter().position(|t| t.name == name);
+        let require = |name: &str| {
+            find(name).ok_or_else(|| {
+                BizClawError::Brain(format!(
+                    "Missing tensor {name} for {:?} architecture",
+                    params.arch
+                ))
+            })
+        };
+        let linear = |name: &str| -> Result<Linear> {
+            Ok(Linear {
+                weight: require(&format!("{name}.weight"))?,
+                bias: find(&format!("{name}.bias")),
+            })
+        };
+        let norm = |name: &str| -> Result<Norm> {
+            Ok(Norm {
+                weight: require(&format!("{name}.weight"))?,
+                bias: find(&format!("{name}.bias")),
+            })
+        };
+
+        let fused = params.arch == Architecture::NomicBert;
+        let mut layers = Vec::new();
+        for l in 0..params.n_layers {
+            let name = |t: &str| format!("blk.{l}.{t}");
+            let separate = |t: &str| (!fused).then(|| linear(&name(t))).transpose();
+            layers.push(EncoderLayer {
+                attn_q: separate("attn_q")?,
+                attn_k: separate("attn_k")?,
+                attn_v: separate("attn_v")?,
+                attn_qkv: fused.then(|| linear(&name("attn_qkv"))).transpose()?,
+                attn_output: linear(&name("attn_output"))?,
+                attn_output_norm: norm(&name("attn_output_norm"))?,
+                ffn_up: linear(&name("ffn_up"))?,
+                ffn_gate: fused.then(|| linear(&name("ffn_gate"))).transpose()?,
+                ffn_down: linear(&name("ffn_down"))?,
+                layer_output_norm: norm(&name("layer_output_norm"))?,
+            });
+        }
+
+        Ok(Self {
+            token_embd: require("token_embd.weight")?,
+            token_types: find("token_types.weight"),
+            position_embd: find("position_embd.weight"),
+            embd_norm: norm("token_embd_norm")?,
+            layers,
+        })
+    }
+}
+
+/// Run the encoder over `tokens` (at most `params.max_seq_len` of them) and
+/// return the final hidden states `[tokens.len() x dim]`.
+pub fn encode(
+    model: &MmapModel,
+    weights: &EncoderWeights,
+    params: &ModelParams,
+    tokens: &[u32],
+) -> Result<Vec<f32>> {
+    let n = tokens.len();
+    if n == 0 {
+        return Err(BizClawError::Brain("encode: empty input".into()));
+    }
+    if n > params.max_seq_len as usize {
+        return Err(BizClawError::Brain(format!(
+            "encode: {n} tokens exceed the model's {} positions",
+            params.max_seq_len
+        )));
+    }
+
+    let dim = params.dim as usize;
+    let hidden_dim = params.hidden_dim as usize;
+    let (q_dim, kv_dim) = (params.q_dim(), params.kv_dim());
+    let eps = params.rms_norm_eps;
+
+    // Token + segment 0 + position embeddings, then LayerNorm
+    let mut x = vec![0.0f32; n * dim];
+    let mut row = vec![0.0f32; dim];
+    for (t, &token) in tokens.iter().enumerate() {
+        let xt = &mut x[t * dim..(t + 1) * dim];
+        embedding_row(model, Some(weights.token_embd), token as usize, xt)?;
+        if weights.token_types.is_some() {
+            embedding_row(model, weights.token_types, 0, &mut row)?;
+            tensor::elementwise_add(xt, &row);
+        }
+        if weights.position_embd.is_some() {
+            embedding_row(model, weights.position_embd, t, &mut row)?;
+            tensor::elementwise_add(xt, &row);
+        }
+    }
+    layernorm_rows(model, weights.embd_norm, &mut x, dim, eps)?;
+
+    let mut q = vec![0.0f32; n * q_dim];
+    let mut k = vec![0.0f32; n * kv_dim];
+    let mut v = vec![0.0f32; n * kv_dim];
+    let mut att_out = vec![0.0f32; n * q_dim];
+    let mut xb = vec![0.0f32; n * dim];
+    let mut hb = vec![0.0f32; n * hidden_dim];
+    let mut hb2 = vec![0.0f32; n * hidden_dim];
+
+    for layer in &weights.layers {
+        if let Some(qkv_w) = layer.attn_qkv {
+            let width = q_dim + 2 * kv_dim;
+            let mut qkv = vec![0.0f32; n * width];
+            linear(model, qkv_w, &x, &mut qkv, n, width, dim)?;
+            for (t, r) in qkv.chunks_exact(width).enumerate() {
+                q[t * q_dim..(t + 1) * q_dim].copy_from_slice(&r[..q_dim]);
+                k[t * kv_dim..(t + 1) * kv_dim].copy_from_slice(&r[q_dim..q_dim + kv_dim]);
+                v[t * kv_dim..(t + 1) * kv_dim].copy_from_slice(&r[q_dim + kv_dim..]);
+            }
+        } else if let (Some(wq), Some(wk), Some(wv)) = (layer.attn_q, layer.attn_k, layer.attn_v) {
+            linear(model, wq, &x, &mut q, n, q_dim, dim)?;
+            linear(model, wk, &x, &mut k, n, kv_dim, dim)?;
+            linear(model, wv, &x, &mut v, n, kv_dim, dim)?;
+        }
+
+        if params.arch == Architecture::NomicBert {
+            let (heads, kv_heads) = (params.n_heads as usize, params.n_kv_heads as usize);
+            let head_dim = params.head_dim as usize;
+            let rope = rope::Rope::from_params(params);
+            for t in 0..n {
+                rope.apply(&mut q[t * q_dim..(t + 1) * q_dim], t, heads, head_dim);
+                rope.apply(&mut k[t * kv_dim..(t + 1) * kv_dim], t, kv_heads, head_dim);
+            }
+        }
+
+        // Bidirectional: every token sees all `n` keys
+        let config = attention_config(params, n);
+        for t in 0..n {
+            multi_head_attention(
+                &mut att_out[t * q_dim..(t + 1) * q_dim],
+                &q[t * q_dim..(t + 1) * q_dim],
+                &k,
+                &v,
+                &config,
+            );
+        }
+
+        linear(model, layer.attn_output, &att_out, &mut xb, n, dim, q_dim)?;
+        tensor::elementwise_add(&mut x, &xb);
+        layernorm_rows(model, layer.attn_output_norm, &mut x, dim, eps)?;
+
+        linear(model, layer.ffn_up, &x, &mut hb, n, hidden_dim, dim)?;
+        match layer.ffn_gate {
+            Some(gate) => {
+                linear(model, gate, &x, &mut hb2, n, hidden_dim, dim)?;
+                tensor::silu(&mut hb2);
+                tensor::elementwise_mul(&mut hb, &hb2);
+            }
+            None => tensor::gelu(&mut hb),
+        }
+        linear(model, layer.ffn_down, &hb, &mut xb, n, dim, hidden_dim)?;
+        tensor::elementwise_add(&mut x, &xb);
+        layernorm_rows(model, layer.layer_output_norm, &mut x, dim, eps)?;
+    }
+
+    Ok(x)
+}
+
+/// `output = input @ weight^T + bias` for every row.
+fn linear(
+    model: &MmapModel,
+    layer: Linear,
+    input: &[f32],
+    output: &mut [f32],
+    n_tokens: usize,
+    rows: usize,
+    cols: usize,
+) -> Result<()> {
+    project(
+        model,
+        Some(layer.weight),
+        input,
+        output,
+        n_tokens,
+        rows,
+        cols,
+    )?;
+    if let Some(idx) = layer.bias {
+        let bias = dequant_weight(model, idx, rows)?;
+        for row in output.chunks_exact_mut(rows) {
+            tensor::elementwise_add(row, &bias);
+        }
+    }
+    Ok(())
+}
+
+/// LayerNorm every `dim`-wide row of `x` in place.
+fn layernorm_rows(
+    model: &MmapModel,
+    norm: Norm,
+    x: &mut [f32],
+    dim: usize,
+    eps: f32,
+) -> Result<()> {
+    let weight = dequant_weight(model, norm.weight, dim)?;
+    let bias = norm
+        .bias
+        .map(|idx| dequant_weight(model, idx, dim))
+        .transpose()?;
+    for row in x.chunks_exact_mut(dim) {
+        tensor::layernorm(row, &weight, bias.as_deref(), eps);
+    }
+    Ok(())
+}
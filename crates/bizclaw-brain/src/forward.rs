@@ -1,12 +1,18 @@
-//! LLaMA transformer forward pass.
+//! Transformer forward pass for LLaMA-family models.
 //!
-//! Implements the complete LLaMA-2/3 transformer architecture:
-//! Embedding → N × (RMSNorm → Attention → RMSNorm → FFN) → RMSNorm → LM Head
+//! Embedding → N × (RMSNorm → Attention → RMSNorm → FFN) → RMSNorm → LM Head,
+//! with the per-architecture differences described by
+//! [`Architecture`](crate::model::Architecture): fused or separate Q/K/V and
+//! gate/up projections, attention biases, RoPE layout, FFN activation,
+//! embedding scaling, tied output weights and sliding-window attention.
 //!
-//! Reads weights from mmap, dequantizes on-the-fly, computes the forward
+//! Reads weights from mmap, multiplies directly on quantized blocks, computes the forward
 //! pass, and produces logits for the next token.
 
-use crate::{kv_cache::KvCache, mmap::MmapModel, model::ModelParams, quant, rope, tensor};
+use crate::attention::{AttentionConfig, multi_head_attention};
+use crate::kv_cache::{KvScratch, KvStorage};
+use crate::model::{Architecture, ModelParams};
+use crate::{mmap::MmapModel, qmatmul, quant, rope, tensor};
 use bizclaw_core::error::{BizClawError, Result};
 
 /// Transformer weights — indices into the GGUF tensor list.
@@ -15,7 +21,7 @@ pub struct TransformerWeights {
     pub token_embd: Option<usize>,
     // Output norm
     pub output_norm: Option<usize>,
-    // LM head (output projection)
+    // LM head (output projection); the embedding table when tied
     pub output: Option<usize>,
     // Per-layer weight indices
     pub layers: Vec<LayerWeights>,
@@ -27,220 +33,538 @@ pub struct LayerWeights {
     pub attn_q: Option<usize>,
     pub attn_k: Option<usize>,
     pub attn_v: Option<usize>,
+    /// Fused Q/K/V projection (Phi-3), rows `[q | k | v]`
+    pub attn_qkv: Option<usize>,
+    pub attn_q_bias: Option<usize>,
+    pub attn_k_bias: Option<usize>,
+    pub attn_v_bias: Option<usize>,
     pub attn_output: Option<usize>,
     pub ffn_norm: Option<usize>,
-    pub ffn_gate: Option<usize>, // gate_proj (SiLU activation)
-    pub ffn_up: Option<usize>,   // up_proj
+    pub ffn_gate: Option<usize>, // gate_proj (SiLU/GELU activation); None when fused into ffn_up
+    pub ffn_up: Option<usize>,   // up_proj, or rows `[gate | up]` when fused (Phi-3)
     pub ffn_down: Option<usize>, // down_proj
 }
 
 impl TransformerWeights {
-    /// Build weight index from GGUF tensor names.
-    pub fn from_gguf(model: &MmapModel, params: &ModelParams) -> Self {
+    /// Build weight index from GGUF tensor names, checking that every tensor
+    /// the architecture needs is present.
+    pub fn from_gguf(model: &MmapModel, params: &ModelParams) -> Result<Self> {
         let find = |name: &str| -> Option<usize> {
             model.gguf.tensors.iter().position(|t| t.name == name)
         };
+        let require = |name: &str| -> Result<Option<usize>> {
+            find(name).map(Some).ok_or_else(|| {
+                BizClawError::Brain(format!(
+                    "Missing tensor {name} for {:?} architecture",
+                    params.arch
+                ))
+            })
+        };
 
+        let fused = params.arch == Architecture::Phi3;
         let mut layers = Vec::new();
         for l in 0..params.n_layers {
+            let name = |t: &str| format!("blk.{l}.{t}");
+            let (attn_q, attn_k, attn_v, attn_qkv) = if fused {
+                (None, None, None, require(&name("attn_qkv.weight"))?)
+            } else {
+                (
+                    require(&name("attn_q.weight"))?,
+                    require(&name("attn_k.weight"))?,
+                    require(&name("attn_v.weight"))?,
+                    None,
+                )
+            };
             layers.push(LayerWeights {
-                attn_norm: find(&format!("blk.{l}.attn_norm.weight")),
-                attn_q: find(&format!("blk.{l}.attn_q.weight")),
-                attn_k: find(&format!("blk.{l}.attn_k.weight")),
-                attn_v: find(&format!("blk.{l}.attn_v.weight")),
-                attn_output: find(&format!("blk.{l}.attn_output.weight")),
-                ffn_norm: find(&format!("blk.{l}.ffn_norm.weight")),
-                ffn_gate: find(&format!("blk.{l}.ffn_gate.weight")),
-                ffn_up: find(&format!("blk.{l}.ffn_up.weight")),
-                ffn_down: find(&format!("blk.{l}.ffn_down.weight")),
+                attn_norm: find(&name("attn_norm.weight")),
+                attn_q,
+                attn_k,
+                attn_v,
+                attn_qkv,
+                attn_q_bias: find(&name("attn_q.bias")),
+                attn_k_bias: find(&name("attn_k.bias")),
+                attn_v_bias: find(&name("attn_v.bias")),
+                attn_output: require(&name("attn_output.weight"))?,
+                ffn_norm: find(&name("ffn_norm.weight")),
+                ffn_gate: if fused {
+                    None
+                } else {
+                    require(&name("ffn_gate.weight"))?
+                },
+                ffn_up: require(&name("ffn_up.weight"))?,
+                ffn_down: require(&name("ffn_down.weight"))?,
             });
         }
 
-        Self {
-            token_embd: find("token_embd.weight"),
+        let token_embd = require("token_embd.weight")?;
+        Ok(Self {
+            token_embd,
             output_norm: find("output_norm.weight"),
-            output: find("output.weight"),
+            // Tied embeddings (Gemma, small Qwen2) have no separate LM head
+            output: find("output.weight").or(token_embd),
             layers,
-        }
+        })
     }
 }
 
-/// Run a single-token forward pass through the LLaMA transformer.
+/// Run a single-token forward pass through the transformer.
 ///
 /// Returns logits of shape [vocab_size].
 pub fn forward(
     model: &MmapModel,
     weights: &TransformerWeights,
     params: &ModelParams,
-    kv_cache: &mut KvCache,
+    kv_cache: &mut KvStorage,
     token: u32,
     pos: usize,
     logits: &mut [f32],
 ) -> Result<()> {
+    let x = transformer_batch(model, weights, params, kv_cache, &[token], pos)?;
+
+    // Final RMSNorm + LM head
+    let dim = params.dim as usize;
+    let mut xb = vec![0.0f32; dim];
+    rmsnorm_rows(
+        model,
+        weights.output_norm,
+        &mut xb,
+        &x,
+        dim,
+        params.rms_norm_eps,
+    )?;
+    matmul_weight(
+        model,
+        weights.output,
+        &xb,
+        logits,
+        params.vocab_size as usize,
+        dim,
+    )
+}
+
+/// Run a batched forward pass over `tokens` placed at `start_pos..start_pos + n`.
+///
+/// Every projection is a matrix-matrix product over the whole batch, and the
+/// KV cache is filled for all positions in one go. Attention stays causal:
+/// token `t` only sees positions up to `start_pos + t`. Only the logits of
+/// the last token are computed (that is all prefill needs).
+pub fn forward_batch(
+    model: &MmapModel,
+    weights: &TransformerWeights,
+    params: &ModelParams,
+    kv_cache: &mut KvStorage,
+    tokens: &[u32],
+    start_pos: usize,
+    logits: &mut [f32],
+) -> Result<()> {
+    let x = transformer_batch(model, weights, params, kv_cache, tokens, start_pos)?;
+
+    // Prefill only needs the last token's logits
+    let dim = params.dim as usize;
+    let last = &x[x.len() - dim..];
+    let mut xl = vec![0.0f32; dim];
+    rmsnorm_rows(
+        model,
+        weights.output_norm,
+        &mut xl,
+        last,
+        dim,
+        params.rms_norm_eps,
+    )?;
+    matmul_weight(
+        model,
+        weights.output,
+        &xl,
+        logits,
+        params.vocab_size as usize,
+        dim,
+    )
+}
+
+/// Like [`forward_batch`], but computes the logits of every token:
+/// `logits` is `[tokens.len() x vocab_size]`, row `t` predicting the token
+/// after `tokens[t]`. Used to verify draft tokens in one pass.
+pub fn forward_batch_all_logits(
+    model: &MmapModel,
+    weights: &TransformerWeights,
+    params: &ModelParams,
+    kv_cache: &mut KvStorage,
+    tokens: &[u32],
+    start_pos: usize,
+    logits: &mut [f32],
+) -> Result<()> {
+    let x = transformer_batch(model, weights, params, kv_cache, tokens, start_pos)?;
+
+    let dim = params.dim as usize;
+    let mut xn = vec![0.0f32; x.len()];
+    rmsnorm_rows(
+        model,
+        weights.output_norm,
+        &mut xn,
+        &x,
+        dim,
+        params.rms_norm_eps,
+    )?;
+    matmul_weight_batch(
+        model,
+        weights.output,
+        &xn,
+        logits,
+        tokens.len(),
+        params.vocab_size as usize,
+        dim,
+    )
+}
+
+/// Final (output-normed) hidden states of every token, `[tokens.len() x dim]`,
+/// for decoder embedding models.
+pub fn hidden_states(
+    model: &MmapModel,
+    weights: &TransformerWeights,
+    params: &ModelParams,
+    kv_cache: &mut KvStorage,
+    tokens: &[u32],
+    start_pos: usize,
+) -> Result<Vec<f32>> {
+    let x = transformer_batch(model, weights, params, kv_cache, tokens, start_pos)?;
+    let mut xn = vec![0.0f32; x.len()];
+    rmsnorm_rows(
+        model,
+        weights.output_norm,
+        &mut xn,
+        &x,
+        params.dim as usize,
+        params.rms_norm_eps,
+    )?;
+    Ok(xn)
+}
+
+/// Tokens of one sequence in a multi-sequence batch, with the KV cache
+/// they attend over.
+pub struct SeqBatch<'a> {
+    pub kv_cache: &'a mut KvStorage,
+    pub tokens: &'a [u32],
+    /// Position of `tokens[0]`
+    pub start_pos: usize,
+}
+
+/// Run several independent sequences through the model together
+/// (continuous batching). Projections and the FFN see the rows of all
+/// sequences as one batch, so each weight is read once per step; RoPE,
+/// the KV cache and attention stay per sequence. `logits` is
+/// `[seqs.len() x vocab_size]`, row `s` holding the logits after the last
+/// token of `seqs[s]`.
+pub fn forward_seqs(
+    model: &MmapModel,
+    weights: &TransformerWeights,
+    params: &ModelParams,
+    seqs: &mut [SeqBatch<'_>],
+    logits: &mut [f32],
+) -> Result<()> {
+    let x = transformer_seqs(model, weights, params, seqs)?;
+
+    let dim = params.dim as usize;
+    let mut last = Vec::with_capacity(seqs.len() * dim);
+    let mut end = 0;
+    for seq in seqs.iter() {
+        end += seq.tokens.len();
+        last.extend_from_slice(&x[(end - 1) * dim..end * dim]);
+    }
+    let mut xn = vec![0.0f32; last.len()];
+    rmsnorm_rows(
+        model,
+        weights.output_norm,
+        &mut xn,
+        &last,
+        dim,
+        params.rms_norm_eps,
+    )?;
+    project(
+        model,
+        weights.output,
+        &xn,
+        logits,
+        seqs.len(),
+        params.vocab_size as usize,
+        dim,
+    )
+}
+
+/// Run the transformer layers over a batch, filling the KV cache; returns
+/// the final hidden states `[tokens.len() x dim]` (before the output norm).
+fn transformer_batch(
+    model: &MmapModel,
+    weights: &TransformerWeights,
+    params: &ModelParams,
+    kv_cache: &mut KvStorage,
+    tokens: &[u32],
+    start_pos: usize,
+) -> Result<Vec<f32>> {
+    let mut seqs = [SeqBatch {
+        kv_cache,
+        tokens,
+        start_pos,
+    }];
+    transformer_seqs(model, weights, params, &mut seqs)
+}
+
+/// [`transformer_batch`] over the rows of several sequences, in order.
+fn transformer_seqs(
+    model: &MmapModel,
+    weights: &TransformerWeights,
+    params: &ModelParams,
+    seqs: &mut [SeqBatch<'_>],
+) -> Result<Vec<f32>> {
+    let n: usize = seqs.iter().map(|s| s.tokens.len()).sum();
+    if seqs.iter().any(|s| s.tokens.is_empty()) || n == 0 {
+        return Err(BizClawError::Brain("forward_batch: empty batch".into()));
+    }
+
     let dim = params.dim as usize;
     let hidden_dim = params.hidden_dim as usize;
     let n_heads = params.n_heads as usize;
     let n_kv_heads = params.n_kv_heads as usize;
     let head_dim = params.head_dim as usize;
-    let kv_dim = n_kv_heads * head_dim;
-    let vocab_size = params.vocab_size as usize;
-
-    // ---- Step 1: Token embedding lookup ----
-    let mut x = vec![0.0f32; dim];
-    if let Some(embd_idx) = weights.token_embd {
-        let embd_tensor = &model.gguf.tensors[embd_idx];
-        let embd_data = model.tensor_data(embd_idx)?;
-        let offset = token as usize * dim;
-        let row_bytes =
-            dim * embd_tensor.ggml_type.type_size() / embd_tensor.ggml_type.block_size();
-
-        // If embedding is F32, direct copy. Otherwise dequantize.
-        if embd_tensor.ggml_type == crate::gguf::GgmlType::F32 {
-            let byte_offset = offset * 4;
-            for (i, xi) in x.iter_mut().enumerate().take(dim) {
-                let o = byte_offset + i * 4;
-                if o + 4 <= embd_data.len() {
-                    *xi = f32::from_le_bytes([
-                        embd_data[o],
-                        embd_data[o + 1],
-                        embd_data[o + 2],
-                        embd_data[o + 3],
-                    ]);
-                }
-            }
-        } else {
-            let row_offset = token as usize * row_bytes;
-            if row_offset + row_bytes <= embd_data.len() {
-                quant::dequantize_row(
-                    &embd_data[row_offset..],
-                    &mut x,
-                    dim,
-                    embd_tensor.ggml_type,
-                )?;
-            }
-        }
-    } else {
-        return Err(BizClawError::Brain("Missing token_embd.weight".into()));
+    let q_dim = params.q_dim();
+    let kv_dim = params.kv_dim();
+    let rope = rope::Rope::from_params(params);
+
+    // ---- Step 1: Token embeddings, one row per token ----
+    let mut x = vec![0.0f32; n * dim];
+    let tokens = seqs.iter().flat_map(|s| s.tokens.iter());
+    for (t, &token) in tokens.enumerate() {
+        embedding_row(
+            model,
+            weights.token_embd,
+            token as usize,
+            &mut x[t * dim..(t + 1) * dim],
+        )?;
+    }
+    if params.arch.scales_embeddings() {
+        let scale = (dim as f32).sqrt();
+        x.iter_mut().for_each(|v| *v *= scale);
     }
 
-    // Scratch buffers
-    let mut xb = vec![0.0f32; dim]; // after RMSNorm
-    let mut xb2 = vec![0.0f32; dim]; // second residual
-    let mut q = vec![0.0f32; dim]; // query
-    let mut k = vec![0.0f32; kv_dim]; // key
-    let mut v = vec![0.0f32; kv_dim]; // value
-    let mut att_out = vec![0.0f32; dim]; // attention output
-    let mut hb = vec![0.0f32; hidden_dim]; // FFN hidden
-    let mut hb2 = vec![0.0f32; hidden_dim]; // FFN gate
+    // Scratch buffers, token-major [n x width]
+    let mut xb = vec![0.0f32; n * dim];
+    let mut xb2 = vec![0.0f32; n * dim];
+    let mut q = vec![0.0f32; n * q_dim];
+    let mut k = vec![0.0f32; n * kv_dim];
+    let mut v = vec![0.0f32; n * kv_dim];
+    let mut att_out = vec![0.0f32; n * q_dim];
+    let mut hb = vec![0.0f32; n * hidden_dim];
+    let mut hb2 = vec![0.0f32; n * hidden_dim];
+    let mut kv_scratch = KvScratch::default();
 
     // ---- Step 2: Transformer layers ----
     for l in 0..params.n_layers as usize {
         let layer = &weights.layers[l];
 
-        // 2a. Attention RMSNorm
-        if let Some(norm_idx) = layer.attn_norm {
-            let norm_w = dequant_weight(model, norm_idx, dim)?;
-            tensor::rmsnorm(&mut xb, &x, &norm_w, params.rms_norm_eps);
-        } else {
-            xb.copy_from_slice(&x);
-        }
+        rmsnorm_rows(
+            model,
+            layer.attn_norm,
+            &mut xb,
+            &x,
+            dim,
+            params.rms_norm_eps,
+        )?;
 
-        // 2b. Q/K/V projections
-        matmul_weight(model, layer.attn_q, &xb, &mut q, dim, dim)?;
-        matmul_weight(model, layer.attn_k, &xb, &mut k, kv_dim, dim)?;
-        matmul_weight(model, layer.attn_v, &xb, &mut v, kv_dim, dim)?;
-
-        // 2c. RoPE on Q and K
-        rope::apply_rope_multi_head(&mut q, pos, n_heads, head_dim, params.rope_theta);
-        rope::apply_rope_multi_head(&mut k, pos, n_kv_heads, head_dim, params.rope_theta);
-
-        // 2d. Store K/V in cache
-        kv_cache.key_at_mut(l, pos).copy_from_slice(&k);
-        kv_cache.value_at_mut(l, pos).copy_from_slice(&v);
-
-        let seq_len = pos + 1;
-
-        // 2e. Multi-head attention (with GQA)
-        let kv_keys = kv_cache.keys(l, seq_len);
-        let kv_values = kv_cache.values(l, seq_len);
-
-        for h in 0..n_heads {
-            let kv_h = h * n_kv_heads / n_heads; // GQA: map query head to kv head
-            let q_slice = &q[h * head_dim..(h + 1) * head_dim];
-
-            // Build key/value slices for this kv head
-            let mut head_keys = vec![0.0f32; seq_len * head_dim];
-            let mut head_values = vec![0.0f32; seq_len * head_dim];
-            for t in 0..seq_len {
-                let k_start = t * kv_dim + kv_h * head_dim;
-                let v_start = t * kv_dim + kv_h * head_dim;
-                head_keys[t * head_dim..(t + 1) * head_dim]
-                    .copy_from_slice(&kv_keys[k_start..k_start + head_dim]);
-                head_values[t * head_dim..(t + 1) * head_dim]
-                    .copy_from_slice(&kv_values[v_start..v_start + head_dim]);
+        // Q/K/V projections (+ biases), RoPE, then into each sequence's cache
+        project_qkv(model, layer, &xb, &mut q, &mut k, &mut v, params)?;
+        let mut row = 0;
+        for seq in seqs.iter_mut() {
+            let start_pos = seq.start_pos;
+            let len = seq.tokens.len();
+            for t in row..row + len {
+                let pos = start_pos + t - row;
+                let qt = &mut q[t * q_dim..(t + 1) * q_dim];
+                let kt = &mut k[t * kv_dim..(t + 1) * kv_dim];
+                rope.apply(qt, pos, n_heads, head_dim);
+                rope.apply(kt, pos, n_kv_heads, head_dim);
+                seq.kv_cache
+                    .store(l, pos, kt, &v[t * kv_dim..(t + 1) * kv_dim]);
             }
 
-            // Attention for this head
-            let mut head_out = vec![0.0f32; head_dim];
-            crate::attention::attention(
-                &mut head_out,
-                q_slice,
-                &head_keys,
-                &head_values,
-                seq_len,
-                head_dim,
-            );
-
-            // Copy to full output
-            att_out[h * head_dim..(h + 1) * head_dim].copy_from_slice(&head_out);
+            // Decode the layer's cache once; token t attends to its causal
+            // prefix, limited to the last `sliding_window` positions if the
+            // model has one
+            let (keys, values) = seq.kv_cache.layer(l, start_pos + len, &mut kv_scratch);
+            for t in row..row + len {
+                let seq_len = start_pos + t - row + 1;
+                let first = params
+                    .sliding_window
+                    .map_or(0, |w| seq_len.saturating_sub(w as usize));
+                multi_head_attention(
+                    &mut att_out[t * q_dim..(t + 1) * q_dim],
+                    &q[t * q_dim..(t + 1) * q_dim],
+                    &keys[first * kv_dim..seq_len * kv_dim],
+                    &values[first * kv_dim..seq_len * kv_dim],
+                    &attention_config(params, seq_len - first),
+                );
+            }
+            row += len;
         }
 
-        // 2f. Output projection
-        matmul_weight(model, layer.attn_output, &att_out, &mut xb2, dim, dim)?;
-
-        // 2g. Residual connection
+        project(model, layer.attn_output, &att_out, &mut xb2, n, dim, q_dim)?;
         tensor::elementwise_add(&mut x, &xb2);
 
-        // 2h. FFN RMSNorm
-        if let Some(norm_idx) = layer.ffn_norm {
-            let norm_w = dequant_weight(model, norm_idx, dim)?;
-            tensor::rmsnorm(&mut xb, &x, &norm_w, params.rms_norm_eps);
+        rmsnorm_rows(model, layer.ffn_norm, &mut xb, &x, dim, params.rms_norm_eps)?;
+
+        // FFN: act(xb @ gate) * (xb @ up), then down
+        if layer.ffn_gate.is_some() {
+            project(model, layer.ffn_gate, &xb, &mut hb, n, hidden_dim, dim)?;
+            project(model, layer.ffn_up, &xb, &mut hb2, n, hidden_dim, dim)?;
         } else {
-            xb.copy_from_slice(&x);
+            let mut gate_up = vec![0.0f32; n * 2 * hidden_dim];
+            project(
+                model,
+                layer.ffn_up,
+                &xb,
+                &mut gate_up,
+                n,
+                2 * hidden_dim,
+                dim,
+            )?;
+            for (t, row) in gate_up.chunks_exact(2 * hidden_dim).enumerate() {
+                hb[t * hidden_dim..(t + 1) * hidden_dim].copy_from_slice(&row[..hidden_dim]);
+                hb2[t * hidden_dim..(t + 1) * hidden_dim].copy_from_slice(&row[hidden_dim..]);
+            }
         }
+        if params.arch.uses_gelu() {
+            tensor::gelu(&mut hb);
+        } else {
+            tensor::silu(&mut hb);
+        }
+        tensor::elementwise_mul(&mut hb, &hb2);
 
-        // 2i. FFN: SwiGLU
-        // gate = silu(xb @ gate_proj)
-        // up   = xb @ up_proj
-        // down = (gate * up) @ down_proj
-        matmul_weight(model, layer.ffn_gate, &xb, &mut hb, hidden_dim, dim)?;
-        matmul_weight(model, layer.ffn_up, &xb, &mut hb2, hidden_dim, dim)?;
+        project(model, layer.ffn_down, &hb, &mut xb2, n, dim, hidden_dim)?;
+        tensor::elementwise_add(&mut x, &xb2);
+    }
 
-        tensor::silu(&mut hb);
-        tensor::elementwise_mul(&mut hb, &hb2);
+    Ok(x)
+}
 
-        matmul_weight(model, layer.ffn_down, &hb, &mut xb2, dim, hidden_dim)?;
+/// Q/K/V projections for every row of `xb`, from separate or fused weights,
+/// with biases added when the model has them.
+fn project_qkv(
+    model: &MmapModel,
+    layer: &LayerWeights,
+    xb: &[f32],
+    q: &mut [f32],
+    k: &mut [f32],
+    v: &mut [f32],
+    params: &ModelParams,
+) -> Result<()> {
+    let dim = params.dim as usize;
+    let (q_dim, kv_dim) = (params.q_dim(), params.kv_dim());
+    let n = xb.len() / dim;
 
-        // 2j. Residual connection
-        tensor::elementwise_add(&mut x, &xb2);
+    if layer.attn_qkv.is_some() {
+        let width = q_dim + 2 * kv_dim;
+        let mut qkv = vec![0.0f32; n * width];
+        project(model, layer.attn_qkv, xb, &mut qkv, n, width, dim)?;
+        for (t, row) in qkv.chunks_exact(width).enumerate() {
+            q[t * q_dim..(t + 1) * q_dim].copy_from_slice(&row[..q_dim]);
+            k[t * kv_dim..(t + 1) * kv_dim].copy_from_slice(&row[q_dim..q_dim + kv_dim]);
+            v[t * kv_dim..(t + 1) * kv_dim].copy_from_slice(&row[q_dim + kv_dim..]);
+        }
+    } else {
+        project(model, layer.attn_q, xb, q, n, q_dim, dim)?;
+        project(model, layer.attn_k, xb, k, n, kv_dim, dim)?;
+        project(model, layer.attn_v, xb, v, n, kv_dim, dim)?;
     }
 
-    // ---- Step 3: Final RMSNorm ----
-    if let Some(norm_idx) = weights.output_norm {
-        let norm_w = dequant_weight(model, norm_idx, dim)?;
-        tensor::rmsnorm(&mut xb, &x, &norm_w, params.rms_norm_eps);
+    for (bias, out, width) in [
+        (layer.attn_q_bias, q, q_dim),
+        (layer.attn_k_bias, k, kv_dim),
+        (layer.attn_v_bias, v, kv_dim),
+    ] {
+        if let Some(idx) = bias {
+            let bias = dequant_weight(model, idx, width)?;
+            for row in out.chunks_exact_mut(width) {
+                tensor::elementwise_add(row, &bias);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Look up (and dequantize if needed) row `row` of an embedding table.
+pub(crate) fn embedding_row(
+    model: &MmapModel,
+    tensor_idx: Option<usize>,
+    row: usize,
+    x: &mut [f32],
+) -> Result<()> {
+    let dim = x.len();
+    let Some(embd_idx) = tensor_idx else {
+        return Err(BizClawError::Brain("Missing embedding table".into()));
+    };
+
+    let embd_tensor = &model.gguf.tensors[embd_idx];
+    let embd_data = model.tensor_data(embd_idx)?;
+    let offset = row * dim;
+    let row_bytes = dim * embd_tensor.ggml_type.type_size() / embd_tensor.ggml_type.block_size();
+
+    // If embedding is F32, direct copy. Otherwise dequantize.
+    if embd_tensor.ggml_type == crate::gguf::GgmlType::F32 {
+        let byte_offset = offset * 4;
+        for (i, xi) in x.iter_mut().enumerate().take(dim) {
+            let o = byte_offset + i * 4;
+            if o + 4 <= embd_data.len() {
+                *xi = f32::from_le_bytes([
+                    embd_data[o],
+                    embd_data[o + 1],
+                    embd_data[o + 2],
+                    embd_data[o + 3],
+                ]);
+            }
+        }
     } else {
-        xb.copy_from_slice(&x);
+        let row_offset = row * row_bytes;
+        if row_offset + row_bytes <= embd_data.len() {
+            quant::dequantize_row(&embd_data[row_offset..], x, dim, embd_tensor.ggml_type)?;
+        }
     }
+    Ok(())
+}
 
-    // ---- Step 4: LM Head → logits ----
-    matmul_weight(model, weights.output, &xb, logits, vocab_size, dim)?;
+pub(crate) fn attention_config(params: &ModelParams, seq_len: usize) -> AttentionConfig {
+    AttentionConfig {
+        n_heads: params.n_heads as usize,
+        n_kv_heads: params.n_kv_heads as usize,
+        seq_len,
+        head_dim: params.head_dim as usize,
+    }
+}
 
+/// RMSNorm every `dim`-wide row of `input` (copy through if the norm weight is absent).
+fn rmsnorm_rows(
+    model: &MmapModel,
+    norm_idx: Option<usize>,
+    output: &mut [f32],
+    input: &[f32],
+    dim: usize,
+    eps: f32,
+) -> Result<()> {
+    match norm_idx {
+        Some(idx) => {
+            let norm_w = dequant_weight(model, idx, dim)?;
+            for (out, row) in output.chunks_exact_mut(dim).zip(input.chunks_exact(dim)) {
+                tensor::rmsnorm(out, row, &norm_w, eps);
+            }
+        }
+        None => output.copy_from_slice(input),
+    }
     Ok(())
 }
 
 /// Dequantize a full weight tensor to f32.
-fn dequant_weight(model: &MmapModel, tensor_idx: usize, n_elements: usize) -> Result<Vec<f32>> {
+pub(crate) fn dequant_weight(
+    model: &MmapModel,
+    tensor_idx: usize,
+    n_elements: usize,
+) -> Result<Vec<f32>> {
     let data = model.tensor_data(tensor_idx)?;
     let tensor = &model.gguf.tensors[tensor_idx];
     let mut output = vec![0.0f32; n_elements];
@@ -250,6 +574,10 @@ fn dequant_weight(model: &MmapModel, tensor_idx: usize, n_elements: usize) -> Re
 
 /// Matrix-vector multiply using a weight tensor from mmap.
 /// output[rows] = weight[rows x cols] @ input[cols]
+///
+/// Runs directly on the quantized blocks (see `qmatmul`) — the weight
+/// matrix is never dequantized into a temporary f32 buffer. An active LoRA
+/// adapter adds its low-rank update on top.
 fn matmul_weight(
     model: &MmapModel,
     tensor_idx: Option<usize>,
@@ -262,12 +590,440 @@ fn matmul_weight(
     let data = model.tensor_data(idx)?;
     let tensor = &model.gguf.tensors[idx];
 
-    // Dequantize entire weight matrix
-    let n_elements = rows * cols;
-    let mut weight = vec![0.0f32; n_elements];
-    quant::dequantize_row(data, &mut weight, n_elements, tensor.ggml_type)?;
+    qmatmul::matmul(output, data, tensor.ggml_type, input, rows, cols)?;
+    apply_lora(model, idx, input, &mut output[..rows])
+}
 
-    // MatMul
-    tensor::matmul(output, &weight, input, rows, cols);
-    Ok(())
+/// Batched matrix multiply: output[n x rows] = input[n x cols] @ weight[rows x cols]^T.
+fn matmul_weight_batch(
+    model: &MmapModel,
+    tensor_idx: Option<usize>,
+    input: &[f32],
+    output: &mut [f32],
+    n_tokens: usize,
+    rows: usize,
+    cols: usize,
+) -> Result<()> {
+    let idx = tensor_idx.ok_or_else(|| BizClawError::Brain("Missing weight tensor".into()))?;
+    let data = model.tensor_data(idx)?;
+    let tensor = &model.gguf.tensors[idx];
+
+    qmatmul::matmul_batch(output, data, tensor.ggml_type, input, n_tokens, rows, cols)?;
+    apply_lora(model, idx, input, &mut output[..n_tokens * rows])
+}
+
+fn apply_lora(model: &MmapModel, idx: usize, input: &[f32], output: &mut [f32]) -> Result<()> {
+    match model.lora() {
+        Some(lora) => lora.adapter.apply(idx, lora.scale, input, output),
+        None => Ok(()),
+    }
+}
+
+/// `output[n x rows] = input[n x cols] @ weight^T`, as a matrix-vector
+/// product for single tokens (decode) and a batched product otherwise.
+pub(crate) fn project(
+    model: &MmapModel,
+    tensor_idx: Option<usize>,
+    input: &[f32],
+    output: &mut [f32],
+    n_tokens: usize,
+    rows: usize,
+    cols: usize,
+) -> Result<()> {
+    if n_tokens == 1 {
+        matmul_weight(model, tensor_idx, input, output, rows, cols)
+    } else {
+        matmul_weight_batch(model, tensor_idx, input, output, n_tokens, rows, cols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf::GgmlType;
+    use crate::kv_cache::KvPrecision;
+    use crate::test_model::TinyLlama;
+
+    fn load(ty: GgmlType) -> (crate::test_model::TempModel, MmapModel, ModelParams) {
+        load_spec(TinyLlama {
+            weight_type: ty,
+            ..Default::default()
+        })
+    }
+
+    fn load_spec(spec: TinyLlama) -> (crate::test_model::TempModel, MmapModel, ModelParams) {
+        let file = spec.write();
+        let model = MmapModel::load(&file.path).unwrap();
+        let params = ModelParams::from_gguf(&model.gguf).unwrap();
+        (file, model, params)
+    }
+
+    fn new_cache(params: &ModelParams) -> KvStorage {
+        new_cache_with(params, KvPrecision::F32)
+    }
+
+    fn new_cache_with(params: &ModelParams, precision: KvPrecision) -> KvStorage {
+        KvStorage::new(
+            precision,
+            params.n_layers as usize,
+            params.max_seq_len as usize,
+            params.n_kv_heads as usize,
+            params.head_dim as usize,
+        )
+    }
+
+    /// Last-token logits for `tokens` prefilled from position 0.
+    fn prefill_logits(spec: TinyLlama, tokens: &[u32]) -> Vec<f32> {
+        let (_file, model, params) = load_spec(spec);
+        let weights = TransformerWeights::from_gguf(&model, &params).unwrap();
+        let mut cache = new_cache(&params);
+        let mut logits = vec![0.0f32; params.vocab_size as usize];
+        forward_batch(
+            &model,
+            &weights,
+            &params,
+            &mut cache,
+            tokens,
+            0,
+            &mut logits,
+        )
+        .unwrap();
+        logits
+    }
+
+    fn max_diff(a: &[f32], b: &[f32]) -> f32 {
+        a.iter()
+            .zip(b)
+            .fold(0.0f32, |m, (x, y)| m.max((x - y).abs()))
+    }
+
+    fn check_batch_matches_sequential(ty: GgmlType) {
+        check_spec_batch_matches_sequential(TinyLlama {
+            weight_type: ty,
+            ..Default::default()
+        });
+    }
+
+    fn check_spec_batch_matches_sequential(spec: TinyLlama) {
+        let label = format!("{} {:?}", spec.architecture, spec.weight_type);
+        let (_file, model, params) = load_spec(spec);
+        let weights = TransformerWeights::from_gguf(&model, &params).unwrap();
+        let tokens: Vec<u32> = (0..11).map(|i| (i * 7 + 3) % params.vocab_size).collect();
+        let vocab = params.vocab_size as usize;
+
+        let mut seq_cache = new_cache(&params);
+        let mut seq_logits = vec![0.0f32; vocab];
+        for (pos, &tok) in tokens.iter().enumerate() {
+            forward(
+                &model,
+                &weights,
+                &params,
+                &mut seq_cache,
+                tok,
+                pos,
+                &mut seq_logits,
+            )
+            .unwrap();
+        }
+
+        // Uneven chunks make sure start_pos is honoured across batches.
+        let mut batch_cache = new_cache(&params);
+        let mut batch_logits = vec![0.0f32; vocab];
+        let mut pos = 0;
+        for chunk in tokens.chunks(4) {
+            forward_batch(
+                &model,
+                &weights,
+                &params,
+                &mut batch_cache,
+                chunk,
+                pos,
+                &mut batch_logits,
+            )
+            .unwrap();
+            pos += chunk.len();
+        }
+
+        for (i, (a, b)) in seq_logits.iter().zip(&batch_logits).enumerate() {
+            assert!(
+                (a - b).abs() < 1e-4,
+                "{label} logit {i}: sequential={a} batched={b}"
+            );
+        }
+        let kv_len = tokens.len();
+        let (mut seq_scratch, mut batch_scratch) = (KvScratch::default(), KvScratch::default());
+        for l in 0..params.n_layers as usize {
+            let (seq_keys, _) = seq_cache.layer(l, kv_len, &mut seq_scratch);
+            let (batch_keys, _) = batch_cache.layer(l, kv_len, &mut batch_scratch);
+            for (a, b) in seq_keys.iter().zip(batch_keys) {
+                assert!((a - b).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_forward_batch_matches_sequential_f32() {
+        check_batch_matches_sequential(GgmlType::F32);
+    }
+
+    #[test]
+    fn test_forward_batch_matches_sequential_q8_0() {
+        check_batch_matches_sequential(GgmlType::Q8_0);
+    }
+
+    #[test]
+    fn test_reduced_kv_precision_tracks_f32() {
+        let (_file, model, params) = load(GgmlType::F32);
+        let weights = TransformerWeights::from_gguf(&model, &params).unwrap();
+        let tokens: Vec<u32> = (0..9).map(|i| (i * 5 + 1) % params.vocab_size).collect();
+        let vocab = params.vocab_size as usize;
+
+        let run = |precision| {
+            let mut cache = new_cache_with(&params, precision);
+            let mut logits = vec![0.0f32; vocab];
+            forward_batch(
+                &model,
+                &weights,
+                &params,
+                &mut cache,
+                &tokens[..6],
+                0,
+                &mut logits,
+            )
+            .unwrap();
+            for (pos, &tok) in tokens.iter().enumerate().skip(6) {
+                forward(&model, &weights, &params, &mut cache, tok, pos, &mut logits).unwrap();
+            }
+            logits
+        };
+
+        let reference = run(KvPrecision::F32);
+        let scale = reference.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+        for precision in [KvPrecision::F16, KvPrecision::Q8_0] {
+            let logits = run(precision);
+            let max_err = reference
+                .iter()
+                .zip(&logits)
+                .fold(0.0f32, |m, (a, b)| m.max((a - b).abs()));
+            assert!(
+                max_err < scale * 0.05,
+                "{precision:?}: max error {max_err} (scale {scale})"
+            );
+        }
+    }
+
+    #[test]
+    fn test_forward_batch_all_logits() {
+        let (_file, model, params) = load(GgmlType::F32);
+        let weights = TransformerWeights::from_gguf(&model, &params).unwrap();
+        let tokens = [5u32, 9, 14, 3];
+        let vocab = params.vocab_size as usize;
+
+        let mut cache = new_cache(&params);
+        let mut all = vec![0.0f32; tokens.len() * vocab];
+        forward_batch_all_logits(&model, &weights, &params, &mut cache, &tokens, 0, &mut all)
+            .unwrap();
+
+        let mut seq_cache = new_cache(&params);
+        let mut logits = vec![0.0f32; vocab];
+        for (pos, &tok) in tokens.iter().enumerate() {
+            forward(
+                &model,
+                &weights,
+                &params,
+                &mut seq_cache,
+                tok,
+                pos,
+                &mut logits,
+            )
+            .unwrap();
+            let row = &all[pos * vocab..(pos + 1) * vocab];
+            for (a, b) in row.iter().zip(&logits) {
+                assert!((a - b).abs() < 1e-4, "position {pos}: {a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_forward_seqs_matches_separate_sequences() {
+        let (_file, model, params) = load(GgmlType::Q8_0);
+        let weights = TransformerWeights::from_gguf(&model, &params).unwrap();
+        let vocab = params.vocab_size as usize;
+        let a = [1u32, 40, 41, 42, 43, 44];
+        let b = [1u32, 70, 71];
+
+        // Reference: each sequence on its own
+        let reference = |tokens: &[u32]| {
+            let mut cache = new_cache(&params);
+            let mut logits = vec![0.0f32; vocab];
+            forward_batch(
+                &model,
+                &weights,
+                &params,
+                &mut cache,
+                tokens,
+                0,
+                &mut logits,
+            )
+            .unwrap();
+            logits
+        };
+
+        // `a` is decoding its last token while `b` prefills its prompt
+        let (mut cache_a, mut cache_b) = (new_cache(&params), new_cache(&params));
+        let mut logits = vec![0.0f32; vocab];
+        forward_batch(
+            &model,
+            &weights,
+            &params,
+            &mut cache_a,
+            &a[..5],
+            0,
+            &mut logits,
+        )
+        .unwrap();
+        let mut both = vec![0.0f32; 2 * vocab];
+        let mut seqs = [
+            SeqBatch {
+                kv_cache: &mut cache_a,
+                tokens: &a[5..],
+                start_pos: 5,
+            },
+            SeqBatch {
+                kv_cache: &mut cache_b,
+                tokens: &b,
+                start_pos: 0,
+            },
+        ];
+        forward_seqs(&model, &weights, &params, &mut seqs, &mut both).unwrap();
+
+        assert!(max_diff(&both[..vocab], &reference(&a)) < 1e-3);
+        assert!(max_diff(&both[vocab..], &reference(&b)) < 1e-3);
+
+        let mut empty = [SeqBatch {
+            kv_cache: &mut cache_b,
+            tokens: &[],
+            start_pos: 0,
+        }];
+        assert!(forward_seqs(&model, &weights, &params, &mut empty, &mut both).is_err());
+    }
+
+    #[test]
+    fn test_architectures_batch_matches_sequential() {
+        for spec in [
+            TinyLlama {
+                architecture: "qwen2",
+                attn_bias: true,
+                ..Default::default()
+            },
+            TinyLlama {
+                architecture: "phi3",
+                ..Default::default()
+            },
+            TinyLlama {
+                architecture: "gemma",
+                key_length: Some(32),
+                ..Default::default()
+            },
+            TinyLlama {
+                architecture: "mistral",
+                sliding_window: Some(5),
+                ..Default::default()
+            },
+        ] {
+            check_spec_batch_matches_sequential(spec);
+        }
+    }
+
+    #[test]
+    fn test_phi3_fused_weights_match_split() {
+        // Same random weights, written fused (phi3) and split (qwen2); both
+        // use NeoX RoPE and SiLU, so only the tensor layout differs.
+        let tokens = [4u32, 17, 29, 8, 40];
+        let split = prefill_logits(
+            TinyLlama {
+                architecture: "qwen2",
+                ..Default::default()
+            },
+            &tokens,
+        );
+        let fused = prefill_logits(
+            TinyLlama {
+                architecture: "phi3",
+                ..Default::default()
+            },
+            &tokens,
+        );
+        assert!(max_diff(&split, &fused) < 1e-4);
+
+        let biased = prefill_logits(
+            TinyLlama {
+                architecture: "qwen2",
+                attn_bias: true,
+                ..Default::default()
+            },
+            &tokens,
+        );
+        assert!(max_diff(&split, &biased) > 1e-3);
+    }
+
+    #[test]
+    fn test_gemma_tied_output_and_wide_heads() {
+        let (_file, model, params) = load_spec(TinyLlama {
+            architecture: "gemma",
+            key_length: Some(32),
+            ..Default::default()
+        });
+        assert_eq!(params.q_dim(), 128);
+        assert_eq!(params.dim, 64);
+        let weights = TransformerWeights::from_gguf(&model, &params).unwrap();
+        assert_eq!(weights.output, weights.token_embd);
+    }
+
+    #[test]
+    fn test_sliding_window_limits_attention() {
+        // With one layer each key depends only on its own token, and RoPE
+        // scores only on relative positions, so a windowed model sees exactly
+        // what a fresh run over the last `window` tokens sees.
+        let spec = TinyLlama {
+            n_layers: 1,
+            architecture: "mistral",
+            sliding_window: Some(4),
+            ..Default::default()
+        };
+        let tokens: Vec<u32> = (0..10).map(|i| (i * 11 + 5) % 90).collect();
+        let windowed = prefill_logits(spec.clone(), &tokens);
+        let tail = prefill_logits(spec.clone(), &tokens[6..]);
+        assert!(max_diff(&windowed, &tail) < 1e-3);
+
+        let full = prefill_logits(
+            TinyLlama {
+                sliding_window: None,
+                ..spec
+            },
+            &tokens,
+        );
+        assert!(max_diff(&windowed, &full) > 1e-3);
+    }
+
+    #[test]
+    fn test_missing_tensor_is_reported() {
+        // A llama file read as phi3 lacks the fused QKV tensor
+        let (_file, model, mut params) = load(GgmlType::F32);
+        params.arch = Architecture::Phi3;
+        let err = TransformerWeights::from_gguf(&model, &params)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("blk.0.attn_qkv.weight"), "{err}");
+    }
+
+    #[test]
+    fn test_forward_batch_rejects_empty() {
+        let (_file, model, params) = load(GgmlType::F32);
+        let weights = TransformerWeights::from_gguf(&model, &params).unwrap();
+        let mut cache = new_cache(&params);
+        let mut logits = vec![0.0f32; params.vocab_size as usize];
+        assert!(forward_batch(&model, &weights, &params, &mut cache, &[], 0, &mut logits).is_err());
+    }
 }
@@ -0,0 +1,827 @@
+//! GBNF grammars — parsing and incremental matching for constrained decoding.
+//!
+//! Same dialect as llama.cpp: `name ::= alternatives`, string literals,
+//! character classes (`[a-z]`, `[^"]`), `.`, groups and the repetition
+//! operators `* + ? {m} {m,} {m,n}`. Generation starts at the `root` rule.
+//!
+//! Matching is a pushdown automaton over Unicode characters. Each stack is one
+//! way the text so far can be parsed; a token is allowed when at least one
+//! stack survives all of its characters.
+
+use bizclaw_core::error::{BizClawError, Result};
+use std::collections::{HashMap, HashSet};
+
+/// A set of characters: ranges, optionally negated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CharClass {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl CharClass {
+    fn single(c: char) -> Self {
+        Self {
+            ranges: vec![(c, c)],
+            negated: false,
+        }
+    }
+
+    fn any() -> Self {
+        Self {
+            ranges: Vec::new(),
+            negated: true,
+        }
+    }
+
+    fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+
+    /// Whether some non-ASCII character could match (for split UTF-8 tokens).
+    fn may_match_non_ascii(&self) -> bool {
+        self.negated || self.ranges.iter().any(|&(_, hi)| hi >= '\u{80}')
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Element {
+    Chars(CharClass),
+    Rule(usize),
+}
+
+type Alternative = Vec<Element>;
+
+/// A compiled GBNF grammar.
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    rules: Vec<Vec<Alternative>>,
+    names: Vec<String>,
+    root: usize,
+}
+
+/// Position inside one alternative of a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Frame {
+    rule: u32,
+    alt: u32,
+    pos: u32,
+}
+
+type Stack = Vec<Frame>;
+
+impl Grammar {
+    /// Parse GBNF source. The grammar must define a `root` rule.
+    pub fn parse(src: &str) -> Result<Self> {
+        Parser::new(src).parse()
+    }
+
+    /// Matcher state before any text has been generated.
+    pub fn start(&self) -> GrammarState<'_> {
+        let mut stacks = HashSet::new();
+        for alt in 0..self.rules[self.root].len() {
+            self.expand(
+                vec![Frame {
+                    rule: self.root as u32,
+                    alt: alt as u32,
+                    pos: 0,
+                }],
+                &mut stacks,
+            );
+        }
+        GrammarState {
+            grammar: self,
+            stacks: stacks.into_iter().collect(),
+            partial: Vec::new(),
+        }
+    }
+
+    /// Names of all rules, including generated helper rules.
+    pub fn rule_names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn element(&self, frame: Frame) -> Option<&Element> {
+        self.rules[frame.rule as usize][frame.alt as usize].get(frame.pos as usize)
+    }
+
+    /// Normalize a stack so its top frame points at a character class,
+    /// following rule references into every alternative. Finished frames are
+    /// popped; an empty stack means the root rule is complete.
+    fn expand(&self, mut stack: Stack, out: &mut HashSet<Stack>) {
+        loop {
+            let Some(&top) = stack.last() else {
+                out.insert(stack);
+                return;
+            };
+            match self.element(top) {
+                None => {
+                    stack.pop();
+                }
+                Some(Element::Chars(_)) => {
+                    out.insert(stack);
+                    return;
+                }
+                Some(&Element::Rule(rule)) => {
+                    // Continue after the reference once the rule is done; drop
+                    // frames that have nothing left so recursion stays shallow.
+                    if let Some(top) = stack.last_mut() {
+                        top.pos += 1;
+                    }
+                    while let Some(&top) = stack.last() {
+                        if self.element(top).is_some() {
+                            break;
+                        }
+                        stack.pop();
+                    }
+                    for alt in 0..self.rules[rule].len() {
+                        let mut next = stack.clone();
+                        next.push(Frame {
+                            rule: rule as u32,
+                            alt: alt as u32,
+                            pos: 0,
+                        });
+                        self.expand(next, out);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Advance every stack over one character.
+    fn advance(&self, stacks: &[Stack], c: char) -> Vec<Stack> {
+        let mut out = HashSet::new();
+        for stack in stacks {
+            let Some(&top) = stack.last() else { continue };
+            if let Some(Element::Chars(class)) = self.element(top)
+                && class.matches(c)
+            {
+                let mut next = stack.clone();
+                if let Some(top) = next.last_mut() {
+                    top.pos += 1;
+                }
+                self.expand(next, &mut out);
+            }
+        }
+        out.into_iter().collect()
+    }
+}
+
+/// Incremental matcher over generated text. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct GrammarState<'g> {
+    grammar: &'g Grammar,
+    stacks: Vec<Stack>,
+    /// Trailing bytes of an unfinished UTF-8 character.
+    partial: Vec<u8>,
+}
+
+impl<'g> GrammarState<'g> {
+    /// State after appending `bytes`, or `None` if the grammar rejects them.
+    /// Bytes may end in the middle of a UTF-8 character.
+    pub fn accept_bytes(&self, bytes: &[u8]) -> Option<Self> {
+        let mut buf = self.partial.clone();
+        buf.extend_from_slice(bytes);
+
+        let (text, rest) = match std::str::from_utf8(&buf) {
+            Ok(s) => (s, &[][..]),
+            Err(e) if e.error_len().is_none() => {
+                let valid = e.valid_up_to();
+                (
+                    std::str::from_utf8(&buf[..valid]).unwrap_or_default(),
+                    &buf[valid..],
+                )
+            }
+            Err(_) => return None,
+        };
+
+        let mut stacks = self.stacks.clone();
+        for c in text.chars() {
+            stacks = self.grammar.advance(&stacks, c);
+            if stacks.is_empty() {
+                return None;
+            }
+        }
+
+        if !rest.is_empty() {
+            let possible = stacks.iter().any(|s| {
+                s.last()
+                    .and_then(|&top| self.grammar.element(top))
+                    .is_some_and(|e| matches!(e, Element::Chars(c) if c.may_match_non_ascii()))
+            });
+            if !possible {
+                return None;
+            }
+        }
+
+        Some(Self {
+            grammar: self.grammar,
+            stacks,
+            partial: rest.to_vec(),
+        })
+    }
+
+    /// State after appending `text`, or `None` if the grammar rejects it.
+    pub fn accept_str(&self, text: &str) -> Option<Self> {
+        self.accept_bytes(text.as_bytes())
+    }
+
+    /// The text so far is a complete match (generation may stop here).
+    pub fn is_complete(&self) -> bool {
+        self.partial.is_empty() && self.stacks.iter().any(|s| s.is_empty())
+    }
+
+    /// The text so far is complete and nothing more can be appended.
+    pub fn is_finished(&self) -> bool {
+        self.partial.is_empty() && self.stacks.iter().all(|s| s.is_empty())
+    }
+
+    /// Drop the borrow of the grammar, e.g. to store the state next to an
+    /// owned grammar. [`Grammar::resume`] reattaches it.
+    pub fn detach(self) -> DetachedState {
+        DetachedState {
+            stacks: self.stacks,
+            partial: self.partial,
+        }
+    }
+}
+
+/// A [`GrammarState`] without its grammar reference.
+#[derive(Debug, Clone)]
+pub struct DetachedState {
+    stacks: Vec<Stack>,
+    partial: Vec<u8>,
+}
+
+impl Grammar {
+    /// Reattach a state detached from a matcher of this grammar.
+    pub fn resume(&self, state: DetachedState) -> GrammarState<'_> {
+        GrammarState {
+            grammar: self,
+            stacks: state.stacks,
+            partial: state.partial,
+        }
+    }
+}
+
+// ── Parser ──────────────────────────────────────────────
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    ids: HashMap<String, usize>,
+    rules: Vec<Option<Vec<Alternative>>>,
+    names: Vec<String>,
+}
+
+fn parse_error(msg: impl Into<String>) -> BizClawError {
+    BizClawError::Brain(format!("grammar: {}", msg.into()))
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+impl Parser {
+    fn new(src: &str) -> Self {
+        Self {
+            chars: src.chars().collect(),
+            pos: 0,
+            ids: HashMap::new(),
+            rules: Vec::new(),
+            names: Vec::new(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Grammar> {
+        loop {
+            self.skip_space();
+            if self.pos >= self.chars.len() {
+                break;
+            }
+            let name = self.parse_name()?;
+            self.skip_space();
+            if !self.eat_str("::=") {
+                return Err(parse_error(format!("expected '::=' after '{name}'")));
+            }
+            let id = self.rule_id(&name);
+            if self.rules[id].is_some() {
+                return Err(parse_error(format!("rule '{name}' defined twice")));
+            }
+            let alts = self.parse_alternates()?;
+            self.rules[id] = Some(alts);
+        }
+
+        let root = *self
+            .ids
+            .get("root")
+            .ok_or_else(|| parse_error("missing 'root' rule"))?;
+        let mut rules = Vec::with_capacity(self.rules.len());
+        for (id, rule) in self.rules.into_iter().enumerate() {
+            match rule {
+                Some(alts) => rules.push(alts),
+                None => {
+                    return Err(parse_error(format!("undefined rule '{}'", self.names[id])));
+                }
+            }
+        }
+        let grammar = Grammar {
+            rules,
+            names: self.names,
+            root,
+        };
+        check_left_recursion(&grammar)?;
+        Ok(grammar)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat_str(&mut self, s: &str) -> bool {
+        let n = s.chars().count();
+        if self.chars.len() >= self.pos + n
+            && self.chars[self.pos..self.pos + n]
+                .iter()
+                .copied()
+                .eq(s.chars())
+        {
+            self.pos += n;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skip whitespace (including newlines) and `#` comments.
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '#' {
+                while let Some(c) = self.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+            } else if c.is_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String> {
+        let start = self.pos;
+        while self.peek().is_some_and(is_name_char) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(parse_error(format!(
+                "expected rule name at offset {}",
+                self.pos
+            )));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    /// Whether the cursor is at `name ::=`, i.e. the start of the next rule.
+    fn at_rule_start(&mut self) -> bool {
+        let save = self.pos;
+        let found = self.parse_name().is_ok() && {
+            self.skip_space();
+            self.eat_str("::=")
+        };
+        self.pos = save;
+        found
+    }
+
+    fn rule_id(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.rules.len();
+        self.ids.insert(name.to_string(), id);
+        self.names.push(name.to_string());
+        self.rules.push(None);
+        id
+    }
+
+    /// Define an anonymous helper rule.
+    fn helper_rule(&mut self, alts: Vec<Alternative>) -> usize {
+        let id = self.rules.len();
+        self.names.push(format!("_{id}"));
+        self.rules.push(Some(alts));
+        id
+    }
+
+    fn parse_alternates(&mut self) -> Result<Vec<Alternative>> {
+        let mut alts = vec![self.parse_sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alts.push(self.parse_sequence()?);
+        }
+        Ok(alts)
+    }
+
+    fn parse_sequence(&mut self) -> Result<Alternative> {
+        let mut seq = Vec::new();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                None | Some('|') | Some(')') => break,
+                Some(c) if is_name_char(c) && self.at_rule_start() => break,
+                _ => {}
+            }
+            let atom = self.parse_atom()?;
+            self.skip_space();
+            let repeated = self.parse_repetition(atom)?;
+            seq.extend(repeated);
+        }
+        Ok(seq)
+    }
+
+    fn parse_atom(&mut self) -> Result<Vec<Element>> {
+        match self.peek() {
+            Some('"') => {
+                self.pos += 1;
+                let mut elems = Vec::new();
+                loop {
+                    match self.peek() {
+                        None => return Err(parse_error("unterminated string literal")),
+                        Some('"') => {
+                            self.pos += 1;
+                            break;
+                        }
+                        _ => elems.push(Element::Chars(CharClass::single(self.parse_char()?))),
+                    }
+                }
+                Ok(elems)
+            }
+            Some('[') => {
+                self.pos += 1;
+                let negated = self.peek() == Some('^');
+                if negated {
+                    self.pos += 1;
+                }
+                let mut ranges = Vec::new();
+                loop {
+                    match self.peek() {
+                        None => return Err(parse_error("unterminated character class")),
+                        Some(']') => {
+                            self.pos += 1;
+                            break;
+                        }
+                        _ => {
+                            let lo = self.parse_char()?;
+                            let hi = if self.peek() == Some('-')
+                                && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']')
+                            {
+                                self.pos += 1;
+                                self.parse_char()?
+                            } else {
+                                lo
+                            };
+                            ranges.push((lo, hi));
+                        }
+                    }
+                }
+                Ok(vec![Element::Chars(CharClass { ranges, negated })])
+            }
+            Some('.') => {
+                self.pos += 1;
+                Ok(vec![Element::Chars(CharClass::any())])
+            }
+            Some('(') => {
+                self.pos += 1;
+                let alts = self.parse_alternates()?;
+                self.skip_space();
+                if self.peek() != Some(')') {
+                    return Err(parse_error("expected ')'"));
+                }
+                self.pos += 1;
+                Ok(vec![Element::Rule(self.helper_rule(alts))])
+            }
+            Some(c) if is_name_char(c) => {
+                let name = self.parse_name()?;
+                Ok(vec![Element::Rule(self.rule_id(&name))])
+            }
+            Some(c) => Err(parse_error(format!(
+                "unexpected '{c}' at offset {}",
+                self.pos
+            ))),
+            None => Err(parse_error("unexpected end of grammar")),
+        }
+    }
+
+    /// One literal character, with escapes.
+    fn parse_char(&mut self) -> Result<char> {
+        let c = self
+            .peek()
+            .ok_or_else(|| parse_error("unexpected end of grammar"))?;
+        self.pos += 1;
+        if c != '\\' {
+            return Ok(c);
+        }
+        let e = self.peek().ok_or_else(|| parse_error("dangling escape"))?;
+        self.pos += 1;
+        let hex_len = match e {
+            'n' => return Ok('\n'),
+            'r' => return Ok('\r'),
+            't' => return Ok('\t'),
+            'x' => 2,
+            'u' => 4,
+            'U' => 8,
+            other => return Ok(other),
+        };
+        let end = self.pos + hex_len;
+        let digits: String = self
+            .chars
+            .get(self.pos..end)
+            .ok_or_else(|| parse_error("truncated escape"))?
+            .iter()
+            .collect();
+        self.pos = end;
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| parse_error(format!("invalid escape \\{e}{digits}")))
+    }
+
+    /// Apply a trailing `* + ? {m,n}` to `atom`, expanding into helper rules.
+    fn parse_repetition(&mut self, atom: Vec<Element>) -> Result<Vec<Element>> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.pos += 1;
+                let bounds = self.parse_bounds()?;
+                self.skip_space();
+                return Ok(self.repeat(atom, bounds.0, bounds.1));
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        self.skip_space();
+        Ok(self.repeat(atom, min, max))
+    }
+
+    fn parse_bounds(&mut self) -> Result<(usize, Option<usize>)> {
+        let number = |p: &mut Self| -> Option<usize> {
+            p.skip_space();
+            let start = p.pos;
+            while p.peek().is_some_and(|c| c.is_ascii_digit()) {
+                p.pos += 1;
+            }
+            p.chars[start..p.pos]
+                .iter()
+                .collect::<String>()
+                .parse()
+                .ok()
+        };
+        let min = number(self).ok_or_else(|| parse_error("expected repetition count"))?;
+        self.skip_space();
+        let max = if self.peek() == Some(',') {
+            self.pos += 1;
+            number(self)
+        } else {
+            Some(min)
+        };
+        self.skip_space();
+        if self.peek() != Some('}') {
+            return Err(parse_error("expected '}'"));
+        }
+        self.pos += 1;
+        if max.is_some_and(|max| max < min) {
+            return Err(parse_error(format!("bad repetition {{{min},{max:?}}}")));
+        }
+        Ok((min, max))
+    }
+
+    fn repeat(&mut self, atom: Vec<Element>, min: usize, max: Option<usize>) -> Vec<Element> {
+        let mut out = Vec::new();
+        for _ in 0..min {
+            out.extend(atom.iter().cloned());
+        }
+        match max {
+            // atom* ::= atom atom* | ""
+            None => {
+                let id = self.helper_rule(Vec::new());
+                let mut again = atom;
+                again.push(Element::Rule(id));
+                self.rules[id] = Some(vec![again, Vec::new()]);
+                out.push(Element::Rule(id));
+            }
+            // Nested optionals: (atom (atom (...)?)?)?
+            Some(max) => {
+                let mut tail: Option<usize> = None;
+                for _ in min..max {
+                    let mut seq = atom.clone();
+                    seq.extend(tail.map(Element::Rule));
+                    tail = Some(self.helper_rule(vec![seq, Vec::new()]));
+                }
+                out.extend(tail.map(Element::Rule));
+            }
+        }
+        out
+    }
+}
+
+/// Reject grammars where a rule can reach itself without consuming input;
+/// the matcher would expand them forever.
+fn check_left_recursion(grammar: &Grammar) -> Result<()> {
+    let n = grammar.rules.len();
+    let mut nullable = vec![false; n];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (id, alts) in grammar.rules.iter().enumerate() {
+            if nullable[id] {
+                continue;
+            }
+            let empty = alts.iter().any(|alt| {
+                alt.iter()
+                    .all(|e| matches!(e, Element::Rule(r) if nullable[*r]))
+            });
+            if empty {
+                nullable[id] = true;
+                changed = true;
+            }
+        }
+    }
+
+    // Rules reachable from each rule without consuming a character
+    let leftmost: Vec<Vec<usize>> = grammar
+        .rules
+        .iter()
+        .map(|alts| {
+            let mut refs = Vec::new();
+            for alt in alts {
+                for e in alt {
+                    match e {
+                        Element::Rule(r) => {
+                            refs.push(*r);
+                            if !nullable[*r] {
+                                break;
+                            }
+                        }
+                        Element::Chars(_) => break,
+                    }
+                }
+            }
+            refs
+        })
+        .collect();
+
+    // 0 = unvisited, 1 = on the DFS path, 2 = done
+    fn visit(id: usize, leftmost: &[Vec<usize>], state: &mut [u8]) -> Option<usize> {
+        state[id] = 1;
+        for &next in &leftmost[id] {
+            match state[next] {
+                1 => return Some(next),
+                0 => {
+                    if let Some(r) = visit(next, leftmost, state) {
+                        return Some(r);
+                    }
+                }
+                _ => {}
+            }
+        }
+        state[id] = 2;
+        None
+    }
+    let mut state = vec![0u8; n];
+    for id in 0..n {
+        if state[id] == 0
+            && let Some(r) = visit(id, &leftmost, &mut state)
+        {
+            return Err(parse_error(format!(
+                "left recursion in rule '{}'",
+                grammar.names[r]
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(grammar: &Grammar, text: &str) -> bool {
+        grammar
+            .start()
+            .accept_str(text)
+            .is_some_and(|s| s.is_complete())
+    }
+
+    #[test]
+    fn test_literals_and_alternates() {
+        let g = Grammar::parse(r#"root ::= "yes" | "no" # answer"#).unwrap();
+        assert!(matches(&g, "yes"));
+        assert!(matches(&g, "no"));
+        assert!(!matches(&g, "ye"));
+        assert!(g.start().accept_str("maybe").is_none());
+        assert!(g.start().accept_str("yes").unwrap().is_finished());
+    }
+
+    #[test]
+    fn test_detach_and_resume() {
+        let g = Grammar::parse(r#"root ::= "ab" "é""#).unwrap();
+        // Stop in the middle of the two-byte "é"
+        let state = g
+            .start()
+            .accept_bytes(&[b'a', b'b', 0xC3])
+            .unwrap()
+            .detach();
+        let state = g.resume(state).accept_bytes(&[0xA9]).unwrap();
+        assert!(state.is_finished());
+    }
+
+    #[test]
+    fn test_char_classes_and_escapes() {
+        let g = Grammar::parse(
+            r#"
+            root ::= [a-c] [^a-z\n] "\x41é" .
+            "#,
+        )
+        .unwrap();
+        assert!(matches(&g, "b!Aé?"));
+        assert!(!matches(&g, "bxAé?"));
+        assert!(!matches(&g, "d!Aé?"));
+        assert!(!matches(&g, "b\nAé?"));
+    }
+
+    #[test]
+    fn test_repetition_bounds() {
+        let g = Grammar::parse(r#"root ::= "a"{2,3} "b"? ("c" "d")* [0-9]+"#).unwrap();
+        assert!(matches(&g, "aa1"));
+        assert!(matches(&g, "aaab1"));
+        assert!(matches(&g, "aacdcd42"));
+        assert!(!matches(&g, "a1"));
+        assert!(!matches(&g, "aaaa1"));
+        assert!(!matches(&g, "aa"));
+        assert!(!matches(&g, "aac1"));
+
+        let exact = Grammar::parse(r#"root ::= [0-9]{3}"#).unwrap();
+        assert!(matches(&exact, "123"));
+        assert!(!matches(&exact, "12"));
+        assert!(exact.start().accept_str("1234").is_none());
+    }
+
+    #[test]
+    fn test_multiline_rules_and_recursion() {
+        let g = Grammar::parse(
+            r#"
+            root ::= list
+            # nested lists like [1,[2,3]]
+            list ::= "[" (
+                item ("," item)*
+            )? "]"
+            item ::= [0-9] | list
+            "#,
+        )
+        .unwrap();
+        assert!(matches(&g, "[]"));
+        assert!(matches(&g, "[1,[2,3],[[]]]"));
+        assert!(!matches(&g, "[1,]"));
+        let open = g.start().accept_str("[[1").unwrap();
+        assert!(!open.is_complete());
+        assert!(
+            open.accept_str("]")
+                .unwrap()
+                .accept_str("]")
+                .unwrap()
+                .is_finished()
+        );
+    }
+
+    #[test]
+    fn test_split_utf8_bytes() {
+        let g = Grammar::parse(r#"root ::= "é" "!""#).unwrap();
+        let state = g.start().accept_bytes(&[0xC3]).unwrap();
+        assert!(!state.is_complete());
+        let state = state.accept_bytes(&[0xA9, b'!']).unwrap();
+        assert!(state.is_finished());
+        assert!(g.start().accept_bytes(&[0xC3, 0xA8]).is_none());
+
+        let ascii = Grammar::parse(r#"root ::= [a-z]"#).unwrap();
+        assert!(ascii.start().accept_bytes(&[0xC3]).is_none());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (src, expected) in [
+            (r#"item ::= "a""#, "missing 'root'"),
+            (r#"root ::= item"#, "undefined rule 'item'"),
+            (r#"root ::= root "a" | "b""#, "left recursion"),
+            (r#"root ::= "a"  root ::= "b""#, "defined twice"),
+            (r#"root ::= "a"{3,1}"#, "bad repetition"),
+            (r#"root ::= "abc"#, "unterminated"),
+        ] {
+            let err = Grammar::parse(src).unwrap_err().to_string();
+            assert!(err.contains(expected), "{src}: {err}");
+        }
+    }
+}
@@ -156,7 +156,7 @@ impl GgmlType {
             GgmlType::Q5_0 => 22, // 2 + 4 + 32/2
             GgmlType::Q5_1 => 24, // 2 + 2 + 4 + 32/2
             GgmlType::Q8_0 => 34, // 2 + 32
-            GgmlType::Q8_1 => 40, // 4 + 4 + 32
+            GgmlType::Q8_1 => 36, // 2 + 2 + 32
             GgmlType::Q2K => 84,
             GgmlType::Q3K => 110,
             GgmlType::Q4K => 144,
@@ -364,6 +364,10 @@ fn read_string<R: Read>(r: &mut R) -> Result<String> {
 
 fn read_value<R: Read>(r: &mut R) -> Result<GgufValue> {
     let type_id = read_u32(r)?;
+    read_typed_value(r, type_id)
+}
+
+fn read_typed_value<R: Read>(r: &mut R, type_id: u32) -> Result<GgufValue> {
     match type_id {
         0 => Ok(GgufValue::U8(read_u8(r)?)),
         1 => Ok(GgufValue::I8(read_u8(r)? as i8)),
@@ -393,22 +397,7 @@ fn read_value<R: Read>(r: &mut R) -> Result<GgufValue> {
             }
             let mut arr = Vec::with_capacity(count);
             for _ in 0..count {
-                let val = match elem_type {
-                    0 => GgufValue::U8(read_u8(r)?),
-                    4 => GgufValue::U32(read_u32(r)?),
-                    5 => GgufValue::I32(read_i32(r)?),
-                    6 => GgufValue::F32(read_f32(r)?),
-                    8 => GgufValue::String(read_string(r)?),
-                    10 => GgufValue::U64(read_u64(r)?),
-                    11 => GgufValue::I64(read_i64(r)?),
-                    12 => GgufValue::F64(read_f64(r)?),
-                    _ => {
-                        return Err(BizClawError::GgufParse(format!(
-                            "Unknown array element type: {elem_type}"
-                        )));
-                    }
-                };
-                arr.push(val);
+                arr.push(read_typed_value(r, elem_type)?);
             }
             Ok(GgufValue::Array(arr))
         }
@@ -0,0 +1,255 @@
+//! GGUF writer — the inverse of [`gguf`](crate::gguf).
+//!
+//! The header (metadata, tensor infos with precomputed offsets) is written
+//! up front, then tensor data is streamed in declaration order, so a whole
+//! model never has to sit in memory. Files are GGUF v3 and load with
+//! [`MmapModel::load`](crate::mmap::MmapModel::load).
+
+use crate::gguf::{GgmlType, GgufValue};
+use bizclaw_core::error::{BizClawError, Result};
+use std::io::Write;
+
+/// Name, shape and storage type of a tensor to write.
+#[derive(Debug, Clone)]
+pub struct TensorSpec {
+    pub name: String,
+    /// Dimensions, innermost (row length) first as in GGUF.
+    pub dims: Vec<u64>,
+    pub ggml_type: GgmlType,
+}
+
+impl TensorSpec {
+    /// Bytes of data this tensor takes.
+    pub fn size_bytes(&self) -> u64 {
+        let n = self.dims.iter().product::<u64>() as usize;
+        (n.div_ceil(self.ggml_type.block_size()) * self.ggml_type.type_size()) as u64
+    }
+}
+
+/// Streaming GGUF v3 writer.
+pub struct GgufWriter<W: Write> {
+    out: W,
+    tensors: Vec<TensorSpec>,
+    alignment: u64,
+    /// Index of the next tensor whose data is expected
+    next: usize,
+    /// Bytes written since the start of the data section
+    written: u64,
+}
+
+impl<W: Write> GgufWriter<W> {
+    /// Write the header. `general.alignment` in `metadata` (default 32)
+    /// sets the data alignment; `general.architecture` is written first,
+    /// the remaining keys in the given order.
+    pub fn new(
+        mut out: W,
+        metadata: &[(String, GgufValue)],
+        tensors: Vec<TensorSpec>,
+    ) -> Result<Self> {
+        let alignment = metadata
+            .iter()
+            .find(|(k, _)| k == "general.alignment")
+            .and_then(|(_, v)| v.as_u64())
+            .unwrap_or(32);
+
+        let mut header = Vec::new();
+        header.extend_from_slice(b"GGUF");
+        header.extend_from_slice(&3u32.to_le_bytes());
+        header.extend_from_slice(&(tensors.len() as u64).to_le_bytes());
+        header.extend_from_slice(&(metadata.len() as u64).to_le_bytes());
+        let arch_first = metadata
+            .iter()
+            .filter(|(k, _)| k == "general.architecture")
+            .chain(metadata.iter().filter(|(k, _)| k != "general.architecture"));
+        for (key, value) in arch_first {
+            write_string(&mut header, key);
+            header.extend_from_slice(&value_type(value).to_le_bytes());
+            write_value(&mut header, value)?;
+        }
+
+        let mut offset = 0u64;
+        for t in &tensors {
+            write_string(&mut header, &t.name);
+            header.extend_from_slice(&(t.dims.len() as u32).to_le_bytes());
+            for d in &t.dims {
+                header.extend_from_slice(&d.to_le_bytes());
+            }
+            header.extend_from_slice(&(t.ggml_type as u32).to_le_bytes());
+            header.extend_from_slice(&offset.to_le_bytes());
+            offset = (offset + t.size_bytes()).next_multiple_of(alignment);
+        }
+        header.resize(
+            (header.len() as u64).next_multiple_of(alignment) as usize,
+            0,
+        );
+        out.write_all(&header)?;
+
+        Ok(Self {
+            out,
+            tensors,
+            alignment,
+            next: 0,
+            written: 0,
+        })
+    }
+
+    /// Write the data of the next tensor; its size must match the spec.
+    pub fn write_tensor(&mut self, data: &[u8]) -> Result<()> {
+        let spec = self
+            .tensors
+            .get(self.next)
+            .ok_or_else(|| BizClawError::Brain("GGUF writer: more tensors than declared".into()))?;
+        if data.len() as u64 != spec.size_bytes() {
+            return Err(BizClawError::Brain(format!(
+                "GGUF writer: tensor {} has {} bytes, expected {}",
+                spec.name,
+                data.len(),
+                spec.size_bytes()
+            )));
+        }
+        let padding = self.written.next_multiple_of(self.alignment) - self.written;
+        self.out.write_all(&vec![0u8; padding as usize])?;
+        self.out.write_all(data)?;
+        self.written += padding + data.len() as u64;
+        self.next += 1;
+        Ok(())
+    }
+
+    /// Check every declared tensor was written and flush.
+    pub fn finish(mut self) -> Result<W> {
+        if self.next != self.tensors.len() {
+            return Err(BizClawError::Brain(format!(
+                "GGUF writer: {} of {} tensors written",
+                self.next,
+                self.tensors.len()
+            )));
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// GGUF type id of a metadata value.
+fn value_type(value: &GgufValue) -> u32 {
+    match value {
+        GgufValue::U8(_) => 0,
+        GgufValue::I8(_) => 1,
+        GgufValue::U16(_) => 2,
+        GgufValue::I16(_) => 3,
+        GgufValue::U32(_) => 4,
+        GgufValue::I32(_) => 5,
+        GgufValue::F32(_) => 6,
+        GgufValue::Bool(_) => 7,
+        GgufValue::String(_) => 8,
+        GgufValue::Array(_) => 9,
+        GgufValue::U64(_) => 10,
+        GgufValue::I64(_) => 11,
+        GgufValue::F64(_) => 12,
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u64).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Write a value's payload (without its type id).
+fn write_value(out: &mut Vec<u8>, value: &GgufValue) -> Result<()> {
+    match value {
+        GgufValue::U8(v) => out.push(*v),
+        GgufValue::I8(v) => out.push(*v as u8),
+        GgufValue::U16(v) => out.extend_from_slice(&v.to_le_bytes()),
+        GgufValue::I16(v) => out.extend_from_slice(&v.to_le_bytes()),
+        GgufValue::U32(v) => out.extend_from_slice(&v.to_le_bytes()),
+        GgufValue::I32(v) => out.extend_from_slice(&v.to_le_bytes()),
+        GgufValue::F32(v) => out.extend_from_slice(&v.to_le_bytes()),
+        GgufValue::Bool(v) => out.push(*v as u8),
+        GgufValue::String(s) => write_string(out, s),
+        GgufValue::U64(v) => out.extend_from_slice(&v.to_le_bytes()),
+        GgufValue::I64(v) => out.extend_from_slice(&v.to_le_bytes()),
+        GgufValue::F64(v) => out.extend_from_slice(&v.to_le_bytes()),
+        GgufValue::Array(items) => {
+            // The parser drops the element type of empty arrays; u32 is
+            // as good as any for zero elements
+            let elem_type = items.first().map_or(4, value_type);
+            if items.iter().any(|v| value_type(v) != elem_type) {
+                return Err(BizClawError::Brain(
+                    "GGUF writer: arrays must hold a single value type".into(),
+                ));
+            }
+            out.extend_from_slice(&elem_type.to_le_bytes());
+            out.extend_from_slice(&(items.len() as u64).to_le_bytes());
+            for item in items {
+                write_value(out, item)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf::GgufFile;
+
+    #[test]
+    fn test_roundtrip() {
+        let metadata = vec![
+            ("general.name".to_string(), GgufValue::String("t".into())),
+            (
+                "general.architecture".into(),
+                GgufValue::String("llama".into()),
+            ),
+            ("general.alignment".into(), GgufValue::U32(64)),
+            ("a.flag".into(), GgufValue::Bool(true)),
+            ("a.i16".into(), GgufValue::I16(-3)),
+            (
+                "a.list".into(),
+                GgufValue::Array(vec![GgufValue::Bool(false), GgufValue::Bool(true)]),
+            ),
+            ("a.empty".into(), GgufValue::Array(vec![])),
+        ];
+        let tensors = vec![
+            TensorSpec {
+                name: "x".into(),
+                dims: vec![3],
+                ggml_type: GgmlType::F32,
+            },
+            TensorSpec {
+                name: "y".into(),
+                dims: vec![32, 2],
+                ggml_type: GgmlType::Q8_0,
+            },
+        ];
+        let mut writer = GgufWriter::new(Vec::new(), &metadata, tensors).unwrap();
+        assert!(writer.write_tensor(&[0u8; 5]).is_err());
+        writer.write_tensor(&[1u8; 12]).unwrap();
+        writer.write_tensor(&[2u8; 68]).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let file = GgufFile::parse(&mut std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(file.architecture(), Some("llama"));
+        assert_eq!(file.alignment, 64);
+        assert_eq!(file.metadata.len(), metadata.len());
+        assert_eq!(file.metadata["a.flag"].as_bool(), Some(true));
+        assert!(matches!(file.metadata["a.i16"], GgufValue::I16(-3)));
+        assert!(matches!(&file.metadata["a.list"], GgufValue::Array(v) if v.len() == 2));
+        assert_eq!(file.tensors[1].offset, 64);
+        assert_eq!(file.tensors[1].dims, [32, 2]);
+        assert_eq!(file.data_offset % 64, 0);
+        let y = file.data_offset as usize + 64;
+        assert_eq!(&bytes[y..y + 68], &[2u8; 68][..]);
+        assert_eq!(bytes.len(), y + 68);
+    }
+
+    #[test]
+    fn test_missing_tensor_data() {
+        let tensors = vec![TensorSpec {
+            name: "x".into(),
+            dims: vec![1],
+            ggml_type: GgmlType::F32,
+        }];
+        let writer = GgufWriter::new(Vec::new(), &[], tensors).unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
@@ -0,0 +1,469 @@
+//! Model inspection — what a GGUF file holds and how much RAM it needs.
+//!
+//! Only the header is read (metadata and tensor index), so inspecting a
+//! multi-GB model is instant and works for files this build cannot run.
+//! The RAM estimate adds up what loading the model allocates: the mapped
+//! weights (all of them end up resident during a forward pass), the KV
+//! cache at the chosen context and precision, and the activation buffers
+//! of one prefill batch plus the tokenizer tables.
+
+use crate::chat_template::ChatFormat;
+use crate::gguf::{GgmlType, GgufFile, GgufValue};
+use crate::kv_cache::KvPrecision;
+use crate::model::ModelParams;
+use bizclaw_core::error::{BizClawError, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// Longest metadata string shown in full.
+const MAX_VALUE_CHARS: usize = 96;
+/// Array items shown before the count.
+const MAX_ARRAY_ITEMS: usize = 4;
+
+/// Settings for [`inspect`].
+#[derive(Debug, Clone)]
+pub struct InspectOptions {
+    /// Context the KV cache is sized for (0 = the model's own)
+    pub context: usize,
+    /// Prefill batch the activation buffers are sized for
+    pub batch_size: usize,
+    pub kv_precision: KvPrecision,
+}
+
+impl Default for InspectOptions {
+    fn default() -> Self {
+        Self {
+            context: 0,
+            batch_size: 64,
+            kv_precision: KvPrecision::F32,
+        }
+    }
+}
+
+/// Everything [`inspect`] learns about a model file.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelReport {
+    pub path: String,
+    pub file_size_bytes: u64,
+    pub gguf_version: u32,
+    pub name: Option<String>,
+    pub architecture: String,
+    /// Storage type holding most of the weight bytes
+    pub weight_type: String,
+    /// Hyperparameters; `None` when the architecture is not supported
+    pub params: Option<ParamsSummary>,
+    /// Why the model cannot be loaded, if it cannot
+    pub unsupported: Option<String>,
+    /// `tokenizer.ggml.model` (`llama`, `gpt2`, `bert`, ...)
+    pub tokenizer: Option<String>,
+    pub vocab_size: usize,
+    /// Prompt format the chat template maps to
+    pub chat_format: String,
+    pub chat_template: Option<String>,
+    /// Every metadata key, values shortened for display
+    pub metadata: BTreeMap<String, String>,
+    pub tensors: Vec<TensorSummary>,
+    pub ram: RamEstimate,
+}
+
+/// Hyperparameters that size the model and its cache.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamsSummary {
+    pub context_length: u32,
+    pub dim: u32,
+    pub hidden_dim: u32,
+    pub n_layers: u32,
+    pub n_heads: u32,
+    pub n_kv_heads: u32,
+    pub head_dim: u32,
+    pub rope_scaling: String,
+    pub sliding_window: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TensorSummary {
+    pub name: String,
+    pub shape: Vec<u64>,
+    pub ggml_type: String,
+    pub bytes: u64,
+}
+
+/// Peak memory of a loaded model, by part.
+#[derive(Debug, Clone, Serialize)]
+pub struct RamEstimate {
+    pub context_length: usize,
+    pub kv_precision: String,
+    pub weights_bytes: u64,
+    pub kv_cache_bytes: u64,
+    /// Activations of one prefill batch, logits and tokenizer tables
+    pub scratch_bytes: u64,
+    pub total_bytes: u64,
+}
+
+impl RamEstimate {
+    /// Whether the model fits in the memory `host` has available.
+    pub fn fits(&self, host: &HostMemory) -> bool {
+        self.total_bytes <= host.available_bytes
+    }
+}
+
+/// Physical memory of this machine.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct HostMemory {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Read a GGUF header and estimate what running the model takes.
+pub fn inspect(path: &Path, options: &InspectOptions) -> Result<ModelReport> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| BizClawError::ModelLoad(format!("Cannot open {}: {e}", path.display())))?;
+    let file_size_bytes = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let gguf = GgufFile::parse(&mut std::io::BufReader::new(file))?;
+
+    let architecture = gguf.architecture().unwrap_or("unknown").to_string();
+    let (params, unsupported) = match ModelParams::from_gguf(&gguf) {
+        Ok(p) => (Some(p), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    let tokens = match gguf.metadata.get("tokenizer.ggml.tokens") {
+        Some(GgufValue::Array(items)) => items.as_slice(),
+        _ => &[],
+    };
+    let text = |key: &str| gguf.metadata.get(key).and_then(|v| v.as_str());
+    let chat_template = text("tokenizer.chat_template").map(str::to_string);
+    let chat_format = ChatFormat::detect(chat_template.as_deref(), &architecture, |t| {
+        tokens.iter().any(|v| v.as_str() == Some(t))
+    });
+
+    let tensors: Vec<TensorSummary> = gguf
+        .tensors
+        .iter()
+        .map(|t| TensorSummary {
+            name: t.name.clone(),
+            shape: t.dims.clone(),
+            ggml_type: format!("{:?}", t.ggml_type),
+            bytes: t.size_bytes(),
+        })
+        .collect();
+
+    let weights_bytes = tensors.iter().map(|t| t.bytes).sum();
+    let ram = match &params {
+        Some(p) => estimate_ram(p, weights_bytes, tokens.len(), options),
+        None => RamEstimate {
+            context_length: options.context,
+            kv_precision: options.kv_precision.as_str().into(),
+            weights_bytes,
+            kv_cache_bytes: 0,
+            scratch_bytes: 0,
+            total_bytes: weights_bytes,
+        },
+    };
+
+    Ok(ModelReport {
+        path: path.display().to_string(),
+        file_size_bytes,
+        gguf_version: gguf.version,
+        name: gguf.model_name().map(str::to_string),
+        weight_type: weight_type(&gguf).map_or_else(String::new, |t| format!("{t:?}")),
+        params: params.as_ref().map(|p| ParamsSummary {
+            context_length: p.max_seq_len,
+            dim: p.dim,
+            hidden_dim: p.hidden_dim,
+            n_layers: p.n_layers,
+            n_heads: p.n_heads,
+            n_kv_heads: p.n_kv_heads,
+            head_dim: p.head_dim,
+            rope_scaling: format!("{:?}", p.rope_scaling),
+            sliding_window: p.sliding_window,
+        }),
+        unsupported,
+        tokenizer: text("tokenizer.ggml.model").map(str::to_string),
+        vocab_size: tokens.len(),
+        chat_format: format!("{chat_format:?}"),
+        chat_template,
+        metadata: gguf
+            .metadata
+            .iter()
+            .map(|(k, v)| (k.clone(), display_value(v)))
+            .collect(),
+        architecture,
+        tensors,
+        ram,
+    })
+}
+
+/// The [`GgmlType`] holding the most bytes.
+pub fn weight_type(gguf: &GgufFile) -> Option<GgmlType> {
+    let mut bytes: Vec<(GgmlType, u64)> = Vec::new();
+    for t in &gguf.tensors {
+        match bytes.iter_mut().find(|(ty, _)| *ty == t.ggml_type) {
+            Some((_, total)) => *total += t.size_bytes(),
+            None => bytes.push((t.ggml_type, t.size_bytes())),
+        }
+    }
+    bytes
+        .into_iter()
+        .max_by_key(|(_, total)| *total)
+        .map(|(ty, _)| ty)
+}
+
+fn estimate_ram(
+    p: &ModelParams,
+    weights_bytes: u64,
+    vocab: usize,
+    options: &InspectOptions,
+) -> RamEstimate {
+    let context = match options.context {
+        0 => p.max_seq_len as usize,
+        n => n,
+    };
+    let kv_cache_bytes = options.kv_precision.cache_bytes(
+        p.n_layers as usize,
+        context,
+        p.n_kv_heads as usize,
+        p.head_dim as usize,
+    );
+
+    // Per batch row: residual and norm, Q/K/V and attention output, the FFN
+    // gate/up/down, and the attention scores over the whole context
+    let q_dim = (p.n_heads * p.head_dim) as usize;
+    let kv_dim = (p.n_kv_heads * p.head_dim) as usize;
+    let per_token = 2 * p.dim as usize
+        + 2 * q_dim
+        + 2 * kv_dim
+        + 3 * p.hidden_dim as usize
+        + p.n_heads as usize * context;
+    let activations = options.batch_size.max(1) * per_token + p.vocab_size as usize;
+    // Token strings, ids and byte pieces: a few dozen bytes per entry
+    let tokenizer = vocab * 48;
+    let scratch_bytes = (activations * 4 + tokenizer) as u64;
+
+    RamEstimate {
+        context_length: context,
+        kv_precision: options.kv_precision.as_str().into(),
+        weights_bytes,
+        kv_cache_bytes,
+        scratch_bytes,
+        total_bytes: weights_bytes + kv_cache_bytes + scratch_bytes,
+    }
+}
+
+fn display_value(value: &GgufValue) -> String {
+    match value {
+        GgufValue::String(s) if s.chars().count() > MAX_VALUE_CHARS => {
+            let head: String = s.chars().take(MAX_VALUE_CHARS).collect();
+            format!("{head:?}… ({} chars)", s.chars().count())
+        }
+        GgufValue::String(s) => format!("{s:?}"),
+        GgufValue::Array(items) => {
+            let shown: Vec<String> = items
+                .iter()
+                .take(MAX_ARRAY_ITEMS)
+                .map(display_value)
+                .collect();
+            if items.len() > MAX_ARRAY_ITEMS {
+                format!("[{}, … ({} items)]", shown.join(", "), items.len())
+            } else {
+                format!("[{}]", shown.join(", "))
+            }
+        }
+        GgufValue::U8(v) => v.to_string(),
+        GgufValue::I8(v) => v.to_string(),
+        GgufValue::U16(v) => v.to_string(),
+        GgufValue::I16(v) => v.to_string(),
+        GgufValue::U32(v) => v.to_string(),
+        GgufValue::I32(v) => v.to_string(),
+        GgufValue::U64(v) => v.to_string(),
+        GgufValue::I64(v) => v.to_string(),
+        GgufValue::F32(v) => v.to_string(),
+        GgufValue::F64(v) => v.to_string(),
+        GgufValue::Bool(v) => v.to_string(),
+    }
+}
+
+/// Total and available physical memory (`/proc/meminfo`); `None` off Linux.
+pub fn host_memory() -> Option<HostMemory> {
+    parse_meminfo(&std::fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+fn parse_meminfo(text: &str) -> Option<HostMemory> {
+    let field = |name: &str| -> Option<u64> {
+        let line = text.lines().find(|l| l.starts_with(name))?;
+        let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kb * 1024)
+    };
+    let total_bytes = field("MemTotal:")?;
+    Some(HostMemory {
+        total_bytes,
+        // Kernels before 3.14 have no MemAvailable
+        available_bytes: field("MemAvailable:").or_else(|| field("MemFree:"))?,
+    })
+}
+
+fn mb(bytes: u64) -> f64 {
+    bytes as f64 / 1024.0 / 1024.0
+}
+
+impl fmt::Display for ModelReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Model:        {}", self.path)?;
+        if let Some(name) = &self.name {
+            writeln!(f, "Name:         {name}")?;
+        }
+        writeln!(
+            f,
+            "File:         {:.1} MB, GGUF v{}, mostly {}",
+            mb(self.file_size_bytes),
+            self.gguf_version,
+            self.weight_type
+        )?;
+        writeln!(f, "Architecture: {}", self.architecture)?;
+        if let Some(p) = &self.params {
+            writeln!(
+                f,
+                "              dim {}, ffn {}, {} layers, {} heads ({} KV, {} dims), context {}",
+                p.dim,
+                p.hidden_dim,
+                p.n_layers,
+                p.n_heads,
+                p.n_kv_heads,
+                p.head_dim,
+                p.context_length
+            )?;
+            writeln!(f, "              rope scaling {}", p.rope_scaling)?;
+            if let Some(window) = p.sliding_window {
+                writeln!(f, "              sliding window {window}")?;
+            }
+        }
+        if let Some(reason) = &self.unsupported {
+            writeln!(f, "              not supported: {reason}")?;
+        }
+        writeln!(
+            f,
+            "Tokenizer:    {} ({} tokens)",
+            self.tokenizer.as_deref().unwrap_or("unknown"),
+            self.vocab_size
+        )?;
+        writeln!(
+            f,
+            "Chat format:  {}{}",
+            self.chat_format,
+            if self.chat_template.is_some() {
+                " (GGUF template)"
+            } else {
+                ""
+            }
+        )?;
+
+        writeln!(f, "\nMetadata:")?;
+        for (key, value) in &self.metadata {
+            writeln!(f, "  {key} = {value}")?;
+        }
+
+        writeln!(f, "\nTensors ({}):", self.tensors.len())?;
+        let width = self.tensors.iter().map(|t| t.name.len()).max().unwrap_or(0);
+        for t in &self.tensors {
+            let shape: Vec<String> = t.shape.iter().map(u64::to_string).collect();
+            writeln!(
+                f,
+                "  {:width$}  {:>16}  {:>6}  {:>10}",
+                t.name,
+                shape.join(" x "),
+                t.ggml_type,
+                t.bytes
+            )?;
+        }
+
+        if let Some(template) = &self.chat_template {
+            writeln!(f, "\nChat template:\n{template}")?;
+        }
+
+        let ram = &self.ram;
+        writeln!(
+            f,
+            "\nEstimated RAM at context {} (KV {}):",
+            ram.context_length, ram.kv_precision
+        )?;
+        writeln!(f, "  weights   {:>10.1} MB", mb(ram.weights_bytes))?;
+        writeln!(f, "  KV cache  {:>10.1} MB", mb(ram.kv_cache_bytes))?;
+        writeln!(f, "  scratch   {:>10.1} MB", mb(ram.scratch_bytes))?;
+        write!(f, "  total     {:>10.1} MB", mb(ram.total_bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_model::TinyLlama;
+
+    #[test]
+    fn test_inspect_tiny_model() {
+        let file = TinyLlama {
+            chat_template: Some("{{ '<|im_start|>' }}".into()),
+            ..Default::default()
+        }
+        .write();
+        let report = inspect(&file.path, &InspectOptions::default()).unwrap();
+        assert_eq!(report.architecture, "llama");
+        assert_eq!(report.weight_type, "F32");
+        assert_eq!(report.chat_format, "ChatMl");
+        assert_eq!(report.params.as_ref().unwrap().context_length, 128);
+        assert!(report.unsupported.is_none());
+        assert!(report.metadata["tokenizer.ggml.tokens"].contains("items)]"));
+
+        let q = report
+            .tensors
+            .iter()
+            .find(|t| t.name == "blk.0.attn_q.weight")
+            .unwrap();
+        assert_eq!((q.shape.as_slice(), q.bytes), (&[64, 64][..], 64 * 64 * 4));
+        let ram = &report.ram;
+        assert_eq!(
+            ram.weights_bytes,
+            report.tensors.iter().map(|t| t.bytes).sum::<u64>()
+        );
+        // 2 layers x 128 positions x 32 KV dims, keys and values, f32
+        assert_eq!(ram.kv_cache_bytes, 2 * 128 * 32 * 2 * 4);
+        assert_eq!(
+            ram.total_bytes,
+            ram.weights_bytes + ram.kv_cache_bytes + ram.scratch_bytes
+        );
+
+        let smaller = inspect(
+            &file.path,
+            &InspectOptions {
+                context: 64,
+                kv_precision: KvPrecision::F16,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(smaller.ram.kv_cache_bytes, ram.kv_cache_bytes / 4);
+
+        let text = report.to_string();
+        assert!(text.contains("blk.1.ffn_down.weight"));
+        assert!(text.contains("Estimated RAM at context 128"));
+    }
+
+    #[test]
+    fn test_fit_against_host_memory() {
+        let host = parse_meminfo(
+            "MemTotal:        8000000 kB\nMemFree:          100000 kB\nMemAvailable:    4000000 kB\n",
+        )
+        .unwrap();
+        assert_eq!(host.total_bytes, 8_000_000 * 1024);
+        assert_eq!(host.available_bytes, 4_000_000 * 1024);
+        let old_kernel = parse_meminfo("MemTotal: 100 kB\nMemFree: 40 kB\n").unwrap();
+        assert_eq!(old_kernel.available_bytes, 40 * 1024);
+        assert!(parse_meminfo("nothing").is_none());
+
+        let file = TinyLlama::default().write();
+        let ram = inspect(&file.path, &InspectOptions::default()).unwrap().ram;
+        assert!(ram.fits(&host));
+        assert!(!ram.fits(&old_kernel));
+    }
+}
@@ -0,0 +1,1741 @@
+//! Minimal Jinja2 renderer for GGUF chat templates.
+//!
+//! Covers the subset that HuggingFace chat templates actually use:
+//! `{{ }}` output, `{% if/elif/else %}`, `{% for %}` (with `loop.*`, tuple
+//! targets, inline `if` filter, `break`/`continue`), `{% set %}` including
+//! `namespace()` attributes, `{# #}` comments, whitespace control (`-`) and
+//! the `trim_blocks`/`lstrip_blocks` behaviour transformers enables.
+//! Expressions support literals, attribute/index/slice access, arithmetic,
+//! comparisons, `in`, `is` tests, ternaries, filters and common string methods.
+//!
+//! Anything else is a parse/render error so the caller can fall back to a
+//! built-in chat format.
+
+use bizclaw_core::error::{BizClawError, Result};
+use std::collections::{BTreeMap, HashMap};
+
+fn err(msg: impl Into<String>) -> BizClawError {
+    BizClawError::Brain(format!("chat template: {}", msg.into()))
+}
+
+// ─────────────────────────────────────────────────────────────
+// Values
+// ─────────────────────────────────────────────────────────────
+
+/// A runtime value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+    #[default]
+    Undefined,
+    None,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Undefined | Value::None => false,
+            Value::Bool(b) => *b,
+            Value::Int(i) => *i != 0,
+            Value::Float(f) => *f != 0.0,
+            Value::Str(s) => !s.is_empty(),
+            Value::List(l) => !l.is_empty(),
+            Value::Map(m) => !m.is_empty(),
+        }
+    }
+
+    /// Python-style `repr`, used for items inside printed containers.
+    fn repr(&self) -> String {
+        match self {
+            Value::Str(s) => format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'")),
+            other => other.to_string(),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Undefined | Value::None => serde_json::Value::Null,
+            Value::Bool(b) => (*b).into(),
+            Value::Int(i) => (*i).into(),
+            Value::Float(f) => serde_json::Number::from_f64(*f)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            Value::Str(s) => s.clone().into(),
+            Value::List(l) => l.iter().map(Value::to_json).collect(),
+            Value::Map(m) => m.iter().map(|(k, v)| (k.clone(), v.to_json())).collect(),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Undefined => "undefined",
+            Value::None => "none",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "dict",
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            Value::Bool(b) => Some(*b as i64 as f64),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Undefined => Ok(()),
+            Value::None => write!(f, "None"),
+            Value::Bool(true) => write!(f, "True"),
+            Value::Bool(false) => write!(f, "False"),
+            Value::Int(i) => write!(f, "{i}"),
+            Value::Float(x) if x.fract() == 0.0 && x.is_finite() => write!(f, "{x:.1}"),
+            Value::Float(x) => write!(f, "{x}"),
+            Value::Str(s) => write!(f, "{s}"),
+            Value::List(l) => {
+                let items: Vec<String> = l.iter().map(Value::repr).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Map(m) => {
+                let items: Vec<String> = m
+                    .iter()
+                    .map(|(k, v)| format!("'{k}': {}", v.repr()))
+                    .collect();
+                write!(f, "{{{}}}", items.join(", "))
+            }
+        }
+    }
+}
+
+impl From<&serde_json::Value> for Value {
+    fn from(v: &serde_json::Value) -> Self {
+        match v {
+            serde_json::Value::Null => Value::None,
+            serde_json::Value::Bool(b) => Value::Bool(*b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Value::Int(i),
+                None => Value::Float(n.as_f64().unwrap_or(0.0)),
+            },
+            serde_json::Value::String(s) => Value::Str(s.clone()),
+            serde_json::Value::Array(a) => Value::List(a.iter().map(Value::from).collect()),
+            serde_json::Value::Object(o) => {
+                Value::Map(o.iter().map(|(k, v)| (k.clone(), Value::from(v))).collect())
+            }
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Template source → segments
+// ─────────────────────────────────────────────────────────────
+
+enum Segment {
+    Text(String),
+    Expr(String),
+    Stmt(String),
+}
+
+/// Find the closing delimiter, skipping over string literals.
+fn find_close(s: &str, close: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut quote: Option<u8> = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match quote {
+            Some(q) => {
+                if c == b'\\' {
+                    i += 1;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None => {
+                if c == b'\'' || c == b'"' {
+                    quote = Some(c);
+                } else if bytes[i..].starts_with(close.as_bytes()) {
+                    return Some(i);
+                }
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+fn split_segments(src: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut rest = src;
+    let mut trim_left = false; // previous tag ended with `-`
+    let mut trim_newline = false; // previous tag was a block (trim_blocks)
+    let mut at_start = true;
+
+    loop {
+        let open = ["{{", "{%", "{#"]
+            .iter()
+            .filter_map(|o| rest.find(o).map(|p| (p, *o)))
+            .min_by_key(|(p, _)| *p);
+
+        let text_end = open.map(|(p, _)| p).unwrap_or(rest.len());
+        let mut text = &rest[..text_end];
+        let mut line_start = at_start;
+        if trim_left {
+            text = text.trim_start();
+        } else if trim_newline
+            && let Some(t) = text
+                .strip_prefix("\r\n")
+                .or_else(|| text.strip_prefix('\n'))
+        {
+            text = t;
+            line_start = true;
+        }
+
+        let Some((pos, opener)) = open else {
+            if !text.is_empty() {
+                segments.push(Segment::Text(text.to_string()));
+            }
+            return Ok(segments);
+        };
+
+        let after = &rest[pos + 2..];
+        let strip_before = after.starts_with('-');
+        let keep_before = after.starts_with('+');
+        if strip_before {
+            text = text.trim_end();
+        } else if opener != "{{" && !keep_before {
+            // lstrip_blocks: drop indentation in front of a block tag
+            let stripped = text.trim_end_matches([' ', '\t']);
+            if stripped.ends_with('\n') || (stripped.is_empty() && line_start) {
+                text = stripped;
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text.to_string()));
+        }
+
+        let close = match opener {
+            "{{" => "}}",
+            "{%" => "%}",
+            _ => "#}",
+        };
+        let body_start = if strip_before || keep_before { 1 } else { 0 };
+        let close_pos = if opener == "{#" {
+            after.find(close)
+        } else {
+            find_close(after, close)
+        }
+        .ok_or_else(|| err(format!("unclosed '{opener}'")))?;
+        let mut inner = &after[body_start.min(close_pos)..close_pos];
+        trim_left = inner.ends_with('-');
+        if trim_left {
+            inner = &inner[..inner.len() - 1];
+        }
+        let inner = inner.trim().to_string();
+
+        match opener {
+            "{{" => segments.push(Segment::Expr(inner)),
+            "{%" => segments.push(Segment::Stmt(inner)),
+            _ => {}
+        }
+        trim_newline = opener != "{{";
+        at_start = false;
+        rest = &after[close_pos + 2..];
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Expression lexer
+// ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Name(String),
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Op(&'static str),
+}
+
+const OPS: &[&str] = &[
+    "==", "!=", "<=", ">=", "//", "**", "(", ")", "[", "]", "{", "}", ".", ",", ":", "|", "~", "+",
+    "-", "*", "/", "%", "<", ">", "=",
+];
+
+fn lex(src: &str) -> Result<Vec<Tok>> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut toks = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            let mut s = String::new();
+            i += 1;
+            loop {
+                let Some(&ch) = chars.get(i) else {
+                    return Err(err("unterminated string literal"));
+                };
+                i += 1;
+                if ch == c {
+                    break;
+                }
+                if ch == '\\' {
+                    let esc = chars.get(i).copied().unwrap_or('\\');
+                    i += 1;
+                    s.push(match esc {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        other => other,
+                    });
+                } else {
+                    s.push(ch);
+                }
+            }
+            toks.push(Tok::Str(s));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '_') {
+                i += 1;
+            }
+            let is_float = i + 1 < chars.len() && chars[i] == '.' && chars[i + 1].is_ascii_digit();
+            if is_float {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let text: String = chars[start..i].iter().filter(|&&c| c != '_').collect();
+            if is_float {
+                toks.push(Tok::Float(text.parse().map_err(|_| err("bad float"))?));
+            } else {
+                toks.push(Tok::Int(text.parse().map_err(|_| err("bad integer"))?));
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            toks.push(Tok::Name(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let op = OPS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| err(format!("unexpected character '{c}'")))?;
+            toks.push(Tok::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(toks)
+}
+
+// ─────────────────────────────────────────────────────────────
+// AST
+// ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    Var(String),
+    List(Vec<Expr>),
+    Dict(Vec<(Expr, Expr)>),
+    Attr(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Slice(Box<Expr>, [Option<Box<Expr>>; 3]),
+    Call(Box<Expr>, Vec<Expr>, Vec<(String, Expr)>),
+    Filter(Box<Expr>, String, Vec<Expr>, Vec<(String, Expr)>),
+    Test(Box<Expr>, String, bool),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Ternary(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+}
+
+/// Positional and keyword call arguments.
+type CallArgs = (Vec<Expr>, Vec<(String, Expr)>);
+
+/// Parsed body plus the statement (keyword, rest) that terminated it.
+type Block = (Vec<Node>, Option<(String, String)>);
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Output(Expr),
+    If(Vec<(Expr, Vec<Node>)>, Vec<Node>),
+    For {
+        targets: Vec<String>,
+        iter: Expr,
+        filter: Option<Expr>,
+        body: Vec<Node>,
+        else_body: Vec<Node>,
+    },
+    Set(Vec<String>, Expr),
+    Break,
+    Continue,
+}
+
+// ─────────────────────────────────────────────────────────────
+// Expression parser
+// ─────────────────────────────────────────────────────────────
+
+struct Parser {
+    toks: Vec<Tok>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(src: &str) -> Result<Self> {
+        Ok(Self {
+            toks: lex(src)?,
+            pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let t = self.toks.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn at_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Op(o)) if *o == op)
+    }
+
+    fn at_name(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Name(n)) if n == name)
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        let hit = self.at_op(op);
+        if hit {
+            self.pos += 1;
+        }
+        hit
+    }
+
+    fn eat_name(&mut self, name: &str) -> bool {
+        let hit = self.at_name(name);
+        if hit {
+            self.pos += 1;
+        }
+        hit
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<()> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            Err(err(format!("expected '{op}', found {:?}", self.peek())))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Tok::Name(n)) => Ok(n),
+            other => Err(err(format!("expected identifier, found {other:?}"))),
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.pos >= self.toks.len()
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let value = self.or()?;
+        if self.eat_name("if") {
+            let cond = self.or()?;
+            let otherwise = if self.eat_name("else") {
+                Some(Box::new(self.expr()?))
+            } else {
+                None
+            };
+            return Ok(Expr::Ternary(Box::new(cond), Box::new(value), otherwise));
+        }
+        Ok(value)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut lhs = self.and()?;
+        while self.eat_name("or") {
+            lhs = Expr::Binary("or", Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut lhs = self.not()?;
+        while self.eat_name("and") {
+            lhs = Expr::Binary("and", Box::new(lhs), Box::new(self.not()?));
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.eat_name("not") {
+            return Ok(Expr::Unary("not", Box::new(self.not()?)));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Expr> {
+        let mut lhs = self.concat()?;
+        loop {
+            let op = match self.peek() {
+                Some(Tok::Op(o)) if ["==", "!=", "<", ">", "<=", ">="].contains(o) => *o,
+                Some(Tok::Name(n)) if n == "in" => "in",
+                Some(Tok::Name(n))
+                    if n == "not"
+                        && matches!(self.toks.get(self.pos + 1), Some(Tok::Name(m)) if m == "in") =>
+                {
+                    self.pos += 1;
+                    "not in"
+                }
+                Some(Tok::Name(n)) if n == "is" => {
+                    self.pos += 1;
+                    let negated = self.eat_name("not");
+                    let test = self.ident()?;
+                    // `is divisibleby(3)` style arguments are not supported
+                    lhs = Expr::Test(Box::new(lhs), test, negated);
+                    continue;
+                }
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.concat()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn concat(&mut self) -> Result<Expr> {
+        let mut lhs = self.additive()?;
+        while self.eat_op("~") {
+            lhs = Expr::Binary("~", Box::new(lhs), Box::new(self.additive()?));
+        }
+        Ok(lhs)
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let op = if self.eat_op("+") {
+                "+"
+            } else if self.eat_op("-") {
+                "-"
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.multiplicative()?));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Tok::Op(o)) if ["*", "/", "//", "%"].contains(o) => *o,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat_op("-") {
+            return Ok(Expr::Unary("-", Box::new(self.unary()?)));
+        }
+        if self.eat_op("+") {
+            return self.unary();
+        }
+        self.postfix()
+    }
+
+    fn call_args(&mut self) -> Result<CallArgs> {
+        let mut args = Vec::new();
+        let mut kwargs = Vec::new();
+        while !self.eat_op(")") {
+            if let (Some(Tok::Name(n)), Some(Tok::Op("="))) =
+                (self.peek().cloned(), self.toks.get(self.pos + 1))
+            {
+                self.pos += 2;
+                kwargs.push((n, self.expr()?));
+            } else {
+                args.push(self.expr()?);
+            }
+            if !self.eat_op(",") {
+                self.expect_op(")")?;
+                break;
+            }
+        }
+        Ok((args, kwargs))
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let mut e = self.primary()?;
+        loop {
+            if self.eat_op(".") {
+                let name = self.ident()?;
+                e = Expr::Attr(Box::new(e), name);
+            } else if self.eat_op("[") {
+                let mut parts: [Option<Box<Expr>>; 3] = [None, None, None];
+                let mut idx = 0;
+                let mut is_slice = false;
+                loop {
+                    if self.eat_op("]") {
+                        break;
+                    }
+                    if self.eat_op(":") {
+                        is_slice = true;
+                        idx += 1;
+                        if idx > 2 {
+                            return Err(err("bad slice"));
+                        }
+                        continue;
+                    }
+                    parts[idx] = Some(Box::new(self.expr()?));
+                }
+                e = if is_slice {
+                    Expr::Slice(Box::new(e), parts)
+                } else {
+                    let index = parts[0].take().ok_or_else(|| err("empty subscript"))?;
+                    Expr::Index(Box::new(e), index)
+                };
+            } else if self.eat_op("(") {
+                let (args, kwargs) = self.call_args()?;
+                e = Expr::Call(Box::new(e), args, kwargs);
+            } else if self.eat_op("|") {
+                let name = self.ident()?;
+                let (args, kwargs) = if self.eat_op("(") {
+                    self.call_args()?
+                } else {
+                    (Vec::new(), Vec::new())
+                };
+                e = Expr::Filter(Box::new(e), name, args, kwargs);
+            } else {
+                return Ok(e);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Tok::Str(s)) => {
+                // Adjacent string literals concatenate, as in Python
+                let mut s = s;
+                while let Some(Tok::Str(more)) = self.peek().cloned() {
+                    self.pos += 1;
+                    s.push_str(&more);
+                }
+                Ok(Expr::Literal(Value::Str(s)))
+            }
+            Some(Tok::Int(i)) => Ok(Expr::Literal(Value::Int(i))),
+            Some(Tok::Float(f)) => Ok(Expr::Literal(Value::Float(f))),
+            Some(Tok::Name(n)) => Ok(match n.as_str() {
+                "true" | "True" => Expr::Literal(Value::Bool(true)),
+                "false" | "False" => Expr::Literal(Value::Bool(false)),
+                "none" | "None" => Expr::Literal(Value::None),
+                _ => Expr::Var(n),
+            }),
+            Some(Tok::Op("(")) => {
+                let e = self.expr()?;
+                if self.at_op(",") {
+                    // Tuple literal — treat as a list
+                    let mut items = vec![e];
+                    while self.eat_op(",") {
+                        if self.at_op(")") {
+                            break;
+                        }
+                        items.push(self.expr()?);
+                    }
+                    self.expect_op(")")?;
+                    return Ok(Expr::List(items));
+                }
+                self.expect_op(")")?;
+                Ok(e)
+            }
+            Some(Tok::Op("[")) => {
+                let mut items = Vec::new();
+                while !self.eat_op("]") {
+                    items.push(self.expr()?);
+                    if !self.eat_op(",") {
+                        self.expect_op("]")?;
+                        break;
+                    }
+                }
+                Ok(Expr::List(items))
+            }
+            Some(Tok::Op("{")) => {
+                let mut items = Vec::new();
+                while !self.eat_op("}") {
+                    let k = self.expr()?;
+                    self.expect_op(":")?;
+                    let v = self.expr()?;
+                    items.push((k, v));
+                    if !self.eat_op(",") {
+                        self.expect_op("}")?;
+                        break;
+                    }
+                }
+                Ok(Expr::Dict(items))
+            }
+            other => Err(err(format!("unexpected token {other:?}"))),
+        }
+    }
+}
+
+fn parse_expr(src: &str) -> Result<Expr> {
+    let mut p = Parser::new(src)?;
+    let e = p.expr()?;
+    if !p.done() {
+        return Err(err(format!("trailing tokens in '{src}'")));
+    }
+    Ok(e)
+}
+
+// ─────────────────────────────────────────────────────────────
+// Statement parser
+// ─────────────────────────────────────────────────────────────
+
+struct NodeParser {
+    segments: std::vec::IntoIter<Segment>,
+}
+
+impl NodeParser {
+    /// Parse nodes until one of `ends` is hit; returns the nodes and the
+    /// terminating statement (keyword + rest).
+    fn parse_until(&mut self, ends: &[&str]) -> Result<Block> {
+        let mut nodes = Vec::new();
+        while let Some(seg) = self.segments.next() {
+            match seg {
+                Segment::Text(t) => nodes.push(Node::Text(t)),
+                Segment::Expr(e) => nodes.push(Node::Output(parse_expr(&e)?)),
+                Segment::Stmt(s) => {
+                    let (kw, rest) = match s.split_once(char::is_whitespace) {
+                        Some((k, r)) => (k.to_string(), r.trim().to_string()),
+                        None => (s.clone(), String::new()),
+                    };
+                    if ends.contains(&kw.as_str()) {
+                        return Ok((nodes, Some((kw, rest))));
+                    }
+                    nodes.push(self.statement(&kw, &rest)?);
+                }
+            }
+        }
+        if ends.is_empty() {
+            Ok((nodes, None))
+        } else {
+            Err(err(format!("missing '{}'", ends.join("' or '"))))
+        }
+    }
+
+    fn statement(&mut self, kw: &str, rest: &str) -> Result<Node> {
+        match kw {
+            "if" => {
+                let mut branches = Vec::new();
+                let mut cond = parse_expr(rest)?;
+                loop {
+                    let (body, end) = self.parse_until(&["elif", "else", "endif"])?;
+                    branches.push((cond, body));
+                    match end {
+                        Some((k, r)) if k == "elif" => cond = parse_expr(&r)?,
+                        Some((k, _)) if k == "else" => {
+                            let (else_body, _) = self.parse_until(&["endif"])?;
+                            return Ok(Node::If(branches, else_body));
+                        }
+                        _ => return Ok(Node::If(branches, Vec::new())),
+                    }
+                }
+            }
+            "for" => {
+                let mut p = Parser::new(rest)?;
+                let mut targets = vec![p.ident()?];
+                while p.eat_op(",") {
+                    targets.push(p.ident()?);
+                }
+                if !p.eat_name("in") {
+                    return Err(err("expected 'in' in for loop"));
+                }
+                // Parse the iterable without the ternary so `if` is the loop filter
+                let iter = p.or()?;
+                let filter = if p.eat_name("if") {
+                    Some(p.expr()?)
+                } else {
+                    None
+                };
+                if !p.done() {
+                    return Err(err(format!("unsupported for loop: '{rest}'")));
+                }
+                let (body, end) = self.parse_until(&["else", "endfor"])?;
+                let else_body = match end {
+                    Some((k, _)) if k == "else" => self.parse_until(&["endfor"])?.0,
+                    _ => Vec::new(),
+                };
+                Ok(Node::For {
+                    targets,
+                    iter,
+                    filter,
+                    body,
+                    else_body,
+                })
+            }
+            "set" => {
+                let (target, value) = rest
+                    .split_once('=')
+                    .filter(|(_, v)| !v.starts_with('='))
+                    .ok_or_else(|| err("block set is not supported"))?;
+                let path: Vec<String> = target.split('.').map(|s| s.trim().to_string()).collect();
+                if path.is_empty() || path.len() > 2 || path.iter().any(|s| s.is_empty()) {
+                    return Err(err(format!("unsupported set target '{target}'")));
+                }
+                Ok(Node::Set(path, parse_expr(value)?))
+            }
+            "break" => Ok(Node::Break),
+            "continue" => Ok(Node::Continue),
+            "generation" => {
+                // HF marker for assistant-token masks; render the body as-is
+                let (body, _) = self.parse_until(&["endgeneration"])?;
+                Ok(Node::If(
+                    vec![(Expr::Literal(Value::Bool(true)), body)],
+                    Vec::new(),
+                ))
+            }
+            other => Err(err(format!("unsupported tag '{other}'"))),
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Renderer
+// ─────────────────────────────────────────────────────────────
+
+/// A parsed template.
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+}
+
+struct Env {
+    scopes: Vec<HashMap<String, Value>>,
+    out: String,
+}
+
+impl Template {
+    /// Parse template source.
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut parser = NodeParser {
+            segments: split_segments(src)?.into_iter(),
+        };
+        let (nodes, _) = parser.parse_until(&[])?;
+        Ok(Self { nodes })
+    }
+
+    /// Render with the given top-level variables.
+    pub fn render(&self, vars: HashMap<String, Value>) -> Result<String> {
+        let mut env = Env {
+            scopes: vec![vars],
+            out: String::new(),
+        };
+        env.exec(&self.nodes)?;
+        Ok(env.out)
+    }
+}
+
+impl Env {
+    fn lookup(&self, name: &str) -> Value {
+        for scope in self.scopes.iter().rev() {
+            if let Some(v) = scope.get(name) {
+                return v.clone();
+            }
+        }
+        Value::Undefined
+    }
+
+    fn exec(&mut self, nodes: &[Node]) -> Result<Flow> {
+        for node in nodes {
+            match node {
+                Node::Text(t) => self.out.push_str(t),
+                Node::Output(e) => {
+                    let v = self.eval(e)?;
+                    self.out.push_str(&v.to_string());
+                }
+                Node::If(branches, else_body) => {
+                    let mut taken = false;
+                    for (cond, body) in branches {
+                        if self.eval(cond)?.is_truthy() {
+                            taken = true;
+                            match self.exec(body)? {
+                                Flow::Normal => {}
+                                flow => return Ok(flow),
+                            }
+                            break;
+                        }
+                    }
+                    if !taken {
+                        match self.exec(else_body)? {
+                            Flow::Normal => {}
+                            flow => return Ok(flow),
+                        }
+                    }
+                }
+                Node::For {
+                    targets,
+                    iter,
+                    filter,
+                    body,
+                    else_body,
+                } => self.exec_for(targets, iter, filter.as_ref(), body, else_body)?,
+                Node::Set(path, e) => {
+                    let v = self.eval(e)?;
+                    self.set(path, v)?;
+                }
+                Node::Break => return Ok(Flow::Break),
+                Node::Continue => return Ok(Flow::Continue),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn set(&mut self, path: &[String], v: Value) -> Result<()> {
+        if path.len() == 1 {
+            self.scopes
+                .last_mut()
+                .expect("at least one scope")
+                .insert(path[0].clone(), v);
+            return Ok(());
+        }
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(target) = scope.get_mut(&path[0]) {
+                return match target {
+                    Value::Map(m) => {
+                        m.insert(path[1].clone(), v);
+                        Ok(())
+                    }
+                    _ => Err(err(format!("cannot set attribute on '{}'", path[0]))),
+                };
+            }
+        }
+        Err(err(format!("'{}' is undefined", path[0])))
+    }
+
+    fn exec_for(
+        &mut self,
+        targets: &[String],
+        iter: &Expr,
+        filter: Option<&Expr>,
+        body: &[Node],
+        else_body: &[Node],
+    ) -> Result<()> {
+        let items: Vec<Value> = match self.eval(iter)? {
+            Value::List(l) => l,
+            Value::Map(m) => m.into_keys().map(Value::Str).collect(),
+            Value::Str(s) => s.chars().map(|c| Value::Str(c.to_string())).collect(),
+            Value::Undefined | Value::None => Vec::new(),
+            other => return Err(err(format!("cannot iterate over {}", other.type_name()))),
+        };
+
+        // Apply the inline filter first so loop.* reflects the filtered list
+        let mut selected = Vec::with_capacity(items.len());
+        for item in items {
+            if let Some(f) = filter {
+                self.scopes.push(HashMap::new());
+                self.bind(targets, item.clone())?;
+                let keep = self.eval(f);
+                self.scopes.pop();
+                if !keep?.is_truthy() {
+                    continue;
+                }
+            }
+            selected.push(item);
+        }
+
+        if selected.is_empty() {
+            self.exec(else_body)?;
+            return Ok(());
+        }
+
+        let n = selected.len();
+        for (i, item) in selected.into_iter().enumerate() {
+            self.scopes.push(HashMap::new());
+            self.bind(targets, item)?;
+            let loop_var: BTreeMap<String, Value> = [
+                ("index", Value::Int(i as i64 + 1)),
+                ("index0", Value::Int(i as i64)),
+                ("revindex", Value::Int((n - i) as i64)),
+                ("revindex0", Value::Int((n - i - 1) as i64)),
+                ("first", Value::Bool(i == 0)),
+                ("last", Value::Bool(i == n - 1)),
+                ("length", Value::Int(n as i64)),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect();
+            self.scopes
+                .last_mut()
+                .expect("loop scope")
+                .insert("loop".into(), Value::Map(loop_var));
+            let flow = self.exec(body);
+            self.scopes.pop();
+            if let Flow::Break = flow? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn bind(&mut self, targets: &[String], item: Value) -> Result<()> {
+        let scope = self.scopes.last_mut().expect("loop scope");
+        if targets.len() == 1 {
+            scope.insert(targets[0].clone(), item);
+            return Ok(());
+        }
+        match item {
+            Value::List(parts) if parts.len() == targets.len() => {
+                for (t, v) in targets.iter().zip(parts) {
+                    scope.insert(t.clone(), v);
+                }
+                Ok(())
+            }
+            _ => Err(err("cannot unpack loop item")),
+        }
+    }
+
+    fn eval(&mut self, e: &Expr) -> Result<Value> {
+        Ok(match e {
+            Expr::Literal(v) => v.clone(),
+            Expr::Var(n) => self.lookup(n),
+            Expr::List(items) => Value::List(
+                items
+                    .iter()
+                    .map(|i| self.eval(i))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            Expr::Dict(items) => {
+                let mut m = BTreeMap::new();
+                for (k, v) in items {
+                    let k = self.eval(k)?.to_string();
+                    m.insert(k, self.eval(v)?);
+                }
+                Value::Map(m)
+            }
+            Expr::Attr(obj, name) => {
+                let obj = self.eval(obj)?;
+                get_item(&obj, &Value::Str(name.clone()))
+            }
+            Expr::Index(obj, idx) => {
+                let obj = self.eval(obj)?;
+                let idx = self.eval(idx)?;
+                get_item(&obj, &idx)
+            }
+            Expr::Slice(obj, parts) => {
+                let obj = self.eval(obj)?;
+                let mut bounds = [None, None, None];
+                for (b, p) in bounds.iter_mut().zip(parts) {
+                    if let Some(p) = p {
+                        match self.eval(p)? {
+                            Value::Int(i) => *b = Some(i),
+                            Value::None => {}
+                            _ => return Err(err("slice bounds must be integers")),
+                        }
+                    }
+                }
+                slice(&obj, bounds)?
+            }
+            Expr::Call(callee, args, kwargs) => {
+                let args = args
+                    .iter()
+                    .map(|a| self.eval(a))
+                    .collect::<Result<Vec<_>>>()?;
+                let kwargs = kwargs
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), self.eval(v)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                match callee.as_ref() {
+                    Expr::Attr(obj, method) => {
+                        let obj = self.eval(obj)?;
+                        call_method(&obj, method, &args)?
+                    }
+                    Expr::Var(name) => call_function(name, &args, kwargs)?,
+                    _ => return Err(err("unsupported call")),
+                }
+            }
+            Expr::Filter(value, name, args, kwargs) => {
+                let value = self.eval(value)?;
+                let args = args
+                    .iter()
+                    .map(|a| self.eval(a))
+                    .collect::<Result<Vec<_>>>()?;
+                let kwargs = kwargs
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), self.eval(v)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                apply_filter(value, name, &args, &kwargs)?
+            }
+            Expr::Test(value, test, negated) => {
+                let v = self.eval(value)?;
+                let result = match test.as_str() {
+                    "defined" => v != Value::Undefined,
+                    "undefined" => v == Value::Undefined,
+                    "none" => v == Value::None,
+                    "string" => matches!(v, Value::Str(_)),
+                    "number" => matches!(v, Value::Int(_) | Value::Float(_)),
+                    "integer" => matches!(v, Value::Int(_)),
+                    "float" => matches!(v, Value::Float(_)),
+                    "boolean" => matches!(v, Value::Bool(_)),
+                    "true" => v == Value::Bool(true),
+                    "false" => v == Value::Bool(false),
+                    "mapping" => matches!(v, Value::Map(_)),
+                    "sequence" | "iterable" => {
+                        matches!(v, Value::List(_) | Value::Str(_) | Value::Map(_))
+                    }
+                    "even" => matches!(v, Value::Int(i) if i % 2 == 0),
+                    "odd" => matches!(v, Value::Int(i) if i % 2 != 0),
+                    other => return Err(err(format!("unsupported test '{other}'"))),
+                };
+                Value::Bool(result != *negated)
+            }
+            Expr::Unary(op, inner) => {
+                let v = self.eval(inner)?;
+                match *op {
+                    "not" => Value::Bool(!v.is_truthy()),
+                    _ => match v {
+                        Value::Int(i) => Value::Int(-i),
+                        Value::Float(f) => Value::Float(-f),
+                        other => return Err(err(format!("cannot negate {}", other.type_name()))),
+                    },
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => match *op {
+                "and" => {
+                    let l = self.eval(lhs)?;
+                    if l.is_truthy() { self.eval(rhs)? } else { l }
+                }
+                "or" => {
+                    let l = self.eval(lhs)?;
+                    if l.is_truthy() { l } else { self.eval(rhs)? }
+                }
+                _ => {
+                    let l = self.eval(lhs)?;
+                    let r = self.eval(rhs)?;
+                    binary(op, l, r)?
+                }
+            },
+            Expr::Ternary(cond, value, otherwise) => {
+                if self.eval(cond)?.is_truthy() {
+                    self.eval(value)?
+                } else if let Some(o) = otherwise {
+                    self.eval(o)?
+                } else {
+                    Value::Undefined
+                }
+            }
+        })
+    }
+}
+
+fn get_item(obj: &Value, key: &Value) -> Value {
+    match (obj, key) {
+        (Value::Map(m), k) => m.get(&k.to_string()).cloned().unwrap_or_default(),
+        (Value::List(l), Value::Int(i)) => {
+            let idx = if *i < 0 { l.len() as i64 + i } else { *i };
+            usize::try_from(idx)
+                .ok()
+                .and_then(|i| l.get(i).cloned())
+                .unwrap_or_default()
+        }
+        (Value::Str(s), Value::Int(i)) => {
+            let chars: Vec<char> = s.chars().collect();
+            let idx = if *i < 0 { chars.len() as i64 + i } else { *i };
+            usize::try_from(idx)
+                .ok()
+                .and_then(|i| chars.get(i))
+                .map(|c| Value::Str(c.to_string()))
+                .unwrap_or_default()
+        }
+        _ => Value::Undefined,
+    }
+}
+
+fn slice(obj: &Value, [start, stop, step]: [Option<i64>; 3]) -> Result<Value> {
+    let step = step.unwrap_or(1);
+    if step == 0 {
+        return Err(err("slice step cannot be zero"));
+    }
+    let pick = |len: usize| -> Vec<usize> {
+        let len = len as i64;
+        let clamp = |v: i64, lo: i64, hi: i64| v.clamp(lo, hi);
+        let norm = |v: i64| if v < 0 { v + len } else { v };
+        let mut out = Vec::new();
+        if step > 0 {
+            let mut i = clamp(start.map(norm).unwrap_or(0), 0, len);
+            let end = clamp(stop.map(norm).unwrap_or(len), 0, len);
+            while i < end {
+                out.push(i as usize);
+                i += step;
+            }
+        } else {
+            let mut i = clamp(start.map(norm).unwrap_or(len - 1), -1, len - 1);
+            let end = clamp(stop.map(norm).unwrap_or(-1), -1, len - 1);
+            while i > end {
+                out.push(i as usize);
+                i += step;
+            }
+        }
+        out
+    };
+    Ok(match obj {
+        Value::List(l) => Value::List(pick(l.len()).into_iter().map(|i| l[i].clone()).collect()),
+        Value::Str(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            Value::Str(pick(chars.len()).into_iter().map(|i| chars[i]).collect())
+        }
+        Value::Undefined | Value::None => Value::Undefined,
+        other => return Err(err(format!("cannot slice {}", other.type_name()))),
+    })
+}
+
+fn compare(l: &Value, r: &Value) -> Option<std::cmp::Ordering> {
+    match (l, r) {
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        _ => l.as_f64()?.partial_cmp(&r.as_f64()?),
+    }
+}
+
+fn values_equal(l: &Value, r: &Value) -> bool {
+    match (l.as_f64(), r.as_f64()) {
+        (Some(a), Some(b)) if !matches!(l, Value::Bool(_)) || !matches!(r, Value::Bool(_)) => {
+            a == b
+        }
+        _ => l == r,
+    }
+}
+
+fn binary(op: &str, l: Value, r: Value) -> Result<Value> {
+    use std::cmp::Ordering::*;
+    Ok(match op {
+        "==" => Value::Bool(values_equal(&l, &r)),
+        "!=" => Value::Bool(!values_equal(&l, &r)),
+        "<" => Value::Bool(compare(&l, &r) == Some(Less)),
+        ">" => Value::Bool(compare(&l, &r) == Some(Greater)),
+        "<=" => Value::Bool(matches!(compare(&l, &r), Some(Less | Equal))),
+        ">=" => Value::Bool(matches!(compare(&l, &r), Some(Greater | Equal))),
+        "in" | "not in" => {
+            let found = match &r {
+                Value::Str(s) => s.contains(&l.to_string()),
+                Value::List(items) => items.iter().any(|i| values_equal(i, &l)),
+                Value::Map(m) => m.contains_key(&l.to_string()),
+                Value::Undefined | Value::None => false,
+                other => return Err(err(format!("'in' on {}", other.type_name()))),
+            };
+            Value::Bool(found == (op == "in"))
+        }
+        "~" => Value::Str(format!("{l}{r}")),
+        "+" => match (l, r) {
+            (Value::Str(a), Value::Str(b)) => Value::Str(a + &b),
+            (Value::List(mut a), Value::List(b)) => {
+                a.extend(b);
+                Value::List(a)
+            }
+            (Value::Int(a), Value::Int(b)) => Value::Int(a + b),
+            (a, b) => arith(&a, &b, |x, y| x + y)?,
+        },
+        "-" => match (l, r) {
+            (Value::Int(a), Value::Int(b)) => Value::Int(a - b),
+            (a, b) => arith(&a, &b, |x, y| x - y)?,
+        },
+        "*" => match (l, r) {
+            (Value::Int(a), Value::Int(b)) => Value::Int(a * b),
+            (Value::Str(s), Value::Int(n)) => Value::Str(s.repeat(n.max(0) as usize)),
+            (a, b) => arith(&a, &b, |x, y| x * y)?,
+        },
+        "/" => arith(&l, &r, |x, y| x / y)?,
+        "//" | "%" => match (l, r) {
+            (Value::Int(_), Value::Int(0)) => return Err(err("division by zero")),
+            (Value::Int(a), Value::Int(b)) if op == "//" => Value::Int(a.div_euclid(b)),
+            (Value::Int(a), Value::Int(b)) => Value::Int(a.rem_euclid(b)),
+            (a, b) if op == "//" => arith(&a, &b, |x, y| (x / y).floor())?,
+            (a, b) => arith(&a, &b, |x, y| x.rem_euclid(y))?,
+        },
+        other => return Err(err(format!("unsupported operator '{other}'"))),
+    })
+}
+
+fn arith(l: &Value, r: &Value, f: impl Fn(f64, f64) -> f64) -> Result<Value> {
+    match (l.as_f64(), r.as_f64()) {
+        (Some(a), Some(b)) => Ok(Value::Float(f(a, b))),
+        _ => Err(err(format!(
+            "unsupported operand types {} and {}",
+            l.type_name(),
+            r.type_name()
+        ))),
+    }
+}
+
+fn str_arg(args: &[Value], i: usize) -> Option<String> {
+    match args.get(i) {
+        Some(Value::Str(s)) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn call_method(obj: &Value, method: &str, args: &[Value]) -> Result<Value> {
+    if let Value::Str(s) = obj {
+        let chars = str_arg(args, 0);
+        let strip_set = |c: char| match &chars {
+            Some(set) => set.contains(c),
+            None => c.is_whitespace(),
+        };
+        return Ok(match method {
+            "strip" => s.trim_matches(strip_set).into(),
+            "lstrip" => s.trim_start_matches(strip_set).into(),
+            "rstrip" => s.trim_end_matches(strip_set).into(),
+            "upper" => s.to_uppercase().into(),
+            "lower" => s.to_lowercase().into(),
+            "title" => title_case(s).into(),
+            "capitalize" => capitalize(s).into(),
+            "startswith" => Value::Bool(chars.is_some_and(|p| s.starts_with(&p))),
+            "endswith" => Value::Bool(chars.is_some_and(|p| s.ends_with(&p))),
+            "replace" => match (str_arg(args, 0), str_arg(args, 1)) {
+                (Some(a), Some(b)) => s.replace(&a, &b).into(),
+                _ => return Err(err("replace() takes two strings")),
+            },
+            "split" => Value::List(match chars {
+                Some(sep) => s.split(sep.as_str()).map(Value::from).collect(),
+                None => s.split_whitespace().map(Value::from).collect(),
+            }),
+            "count" => Value::Int(chars.map_or(0, |p| s.matches(p.as_str()).count() as i64)),
+            "find" => Value::Int(
+                chars
+                    .and_then(|p| s.find(&p))
+                    .map_or(-1, |i| s[..i].chars().count() as i64),
+            ),
+            other => return Err(err(format!("unsupported string method '{other}'"))),
+        });
+    }
+    if let Value::Map(m) = obj {
+        return Ok(match method {
+            "items" => Value::List(
+                m.iter()
+                    .map(|(k, v)| Value::List(vec![Value::Str(k.clone()), v.clone()]))
+                    .collect(),
+            ),
+            "keys" => Value::List(m.keys().cloned().map(Value::Str).collect()),
+            "values" => Value::List(m.values().cloned().collect()),
+            "get" => {
+                let key = args.first().map(|k| k.to_string()).unwrap_or_default();
+                m.get(&key)
+                    .cloned()
+                    .unwrap_or_else(|| args.get(1).cloned().unwrap_or(Value::None))
+            }
+            other => return Err(err(format!("unsupported dict method '{other}'"))),
+        });
+    }
+    Err(err(format!(
+        "unsupported method '{method}' on {}",
+        obj.type_name()
+    )))
+}
+
+fn call_function(name: &str, args: &[Value], kwargs: Vec<(String, Value)>) -> Result<Value> {
+    match name {
+        "raise_exception" => Err(err(format!(
+            "template raised: {}",
+            args.first().map(|a| a.to_string()).unwrap_or_default()
+        ))),
+        "namespace" | "dict" => Ok(Value::Map(kwargs.into_iter().collect())),
+        "range" => {
+            let ints: Vec<i64> = args
+                .iter()
+                .map(|a| match a {
+                    Value::Int(i) => Ok(*i),
+                    _ => Err(err("range() takes integers")),
+                })
+                .collect::<Result<_>>()?;
+            let (start, stop, step) = match ints.as_slice() {
+                [stop] => (0, *stop, 1),
+                [start, stop] => (*start, *stop, 1),
+                [start, stop, step] if *step != 0 => (*start, *stop, *step),
+                _ => return Err(err("bad range() arguments")),
+            };
+            let mut out = Vec::new();
+            let mut i = start;
+            while (step > 0 && i < stop) || (step < 0 && i > stop) {
+                out.push(Value::Int(i));
+                i += step;
+            }
+            Ok(Value::List(out))
+        }
+        "strftime_now" => Ok(Value::Str(strftime_now(
+            &str_arg(args, 0).unwrap_or_default(),
+        ))),
+        other => Err(err(format!("unknown function '{other}'"))),
+    }
+}
+
+fn apply_filter(
+    value: Value,
+    name: &str,
+    args: &[Value],
+    kwargs: &[(String, Value)],
+) -> Result<Value> {
+    Ok(match name {
+        "trim" => value.to_string().trim().into(),
+        "upper" => value.to_string().to_uppercase().into(),
+        "lower" => value.to_string().to_lowercase().into(),
+        "title" => title_case(&value.to_string()).into(),
+        "capitalize" => capitalize(&value.to_string()).into(),
+        "string" => value.to_string().into(),
+        "safe" | "e" | "escape" => value,
+        "length" | "count" => Value::Int(match &value {
+            Value::Str(s) => s.chars().count(),
+            Value::List(l) => l.len(),
+            Value::Map(m) => m.len(),
+            _ => 0,
+        } as i64),
+        "int" => Value::Int(match &value {
+            Value::Int(i) => *i,
+            Value::Float(f) => *f as i64,
+            Value::Bool(b) => *b as i64,
+            Value::Str(s) => s.trim().parse().unwrap_or(0),
+            _ => 0,
+        }),
+        "float" => Value::Float(
+            value
+                .as_f64()
+                .unwrap_or_else(|| value.to_string().trim().parse().unwrap_or(0.0)),
+        ),
+        "list" => match value {
+            Value::List(l) => Value::List(l),
+            Value::Str(s) => Value::List(s.chars().map(|c| Value::Str(c.into())).collect()),
+            Value::Map(m) => Value::List(m.into_keys().map(Value::Str).collect()),
+            _ => Value::List(Vec::new()),
+        },
+        "first" => match value {
+            Value::List(l) => l.into_iter().next().unwrap_or_default(),
+            Value::Str(s) => s
+                .chars()
+                .next()
+                .map(|c| c.to_string().into())
+                .unwrap_or_default(),
+            _ => Value::Undefined,
+        },
+        "last" => match value {
+            Value::List(l) => l.into_iter().last().unwrap_or_default(),
+            Value::Str(s) => s
+                .chars()
+                .last()
+                .map(|c| c.to_string().into())
+                .unwrap_or_default(),
+            _ => Value::Undefined,
+        },
+        "reverse" => match value {
+            Value::List(mut l) => {
+                l.reverse();
+                Value::List(l)
+            }
+            Value::Str(s) => s.chars().rev().collect::<String>().into(),
+            other => other,
+        },
+        "join" => {
+            let sep = str_arg(args, 0).unwrap_or_default();
+            match value {
+                Value::List(l) => l
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(&sep)
+                    .into(),
+                other => other.to_string().into(),
+            }
+        }
+        "default" | "d" => {
+            let boolean = args.get(1).is_some_and(Value::is_truthy);
+            if value == Value::Undefined || (boolean && !value.is_truthy()) {
+                args.first().cloned().unwrap_or_default()
+            } else {
+                value
+            }
+        }
+        "replace" => match (str_arg(args, 0), str_arg(args, 1)) {
+            (Some(a), Some(b)) => value.to_string().replace(&a, &b).into(),
+            _ => return Err(err("replace filter takes two strings")),
+        },
+        "items" => call_method(&value, "items", &[])?,
+        "tojson" => {
+            let indent = kwargs
+                .iter()
+                .find(|(k, _)| k == "indent")
+                .map(|(_, v)| v.clone())
+                .or_else(|| args.first().cloned());
+            let json = value.to_json();
+            match indent {
+                Some(Value::Int(n)) if n > 0 => {
+                    let pretty = serde_json::to_string_pretty(&json).unwrap_or_default();
+                    // serde_json indents by 2; re-indent to the requested width
+                    if n == 2 {
+                        pretty.into()
+                    } else {
+                        reindent(&pretty, n as usize).into()
+                    }
+                }
+                _ => python_json(&json).into(),
+            }
+        }
+        other => return Err(err(format!("unsupported filter '{other}'"))),
+    })
+}
+
+/// `json.dumps` style output with `", "` and `": "` separators.
+fn python_json(v: &serde_json::Value) -> String {
+    match v {
+        serde_json::Value::Array(a) => {
+            let items: Vec<String> = a.iter().map(python_json).collect();
+            format!("[{}]", items.join(", "))
+        }
+        serde_json::Value::Object(o) => {
+            let items: Vec<String> = o
+                .iter()
+                .map(|(k, v)| {
+                    format!(
+                        "{}: {}",
+                        serde_json::Value::String(k.clone()),
+                        python_json(v)
+                    )
+                })
+                .collect();
+            format!("{{{}}}", items.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
+
+fn reindent(pretty: &str, width: usize) -> String {
+    pretty
+        .lines()
+        .map(|line| {
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            format!("{}{}", " ".repeat(spaces / 2 * width), line.trim_start())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+fn title_case(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut start = true;
+    for c in s.chars() {
+        if start {
+            out.extend(c.to_uppercase());
+        } else {
+            out.extend(c.to_lowercase());
+        }
+        start = !c.is_alphanumeric();
+    }
+    out
+}
+
+/// Minimal `strftime` over the current UTC date (%d %m %Y %b %B %%).
+fn strftime_now(fmt: &str) -> String {
+    const MONTHS: [&str; 12] = [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ];
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    // Civil-from-days (Howard Hinnant)
+    let z = secs.div_euclid(86_400) + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let mut out = String::new();
+    let mut chars = fmt.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('d') => out.push_str(&format!("{day:02}")),
+            Some('m') => out.push_str(&format!("{month:02}")),
+            Some('Y') => out.push_str(&year.to_string()),
+            Some('b') => out.push_str(&MONTHS[month as usize - 1][..3]),
+            Some('B') => out.push_str(MONTHS[month as usize - 1]),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(src: &str, vars: serde_json::Value) -> String {
+        let vars = match Value::from(&vars) {
+            Value::Map(m) => m.into_iter().collect(),
+            _ => HashMap::new(),
+        };
+        Template::parse(src).unwrap().render(vars).unwrap()
+    }
+
+    #[test]
+    fn test_output_and_filters() {
+        let out = render(
+            "{{ name | upper }} {{ items | length }} {{ ' x ' | trim }}",
+            serde_json::json!({"name": "bob", "items": [1, 2, 3]}),
+        );
+        assert_eq!(out, "BOB 3 x");
+    }
+
+    #[test]
+    fn test_for_loop_and_loop_vars() {
+        let out = render(
+            "{% for m in msgs %}{{ loop.index }}:{{ m.role }}{% if not loop.last %},{% endif %}{% endfor %}",
+            serde_json::json!({"msgs": [{"role": "user"}, {"role": "assistant"}]}),
+        );
+        assert_eq!(out, "1:user,2:assistant");
+    }
+
+    #[test]
+    fn test_whitespace_control_and_trim_blocks() {
+        let src = "{%- for x in xs %}\n  {{- x }}\n{%- endfor %}\n";
+        assert_eq!(render(src, serde_json::json!({"xs": [1, 2]})), "12");
+
+        // trim_blocks removes the newline after a block tag,
+        // lstrip_blocks the indentation before it.
+        let src = "{% if true %}\n    {% if true %}\nyes\n    {% endif %}\n{% endif %}\n";
+        assert_eq!(render(src, serde_json::json!({})), "yes\n");
+    }
+
+    #[test]
+    fn test_namespace_and_set() {
+        let src = "{% set ns = namespace(found=false) %}{% for m in msgs %}{% if m == 'b' %}{% set ns.found = true %}{% endif %}{% endfor %}{{ ns.found }}";
+        assert_eq!(render(src, serde_json::json!({"msgs": ["a", "b"]})), "True");
+        // Plain `set` inside a loop does not leak out
+        let src = "{% set x = 1 %}{% for i in [1] %}{% set x = 2 %}{% endfor %}{{ x }}";
+        assert_eq!(render(src, serde_json::json!({})), "1");
+    }
+
+    #[test]
+    fn test_expressions() {
+        let out = render(
+            "{{ 'a' if x is defined else 'b' }}|{{ msgs[1:] | length }}|{{ msgs[-1] }}|{{ 7 % 2 == 1 }}|{{ 'ab' ~ 1 }}|{{ 'x' in 'xyz' }}",
+            serde_json::json!({"msgs": ["a", "b", "c"]}),
+        );
+        assert_eq!(out, "b|2|c|True|ab1|True");
+    }
+
+    #[test]
+    fn test_string_methods() {
+        let out = render(
+            "{{ s.strip() }}|{{ s.split('/')[-1].strip() }}|{{ s.strip().startswith('a') }}",
+            serde_json::json!({"s": " a/b "}),
+        );
+        assert_eq!(out, "a/b|b|True");
+    }
+
+    #[test]
+    fn test_tojson() {
+        let out = render(
+            "{{ t | tojson }}",
+            serde_json::json!({"t": {"name": "f", "args": [1, "x"]}}),
+        );
+        assert_eq!(out, r#"{"args": [1, "x"], "name": "f"}"#);
+    }
+
+    #[test]
+    fn test_raise_exception() {
+        let t = Template::parse("{{ raise_exception('nope') }}").unwrap();
+        assert!(t.render(HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_tag_is_error() {
+        assert!(Template::parse("{% macro f() %}{% endmacro %}").is_err());
+        assert!(Template::parse("{% if x %}").is_err());
+    }
+
+    #[test]
+    fn test_for_loop_filter_and_break() {
+        let out = render(
+            "{% for x in xs if x > 1 %}{% if x == 4 %}{% break %}{% endif %}{{ x }}{{ loop.length }}{% endfor %}",
+            serde_json::json!({"xs": [1, 2, 3, 4, 5]}),
+        );
+        assert_eq!(out, "2434");
+    }
+}
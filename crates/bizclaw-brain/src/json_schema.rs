@@ -0,0 +1,535 @@
+//! JSON Schema → GBNF conversion for structured output.
+//!
+//! Covers the subset local models are asked for in practice: `type` (single
+//! or list), `properties`/`required`, `items` with `minItems`/`maxItems`,
+//! `prefixItems`, string `minLength`/`maxLength`, `enum`, `const`,
+//! `anyOf`/`oneOf` and local `$ref`s (`#/$defs/...`, `#/definitions/...`).
+//! Objects with `properties` only accept the declared keys, in the order
+//! `serde_json::Map` iterates them (sorted), so the model cannot wander off
+//! into extra fields.
+
+use crate::gbnf::Grammar;
+use bizclaw_core::error::{BizClawError, Result};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Built-in rules: (name, body, rules it references).
+const PRIMITIVES: &[(&str, &str, &[&str])] = &[
+    ("space", r#"| " " | "\n" [ \t]{0,20}"#, &[]),
+    ("boolean", r#"("true" | "false") space"#, &["space"]),
+    ("null", r#""null" space"#, &["space"]),
+    (
+        "integer",
+        r#""-"? ([0-9] | [1-9] [0-9]{1,15}) space"#,
+        &["space"],
+    ),
+    (
+        "number",
+        r#""-"? ([0-9] | [1-9] [0-9]{1,15}) ("." [0-9]+)? ([eE] [-+]? [0-9]{1,3})? space"#,
+        &["space"],
+    ),
+    (
+        "char",
+        r#"[^"\\\x7F\x00-\x1F] | "\\" (["\\/bfnrt] | "u" [0-9a-fA-F]{4})"#,
+        &[],
+    ),
+    ("string", r#""\"" char* "\"" space"#, &["char", "space"]),
+    (
+        "value",
+        "object | array | string | number | boolean | null",
+        &["object", "array", "string", "number", "boolean", "null"],
+    ),
+    (
+        "object",
+        r#""{" space (string ":" space value ("," space string ":" space value)*)? "}" space"#,
+        &["space", "string", "value"],
+    ),
+    (
+        "array",
+        r#""[" space (value ("," space value)*)? "]" space"#,
+        &["space", "value"],
+    ),
+];
+
+/// Grammar accepting any single JSON value (objects and arrays at the top).
+pub fn json_grammar() -> Grammar {
+    let src = "root ::= object | array\n".to_string() + &primitive_rules(&["object", "array"]);
+    Grammar::parse(&src).expect("built-in JSON grammar is valid")
+}
+
+/// Compile a JSON Schema into a grammar.
+pub fn schema_to_grammar(schema: &Value) -> Result<Grammar> {
+    Grammar::parse(&schema_to_gbnf(schema)?)
+}
+
+/// Translate a JSON Schema into GBNF source with a `root` rule.
+pub fn schema_to_gbnf(schema: &Value) -> Result<String> {
+    schemas_to_gbnf(&[("root", schema)])
+}
+
+/// Translate several schemas into one GBNF source, each under its own rule
+/// name, sharing the built-in rules. `space` is always defined so callers
+/// can wrap the rules in their own syntax.
+pub fn schemas_to_gbnf(schemas: &[(&str, &Value)]) -> Result<String> {
+    let mut converter = Converter {
+        root: &Value::Null,
+        rules: Vec::new(),
+        taken: schemas.iter().map(|(name, _)| name.to_string()).collect(),
+        refs: HashMap::new(),
+        primitives: HashSet::from(["space"]),
+    };
+    for &(name, schema) in schemas {
+        // `$ref`s resolve against the schema they appear in
+        converter.root = schema;
+        converter.refs.clear();
+        let body = converter.body(schema, name)?;
+        converter.define(name, body);
+    }
+
+    let mut out = String::new();
+    for (name, body) in &converter.rules {
+        out.push_str(&format!("{name} ::= {body}\n"));
+    }
+    let primitives: Vec<&str> = converter.primitives.iter().copied().collect();
+    out.push_str(&primitive_rules(&primitives));
+    Ok(out)
+}
+
+/// GBNF source for the named built-in rules and everything they reference.
+fn primitive_rules(names: &[&str]) -> String {
+    let mut needed = HashSet::new();
+    let mut todo: Vec<&str> = names.to_vec();
+    while let Some(name) = todo.pop() {
+        if needed.insert(name)
+            && let Some((_, _, deps)) = PRIMITIVES.iter().find(|(n, _, _)| *n == name)
+        {
+            todo.extend(deps.iter());
+        }
+    }
+    PRIMITIVES
+        .iter()
+        .filter(|(name, _, _)| needed.contains(name))
+        .map(|(name, body, _)| format!("{name} ::= {body}\n"))
+        .collect()
+}
+
+fn schema_error(msg: impl Into<String>) -> BizClawError {
+    BizClawError::Brain(format!("json schema: {}", msg.into()))
+}
+
+/// Quote `s` as a GBNF string literal.
+fn literal(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\x{:02X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// GBNF literal matching the JSON encoding of `value`.
+fn json_literal(value: &Value) -> String {
+    literal(&value.to_string())
+}
+
+struct Converter<'a> {
+    root: &'a Value,
+    rules: Vec<(String, String)>,
+    taken: HashSet<String>,
+    /// `$ref` target → rule name (reserved before visiting, so recursion works)
+    refs: HashMap<String, String>,
+    primitives: HashSet<&'static str>,
+}
+
+impl<'a> Converter<'a> {
+    /// Reserve a unique rule name derived from `hint`.
+    fn reserve(&mut self, hint: &str) -> String {
+        let base: String = hint
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let base = if base.is_empty() { "rule".into() } else { base };
+        let clashes = |name: &str, taken: &HashSet<String>| {
+            taken.contains(name) || PRIMITIVES.iter().any(|(p, _, _)| *p == name)
+        };
+        let mut name = base.clone();
+        let mut n = 1;
+        while clashes(&name, &self.taken) {
+            name = format!("{base}-{n}");
+            n += 1;
+        }
+        self.taken.insert(name.clone());
+        name
+    }
+
+    fn define(&mut self, name: &str, body: String) {
+        self.taken.insert(name.to_string());
+        self.rules.push((name.to_string(), body));
+    }
+
+    /// Visit `schema` as a named rule and return the rule name.
+    fn rule(&mut self, schema: &Value, hint: &str) -> Result<String> {
+        let name = self.reserve(hint);
+        let body = self.body(schema, &name)?;
+        self.define(&name, body);
+        Ok(name)
+    }
+
+    fn primitive(&mut self, name: &'static str) -> String {
+        self.primitives.insert(name);
+        name.to_string()
+    }
+
+    /// GBNF expression for `schema`; `name` seeds helper rule names.
+    fn body(&mut self, schema: &Value, name: &str) -> Result<String> {
+        let obj = match schema {
+            Value::Bool(true) => return Ok(self.primitive("value")),
+            Value::Object(obj) => obj,
+            other => return Err(schema_error(format!("unsupported schema {other}"))),
+        };
+
+        if let Some(reference) = obj.get("$ref").and_then(|r| r.as_str()) {
+            return self.reference(reference);
+        }
+        if let Some(value) = obj.get("const") {
+            self.primitives.insert("space");
+            return Ok(format!("{} space", json_literal(value)));
+        }
+        if let Some(values) = obj.get("enum").and_then(|e| e.as_array()) {
+            if values.is_empty() {
+                return Err(schema_error("empty enum"));
+            }
+            let alts: Vec<String> = values.iter().map(json_literal).collect();
+            self.primitives.insert("space");
+            return Ok(format!("({}) space", alts.join(" | ")));
+        }
+        for key in ["anyOf", "oneOf"] {
+            if let Some(options) = obj.get(key).and_then(|o| o.as_array()) {
+                let mut alts = Vec::new();
+                for (i, option) in options.iter().enumerate() {
+                    alts.push(self.rule(option, &format!("{name}-{i}"))?);
+                }
+                return Ok(alts.join(" | "));
+            }
+        }
+        if let Some(all) = obj.get("allOf").and_then(|a| a.as_array()) {
+            return match all.as_slice() {
+                [only] => self.body(only, name),
+                _ => Err(schema_error("allOf with several schemas is not supported")),
+            };
+        }
+
+        match obj.get("type") {
+            Some(Value::Array(types)) => {
+                let mut alts = Vec::new();
+                for ty in types {
+                    let mut single = obj.clone();
+                    single.insert("type".into(), ty.clone());
+                    let hint = format!("{name}-{}", ty.as_str().unwrap_or("type"));
+                    alts.push(self.rule(&Value::Object(single), &hint)?);
+                }
+                Ok(alts.join(" | "))
+            }
+            Some(Value::String(ty)) => self.typed(ty, obj, name),
+            Some(other) => Err(schema_error(format!("invalid type {other}"))),
+            None if obj.contains_key("properties") => self.typed("object", obj, name),
+            None if obj.contains_key("items") || obj.contains_key("prefixItems") => {
+                self.typed("array", obj, name)
+            }
+            None => Ok(self.primitive("value")),
+        }
+    }
+
+    fn typed(
+        &mut self,
+        ty: &str,
+        obj: &serde_json::Map<String, Value>,
+        name: &str,
+    ) -> Result<String> {
+        let count = |key: &str| obj.get(key).and_then(|v| v.as_u64()).map(|v| v as usize);
+        match ty {
+            "object" => match obj.get("properties").and_then(|p| p.as_object()) {
+                Some(props) if !props.is_empty() => self.object(props, obj, name),
+                _ => Ok(self.primitive("object")),
+            },
+            "array" => self.array(obj, name),
+            "string" => {
+                let (min, max) = (count("minLength"), count("maxLength"));
+                if min.is_none() && max.is_none() {
+                    return Ok(self.primitive("string"));
+                }
+                self.primitives.extend(["char", "space"]);
+                let min = min.unwrap_or(0);
+                let max = max.map(|m| m.to_string()).unwrap_or_default();
+                Ok(format!(r#""\"" char{{{min},{max}}} "\"" space"#))
+            }
+            "integer" => Ok(self.primitive("integer")),
+            "number" => Ok(self.primitive("number")),
+            "boolean" => Ok(self.primitive("boolean")),
+            "null" => Ok(self.primitive("null")),
+            other => Err(schema_error(format!("unknown type '{other}'"))),
+        }
+    }
+
+    /// Required properties first, then optional ones that may each be skipped.
+    fn object(
+        &mut self,
+        props: &serde_json::Map<String, Value>,
+        obj: &serde_json::Map<String, Value>,
+        name: &str,
+    ) -> Result<String> {
+        let required: HashSet<&str> = obj
+            .get("required")
+            .and_then(|r| r.as_array())
+            .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+            .unwrap_or_default();
+
+        let mut required_kvs = Vec::new();
+        let mut optional_kvs = Vec::new();
+        for (key, schema) in props {
+            let value = self.rule(schema, &format!("{name}-{key}"))?;
+            let kv = self.reserve(&format!("{name}-{key}-kv"));
+            self.define(
+                &kv,
+                format!(
+                    "{} space \":\" space {value}",
+                    json_literal(&Value::from(key.as_str()))
+                ),
+            );
+            if required.contains(key.as_str()) {
+                required_kvs.push(kv);
+            } else {
+                optional_kvs.push(kv);
+            }
+        }
+
+        // rest_i ::= kv_i ("," space rest_{i+1})? | rest_{i+1}
+        // — any non-empty, ordered subset of the optional properties.
+        let mut rest: Option<String> = None;
+        for kv in optional_kvs.iter().rev() {
+            let rule = self.reserve(&format!("{kv}-rest"));
+            let body = match &rest {
+                Some(next) => format!("{kv} (\",\" space {next})? | {next}"),
+                None => kv.clone(),
+            };
+            self.define(&rule, body);
+            rest = Some(rule);
+        }
+
+        self.primitives.insert("space");
+        let inner = match (required_kvs.is_empty(), rest) {
+            (true, None) => String::new(),
+            (true, Some(rest)) => format!("{rest}?"),
+            (false, rest) => {
+                let mut s = required_kvs.join(" \",\" space ");
+                if let Some(rest) = rest {
+                    s.push_str(&format!(" (\",\" space {rest})?"));
+                }
+                s
+            }
+        };
+        Ok(format!("\"{{\" space {inner} \"}}\" space"))
+    }
+
+    fn array(&mut self, obj: &serde_json::Map<String, Value>, name: &str) -> Result<String> {
+        self.primitives.insert("space");
+        if let Some(prefix) = obj.get("prefixItems").and_then(|p| p.as_array()) {
+            let mut items = Vec::new();
+            for (i, item) in prefix.iter().enumerate() {
+                items.push(self.rule(item, &format!("{name}-{i}"))?);
+            }
+            return Ok(format!(
+                "\"[\" space {} \"]\" space",
+                items.join(" \",\" space ")
+            ));
+        }
+
+        let item = match obj.get("items") {
+            Some(items) => self.rule(items, &format!("{name}-item"))?,
+            None => self.primitive("value"),
+        };
+        let min = obj.get("minItems").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+        let max = obj
+            .get("maxItems")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize);
+        if max.is_some_and(|max| max < min) {
+            return Err(schema_error(format!("{name}: maxItems < minItems")));
+        }
+        let more = |from: usize| match max {
+            Some(max) => format!("(\",\" space {item}){{{},{}}}", from, max - 1),
+            None => format!("(\",\" space {item}){{{from},}}"),
+        };
+        let inner = match (min, max) {
+            (_, Some(0)) => String::new(),
+            (0, _) => format!("({item} {})?", more(0)),
+            (min, _) => format!("{item} {}", more(min - 1)),
+        };
+        Ok(format!("\"[\" space {inner} \"]\" space"))
+    }
+
+    fn reference(&mut self, reference: &str) -> Result<String> {
+        if let Some(name) = self.refs.get(reference) {
+            return Ok(name.clone());
+        }
+        let pointer = reference
+            .strip_prefix('#')
+            .ok_or_else(|| schema_error(format!("only local $ref is supported: {reference}")))?;
+        let target = if pointer.is_empty() {
+            self.root
+        } else {
+            self.root
+                .pointer(pointer)
+                .ok_or_else(|| schema_error(format!("unresolved $ref {reference}")))?
+        };
+        let hint = pointer.rsplit('/').next().unwrap_or("ref");
+        let name = self.reserve(if hint.is_empty() { "ref" } else { hint });
+        self.refs.insert(reference.to_string(), name.clone());
+        let body = self.body(target, &name)?;
+        self.define(&name, body);
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn accepts(grammar: &Grammar, text: &str) -> bool {
+        grammar
+            .start()
+            .accept_str(text)
+            .is_some_and(|s| s.is_complete())
+    }
+
+    #[test]
+    fn test_generic_json() {
+        let g = json_grammar();
+        assert!(accepts(
+            &g,
+            r#"{"a": [1, -2.5e3, "x\"yé"], "b": {"c": null}}"#
+        ));
+        assert!(accepts(&g, "[true, false]"));
+        assert!(!accepts(&g, "{'a': 1}"));
+        assert!(!accepts(&g, r#"{"a": 01}"#));
+        assert!(!accepts(&g, r#"{"a": 1,}"#));
+        assert!(!accepts(&g, "\"bare string\""));
+    }
+
+    #[test]
+    fn test_object_required_and_optional() {
+        let g = schema_to_grammar(&json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "age": {"type": "integer"},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["name"]
+        }))
+        .unwrap();
+        assert!(accepts(&g, r#"{"name": "An"}"#));
+        assert!(accepts(&g, r#"{"name": "An", "age": 30}"#));
+        assert!(accepts(&g, r#"{"name": "An", "tags": ["a", "b"]}"#));
+        assert!(accepts(&g, r#"{"name":"An","age":3,"tags":[]}"#));
+        assert!(!accepts(&g, r#"{"age": 30}"#));
+        assert!(!accepts(&g, r#"{"name": "An", "age": "30"}"#));
+        assert!(!accepts(&g, r#"{"name": "An", "extra": 1}"#));
+    }
+
+    #[test]
+    fn test_all_optional_properties() {
+        let g = schema_to_grammar(&json!({
+            "properties": {"a": {"type": "boolean"}, "b": {"type": "null"}}
+        }))
+        .unwrap();
+        for ok in [
+            "{}",
+            r#"{"a": true}"#,
+            r#"{"b": null}"#,
+            r#"{"a": false, "b": null}"#,
+        ] {
+            assert!(accepts(&g, ok), "{ok}");
+        }
+        assert!(!accepts(&g, r#"{"a": true,}"#));
+        assert!(!accepts(&g, r#"{, "b": null}"#));
+    }
+
+    #[test]
+    fn test_enum_const_and_any_of() {
+        let g = schema_to_grammar(&json!({
+            "type": "object",
+            "properties": {
+                "level": {"enum": ["low", "high", 3]},
+                "kind": {"const": "tool"},
+                "id": {"anyOf": [{"type": "integer"}, {"type": "string", "maxLength": 2}]}
+            },
+            "required": ["level", "kind", "id"]
+        }))
+        .unwrap();
+        assert!(accepts(&g, r#"{"id": 7, "kind": "tool", "level": "low"}"#));
+        assert!(accepts(&g, r#"{"id": "ab", "kind": "tool", "level": 3}"#));
+        assert!(!accepts(&g, r#"{"id": 7, "kind": "tool", "level": "mid"}"#));
+        assert!(!accepts(&g, r#"{"id": 7, "kind": "x", "level": "low"}"#));
+        assert!(!accepts(
+            &g,
+            r#"{"id": "abc", "kind": "tool", "level": "low"}"#
+        ));
+    }
+
+    #[test]
+    fn test_array_bounds_and_type_list() {
+        let g = schema_to_grammar(&json!({
+            "type": "array",
+            "items": {"type": ["number", "null"]},
+            "minItems": 1,
+            "maxItems": 3
+        }))
+        .unwrap();
+        assert!(accepts(&g, "[1]"));
+        assert!(accepts(&g, "[1.5, null, -2]"));
+        assert!(!accepts(&g, "[]"));
+        assert!(!accepts(&g, "[1, 2, 3, 4]"));
+        assert!(!accepts(&g, "[\"x\"]"));
+    }
+
+    #[test]
+    fn test_recursive_ref() {
+        let g = schema_to_grammar(&json!({
+            "$ref": "#/$defs/node",
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "properties": {
+                        "value": {"type": "integer"},
+                        "children": {"type": "array", "items": {"$ref": "#/$defs/node"}}
+                    },
+                    "required": ["value"]
+                }
+            }
+        }))
+        .unwrap();
+        assert!(accepts(
+            &g,
+            r#"{"value": 1, "children": [{"value": 2}, {"value": 3, "children": []}]}"#
+        ));
+        assert!(!accepts(&g, r#"{"value": 1, "children": [{}]}"#));
+    }
+
+    #[test]
+    fn test_unsupported_schemas() {
+        assert!(schema_to_grammar(&json!({"$ref": "http://example.com/s.json"})).is_err());
+        assert!(schema_to_grammar(&json!({"$ref": "#/$defs/missing"})).is_err());
+        assert!(schema_to_grammar(&json!({"allOf": [{}, {}]})).is_err());
+        assert!(schema_to_grammar(&json!({"type": "decimal"})).is_err());
+    }
+}
@@ -1,9 +1,12 @@
-//! KV Cache — both f32 (compatible) and FP16 (memory-optimised) variants.
+//! KV Cache — f32 (compatible), FP16 and Q8_0 (memory-optimised) variants.
 //!
-//! FP16 variant halves memory (88MB → 44MB for typical models).
+//! FP16 variant halves memory (88MB → 44MB for typical models); Q8_0 keeps
+//! one byte per value plus an FP16 scale per 32 values (~27% of f32).
+//! [`KvStorage`] picks one of them at load time from [`KvPrecision`].
 //! Includes KV Cache Persistence (save/load .bckv files)
 //! and Pre-computed RoPE tables for fast positional encoding.
 
+use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
 use std::path::Path;
 
@@ -13,7 +16,7 @@ use std::path::Path;
 pub struct KvCache {
     key_cache: Vec<f32>,
     value_cache: Vec<f32>,
-    _n_layers: usize,
+    n_layers: usize,
     max_seq_len: usize,
     kv_dim: usize,
     pos: usize,
@@ -26,7 +29,7 @@ impl KvCache {
         Self {
             key_cache: vec![0.0; total],
             value_cache: vec![0.0; total],
-            _n_layers: n_layers,
+            n_layers,
             max_seq_len,
             kv_dim,
             pos: 0,
@@ -69,6 +72,27 @@ impl KvCache {
     pub fn memory_usage(&self) -> usize {
         (self.key_cache.len() + self.value_cache.len()) * std::mem::size_of::<f32>()
     }
+
+    /// Copy the first `src.pos()` positions of an FP16 cache into this cache.
+    pub fn restore_from_fp16(&mut self, src: &Fp16KvCache) -> std::io::Result<()> {
+        let n = src.pos;
+        if src.n_layers != self.n_layers || src.kv_dim != self.kv_dim || n > self.max_seq_len {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "KV cache shape does not match the model",
+            ));
+        }
+        let mut buf = vec![0.0f32; n * self.kv_dim];
+        for layer in 0..self.n_layers {
+            let offset = layer * self.max_seq_len * self.kv_dim;
+            src.load_keys(layer, n, &mut buf);
+            self.key_cache[offset..offset + buf.len()].copy_from_slice(&buf);
+            src.load_values(layer, n, &mut buf);
+            self.value_cache[offset..offset + buf.len()].copy_from_slice(&buf);
+        }
+        self.pos = n;
+        Ok(())
+    }
 }
 
 // ── FP16 KV Cache (memory optimised) ──────────────────────
@@ -158,6 +182,29 @@ impl Fp16KvCache {
         }
     }
 
+    /// Snapshot the first `n_tokens` positions of an f32 cache, sized to
+    /// exactly those positions (for persistence).
+    pub fn from_f32(cache: &KvCache, n_tokens: usize) -> Self {
+        let n_tokens = n_tokens.min(cache.max_seq_len);
+        let kv_dim = cache.kv_dim;
+        let mut out = Self {
+            key_cache: Vec::with_capacity(cache.n_layers * n_tokens * kv_dim),
+            value_cache: Vec::with_capacity(cache.n_layers * n_tokens * kv_dim),
+            n_layers: cache.n_layers,
+            max_seq_len: n_tokens,
+            kv_dim,
+            pos: n_tokens,
+        };
+        for layer in 0..cache.n_layers {
+            let keys = cache.keys(layer, n_tokens);
+            let values = cache.values(layer, n_tokens);
+            out.key_cache.extend(keys.iter().map(|&v| fp32_to_fp16(v)));
+            out.value_cache
+                .extend(values.iter().map(|&v| fp32_to_fp16(v)));
+        }
+        out
+    }
+
     /// Store a key vector (f32 → fp16) at the given position.
     pub fn store_key(&mut self, layer: usize, pos: usize, data: &[f32]) {
         let offset = (layer * self.max_seq_len + pos) * self.kv_dim;
@@ -290,6 +337,394 @@ impl Fp16KvCache {
     }
 }
 
+// ── Q8_0 KV Cache (memory optimised) ──────────────────────
+
+/// Values per Q8_0 block (one FP16 scale each), as in GGML's Q8_0.
+pub const Q8_BLOCK: usize = 32;
+
+/// Q8_0 KV Cache — int8 values with a per-block FP16 scale.
+pub struct Q8KvCache {
+    /// Quantized keys: [n_layers x max_seq_len x kv_dim]
+    key_quants: Vec<i8>,
+    /// Key block scales (fp16): [n_layers x max_seq_len x blocks_per_row]
+    key_scales: Vec<u16>,
+    value_quants: Vec<i8>,
+    value_scales: Vec<u16>,
+    n_layers: usize,
+    max_seq_len: usize,
+    kv_dim: usize,
+    blocks_per_row: usize,
+    pos: usize,
+}
+
+impl Q8KvCache {
+    /// Create a new Q8_0 KV cache.
+    pub fn new(n_layers: usize, max_seq_len: usize, n_kv_heads: usize, head_dim: usize) -> Self {
+        let kv_dim = n_kv_heads * head_dim;
+        let blocks_per_row = kv_dim.div_ceil(Q8_BLOCK);
+        let rows = n_layers * max_seq_len;
+        Self {
+            key_quants: vec![0; rows * kv_dim],
+            key_scales: vec![0; rows * blocks_per_row],
+            value_quants: vec![0; rows * kv_dim],
+            value_scales: vec![0; rows * blocks_per_row],
+            n_layers,
+            max_seq_len,
+            kv_dim,
+            blocks_per_row,
+            pos: 0,
+        }
+    }
+
+    /// Quantize and store a key vector at the given position.
+    pub fn store_key(&mut self, layer: usize, pos: usize, data: &[f32]) {
+        let row = layer * self.max_seq_len + pos;
+        quantize_row(
+            &data[..self.kv_dim],
+            &mut self.key_quants[row * self.kv_dim..(row + 1) * self.kv_dim],
+            &mut self.key_scales[row * self.blocks_per_row..(row + 1) * self.blocks_per_row],
+        );
+    }
+
+    /// Quantize and store a value vector at the given position.
+    pub fn store_value(&mut self, layer: usize, pos: usize, data: &[f32]) {
+        let row = layer * self.max_seq_len + pos;
+        quantize_row(
+            &data[..self.kv_dim],
+            &mut self.value_quants[row * self.kv_dim..(row + 1) * self.kv_dim],
+            &mut self.value_scales[row * self.blocks_per_row..(row + 1) * self.blocks_per_row],
+        );
+    }
+
+    /// Load key vectors (q8 → f32) for a layer up to seq_len.
+    pub fn load_keys(&self, layer: usize, seq_len: usize, output: &mut [f32]) {
+        let row = layer * self.max_seq_len;
+        dequantize_rows(
+            &self.key_quants[row * self.kv_dim..(row + seq_len) * self.kv_dim],
+            &self.key_scales[row * self.blocks_per_row..],
+            &mut output[..seq_len * self.kv_dim],
+            self.kv_dim,
+        );
+    }
+
+    /// Load value vectors (q8 → f32) for a layer up to seq_len.
+    pub fn load_values(&self, layer: usize, seq_len: usize, output: &mut [f32]) {
+        let row = layer * self.max_seq_len;
+        dequantize_rows(
+            &self.value_quants[row * self.kv_dim..(row + seq_len) * self.kv_dim],
+            &self.value_scales[row * self.blocks_per_row..],
+            &mut output[..seq_len * self.kv_dim],
+            self.kv_dim,
+        );
+    }
+
+    pub fn advance(&mut self) {
+        self.pos += 1;
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn reset(&mut self) {
+        self.key_quants.fill(0);
+        self.key_scales.fill(0);
+        self.value_quants.fill(0);
+        self.value_scales.fill(0);
+        self.pos = 0;
+    }
+
+    /// Memory usage in bytes (~27% of the f32 cache).
+    pub fn memory_usage(&self) -> usize {
+        self.key_quants.len()
+            + self.value_quants.len()
+            + (self.key_scales.len() + self.value_scales.len()) * std::mem::size_of::<u16>()
+    }
+}
+
+/// Quantize one row into Q8_0 blocks (the last block may be short).
+fn quantize_row(data: &[f32], quants: &mut [i8], scales: &mut [u16]) {
+    for ((block, q), scale) in data
+        .chunks(Q8_BLOCK)
+        .zip(quants.chunks_mut(Q8_BLOCK))
+        .zip(scales.iter_mut())
+    {
+        let amax = block.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+        let d = amax / 127.0;
+        let id = if d > 0.0 { 1.0 / d } else { 0.0 };
+        for (q, &v) in q.iter_mut().zip(block) {
+            *q = (v * id).round() as i8;
+        }
+        *scale = fp32_to_fp16(d);
+    }
+}
+
+/// Dequantize consecutive `kv_dim`-wide Q8_0 rows.
+fn dequantize_rows(quants: &[i8], scales: &[u16], output: &mut [f32], kv_dim: usize) {
+    let blocks_per_row = kv_dim.div_ceil(Q8_BLOCK);
+    for (r, (q_row, out_row)) in quants
+        .chunks_exact(kv_dim)
+        .zip(output.chunks_exact_mut(kv_dim))
+        .enumerate()
+    {
+        let row_scales = &scales[r * blocks_per_row..(r + 1) * blocks_per_row];
+        for ((q, out), &scale) in q_row
+            .chunks(Q8_BLOCK)
+            .zip(out_row.chunks_mut(Q8_BLOCK))
+            .zip(row_scales)
+        {
+            let d = fp16_to_fp32(scale);
+            for (o, &v) in out.iter_mut().zip(q) {
+                *o = v as f32 * d;
+            }
+        }
+    }
+}
+
+// ── Precision selection ──────────────────────
+
+/// Storage precision of the model's KV cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KvPrecision {
+    #[default]
+    F32,
+    F16,
+    Q8_0,
+}
+
+impl KvPrecision {
+    /// Parse a config value (`f32`, `f16`, `q8_0`; case-insensitive).
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "f32" => Some(Self::F32),
+            "f16" => Some(Self::F16),
+            "q8_0" | "q8" => Some(Self::Q8_0),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::F32 => "f32",
+            Self::F16 => "f16",
+            Self::Q8_0 => "q8_0",
+        }
+    }
+    /// Bytes [`KvStorage::new`] allocates for these dimensions.
+    pub fn cache_bytes(
+        &self,
+        n_layers: usize,
+        max_seq_len: usize,
+        n_kv_heads: usize,
+        head_dim: usize,
+    ) -> u64 {
+        let kv_dim = n_kv_heads * head_dim;
+        let row = match self {
+            Self::F32 => kv_dim * 4,
+            Self::F16 => kv_dim * 2,
+            Self::Q8_0 => kv_dim + kv_dim.div_ceil(Q8_BLOCK) * 2,
+        };
+        (2 * n_layers * max_seq_len * row) as u64
+    }
+}
+
+/// The model's KV cache, in the precision chosen at load time.
+pub enum KvStorage {
+    F32(KvCache),
+    F16(Fp16KvCache),
+    Q8_0(Q8KvCache),
+}
+
+/// Decode buffers for reading a reduced-precision layer as f32.
+#[derive(Default)]
+pub struct KvScratch {
+    keys: Vec<f32>,
+    values: Vec<f32>,
+}
+
+impl KvStorage {
+    pub fn new(
+        precision: KvPrecision,
+        n_layers: usize,
+        max_seq_len: usize,
+        n_kv_heads: usize,
+        head_dim: usize,
+    ) -> Self {
+        match precision {
+            KvPrecision::F32 => {
+                Self::F32(KvCache::new(n_layers, max_seq_len, n_kv_heads, head_dim))
+            }
+            KvPrecision::F16 => Self::F16(Fp16KvCache::new(
+                n_layers,
+                max_seq_len,
+                n_kv_heads,
+                head_dim,
+            )),
+            KvPrecision::Q8_0 => {
+                Self::Q8_0(Q8KvCache::new(n_layers, max_seq_len, n_kv_heads, head_dim))
+            }
+        }
+    }
+
+    pub fn precision(&self) -> KvPrecision {
+        match self {
+            Self::F32(_) => KvPrecision::F32,
+            Self::F16(_) => KvPrecision::F16,
+            Self::Q8_0(_) => KvPrecision::Q8_0,
+        }
+    }
+
+    fn shape(&self) -> (usize, usize, usize) {
+        match self {
+            Self::F32(c) => (c.n_layers, c.max_seq_len, c.kv_dim),
+            Self::F16(c) => (c.n_layers, c.max_seq_len, c.kv_dim),
+            Self::Q8_0(c) => (c.n_layers, c.max_seq_len, c.kv_dim),
+        }
+    }
+
+    /// Store the key and value vectors of one position.
+    pub fn store(&mut self, layer: usize, pos: usize, key: &[f32], value: &[f32]) {
+        match self {
+            Self::F32(c) => {
+                c.key_at_mut(layer, pos).copy_from_slice(key);
+                c.value_at_mut(layer, pos).copy_from_slice(value);
+            }
+            Self::F16(c) => {
+                c.store_key(layer, pos, key);
+                c.store_value(layer, pos, value);
+            }
+            Self::Q8_0(c) => {
+                c.store_key(layer, pos, key);
+                c.store_value(layer, pos, value);
+            }
+        }
+    }
+
+    /// Keys and values of the first `seq_len` positions of a layer as f32,
+    /// `[seq_len x kv_dim]` each. The f32 cache is borrowed directly; other
+    /// precisions are decoded into `scratch`.
+    pub fn layer<'a>(
+        &'a self,
+        layer: usize,
+        seq_len: usize,
+        scratch: &'a mut KvScratch,
+    ) -> (&'a [f32], &'a [f32]) {
+        let kv_dim = self.shape().2;
+        let n = seq_len * kv_dim;
+        if !matches!(self, Self::F32(_)) {
+            scratch.keys.resize(n, 0.0);
+            scratch.values.resize(n, 0.0);
+        }
+        match self {
+            Self::F32(c) => (c.keys(layer, seq_len), c.values(layer, seq_len)),
+            Self::F16(c) => {
+                c.load_keys(layer, seq_len, &mut scratch.keys);
+                c.load_values(layer, seq_len, &mut scratch.values);
+                (&scratch.keys[..n], &scratch.values[..n])
+            }
+            Self::Q8_0(c) => {
+                c.load_keys(layer, seq_len, &mut scratch.keys);
+                c.load_values(layer, seq_len, &mut scratch.values);
+                (&scratch.keys[..n], &scratch.values[..n])
+            }
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            Self::F32(c) => c.memory_usage(),
+            Self::F16(c) => c.memory_usage(),
+            Self::Q8_0(c) => c.memory_usage(),
+        }
+    }
+
+    /// Drop positions `n_keep..n_keep + n_discard` of the first `n_past`,
+    /// moving the later ones down by `n_discard`. Keys carry their RoPE
+    /// rotation, so each moved key goes through `rerotate` (which must move
+    /// it back by `n_discard` positions).
+    pub fn shift(
+        &mut self,
+        n_keep: usize,
+        n_discard: usize,
+        n_past: usize,
+        mut rerotate: impl FnMut(&mut [f32]),
+    ) {
+        let (n_layers, _, kv_dim) = self.shape();
+        let first = n_keep + n_discard;
+        if n_discard == 0 || first >= n_past {
+            return;
+        }
+        let mut scratch = KvScratch::default();
+        for l in 0..n_layers {
+            let (keys, values) = self.layer(l, n_past, &mut scratch);
+            let mut keys = keys[first * kv_dim..].to_vec();
+            let values = values[first * kv_dim..].to_vec();
+            for (i, (k, v)) in keys
+                .chunks_exact_mut(kv_dim)
+                .zip(values.chunks_exact(kv_dim))
+                .enumerate()
+            {
+                rerotate(k);
+                self.store(l, n_keep + i, k, v);
+            }
+        }
+    }
+
+    /// FP16 snapshot of the first `n_tokens` positions (for persistence).
+    pub fn snapshot(&self, n_tokens: usize) -> Fp16KvCache {
+        if let Self::F32(c) = self {
+            return Fp16KvCache::from_f32(c, n_tokens);
+        }
+        let (n_layers, max_seq_len, kv_dim) = self.shape();
+        let n_tokens = n_tokens.min(max_seq_len);
+        let mut out = Fp16KvCache::new(n_layers, n_tokens, 1, kv_dim);
+        let mut scratch = KvScratch::default();
+        for l in 0..n_layers {
+            let (keys, values) = self.layer(l, n_tokens, &mut scratch);
+            for (pos, (k, v)) in keys
+                .chunks_exact(kv_dim)
+                .zip(values.chunks_exact(kv_dim))
+                .enumerate()
+            {
+                out.store_key(l, pos, k);
+                out.store_value(l, pos, v);
+            }
+        }
+        out.pos = n_tokens;
+        out
+    }
+
+    /// Copy the positions held by an FP16 snapshot into this cache.
+    pub fn restore(&mut self, src: &Fp16KvCache) -> std::io::Result<()> {
+        if let Self::F32(c) = self {
+            return c.restore_from_fp16(src);
+        }
+        let (n_layers, max_seq_len, kv_dim) = self.shape();
+        let n = src.pos;
+        if src.n_layers != n_layers || src.kv_dim != kv_dim || n > max_seq_len {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "KV cache shape does not match the model",
+            ));
+        }
+        let mut keys = vec![0.0f32; n * kv_dim];
+        let mut values = vec![0.0f32; n * kv_dim];
+        for l in 0..n_layers {
+            src.load_keys(l, n, &mut keys);
+            src.load_values(l, n, &mut values);
+            for (pos, (k, v)) in keys
+                .chunks_exact(kv_dim)
+                .zip(values.chunks_exact(kv_dim))
+                .enumerate()
+            {
+                self.store(l, pos, k, v);
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Pre-computed RoPE tables — sin/cos lookup instead of computing per-token.
 pub struct RopeTable {
     cos_table: Vec<f32>,
@@ -301,17 +736,29 @@ pub struct RopeTable {
 impl RopeTable {
     /// Pre-compute all sin/cos values at initialization.
     pub fn new(max_seq_len: usize, head_dim: usize, rope_theta: f32) -> Self {
-        let half_dim = head_dim / 2;
+        let rope = crate::rope::Rope::new(
+            head_dim,
+            rope_theta,
+            crate::rope::RopeScaling::None,
+            crate::rope::RopeStyle::Neox,
+        );
+        Self::from_rope(max_seq_len, &rope)
+    }
+
+    /// Tables for `rope`'s (possibly scaled) frequencies; YaRN's magnitude
+    /// factor is folded into the values.
+    pub fn from_rope(max_seq_len: usize, rope: &crate::rope::Rope) -> Self {
+        let inv_freq = rope.inv_freq();
+        let half_dim = inv_freq.len();
         let total = max_seq_len * half_dim;
         let mut cos_table = vec![0.0f32; total];
         let mut sin_table = vec![0.0f32; total];
 
         for pos in 0..max_seq_len {
-            for i in 0..half_dim {
-                let freq = 1.0 / rope_theta.powf(2.0 * i as f32 / head_dim as f32);
+            for (i, &freq) in inv_freq.iter().enumerate() {
                 let angle = pos as f32 * freq;
-                cos_table[pos * half_dim + i] = angle.cos();
-                sin_table[pos * half_dim + i] = angle.sin();
+                cos_table[pos * half_dim + i] = angle.cos() * rope.mscale();
+                sin_table[pos * half_dim + i] = angle.sin() * rope.mscale();
             }
         }
 
@@ -423,6 +870,124 @@ mod tests {
         let _ = std::fs::remove_file(&path);
     }
 
+    #[test]
+    fn test_fp16_snapshot_restore() {
+        let mut cache = KvCache::new(2, 8, 1, 4);
+        for layer in 0..2 {
+            for pos in 0..3 {
+                let v = (layer * 10 + pos) as f32;
+                cache.key_at_mut(layer, pos).fill(v);
+                cache.value_at_mut(layer, pos).fill(-v);
+            }
+        }
+        let snapshot = Fp16KvCache::from_f32(&cache, 3);
+        assert_eq!(snapshot.pos(), 3);
+        assert_eq!(snapshot.memory_usage(), 2 * 2 * 3 * 4 * 2);
+
+        let mut restored = KvCache::new(2, 8, 1, 4);
+        restored.restore_from_fp16(&snapshot).unwrap();
+        assert_eq!(restored.keys(1, 3), cache.keys(1, 3));
+        assert_eq!(restored.values(0, 3), cache.values(0, 3));
+
+        let mut wrong_shape = KvCache::new(2, 8, 2, 4);
+        assert!(wrong_shape.restore_from_fp16(&snapshot).is_err());
+        let mut too_short = KvCache::new(2, 2, 1, 4);
+        assert!(too_short.restore_from_fp16(&snapshot).is_err());
+    }
+
+    #[test]
+    fn test_q8_kv_cache_store_load() {
+        // 40 values: one full block and a short one
+        let mut cache = Q8KvCache::new(2, 4, 1, 40);
+        let key: Vec<f32> = (0..40).map(|i| (i as f32 - 20.0) / 7.0).collect();
+        cache.store_key(1, 2, &key);
+        cache.store_value(1, 2, &[0.0; 40]);
+
+        let mut output = vec![1.0f32; 3 * 40];
+        cache.load_keys(1, 3, &mut output);
+        for (i, (&v, &got)) in key.iter().zip(&output[80..]).enumerate() {
+            assert!((v - got).abs() < 0.03, "key[{i}]: expected {v}, got {got}");
+        }
+        assert!(output[..80].iter().all(|&v| v == 0.0));
+        cache.load_values(1, 3, &mut output);
+        assert!(output.iter().all(|&v| v == 0.0));
+
+        let q8 = Q8KvCache::new(32, 2048, 8, 128).memory_usage();
+        let f32_size = 32 * 2048 * 8 * 128 * 4 * 2;
+        assert_eq!(q8 * 64, f32_size * 17);
+    }
+
+    #[test]
+    fn test_kv_storage_precisions() {
+        let key: Vec<f32> = (0..8).map(|i| i as f32 * 0.25).collect();
+        let value: Vec<f32> = key.iter().map(|v| -v).collect();
+        for precision in [KvPrecision::F32, KvPrecision::F16, KvPrecision::Q8_0] {
+            let mut cache = KvStorage::new(precision, 2, 4, 2, 4);
+            assert_eq!(cache.precision(), precision);
+            cache.store(1, 0, &key, &value);
+            cache.store(1, 1, &value, &key);
+
+            let mut scratch = KvScratch::default();
+            let (keys, values) = cache.layer(1, 2, &mut scratch);
+            assert_eq!(keys.len(), 16);
+            for (a, b) in keys.iter().zip(key.iter().chain(&value)) {
+                assert!((a - b).abs() < 0.02, "{precision:?}: {a} vs {b}");
+            }
+            assert!((values[1] + 0.25).abs() < 0.02);
+
+            let snapshot = cache.snapshot(2);
+            let mut restored = KvStorage::new(precision, 2, 4, 2, 4);
+            restored.restore(&snapshot).unwrap();
+            let mut scratch2 = KvScratch::default();
+            let (restored_keys, _) = restored.layer(1, 2, &mut scratch2);
+            for (a, b) in restored_keys.iter().zip(keys) {
+                assert!((a - b).abs() < 0.02, "{precision:?}: {a} vs {b}");
+            }
+        }
+        for precision in [KvPrecision::F32, KvPrecision::F16, KvPrecision::Q8_0] {
+            let cache = KvStorage::new(precision, 3, 5, 2, 20);
+            assert_eq!(
+                precision.cache_bytes(3, 5, 2, 20),
+                cache.memory_usage() as u64
+            );
+        }
+        assert_eq!(KvPrecision::parse("Q8_0"), Some(KvPrecision::Q8_0));
+        assert_eq!(KvPrecision::parse("f64"), None);
+    }
+
+    #[test]
+    fn test_kv_storage_shift_rerotates_keys() {
+        use crate::rope::{Rope, RopeScaling, RopeStyle};
+        let rope = Rope::new(4, 10000.0, RopeScaling::None, RopeStyle::Normal);
+        let raw =
+            |pos: usize| -> Vec<f32> { (0..8).map(|i| (pos * 8 + i) as f32 * 0.05).collect() };
+        for precision in [KvPrecision::F32, KvPrecision::F16, KvPrecision::Q8_0] {
+            let mut cache = KvStorage::new(precision, 2, 10, 2, 4);
+            for layer in 0..2 {
+                for pos in 0..10 {
+                    let mut key = raw(pos);
+                    rope.apply(&mut key, pos, 2, 4);
+                    cache.store(layer, pos, &key, &raw(pos));
+                }
+            }
+            // Keep 2, drop 4, move 6..10 down to 2..6
+            cache.shift(2, 4, 10, |k| rope.shift(k, -4, 2, 4));
+
+            let mut scratch = KvScratch::default();
+            let (keys, values) = cache.layer(1, 6, &mut scratch);
+            for (new_pos, old_pos) in [0, 1, 6, 7, 8, 9].into_iter().enumerate() {
+                let mut expected = raw(old_pos);
+                rope.apply(&mut expected, new_pos, 2, 4);
+                for (a, b) in keys[new_pos * 8..][..8].iter().zip(&expected) {
+                    assert!((a - b).abs() < 0.03, "{precision:?}: {a} vs {b}");
+                }
+                for (a, b) in values[new_pos * 8..][..8].iter().zip(&raw(old_pos)) {
+                    assert!((a - b).abs() < 0.03, "{precision:?}: {a} vs {b}");
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_rope_table_position_0() {
         let table = RopeTable::new(16, 4, 10000.0);
@@ -4,24 +4,49 @@
 //! Runs LLaMA-architecture models in GGUF format with mmap, SIMD, and quantization.
 
 pub mod attention;
+pub mod bench;
+pub mod chat_template;
+pub mod embedding;
+pub mod encoder;
 pub mod forward;
+pub mod gbnf;
 pub mod gguf;
+pub mod gguf_writer;
 pub mod grammar;
+pub mod inspect;
+pub mod jinja;
+pub mod json_schema;
 pub mod kv_cache;
 pub mod llamacpp;
+pub mod lora;
 pub mod mmap;
 pub mod model;
+pub mod pool;
+pub mod pre_tokenizer;
+pub mod qmatmul;
 pub mod quant;
+pub mod quantize;
 pub mod rope;
 pub mod sampler;
+pub mod session_cache;
 pub mod simd;
+pub mod speculative;
+pub mod stream;
 pub mod tensor;
+#[cfg(test)]
+mod test_model;
 pub mod thread_pool;
 pub mod tokenizer;
+pub mod tool_calls;
+pub mod worker;
 
 use bizclaw_core::error::{BizClawError, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
+use stream::Utf8StreamDecoder;
+pub use stream::{CancelToken, GenerationStats, StopReason};
 
 /// Brain engine configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,9 +54,78 @@ pub struct BrainConfig {
     pub threads: u32,
     pub max_tokens: u32,
     pub context_length: u32,
+    /// Prompt tokens processed per batched forward pass during prefill.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: u32,
     pub temperature: f32,
     pub top_p: f32,
+    #[serde(default = "default_top_k")]
+    pub top_k: u32,
+    /// Drop tokens less likely than `min_p` times the best one (0 disables).
+    #[serde(default)]
+    pub min_p: f32,
+    /// Locally typical sampling mass (1.0 disables).
+    #[serde(default = "default_typical_p")]
+    pub typical_p: f32,
+    #[serde(default = "default_repeat_penalty")]
+    pub repeat_penalty: f32,
+    #[serde(default)]
+    pub presence_penalty: f32,
+    #[serde(default)]
+    pub frequency_penalty: f32,
+    /// Sample with Mirostat v2 instead of top-k/typical/top-p/min-p.
+    #[serde(default)]
+    pub mirostat: bool,
+    /// Mirostat target surprise (bits per token).
+    #[serde(default = "default_mirostat_tau")]
+    pub mirostat_tau: f32,
+    /// Mirostat learning rate.
+    #[serde(default = "default_mirostat_eta")]
+    pub mirostat_eta: f32,
+    #[serde(default)]
+    pub logit_bias: HashMap<u32, f32>,
+    #[serde(default)]
+    pub banned_tokens: Vec<u32>,
+    /// Seed for reproducible sampling (each generation restarts from it).
+    #[serde(default)]
+    pub seed: Option<u64>,
     pub json_mode: bool,
+    /// Directory for persisted per-session KV caches (`None` disables).
+    #[serde(default)]
+    pub session_cache_dir: Option<PathBuf>,
+    /// Disk budget for persisted session caches, in MB.
+    #[serde(default = "default_session_cache_mb")]
+    pub session_cache_mb: u64,
+    /// Storage precision of the KV cache.
+    #[serde(default)]
+    pub kv_precision: kv_cache::KvPrecision,
+    /// Small GGUF with the same vocabulary, used for speculative decoding.
+    #[serde(default)]
+    pub draft_model_path: Option<PathBuf>,
+    /// Tokens the draft model proposes per verification pass (0 disables).
+    #[serde(default = "default_draft_tokens")]
+    pub draft_tokens: u32,
+    /// GGUF embedding model for [`BrainEngine::embed`]; the loaded chat
+    /// model is used when unset.
+    #[serde(default)]
+    pub embedding_model_path: Option<PathBuf>,
+    /// L2-normalize embeddings (GGUF has no standard key for this).
+    #[serde(default = "default_embedding_normalize")]
+    pub embedding_normalize: bool,
+    /// GGUF LoRA adapter applied when a model loads.
+    #[serde(default)]
+    pub lora_path: Option<PathBuf>,
+    /// Strength of the LoRA update (1.0 = as trained).
+    #[serde(default = "default_lora_scale")]
+    pub lora_scale: f32,
+    /// When generation fills the context, drop the older half of it (after
+    /// `context_keep`) and keep going instead of stopping.
+    #[serde(default = "default_context_shift")]
+    pub context_shift: bool,
+    /// Tokens at the start of the context that a shift never drops (the
+    /// system prompt, say). BOS is always kept.
+    #[serde(default)]
+    pub context_keep: u32,
 }
 
 impl Default for BrainConfig {
@@ -40,18 +134,125 @@ impl Default for BrainConfig {
             threads: 4,
             max_tokens: 256,
             context_length: 2048,
+            batch_size: default_batch_size(),
             temperature: 0.7,
             top_p: 0.9,
+            top_k: default_top_k(),
+            min_p: 0.0,
+            typical_p: default_typical_p(),
+            repeat_penalty: default_repeat_penalty(),
+            presence_penalty: 0.0,
+            frequency_penalty: 0.0,
+            mirostat: false,
+            mirostat_tau: default_mirostat_tau(),
+            mirostat_eta: default_mirostat_eta(),
+            logit_bias: HashMap::new(),
+            banned_tokens: Vec::new(),
+            seed: None,
             json_mode: false,
+            session_cache_dir: None,
+            session_cache_mb: default_session_cache_mb(),
+            kv_precision: kv_cache::KvPrecision::default(),
+            draft_model_path: None,
+            draft_tokens: default_draft_tokens(),
+            embedding_model_path: None,
+            embedding_normalize: default_embedding_normalize(),
+            lora_path: None,
+            lora_scale: default_lora_scale(),
+            context_shift: default_context_shift(),
+            context_keep: 0,
         }
     }
 }
 
+impl BrainConfig {
+    /// Sampler settings described by this config.
+    pub fn sampler_config(&self) -> sampler::SamplerConfig {
+        sampler::SamplerConfig {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            min_p: self.min_p,
+            typical_p: self.typical_p,
+            repeat_penalty: self.repeat_penalty,
+            repeat_last_n: 64,
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+            mirostat: self.mirostat,
+            mirostat_tau: self.mirostat_tau,
+            mirostat_eta: self.mirostat_eta,
+            logit_bias: self.logit_bias.clone(),
+            banned_tokens: self.banned_tokens.clone(),
+            seed: self.seed,
+        }
+    }
+}
+
+fn default_batch_size() -> u32 {
+    64
+}
+
+fn default_top_k() -> u32 {
+    40
+}
+
+fn default_typical_p() -> f32 {
+    1.0
+}
+
+fn default_repeat_penalty() -> f32 {
+    1.1
+}
+
+fn default_mirostat_tau() -> f32 {
+    5.0
+}
+
+fn default_mirostat_eta() -> f32 {
+    0.1
+}
+
+fn default_session_cache_mb() -> u64 {
+    512
+}
+
+fn default_draft_tokens() -> u32 {
+    4
+}
+
+fn default_embedding_normalize() -> bool {
+    true
+}
+
+fn default_lora_scale() -> f32 {
+    1.0
+}
+
+fn default_context_shift() -> bool {
+    true
+}
+
+/// One assistant turn: the reply text plus any tool calls parsed from it.
+#[derive(Debug, Clone, Default)]
+pub struct ChatReply {
+    pub content: String,
+    pub tool_calls: Vec<bizclaw_core::types::ToolCall>,
+    pub stats: GenerationStats,
+}
+
 /// The main brain engine for local LLM inference.
 pub struct BrainEngine {
     config: BrainConfig,
     /// Loaded model (mmap)
     model: Option<LoadedModel>,
+    /// Persisted per-session KV caches
+    sessions: Option<session_cache::SessionStore>,
+    /// Session the next generations belong to
+    session: Option<String>,
+    /// Draft model for speculative decoding
+    draft: Option<speculative::DraftModel>,
+    /// Model used for embeddings, loaded on first use
+    embedder: Option<embedding::EmbeddingModel>,
 }
 
 /// A loaded model ready for inference.
@@ -65,9 +266,25 @@ struct LoadedModel {
     /// BPE tokenizer
     tokenizer: tokenizer::BpeTokenizer,
     /// KV cache for generation
-    kv_cache: kv_cache::KvCache,
+    kv_cache: kv_cache::KvStorage,
     /// Sampler
     sampler: sampler::Sampler,
+    /// Chat prompt template
+    chat_template: chat_template::ChatTemplate,
+    /// Tokens that end generation (EOS plus end-of-turn markers)
+    stop_ids: Vec<u32>,
+    /// Output bytes of every token, for grammar masking
+    token_pieces: Vec<Vec<u8>>,
+    /// Tokens whose keys/values are in `kv_cache`, from position 0
+    cached_tokens: Vec<u32>,
+    /// Session whose conversation `cached_tokens` came from
+    cache_session: Option<String>,
+    /// Identifies the model file (and adapter) for persisted session caches
+    model_key: String,
+    /// `model_key` of the base weights alone
+    base_key: String,
+    /// LoRA adapters loaded against this model, kept for cheap switching
+    adapters: HashMap<PathBuf, std::sync::Arc<lora::LoraAdapter>>,
     /// Model file path
     path: PathBuf,
 }
@@ -75,41 +292,68 @@ struct LoadedModel {
 impl BrainEngine {
     /// Create a new brain engine (model not yet loaded).
     pub fn new(config: BrainConfig) -> Self {
+        let sessions = config.session_cache_dir.as_ref().map(|dir| {
+            session_cache::SessionStore::new(dir, config.session_cache_mb * 1024 * 1024)
+        });
         Self {
             config,
             model: None,
+            sessions,
+            session: None,
+            draft: None,
+            embedder: None,
         }
     }
 
     /// Load a model from a GGUF file.
     pub fn load(model_path: &Path) -> Result<Self> {
-        let config = BrainConfig::default();
-        let mut engine = Self {
-            config,
-            model: None,
-        };
+        let mut engine = Self::new(BrainConfig::default());
         engine.load_model(model_path)?;
         Ok(engine)
     }
 
+    /// Replace the sampler settings of the loaded model, e.g. with per-request
+    /// overrides. Loading a model starts from [`BrainConfig::sampler_config`].
+    pub fn set_sampler(&mut self, config: sampler::SamplerConfig) {
+        if let Some(model) = &mut self.model {
+            model.sampler = sampler::Sampler::new(config);
+        }
+    }
+
+    /// Attach following generations to a chat session. Its KV cache is saved
+    /// after every generation and restored from disk whenever it covers more
+    /// of the prompt than the cache in memory.
+    pub fn set_session(&mut self, session: Option<&str>) {
+        self.session = session.map(str::to_string);
+    }
+
     /// Load a GGUF model into the engine.
     pub fn load_model(&mut self, model_path: &Path) -> Result<()> {
         tracing::info!("Loading model from: {}", model_path.display());
 
         let mmap_model = mmap::MmapModel::load(model_path)?;
-        let params = model::ModelParams::from_gguf(&mmap_model.gguf);
+        let params = model::ModelParams::from_gguf(&mmap_model.gguf)?;
+        if params.arch.is_encoder() {
+            return Err(BizClawError::Brain(format!(
+                "{} is an embedding model ({:?}); load it with load_embedding_model",
+                model_path.display(),
+                params.arch
+            )));
+        }
 
         tracing::info!(
-            "Model params: dim={}, layers={}, heads={}, kv_heads={}, vocab={}",
+            "Model params: arch={:?}, dim={}, layers={}, heads={}, kv_heads={}, vocab={}, rope scaling={:?}",
+            params.arch,
             params.dim,
             params.n_layers,
             params.n_heads,
             params.n_kv_heads,
-            params.vocab_size
+            params.vocab_size,
+            params.rope_scaling
         );
 
         // Build weight index
-        let weights = forward::TransformerWeights::from_gguf(&mmap_model, &params);
+        let weights = forward::TransformerWeights::from_gguf(&mmap_model, &params)?;
         tracing::info!(
             "Weights mapped: embd={}, output={}, layers={}",
             weights.token_embd.is_some(),
@@ -127,25 +371,57 @@ impl BrainEngine {
         tracing::info!("Tokenizer loaded: vocab_size={}", tokenizer.vocab_size());
 
         // Create KV cache
-        let kv_cache = kv_cache::KvCache::new(
+        let kv_cache = kv_cache::KvStorage::new(
+            self.config.kv_precision,
             params.n_layers as usize,
             params.max_seq_len as usize,
             params.n_kv_heads as usize,
             params.head_dim as usize,
         );
         tracing::info!(
-            "KV cache: {:.1} MB",
-            kv_cache.memory_usage() as f64 / 1024.0 / 1024.0
+            "KV cache: {:.1} MB ({})",
+            kv_cache.memory_usage() as f64 / 1024.0 / 1024.0,
+            self.config.kv_precision.as_str()
         );
 
         // Create sampler
-        let sampler = sampler::Sampler::new(sampler::SamplerConfig {
-            temperature: self.config.temperature,
-            top_p: self.config.top_p,
-            top_k: 40,
-            repeat_penalty: 1.1,
-            repeat_last_n: 64,
-        });
+        let sampler = sampler::Sampler::new(self.config.sampler_config());
+
+        // Chat template + end-of-turn tokens
+        let chat_template = chat_template::ChatTemplate::from_gguf(&mmap_model.gguf, &tokenizer);
+        let mut stop_ids = vec![tokenizer.eos_id];
+        let eot_id = mmap_model
+            .gguf
+            .metadata
+            .get("tokenizer.ggml.eot_token_id")
+            .and_then(|v| v.as_u32());
+        stop_ids.extend(eot_id);
+        stop_ids.extend(
+            chat_template
+                .format()
+                .stop_tokens()
+                .iter()
+                .filter_map(|t| tokenizer.token_id(t)),
+        );
+        stop_ids.dedup();
+        tracing::info!(
+            "Chat template: {:?}{}",
+            chat_template.format(),
+            if chat_template.has_jinja() {
+                " (GGUF jinja)"
+            } else {
+                ""
+            }
+        );
+
+        let token_pieces = (0..tokenizer.vocab_size() as u32)
+            .map(|id| tokenizer.token_bytes(id))
+            .collect();
+        let model_key = format!(
+            "{}:{}",
+            model_path.file_name().unwrap_or_default().to_string_lossy(),
+            mmap_model.file_size()
+        );
 
         self.model = Some(LoadedModel {
             mmap_model,
@@ -154,13 +430,120 @@ impl BrainEngine {
             tokenizer,
             kv_cache,
             sampler,
+            chat_template,
+            stop_ids,
+            token_pieces,
+            cached_tokens: Vec::new(),
+            cache_session: None,
+            base_key: model_key.clone(),
+            model_key,
+            adapters: HashMap::new(),
             path: model_path.to_path_buf(),
         });
 
         tracing::info!("✅ Model loaded successfully: {}", model_path.display());
+
+        if let Some(lora_path) = self.config.lora_path.clone()
+            && let Err(e) = self.set_lora(Some(&lora_path), self.config.lora_scale)
+        {
+            tracing::warn!("LoRA adapter not applied: {e}");
+        }
+
+        // A draft must match the (new) target's vocabulary
+        self.draft = None;
+        if let Some(draft_path) = self.config.draft_model_path.clone()
+            && let Err(e) = self.load_draft(&draft_path)
+        {
+            tracing::warn!("Speculative decoding disabled: {e}");
+        }
         Ok(())
     }
 
+    /// Apply a GGUF LoRA adapter on top of the loaded model at `scale`, or
+    /// remove it with `None`. The base model is not reloaded, and adapters
+    /// stay loaded, so switching between them (e.g. per agent) is cheap.
+    pub fn set_lora(&mut self, path: Option<&Path>, scale: f32) -> Result<()> {
+        let model = self
+            .model
+            .as_mut()
+            .ok_or_else(|| BizClawError::Brain("Load the model first".into()))?;
+        if model.set_lora(path, scale)? {
+            // Keys and values in the cache came from the old weights
+            model.cached_tokens.clear();
+            model.cache_session = None;
+        }
+        Ok(())
+    }
+
+    /// Path and scale of the applied LoRA adapter.
+    pub fn lora(&self) -> Option<(&Path, f32)> {
+        let lora = self.model.as_ref()?.mmap_model.lora()?;
+        Some((lora.adapter.path(), lora.scale))
+    }
+
+    /// Load a draft model for speculative decoding. It must share the loaded
+    /// model's vocabulary.
+    pub fn load_draft(&mut self, draft_path: &Path) -> Result<()> {
+        let model = self
+            .model
+            .as_ref()
+            .ok_or_else(|| BizClawError::Brain("Load the target model first".into()))?;
+        let draft = speculative::DraftModel::load(
+            draft_path,
+            self.config.kv_precision,
+            &model.token_pieces,
+        )?;
+        tracing::info!(
+            "Draft model loaded: {} ({} tokens per step)",
+            draft_path.display(),
+            self.config.draft_tokens
+        );
+        self.draft = Some(draft);
+        Ok(())
+    }
+
+    /// Acceptance totals of the draft model, if one is loaded.
+    pub fn draft_stats(&self) -> Option<speculative::DraftStats> {
+        self.draft.as_ref().map(|d| d.stats())
+    }
+
+    /// Load a GGUF model for [`embed`](Self::embed): an encoder (`bert`,
+    /// `nomic-bert`) or any supported decoder.
+    pub fn load_embedding_model(&mut self, path: &Path) -> Result<()> {
+        self.embedder = Some(embedding::EmbeddingModel::load(
+            path,
+            self.config.embedding_normalize,
+        )?);
+        Ok(())
+    }
+
+    /// Embed each text into one vector. Without an embedding model, the one
+    /// from `embedding_model_path` (or else the loaded chat model's file) is
+    /// loaded first.
+    pub fn embed(&mut self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        if self.embedder.is_none() {
+            let path = self
+                .config
+                .embedding_model_path
+                .clone()
+                .or_else(|| self.model.as_ref().map(|m| m.path.clone()))
+                .ok_or_else(|| BizClawError::Brain("No embedding model loaded".into()))?;
+            self.load_embedding_model(&path)?;
+        }
+        let embedder = self.embedder.as_ref().expect("embedding model loaded");
+        let batch_size = self.config.batch_size as usize;
+        texts
+            .iter()
+            .map(|text| embedder.embed(text, batch_size))
+            .collect()
+    }
+
+    /// Length of the vectors [`embed`](Self::embed) returns, once an
+    /// embedding model is loaded.
+    pub fn embedding_dimensions(&self) -> Option<usize> {
+        self.embedder.as_ref().map(|e| e.dimensions())
+    }
+
     /// Check if a model is loaded.
     pub fn is_loaded(&self) -> bool {
         self.model.is_some()
@@ -168,14 +551,76 @@ impl BrainEngine {
 
     /// Generate text completion using the loaded model.
     pub fn generate(&mut self, prompt: &str, max_tokens: u32) -> Result<String> {
+        let mut output = String::new();
+        self.generate_stream(prompt, max_tokens, &CancelToken::new(), |piece| {
+            output.push_str(piece)
+        })?;
+        Ok(output)
+    }
+
+    /// Generate text, calling `on_text` with each decoded piece as soon as it
+    /// forms valid UTF-8. Stops at EOS, `max_tokens`, or when `cancel` fires.
+    /// With `json_mode` set, output is constrained to JSON.
+    pub fn generate_stream<F>(
+        &mut self,
+        prompt: &str,
+        max_tokens: u32,
+        cancel: &CancelToken,
+        on_text: F,
+    ) -> Result<GenerationStats>
+    where
+        F: FnMut(&str),
+    {
+        let json = self.config.json_mode.then(json_schema::json_grammar);
+        self.generate_constrained_stream(prompt, max_tokens, json.as_ref(), cancel, on_text)
+    }
+
+    /// Generate text whose output must match `grammar`.
+    pub fn generate_with_grammar(
+        &mut self,
+        prompt: &str,
+        max_tokens: u32,
+        grammar: &gbnf::Grammar,
+    ) -> Result<String> {
+        let mut output = String::new();
+        self.generate_constrained_stream(
+            prompt,
+            max_tokens,
+            Some(grammar),
+            &CancelToken::new(),
+            |piece| output.push_str(piece),
+        )?;
+        Ok(output)
+    }
+
+    /// Streaming generation, optionally constrained by a grammar. Every step
+    /// masks out tokens the grammar rejects; generation ends early once the
+    /// grammar cannot be extended (`StopReason::GrammarComplete`), and EOS is
+    /// only allowed where the output is a complete match.
+    pub fn generate_constrained_stream<F>(
+        &mut self,
+        prompt: &str,
+        max_tokens: u32,
+        grammar: Option<&gbnf::Grammar>,
+        cancel: &CancelToken,
+        mut on_text: F,
+    ) -> Result<GenerationStats>
+    where
+        F: FnMut(&str),
+    {
         let model = self
             .model
             .as_mut()
             .ok_or_else(|| BizClawError::Brain("Model not loaded".into()))?;
 
-        // Tokenize prompt
-        let mut input_tokens = vec![model.tokenizer.bos_id];
-        input_tokens.extend(model.tokenizer.encode(prompt));
+        // Tokenize prompt (chat templates may already carry BOS)
+        let mut input_tokens = model.tokenizer.encode_with_special(prompt);
+        if model.tokenizer.add_bos && input_tokens.first() != Some(&model.tokenizer.bos_id) {
+            input_tokens.insert(0, model.tokenizer.bos_id);
+        }
+        if input_tokens.is_empty() {
+            return Err(BizClawError::Brain("Empty prompt".into()));
+        }
 
         let total_len = input_tokens.len();
         tracing::debug!(
@@ -184,59 +629,307 @@ impl BrainEngine {
             total_len
         );
 
-        let mut output_tokens = Vec::new();
+        let n_ctx = model.params.max_seq_len as usize;
+        if total_len >= n_ctx {
+            return Err(BizClawError::Brain(format!(
+                "Prompt too long: {total_len} tokens (context {n_ctx})"
+            )));
+        }
+
+        let mut stats = GenerationStats {
+            prompt_tokens: total_len,
+            stop_reason: StopReason::MaxTokens,
+            ..Default::default()
+        };
         let max_gen = max_tokens.min(self.config.max_tokens) as usize;
+        let max_gen = if self.config.context_shift {
+            max_gen
+        } else {
+            max_gen.min(n_ctx - total_len)
+        };
         let mut logits = vec![0.0f32; model.params.vocab_size as usize];
 
-        for step in 0..total_len + max_gen {
-            // Get the token to process
-            let token = if step < total_len {
-                input_tokens[step]
-            } else if let Some(&last) = output_tokens.last() {
-                last
-            } else {
-                break;
-            };
-
-            // Run forward pass
-            forward::forward(
+        // Prefill: skip the prefix already in the KV cache, push the rest
+        // through in batches, keeping the last logits
+        let prefill_start = Instant::now();
+        let reused = reuse_prefix(
+            &mut model.kv_cache,
+            &mut model.cached_tokens,
+            &mut model.cache_session,
+            &model.model_key,
+            self.sessions.as_ref(),
+            self.session.as_deref(),
+            &input_tokens,
+        );
+        stats.cached_tokens = reused;
+        let batch_size = self.config.batch_size.max(1) as usize;
+        let mut pos = reused;
+        for chunk in input_tokens[reused..].chunks(batch_size) {
+            if cancel.is_cancelled() {
+                stats.stop_reason = StopReason::Cancelled;
+                stats.prefill_time = prefill_start.elapsed();
+                return Ok(stats);
+            }
+            forward::forward_batch(
                 &model.mmap_model,
                 &model.weights,
                 &model.params,
                 &mut model.kv_cache,
-                token,
-                step,
+                chunk,
+                pos,
                 &mut logits,
             )?;
+            model.cached_tokens.extend_from_slice(chunk);
+            pos += chunk.len();
+        }
+        stats.prefill_time = prefill_start.elapsed();
+
+        // Decode: sample, then feed the sampled token back one at a time, or
+        // (with a draft model) together with the draft's guesses
+        model.sampler.reset();
+        let mut draft = self.draft.as_mut().filter(|_| self.config.draft_tokens > 0);
+        let mut verified = speculative::Verified::default();
+        let mut grammar_state = grammar.map(|g| g.start());
+        let mut decoder = Utf8StreamDecoder::new();
+        let mut all_tokens = input_tokens;
+        let mut token_start = Instant::now();
+        while stats.generated_tokens < max_gen {
+            if cancel.is_cancelled() {
+                stats.stop_reason = StopReason::Cancelled;
+                break;
+            }
+
+            let next_token = match &grammar_state {
+                Some(state) => sample_constrained(
+                    &mut model.sampler,
+                    &model.stop_ids,
+                    &model.token_pieces,
+                    state,
+                    &mut logits,
+                    &all_tokens,
+                )?,
+                None => model.sampler.sample(&mut logits, &all_tokens),
+            };
 
-            // Only sample after processing all input tokens
-            if step >= total_len - 1 {
-                let all_tokens: Vec<u32> = input_tokens
-                    .iter()
-                    .chain(output_tokens.iter())
-                    .copied()
-                    .collect();
-                let next_token = model.sampler.sample(&mut logits, &all_tokens);
-
-                // Check for EOS
-                if next_token == model.tokenizer.eos_id {
-                    break;
+            // Check for EOS / end of turn
+            if model.stop_ids.contains(&next_token) {
+                stats.stop_reason = StopReason::Eos;
+                break;
+            }
+
+            let mut finished = false;
+            if let Some(state) = &mut grammar_state {
+                *state = state
+                    .accept_bytes(&model.token_pieces[next_token as usize])
+                    .ok_or_else(|| BizClawError::Brain("grammar rejected sampled token".into()))?;
+                finished = state.is_finished();
+            }
+
+            all_tokens.push(next_token);
+            stats.generated_tokens += 1;
+
+            if stats.generated_tokens < max_gen && !finished {
+                if verified.expected() == Some(next_token) {
+                    // The target agreed with the draft: its logits are ready
+                    verified.accept(&mut logits);
+                    stats.accepted_draft_tokens += 1;
+                } else {
+                    if pos == n_ctx {
+                        let n_keep = context_keep(&self.config, &model.tokenizer);
+                        pos -= shift_context(
+                            &model.params,
+                            &mut model.kv_cache,
+                            &mut model.cached_tokens,
+                            n_keep,
+                        );
+                        stats.context_shifts += 1;
+                    }
+                    // Rejected drafts: positions from `pos` on are stale and
+                    // get overwritten
+                    verified.clear();
+                    let room = (max_gen - stats.generated_tokens).min(n_ctx - pos - 1);
+                    // After a shift the context no longer matches the history
+                    let shifted;
+                    let context = if stats.context_shifts == 0 {
+                        &all_tokens[..]
+                    } else {
+                        shifted = [&model.cached_tokens[..], &[next_token]].concat();
+                        &shifted[..]
+                    };
+                    let proposal = match draft.as_deref_mut() {
+                        Some(d) if room > 0 => d.propose(
+                            context,
+                            (self.config.draft_tokens as usize).min(room),
+                            batch_size,
+                        )?,
+                        _ => Vec::new(),
+                    };
+                    if proposal.is_empty() {
+                        forward::forward(
+                            &model.mmap_model,
+                            &model.weights,
+                            &model.params,
+                            &mut model.kv_cache,
+                            next_token,
+                            pos,
+                            &mut logits,
+                        )?;
+                    } else {
+                        verified = verify_draft(model, next_token, proposal, pos, &mut logits)?;
+                        stats.draft_tokens += verified.remaining();
+                    }
                 }
+                model.cached_tokens.push(next_token);
+                pos += 1;
+            }
+            stats.token_times.push(token_start.elapsed());
+            token_start = Instant::now();
 
-                output_tokens.push(next_token);
+            let piece = decoder.push(&model.token_pieces[next_token as usize]);
+            if !piece.is_empty() {
+                on_text(&piece);
+            }
+            if finished {
+                stats.stop_reason = StopReason::GrammarComplete;
+                break;
             }
         }
 
-        // Decode output tokens
-        let output = model.tokenizer.decode(&output_tokens);
-        tracing::debug!("Generated {} tokens", output_tokens.len());
-        Ok(output)
+        let rest = decoder.finish();
+        if !rest.is_empty() {
+            on_text(&rest);
+        }
+        if let Some(d) = draft {
+            d.stats.proposed += stats.draft_tokens as u64;
+            d.stats.accepted += stats.accepted_draft_tokens as u64;
+        }
+
+        if let (Some(store), Some(session)) = (&self.sessions, &self.session) {
+            let snapshot = model.kv_cache.snapshot(model.cached_tokens.len());
+            if let Err(e) = store.save(session, &model.model_key, &model.cached_tokens, &snapshot) {
+                tracing::warn!("Failed to save session cache for {session}: {e}");
+            }
+        }
+
+        tracing::debug!(
+            "Generated {} tokens ({:.1} tok/s, stop={:?})",
+            stats.generated_tokens,
+            stats.tokens_per_second(),
+            stats.stop_reason
+        );
+        Ok(stats)
     }
 
-    /// Generate with JSON grammar constraint.
+    /// Render a conversation with the loaded model's chat template,
+    /// ending with the assistant generation prompt.
+    pub fn format_chat(
+        &self,
+        messages: &[bizclaw_core::types::Message],
+        tools: &[bizclaw_core::types::ToolDefinition],
+    ) -> Result<String> {
+        let model = self
+            .model
+            .as_ref()
+            .ok_or_else(|| BizClawError::Brain("Model not loaded".into()))?;
+        Ok(model.chat_template.render(messages, tools, true))
+    }
+
+    /// Run one chat turn. With `tools`, they are offered in the model's
+    /// native format, the output is constrained to either plain text or
+    /// valid calls, and calls are parsed into `ChatReply::tool_calls`.
+    pub fn chat(
+        &mut self,
+        messages: &[bizclaw_core::types::Message],
+        tools: &[bizclaw_core::types::ToolDefinition],
+        max_tokens: u32,
+    ) -> Result<ChatReply> {
+        self.chat_stream(messages, tools, max_tokens, &CancelToken::new(), |_| {})
+    }
+
+    /// Like [`chat`](Self::chat), streaming the raw text through `on_text`.
+    pub fn chat_stream<F>(
+        &mut self,
+        messages: &[bizclaw_core::types::Message],
+        tools: &[bizclaw_core::types::ToolDefinition],
+        max_tokens: u32,
+        cancel: &CancelToken,
+        mut on_text: F,
+    ) -> Result<ChatReply>
+    where
+        F: FnMut(&str),
+    {
+        let prompt = self.format_chat(messages, tools)?;
+        let style = self
+            .model
+            .as_ref()
+            .map(|m| m.chat_template.tool_style())
+            .unwrap_or(tool_calls::ToolCallStyle::Hermes);
+
+        let grammar = if tools.is_empty() {
+            self.config.json_mode.then(json_schema::json_grammar)
+        } else {
+            style.grammar(tools).unwrap_or_else(|e| {
+                tracing::warn!("Tool calls unconstrained: {e}");
+                None
+            })
+        };
+
+        let mut text = String::new();
+        let stats = self.generate_constrained_stream(
+            &prompt,
+            max_tokens,
+            grammar.as_ref(),
+            cancel,
+            |piece| {
+                text.push_str(piece);
+                on_text(piece);
+            },
+        )?;
+
+        let parsed = if tools.is_empty() {
+            tool_calls::ParsedReply {
+                content: text,
+                tool_calls: Vec::new(),
+            }
+        } else {
+            style.parse(&text, tools)
+        };
+        Ok(ChatReply {
+            content: parsed.content,
+            tool_calls: parsed.tool_calls,
+            stats,
+        })
+    }
+
+    /// Generate a JSON object or array, constrained token by token.
     pub fn generate_json(&mut self, prompt: &str) -> Result<serde_json::Value> {
-        let text = self.generate(prompt, self.config.max_tokens)?;
-        Ok(serde_json::json!({"response": text}))
+        let grammar = json_schema::json_grammar();
+        self.generate_parsed_json(prompt, &grammar)
+    }
+
+    /// Generate JSON matching `schema` (see `json_schema` for the supported subset).
+    pub fn generate_json_schema(
+        &mut self,
+        prompt: &str,
+        schema: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let grammar = json_schema::schema_to_grammar(schema)?;
+        self.generate_parsed_json(prompt, &grammar)
+    }
+
+    fn generate_parsed_json(
+        &mut self,
+        prompt: &str,
+        grammar: &gbnf::Grammar,
+    ) -> Result<serde_json::Value> {
+        let text = self.generate_with_grammar(prompt, self.config.max_tokens, grammar)?;
+        // The grammar guarantees validity unless the token budget ran out first
+        serde_json::from_str(&text).map_err(|e| {
+            BizClawError::Brain(format!(
+                "JSON output incomplete after {} bytes: {e}",
+                text.len()
+            ))
+        })
     }
 
     /// Get the brain config.
@@ -247,13 +940,715 @@ impl BrainEngine {
     /// Get model info if loaded.
     pub fn model_info(&self) -> Option<String> {
         self.model.as_ref().map(|m| {
+            let draft = self
+                .draft
+                .as_ref()
+                .map(|d| {
+                    let stats = d.stats();
+                    format!(
+                        ", draft {} accepting {:.0}% of {} tokens",
+                        d.path().file_name().unwrap_or_default().to_string_lossy(),
+                        stats.acceptance_rate() * 100.0,
+                        stats.proposed
+                    )
+                })
+                .unwrap_or_default();
+            let lora = m
+                .mmap_model
+                .lora()
+                .map(|l| {
+                    format!(
+                        ", LoRA {} x{}",
+                        l.adapter
+                            .path()
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy(),
+                        l.scale
+                    )
+                })
+                .unwrap_or_default();
             format!(
-                "{} ({}MB, {} layers, {} heads)",
+                "{} ({}MB, {} layers, {} heads, KV cache {}MB {}{lora}{draft})",
                 m.path.file_name().unwrap_or_default().to_string_lossy(),
                 m.mmap_model.file_size() / 1024 / 1024,
                 m.params.n_layers,
                 m.params.n_heads,
+                m.kv_cache.memory_usage() / 1024 / 1024,
+                m.kv_cache.precision().as_str(),
             )
         })
     }
 }
+
+/// Run `token` and the draft's proposal through the target in one batch.
+/// Leaves the logits after `token` in `logits` and returns the rest for the
+/// decode loop to confirm one by one.
+fn verify_draft(
+    model: &mut LoadedModel,
+    token: u32,
+    proposal: Vec<u32>,
+    pos: usize,
+    logits: &mut [f32],
+) -> Result<speculative::Verified> {
+    let vocab = logits.len();
+    let mut batch = Vec::with_capacity(proposal.len() + 1);
+    batch.push(token);
+    batch.extend_from_slice(&proposal);
+    let mut rows = vec![0.0f32; batch.len() * vocab];
+    forward::forward_batch_all_logits(
+        &model.mmap_model,
+        &model.weights,
+        &model.params,
+        &mut model.kv_cache,
+        &batch,
+        pos,
+        &mut rows,
+    )?;
+    logits.copy_from_slice(&rows[..vocab]);
+    rows.drain(..vocab);
+    Ok(speculative::Verified::new(proposal, rows))
+}
+
+impl LoadedModel {
+    /// Whether the weights are those of adapter `path` at `scale`.
+    fn lora_is(&self, path: Option<&Path>, scale: f32) -> bool {
+        match (self.mmap_model.lora(), path) {
+            (Some(active), Some(path)) => active.adapter.path() == path && active.scale == scale,
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Switch the LoRA adapter, loading it on first use. Returns whether the
+    /// effective weights changed (making cached keys/values stale).
+    fn set_lora(&mut self, path: Option<&Path>, scale: f32) -> Result<bool> {
+        if self.lora_is(path, scale) {
+            return Ok(false);
+        }
+
+        let lora = match path {
+            Some(path) => {
+                let adapter = match self.adapters.get(path) {
+                    Some(adapter) => adapter.clone(),
+                    None => {
+                        let adapter =
+                            std::sync::Arc::new(lora::LoraAdapter::load(path, &self.mmap_model)?);
+                        self.adapters.insert(path.to_path_buf(), adapter.clone());
+                        adapter
+                    }
+                };
+                self.model_key = format!(
+                    "{}+{}x{scale}",
+                    self.base_key,
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                );
+                Some(lora::ActiveLora { adapter, scale })
+            }
+            None => {
+                self.model_key = self.base_key.clone();
+                None
+            }
+        };
+        self.mmap_model.set_lora(lora);
+        Ok(true)
+    }
+}
+
+/// Decide how much of `input` can be served from KV cache: the common prefix
+/// with what is in memory, or with the session's stored cache if that covers
+/// more (loading it). Returns the number of reused positions; at least one
+/// token is always left to run so there are logits to sample from.
+pub(crate) fn reuse_prefix(
+    kv_cache: &mut kv_cache::KvStorage,
+    cached_tokens: &mut Vec<u32>,
+    cache_session: &mut Option<String>,
+    model_key: &str,
+    sessions: Option<&session_cache::SessionStore>,
+    session: Option<&str>,
+    input: &[u32],
+) -> usize {
+    let limit = input.len().saturating_sub(1);
+    let mut reuse = common_prefix(cached_tokens, input).min(limit);
+
+    if let (Some(store), Some(session)) = (sessions, session)
+        && cache_session.as_deref() != Some(session)
+        && let Some(tokens) = store.tokens(session, model_key)
+    {
+        let stored = common_prefix(&tokens, input).min(limit);
+        if stored > reuse {
+            let restored = store
+                .load(session)
+                .and_then(|cache| kv_cache.restore(&cache).map_err(BizClawError::from));
+            match restored {
+                Ok(()) => {
+                    tracing::debug!("Restored {stored} cached tokens for session {session}");
+                    *cached_tokens = tokens;
+                    reuse = stored;
+                }
+                Err(e) => tracing::warn!("Ignoring session cache for {session}: {e}"),
+            }
+        }
+    }
+
+    cached_tokens.truncate(reuse);
+    *cache_session = session.map(str::to_string);
+    reuse
+}
+
+/// Tokens at the start of the context a shift must keep.
+pub(crate) fn context_keep(config: &BrainConfig, tokenizer: &tokenizer::BpeTokenizer) -> usize {
+    (config.context_keep as usize).max(tokenizer.add_bos as usize)
+}
+
+/// Make room in a full context: keep the first `n_keep` cached tokens, drop
+/// half of the rest and move the newer half down, re-rotating its keys to
+/// their new positions. Returns the number of positions freed.
+pub(crate) fn shift_context(
+    params: &model::ModelParams,
+    kv_cache: &mut kv_cache::KvStorage,
+    cached_tokens: &mut Vec<u32>,
+    n_keep: usize,
+) -> usize {
+    let n_past = cached_tokens.len();
+    let n_keep = n_keep.min(n_past / 2);
+    let n_discard = (n_past - n_keep) / 2;
+    let rope = rope::Rope::from_params(params);
+    let (n_kv_heads, head_dim) = (params.n_kv_heads as usize, params.head_dim as usize);
+    kv_cache.shift(n_keep, n_discard, n_past, |key| {
+        rope.shift(key, -(n_discard as isize), n_kv_heads, head_dim)
+    });
+    cached_tokens.drain(n_keep..n_keep + n_discard);
+    tracing::debug!("Context shift: kept {n_keep}, discarded {n_discard} of {n_past} tokens");
+    n_discard
+}
+
+pub(crate) fn common_prefix(a: &[u32], b: &[u32]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+/// Sample a token the grammar accepts. Tries the unconstrained choice first
+/// (cheap, usually fine); only if the grammar rejects it is the whole
+/// vocabulary masked and the token re-sampled.
+pub(crate) fn sample_constrained(
+    sampler: &mut sampler::Sampler,
+    stop_ids: &[u32],
+    token_pieces: &[Vec<u8>],
+    state: &gbnf::GrammarState<'_>,
+    logits: &mut [f32],
+    last_tokens: &[u32],
+) -> Result<u32> {
+    let allowed = |id: u32| {
+        if stop_ids.contains(&id) {
+            return state.is_complete();
+        }
+        let bytes = &token_pieces[id as usize];
+        !bytes.is_empty() && state.accept_bytes(bytes).is_some()
+    };
+
+    let mut candidate = logits.to_vec();
+    let token = sampler.sample(&mut candidate, last_tokens);
+    if allowed(token) {
+        return Ok(token);
+    }
+
+    let mut any = false;
+    for (id, logit) in logits.iter_mut().enumerate() {
+        if allowed(id as u32) {
+            any = true;
+        } else {
+            *logit = f32::NEG_INFINITY;
+        }
+    }
+    if !any {
+        return Err(BizClawError::Brain(
+            "grammar: no token in the vocabulary can continue the output".into(),
+        ));
+    }
+    let token = sampler.sample(logits, last_tokens);
+    if allowed(token) {
+        return Ok(token);
+    }
+    // Sampler fell back to a masked token (rounding); take the best allowed one
+    Ok(logits
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| l.is_finite())
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i as u32)
+        .unwrap_or(token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_model::TinyLlama;
+
+    #[test]
+    fn test_generate_with_tiny_model() {
+        let file = TinyLlama::default().write();
+        let mut engine = BrainEngine::new(BrainConfig {
+            batch_size: 3,
+            ..Default::default()
+        });
+        engine.load_model(&file.path).unwrap();
+        assert!(engine.is_loaded());
+        // Random weights produce noise, but the prefill + decode loop must run.
+        engine.generate("hello world", 8).unwrap();
+    }
+
+    fn greedy_engine(file: &crate::test_model::TempModel) -> BrainEngine {
+        let mut engine = BrainEngine::new(BrainConfig {
+            temperature: 0.0,
+            ..Default::default()
+        });
+        engine.load_model(&file.path).unwrap();
+        engine
+    }
+
+    #[test]
+    fn test_generate_stream_matches_generate() {
+        let file = TinyLlama::default().write();
+        let mut engine = greedy_engine(&file);
+        let full = engine.generate("abc", 12).unwrap();
+
+        let mut pieces = Vec::new();
+        let stats = engine
+            .generate_stream("abc", 12, &CancelToken::new(), |p| {
+                pieces.push(p.to_string())
+            })
+            .unwrap();
+        assert_eq!(pieces.concat(), full);
+        // BOS, the SentencePiece space prefix and one token per letter
+        assert_eq!(stats.prompt_tokens, 5);
+        assert_eq!(stats.token_times.len(), stats.generated_tokens);
+        if stats.stop_reason == StopReason::MaxTokens {
+            assert_eq!(stats.generated_tokens, 12);
+        }
+    }
+
+    #[test]
+    fn test_lora_hot_swap() {
+        use rand::{Rng, SeedableRng};
+
+        let file = TinyLlama::default().write();
+        let mut engine = greedy_engine(&file);
+        let base = engine.generate("abc", 12).unwrap();
+
+        // A strong rank-2 update of the LM head
+        let vocab = crate::test_model::vocab().len();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut random = |n: usize| (0..n).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let adapter = crate::test_model::write_adapter(
+            "llama",
+            0.0,
+            &[("output.weight", 2, random(2 * 64), random(vocab * 2))],
+        );
+
+        engine.set_lora(Some(&adapter.path), 4.0).unwrap();
+        assert_eq!(engine.lora(), Some((adapter.path.as_path(), 4.0)));
+        assert!(engine.model_info().unwrap().contains("LoRA"));
+        let adapted = engine.generate("abc", 12).unwrap();
+        assert_ne!(adapted, base);
+
+        // Back to the base weights, then to the cached adapter
+        engine.set_lora(None, 1.0).unwrap();
+        assert_eq!(engine.lora(), None);
+        assert_eq!(engine.generate("abc", 12).unwrap(), base);
+        std::fs::remove_file(&adapter.path).unwrap();
+        engine.set_lora(Some(&adapter.path), 4.0).unwrap();
+        assert_eq!(engine.generate("abc", 12).unwrap(), adapted);
+
+        let missing = std::path::Path::new("/nonexistent/adapter.gguf");
+        assert!(engine.set_lora(Some(missing), 1.0).is_err());
+        assert_eq!(engine.lora(), Some((adapter.path.as_path(), 4.0)));
+    }
+
+    #[test]
+    fn test_generate_stream_cancel() {
+        let file = TinyLlama::default().write();
+        let mut engine = greedy_engine(&file);
+
+        let cancel = CancelToken::new();
+        let stats = engine
+            .generate_stream("abc", 50, &cancel, |_| cancel.cancel())
+            .unwrap();
+        assert!(stats.generated_tokens <= 1);
+
+        let cancelled = CancelToken::new();
+        cancelled.cancel();
+        let stats = engine
+            .generate_stream("abc", 50, &cancelled, |_| panic!("no output expected"))
+            .unwrap();
+        assert_eq!(stats.stop_reason, StopReason::Cancelled);
+        assert_eq!(stats.generated_tokens, 0);
+    }
+
+    #[test]
+    fn test_prefix_reuse_matches_fresh_engine() {
+        let file = TinyLlama::default().write();
+        let mut engine = greedy_engine(&file);
+        let first = engine
+            .generate_stream("abc", 4, &CancelToken::new(), |_| {})
+            .unwrap();
+        assert_eq!(first.cached_tokens, 0);
+
+        let mut reused = String::new();
+        let stats = engine
+            .generate_stream("abcab", 6, &CancelToken::new(), |p| reused.push_str(p))
+            .unwrap();
+        assert_eq!(stats.cached_tokens, 5);
+
+        let fresh = greedy_engine(&file).generate("abcab", 6).unwrap();
+        assert_eq!(reused, fresh);
+    }
+
+    #[test]
+    fn test_session_cache_survives_restart() {
+        let file = TinyLlama::default().write();
+        let dir = std::env::temp_dir().join(format!("bizclaw-kv-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let engine_with_sessions = || {
+            let mut engine = BrainEngine::new(BrainConfig {
+                temperature: 0.0,
+                session_cache_dir: Some(dir.clone()),
+                ..Default::default()
+            });
+            engine.load_model(&file.path).unwrap();
+            engine.set_session(Some("chat-1"));
+            engine
+        };
+
+        let mut first = engine_with_sessions();
+        let answer = first.generate("abc", 3).unwrap();
+        drop(first);
+
+        // A new process resumes the conversation without re-prefilling it
+        let mut second = engine_with_sessions();
+        let mut out = String::new();
+        let stats = second
+            .generate_stream(&format!("abc{answer}b"), 3, &CancelToken::new(), |p| {
+                out.push_str(p)
+            })
+            .unwrap();
+        assert!(stats.cached_tokens >= 4, "{}", stats.cached_tokens);
+
+        let fresh = greedy_engine(&file)
+            .generate(&format!("abc{answer}b"), 3)
+            .unwrap();
+        assert_eq!(out, fresh);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_seeded_sampling_is_reproducible() {
+        let file = TinyLlama::default().write();
+        let mut engine = BrainEngine::new(BrainConfig {
+            temperature: 1.2,
+            top_k: 0,
+            min_p: 0.01,
+            seed: Some(42),
+            ..Default::default()
+        });
+        engine.load_model(&file.path).unwrap();
+        let first = engine.generate("abc", 8).unwrap();
+        assert_eq!(engine.generate("abc", 8).unwrap(), first);
+
+        // Per-request settings replace the configured ones
+        let mut other = engine.config().sampler_config();
+        other.seed = Some(42);
+        engine.set_sampler(other);
+        assert_eq!(engine.generate("abc", 8).unwrap(), first);
+    }
+
+    #[test]
+    fn test_speculative_decoding_matches_target() {
+        let file = TinyLlama::default().write();
+        let other = TinyLlama {
+            seed: 7,
+            ..Default::default()
+        }
+        .write();
+        let expected = greedy_engine(&file).generate("abc", 16).unwrap();
+
+        for draft_path in [&file.path, &other.path] {
+            let mut engine = BrainEngine::new(BrainConfig {
+                temperature: 0.0,
+                draft_model_path: Some(draft_path.clone()),
+                draft_tokens: 3,
+                ..Default::default()
+            });
+            engine.load_model(&file.path).unwrap();
+            let mut out = String::new();
+            let stats = engine
+                .generate_stream("abc", 16, &CancelToken::new(), |p| out.push_str(p))
+                .unwrap();
+            assert_eq!(out, expected);
+            assert!(stats.draft_tokens > 0);
+            assert!(stats.accepted_draft_tokens <= stats.draft_tokens);
+            if draft_path == &file.path {
+                assert!(stats.accepted_draft_tokens > 0);
+            }
+
+            let totals = engine.draft_stats().unwrap();
+            assert_eq!(totals.proposed, stats.draft_tokens as u64);
+            assert!(engine.model_info().unwrap().contains("accepting"));
+        }
+    }
+
+    #[test]
+    fn test_load_draft_requires_target() {
+        let file = TinyLlama::default().write();
+        let mut engine = BrainEngine::new(BrainConfig::default());
+        assert!(engine.load_draft(&file.path).is_err());
+        assert!(engine.draft_stats().is_none());
+    }
+
+    #[test]
+    fn test_architectures_generate() {
+        for architecture in ["mistral", "qwen2", "phi3", "gemma"] {
+            let file = TinyLlama {
+                architecture,
+                ..Default::default()
+            }
+            .write();
+            // Random weights may sample EOS first; ban it so every
+            // architecture has to run decode steps
+            let mut engine = BrainEngine::new(BrainConfig {
+                banned_tokens: vec![2],
+                ..Default::default()
+            });
+            engine.load_model(&file.path).unwrap();
+            let stats = engine
+                .generate_stream("abc", 4, &CancelToken::new(), |_| {})
+                .unwrap();
+            assert!(stats.generated_tokens > 0, "{architecture}");
+        }
+    }
+
+    #[test]
+    fn test_unsupported_architecture() {
+        let file = TinyLlama {
+            architecture: "mamba",
+            ..Default::default()
+        }
+        .write();
+        let err = BrainEngine::load(&file.path).err().unwrap().to_string();
+        assert!(err.contains("mamba") && err.contains("qwen2"), "{err}");
+    }
+
+    #[test]
+    fn test_engine_embed() {
+        let bert = TinyLlama {
+            architecture: "bert",
+            ..Default::default()
+        }
+        .write();
+        let err = BrainEngine::load(&bert.path).err().unwrap().to_string();
+        assert!(err.contains("embedding model"), "{err}");
+
+        let mut engine = BrainEngine::new(BrainConfig {
+            embedding_model_path: Some(bert.path.clone()),
+            ..Default::default()
+        });
+        let vectors = engine.embed(&["hello", "world"]).unwrap();
+        assert_eq!(vectors.len(), 2);
+        assert_eq!(vectors[0].len(), 64);
+        assert_eq!(engine.embedding_dimensions(), Some(64));
+
+        // Without a configured embedding model the chat model is used
+        let llama = TinyLlama::default().write();
+        let mut engine = BrainEngine::new(BrainConfig::default());
+        assert!(engine.embed(&["hello"]).is_err());
+        engine.load_model(&llama.path).unwrap();
+        assert_eq!(engine.embed(&["hello"]).unwrap()[0].len(), 64);
+    }
+
+    #[test]
+    fn test_kv_precision_config() {
+        let file = TinyLlama::default().write();
+        let mut engine = BrainEngine::new(BrainConfig {
+            temperature: 0.0,
+            kv_precision: kv_cache::KvPrecision::F16,
+            ..Default::default()
+        });
+        engine.load_model(&file.path).unwrap();
+        assert!(engine.model_info().unwrap().contains("f16"));
+        // Reduced precision only perturbs values slightly; generation still works
+        let stats = engine
+            .generate_stream("abc", 4, &CancelToken::new(), |_| {})
+            .unwrap();
+        assert!(stats.generated_tokens > 0);
+    }
+
+    #[test]
+    fn test_generate_rejects_prompt_longer_than_context() {
+        let file = TinyLlama {
+            context_length: 8,
+            ..Default::default()
+        }
+        .write();
+        let mut engine = BrainEngine::load(&file.path).unwrap();
+        assert!(engine.generate("this prompt does not fit", 4).is_err());
+    }
+
+    #[test]
+    fn test_context_shift_continues_past_context() {
+        let file = TinyLlama {
+            context_length: 16,
+            ..Default::default()
+        }
+        .write();
+        let mut engine = greedy_engine(&file);
+        let mut sampler = engine.config().sampler_config();
+        sampler.banned_tokens = engine.model.as_ref().unwrap().stop_ids.clone();
+        engine.set_sampler(sampler.clone());
+        let stats = engine
+            .generate_stream("abc", 40, &CancelToken::new(), |_| {})
+            .unwrap();
+        assert_eq!(stats.generated_tokens, 40);
+        assert!(stats.context_shifts >= 2);
+
+        // Layer 0 keys depend only on token and position, so the shifted
+        // cache must match the kept tokens run at their new positions
+        let model = engine.model.as_ref().unwrap();
+        let kept = model.cached_tokens.clone();
+        assert!(kept.len() <= 16);
+        assert_eq!(kept[0], model.tokenizer.bos_id);
+        let mut fresh = kv_cache::KvStorage::new(kv_cache::KvPrecision::F32, 2, 16, 2, 16);
+        let mut logits = vec![0.0; model.params.vocab_size as usize];
+        forward::forward_batch(
+            &model.mmap_model,
+            &model.weights,
+            &model.params,
+            &mut fresh,
+            &kept,
+            0,
+            &mut logits,
+        )
+        .unwrap();
+        let (mut s1, mut s2) = Default::default();
+        let (shifted, _) = model.kv_cache.layer(0, kept.len(), &mut s1);
+        let (direct, _) = fresh.layer(0, kept.len(), &mut s2);
+        for (a, b) in shifted.iter().zip(direct) {
+            assert!((a - b).abs() < 1e-3, "{a} vs {b}");
+        }
+
+        let mut engine = BrainEngine::new(BrainConfig {
+            temperature: 0.0,
+            context_shift: false,
+            ..Default::default()
+        });
+        engine.load_model(&file.path).unwrap();
+        engine.set_sampler(sampler);
+        let stats = engine
+            .generate_stream("abc", 40, &CancelToken::new(), |_| {})
+            .unwrap();
+        assert_eq!(stats.generated_tokens, 16 - stats.prompt_tokens);
+        assert_eq!(stats.context_shifts, 0);
+    }
+
+    #[test]
+    fn test_format_chat_uses_gguf_template() {
+        let file = TinyLlama {
+            chat_template: Some(
+                "{% for m in messages %}<|im_start|>{{ m.role }}\n{{ m.content }}<|im_end|>\n{% endfor %}{% if add_generation_prompt %}<|im_start|>assistant\n{% endif %}".into(),
+            ),
+            ..Default::default()
+        }
+        .write();
+        let mut engine = greedy_engine(&file);
+        let messages = [bizclaw_core::types::Message::user("hi")];
+        let prompt = engine.format_chat(&messages, &[]).unwrap();
+        assert_eq!(
+            prompt,
+            "<|im_start|>user\nhi<|im_end|>\n<|im_start|>assistant\n"
+        );
+
+        // <|im_end|> is a stop token for ChatML models, and control tokens
+        // never leak into the output text.
+        let model = engine.model.as_ref().unwrap();
+        let im_end = model.tokenizer.token_id("<|im_end|>").unwrap();
+        assert!(model.stop_ids.contains(&im_end));
+        let out = engine.generate(&prompt, 8).unwrap();
+        assert!(!out.contains("<|im_"));
+    }
+
+    #[test]
+    fn test_generate_with_grammar() {
+        let file = TinyLlama::default().write();
+        let mut engine = BrainEngine::new(BrainConfig::default());
+        engine.load_model(&file.path).unwrap();
+
+        let grammar = gbnf::Grammar::parse(r#"root ::= ("yes" | "no") " " [0-9]{2}"#).unwrap();
+        for _ in 0..3 {
+            let mut output = String::new();
+            let stats = engine
+                .generate_constrained_stream(
+                    "Answer:",
+                    50,
+                    Some(&grammar),
+                    &CancelToken::new(),
+                    |piece| output.push_str(piece),
+                )
+                .unwrap();
+            assert_eq!(stats.stop_reason, StopReason::GrammarComplete);
+            let (answer, digits) = output.split_once(' ').unwrap();
+            assert!(answer == "yes" || answer == "no", "{output}");
+            assert!(digits.len() == 2 && digits.bytes().all(|b| b.is_ascii_digit()));
+        }
+    }
+
+    #[test]
+    fn test_generate_json_schema() {
+        let file = TinyLlama {
+            context_length: 512,
+            ..Default::default()
+        }
+        .write();
+        let mut engine = BrainEngine::new(BrainConfig {
+            max_tokens: 400,
+            ..Default::default()
+        });
+        engine.load_model(&file.path).unwrap();
+
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "ok": {"type": "boolean"},
+                "level": {"enum": [1, 2, 3]}
+            },
+            "required": ["ok", "level"]
+        });
+        let value = engine.generate_json_schema("Status:", &schema).unwrap();
+        assert!(value["ok"].is_boolean(), "{value}");
+        assert!([1, 2, 3].contains(&value["level"].as_i64().unwrap()));
+        assert_eq!(value.as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_chat_with_tools() {
+        let file = TinyLlama {
+            chat_template: Some(
+                include_str!("../tests/fixtures/chat_templates/qwen2.5.jinja").into(),
+            ),
+            context_length: 1024,
+            ..Default::default()
+        }
+        .write();
+        let mut engine = BrainEngine::new(BrainConfig::default());
+        engine.load_model(&file.path).unwrap();
+
+        let tools = [bizclaw_core::types::ToolDefinition {
+            name: "now".into(),
+            description: "Current time".into(),
+            parameters: serde_json::json!({"type": "object", "properties": {}}),
+        }];
+        let messages = [bizclaw_core::types::Message::user("Time?")];
+        let reply = engine.chat(&messages, &tools, 60).unwrap();
+        // The grammar keeps answers and calls apart
+        assert_ne!(reply.content.is_empty(), reply.tool_calls.is_empty());
+        assert!(reply.tool_calls.iter().all(|c| c.function.name == "now"));
+        assert!(!reply.content.starts_with("<tool_call>"));
+    }
+}
@@ -241,6 +241,34 @@ impl SmartBrainEngine {
         self.brain.generate(prompt, max_tokens)
     }
 
+    /// Stream generated text — see `BrainEngine::generate_stream`.
+    ///
+    /// The llama.cpp backend does not stream yet, so its output arrives as a
+    /// single piece.
+    pub fn generate_stream<F>(
+        &mut self,
+        prompt: &str,
+        max_tokens: u32,
+        cancel: &super::CancelToken,
+        mut on_text: F,
+    ) -> Result<super::GenerationStats>
+    where
+        F: FnMut(&str),
+    {
+        if let Some(ref backend) = self.llamacpp
+            && backend.is_loaded()
+        {
+            let text = backend.generate(prompt, max_tokens)?;
+            if !text.is_empty() {
+                on_text(&text);
+            }
+            return Ok(super::GenerationStats::default());
+        }
+
+        self.brain
+            .generate_stream(prompt, max_tokens, cancel, on_text)
+    }
+
     /// Get info about which backend is active.
     pub fn backend_info(&self) -> String {
         if let Some(ref backend) = self.llamacpp
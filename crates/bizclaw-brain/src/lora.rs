@@ -0,0 +1,295 @@
+//! LoRA adapters — low-rank weight updates on top of a base model.
+//!
+//! Adapters use llama.cpp's GGUF layout (`general.type = "adapter"`,
+//! `adapter.type = "lora"`): a base weight `X.weight` of shape
+//! `rows x cols` is adapted by `X.weight.lora_a` (`rank x cols`) and
+//! `X.weight.lora_b` (`rows x rank`). A projection through the adapted
+//! weight computes `W x + s * B (A x)`, where `s` is the user scale times
+//! `adapter.lora.alpha / rank`.
+//!
+//! The factors are dequantized to f32 once at load (they are small) and
+//! keyed by the base tensor's index, so the update is applied inside
+//! `matmul_weight` with no lookup by name. The base model stays mapped and
+//! untouched: switching adapters only swaps an `Arc`.
+
+use crate::mmap::MmapModel;
+use crate::quant;
+use crate::simd::dot_product_simd;
+use bizclaw_core::error::{BizClawError, Result};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A LoRA adapter bound to the base model it was loaded against.
+pub struct LoraAdapter {
+    path: PathBuf,
+    /// `adapter.lora.alpha`; 0 when absent (no rank scaling)
+    alpha: f32,
+    /// Low-rank factors by base tensor index
+    tensors: HashMap<usize, LoraTensor>,
+}
+
+/// `B` and `A` for one base weight.
+struct LoraTensor {
+    /// `rank x cols`
+    a: Vec<f32>,
+    /// `rows x rank`
+    b: Vec<f32>,
+    rank: usize,
+    rows: usize,
+    cols: usize,
+}
+
+/// The adapter applied by a model's projections, at a given strength.
+#[derive(Clone)]
+pub struct ActiveLora {
+    pub adapter: Arc<LoraAdapter>,
+    pub scale: f32,
+}
+
+impl LoraAdapter {
+    /// Load an adapter GGUF, checking every pair of factors against the
+    /// base weight it adapts.
+    pub fn load(path: &Path, base: &MmapModel) -> Result<Self> {
+        let file = MmapModel::load(path)?;
+        let meta = &file.gguf.metadata;
+        let text = |key: &str| meta.get(key).and_then(|v| v.as_str());
+        if let Some(kind) = text("general.type")
+            && kind != "adapter"
+        {
+            return Err(BizClawError::ModelLoad(format!(
+                "{} is a {kind}, not an adapter",
+                path.display()
+            )));
+        }
+        if let Some(kind) = text("adapter.type")
+            && kind != "lora"
+        {
+            return Err(BizClawError::ModelLoad(format!(
+                "Unsupported adapter type {kind} in {}",
+                path.display()
+            )));
+        }
+        if let (Some(arch), Some(base_arch)) = (file.gguf.architecture(), base.gguf.architecture())
+            && arch != base_arch
+        {
+            return Err(BizClawError::ModelLoad(format!(
+                "Adapter is for {arch}, the model is {base_arch}"
+            )));
+        }
+        let alpha = file.gguf.get_f32("adapter.lora.alpha").unwrap_or(0.0);
+
+        let find = |gguf: &crate::gguf::GgufFile, name: &str| {
+            gguf.tensors.iter().position(|t| t.name == name)
+        };
+        let mut tensors = HashMap::new();
+        for (a_idx, info) in file.gguf.tensors.iter().enumerate() {
+            let Some(base_name) = info.name.strip_suffix(".lora_a") else {
+                continue;
+            };
+            let b_idx = find(&file.gguf, &format!("{base_name}.lora_b")).ok_or_else(|| {
+                BizClawError::ModelLoad(format!("Adapter has no {base_name}.lora_b"))
+            })?;
+            let base_idx = find(&base.gguf, base_name).ok_or_else(|| {
+                BizClawError::ModelLoad(format!("Adapter targets {base_name}, not in the model"))
+            })?;
+
+            let dims = |d: &[u64]| -> Option<(usize, usize)> {
+                match *d {
+                    [inner, outer] => Some((outer as usize, inner as usize)),
+                    _ => None,
+                }
+            };
+            let shape_error =
+                || BizClawError::ModelLoad(format!("LoRA factors do not fit {base_name}"));
+            let (rows, cols) = dims(&base.gguf.tensors[base_idx].dims).ok_or_else(shape_error)?;
+            let (rank, a_cols) = dims(&info.dims).ok_or_else(shape_error)?;
+            let (b_rows, b_rank) = dims(&file.gguf.tensors[b_idx].dims).ok_or_else(shape_error)?;
+            if a_cols != cols || b_rows != rows || b_rank != rank || rank == 0 {
+                return Err(shape_error());
+            }
+
+            tensors.insert(
+                base_idx,
+                LoraTensor {
+                    a: dequantize(&file, a_idx, rank * cols)?,
+                    b: dequantize(&file, b_idx, rows * rank)?,
+                    rank,
+                    rows,
+                    cols,
+                },
+            );
+        }
+        if tensors.is_empty() {
+            return Err(BizClawError::ModelLoad(format!(
+                "{} has no LoRA tensors",
+                path.display()
+            )));
+        }
+
+        tracing::info!(
+            "LoRA adapter {}: {} tensors, alpha {alpha}",
+            path.display(),
+            tensors.len()
+        );
+        Ok(Self {
+            path: path.to_path_buf(),
+            alpha,
+            tensors,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of base weights the adapter changes.
+    pub fn n_tensors(&self) -> usize {
+        self.tensors.len()
+    }
+
+    /// Add `scale * B (A x)` for every token of `input` (`[n x cols]`) to
+    /// `output` (`[n x rows]`). Weights the adapter does not touch are left
+    /// alone.
+    pub(crate) fn apply(
+        &self,
+        tensor_idx: usize,
+        scale: f32,
+        input: &[f32],
+        output: &mut [f32],
+    ) -> Result<()> {
+        let Some(t) = self.tensors.get(&tensor_idx) else {
+            return Ok(());
+        };
+        let n_tokens = input.len() / t.cols;
+        if input.len() != n_tokens * t.cols || output.len() < n_tokens * t.rows {
+            return Err(BizClawError::Brain(format!(
+                "LoRA: bad shape input={} output={} for {}x{}",
+                input.len(),
+                output.len(),
+                t.rows,
+                t.cols
+            )));
+        }
+        let scale = if self.alpha > 0.0 {
+            scale * self.alpha / t.rank as f32
+        } else {
+            scale
+        };
+
+        output[..n_tokens * t.rows]
+            .par_chunks_mut(t.rows)
+            .zip(input.par_chunks(t.cols))
+            .for_each(|(out, x)| {
+                let ax: Vec<f32> =
+                    t.a.chunks_exact(t.cols)
+                        .map(|row| dot_product_simd(row, x))
+                        .collect();
+                for (o, row) in out.iter_mut().zip(t.b.chunks_exact(t.rank)) {
+                    *o += scale * dot_product_simd(row, &ax);
+                }
+            });
+        Ok(())
+    }
+}
+
+fn dequantize(file: &MmapModel, idx: usize, n_elements: usize) -> Result<Vec<f32>> {
+    let info = &file.gguf.tensors[idx];
+    if info.n_elements() as usize != n_elements {
+        return Err(BizClawError::ModelLoad(format!(
+            "{}: expected {n_elements} elements",
+            info.name
+        )));
+    }
+    let mut values = vec![0.0f32; n_elements];
+    quant::dequantize_row(
+        file.tensor_data(idx)?,
+        &mut values,
+        n_elements,
+        info.ggml_type,
+    )?;
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_model::{TinyLlama, write_adapter};
+
+    #[test]
+    fn test_apply_adds_low_rank_update() {
+        let base = TinyLlama::default().write();
+        let model = MmapModel::load(&base.path).unwrap();
+        let d = 64;
+        // Rank 2: A picks x[0] and x[1]; B writes them to rows 0 and 5
+        let mut a = vec![0.0; 2 * d];
+        a[0] = 1.0;
+        a[d + 1] = 1.0;
+        let mut b = vec![0.0; d * 2];
+        b[0] = 1.0;
+        b[5 * 2 + 1] = 3.0;
+        let file = write_adapter("llama", 4.0, &[("blk.0.attn_output.weight", 2, a, b)]);
+        let adapter = LoraAdapter::load(&file.path, &model).unwrap();
+        assert_eq!(adapter.n_tensors(), 1);
+
+        let idx = model
+            .gguf
+            .tensors
+            .iter()
+            .position(|t| t.name == "blk.0.attn_output.weight")
+            .unwrap();
+        let mut x = vec![0.0f32; 2 * d];
+        x[0] = 1.0;
+        x[1] = 2.0;
+        x[d + 1] = -1.0;
+        let mut out = vec![1.0f32; 2 * d];
+        adapter.apply(idx, 0.5, &x, &mut out).unwrap();
+        // scale = 0.5 * alpha 4 / rank 2 = 1
+        assert_eq!(out[0], 2.0);
+        assert_eq!(out[5], 7.0);
+        assert_eq!(out[d], 1.0);
+        assert_eq!(out[d + 5], -2.0);
+        assert_eq!(out.iter().filter(|&&v| v != 1.0).count(), 3);
+
+        // Other weights pass through
+        let mut untouched = vec![1.0f32; d];
+        adapter
+            .apply(idx + 1, 1.0, &x[..d], &mut untouched)
+            .unwrap();
+        assert!(untouched.iter().all(|&v| v == 1.0));
+    }
+
+    #[test]
+    fn test_load_rejects_mismatched_adapters() {
+        let base = TinyLlama::default().write();
+        let model = MmapModel::load(&base.path).unwrap();
+        let d = 64;
+
+        let wrong_shape = write_adapter(
+            "llama",
+            0.0,
+            &[(
+                "blk.0.attn_q.weight",
+                2,
+                vec![0.0; 2 * 32],
+                vec![0.0; d * 2],
+            )],
+        );
+        assert!(LoraAdapter::load(&wrong_shape.path, &model).is_err());
+
+        let unknown = write_adapter(
+            "llama",
+            0.0,
+            &[("blk.9.attn_q.weight", 1, vec![0.0; d], vec![0.0; d])],
+        );
+        assert!(LoraAdapter::load(&unknown.path, &model).is_err());
+
+        let other_arch = write_adapter(
+            "qwen2",
+            0.0,
+            &[("blk.0.attn_q.weight", 1, vec![0.0; d], vec![0.0; d])],
+        );
+        assert!(LoraAdapter::load(&other_arch.path, &model).is_err());
+    }
+}
@@ -10,6 +10,7 @@ use std::fs::File;
 use std::path::Path;
 
 use crate::gguf::GgufFile;
+use crate::lora::ActiveLora;
 
 /// A memory-mapped GGUF model file.
 pub struct MmapModel {
@@ -17,6 +18,8 @@ pub struct MmapModel {
     pub gguf: GgufFile,
     /// Memory-mapped file data.
     mmap: Mmap,
+    /// LoRA adapter applied by `forward`'s projections
+    lora: Option<ActiveLora>,
 }
 
 impl MmapModel {
@@ -54,7 +57,11 @@ impl MmapModel {
             mmap.len() as f64 / (1024.0 * 1024.0)
         );
 
-        Ok(Self { gguf, mmap })
+        Ok(Self {
+            gguf,
+            mmap,
+            lora: None,
+        })
     }
 
     /// Get a raw byte slice for a tensor's data.
@@ -109,4 +116,14 @@ impl MmapModel {
     pub fn tensor_count(&self) -> usize {
         self.gguf.tensors.len()
     }
+
+    /// Apply a LoRA adapter on top of the mapped weights (`None` removes it).
+    pub fn set_lora(&mut self, lora: Option<ActiveLora>) {
+        self.lora = lora;
+    }
+
+    /// The adapter currently applied, if any.
+    pub fn lora(&self) -> Option<&ActiveLora> {
+        self.lora.as_ref()
+    }
 }
@@ -1,28 +1,111 @@
-//! LLaMA model forward pass.
+//! Model hyperparameters and architecture detection.
 //!
-//! Implements the transformer architecture for LLaMA-family models.
-//! Reads weights from mmap, dequantizes on-the-fly, and computes
-//! the forward pass producing logits for the next token.
+//! Reads `general.architecture` and the `<arch>.*` metadata keys of a GGUF
+//! file. The forward pass (see `forward`) is shared by all supported
+//! architectures; [`Architecture`] describes where they differ.
+
+use crate::rope::{RopeScaling, RopeStyle};
+use bizclaw_core::error::{BizClawError, Result};
+
+/// Transformer families the forward pass knows how to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Architecture {
+    /// LLaMA 1/2/3, TinyLlama, and Mistral (converted as `llama` or `mistral`).
+    Llama,
+    /// Qwen2 / Qwen2.5: LLaMA layout plus Q/K/V biases, NEOX RoPE.
+    Qwen2,
+    /// Phi-3: fused `attn_qkv` and fused gate/up in `ffn_up`, NEOX RoPE.
+    Phi3,
+    /// Gemma: embeddings scaled by sqrt(dim), GeGLU FFN, tied output, NEOX
+    /// RoPE. Its RMSNorm uses `(1 + w)`; GGUF converters store the `+ 1`
+    /// in the norm weights already, so the plain RMSNorm is correct here.
+    Gemma,
+    /// BERT encoders (bge, e5, MiniLM): post-norm LayerNorm with biases,
+    /// learned positions, GELU FFN, bidirectional attention. Embeddings only.
+    Bert,
+    /// nomic-bert (nomic-embed-text): BERT layout with fused QKV, NEOX RoPE
+    /// and a SwiGLU FFN. Embeddings only.
+    NomicBert,
+}
+
+impl Architecture {
+    /// Supported `general.architecture` values.
+    pub const SUPPORTED: [&str; 7] = [
+        "llama",
+        "mistral",
+        "qwen2",
+        "phi3",
+        "gemma",
+        "bert",
+        "nomic-bert",
+    ];
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "llama" | "mistral" => Ok(Self::Llama),
+            "qwen2" => Ok(Self::Qwen2),
+            "phi3" => Ok(Self::Phi3),
+            "gemma" => Ok(Self::Gemma),
+            "bert" => Ok(Self::Bert),
+            "nomic-bert" => Ok(Self::NomicBert),
+            other => Err(BizClawError::Brain(format!(
+                "Unsupported model architecture '{other}' (supported: {})",
+                Self::SUPPORTED.join(", ")
+            ))),
+        }
+    }
+
+    pub fn rope_style(&self) -> RopeStyle {
+        match self {
+            Self::Llama | Self::Bert => RopeStyle::Normal,
+            Self::Qwen2 | Self::Phi3 | Self::Gemma | Self::NomicBert => RopeStyle::Neox,
+        }
+    }
+
+    /// Gemma multiplies token embeddings by sqrt(dim).
+    pub fn scales_embeddings(&self) -> bool {
+        matches!(self, Self::Gemma)
+    }
+
+    /// GELU-gated FFN (GeGLU) instead of SiLU (SwiGLU).
+    pub fn uses_gelu(&self) -> bool {
+        matches!(self, Self::Gemma)
+    }
+
+    /// Bidirectional encoders that only produce embeddings (see `encoder`).
+    pub fn is_encoder(&self) -> bool {
+        matches!(self, Self::Bert | Self::NomicBert)
+    }
+}
 
 /// Model hyperparameters extracted from GGUF metadata.
 #[derive(Debug, Clone)]
 pub struct ModelParams {
+    pub arch: Architecture,
     pub vocab_size: u32,
     pub dim: u32,        // embedding dimension
     pub hidden_dim: u32, // FFN hidden dimension
     pub n_layers: u32,
     pub n_heads: u32,
     pub n_kv_heads: u32, // for GQA (Grouped Query Attention)
-    pub head_dim: u32,   // dim / n_heads
+    pub head_dim: u32,   // dim / n_heads unless set by `attention.key_length`
     pub max_seq_len: u32,
     pub rope_theta: f32,
+    /// Rotated dimensions per head (partial rotary if < head_dim).
+    pub rope_dim: u32,
+    /// Long-context frequency scaling (`rope.scaling.*`).
+    pub rope_scaling: RopeScaling,
+    /// RMSNorm epsilon (LayerNorm epsilon for encoders).
     pub rms_norm_eps: f32,
+    /// Attend only to the last `n` positions (Mistral, Phi-3 variants).
+    pub sliding_window: Option<u32>,
 }
 
 impl Default for ModelParams {
     fn default() -> Self {
         // TinyLlama 1.1B defaults
         Self {
+            arch: Architecture::Llama,
             vocab_size: 32000,
             dim: 2048,
             hidden_dim: 5632,
@@ -32,16 +115,21 @@ impl Default for ModelParams {
             head_dim: 64,
             max_seq_len: 2048,
             rope_theta: 10000.0,
+            rope_dim: 64,
+            rope_scaling: RopeScaling::None,
             rms_norm_eps: 1e-5,
+            sliding_window: None,
         }
     }
 }
 
 impl ModelParams {
-    /// Extract model parameters from GGUF metadata.
-    pub fn from_gguf(gguf: &crate::gguf::GgufFile) -> Self {
-        let arch = gguf.architecture().unwrap_or("llama");
-        let prefix = format!("{arch}.");
+    /// Extract model parameters from GGUF metadata. Fails for architectures
+    /// the forward pass does not implement.
+    pub fn from_gguf(gguf: &crate::gguf::GgufFile) -> Result<Self> {
+        let arch_name = gguf.architecture().unwrap_or("llama");
+        let arch = Architecture::from_name(arch_name)?;
+        let prefix = format!("{arch_name}.");
 
         let dim = gguf
             .get_u32(&format!("{prefix}embedding_length"))
@@ -52,8 +140,15 @@ impl ModelParams {
         let n_kv_heads = gguf
             .get_u32(&format!("{prefix}attention.head_count_kv"))
             .unwrap_or(n_heads);
+        let head_dim = gguf
+            .get_u32(&format!("{prefix}attention.key_length"))
+            .unwrap_or(dim / n_heads);
+        let max_seq_len = gguf
+            .get_u32(&format!("{prefix}context_length"))
+            .unwrap_or(2048);
 
-        Self {
+        Ok(Self {
+            arch,
             vocab_size: gguf
                 .get_u32(&format!("{prefix}vocab_size"))
                 .or_else(|| {
@@ -73,16 +168,32 @@ impl ModelParams {
             n_layers: gguf.get_u32(&format!("{prefix}block_count")).unwrap_or(22),
             n_heads,
             n_kv_heads,
-            head_dim: dim / n_heads,
-            max_seq_len: gguf
-                .get_u32(&format!("{prefix}context_length"))
-                .unwrap_or(2048),
+            head_dim,
+            max_seq_len,
             rope_theta: gguf
                 .get_f32(&format!("{prefix}rope.freq_base"))
                 .unwrap_or(10000.0),
+            rope_dim: gguf
+                .get_u32(&format!("{prefix}rope.dimension_count"))
+                .unwrap_or(head_dim)
+                .min(head_dim),
+            rope_scaling: RopeScaling::from_gguf(gguf, arch_name, max_seq_len),
             rms_norm_eps: gguf
                 .get_f32(&format!("{prefix}attention.layer_norm_rms_epsilon"))
+                .or_else(|| gguf.get_f32(&format!("{prefix}attention.layer_norm_epsilon")))
                 .unwrap_or(1e-5),
-        }
+            sliding_window: gguf
+                .get_u32(&format!("{prefix}attention.sliding_window"))
+                .filter(|&w| w > 0),
+        })
+    }
+
+    /// Width of the concatenated query heads (differs from `dim` for Gemma).
+    pub fn q_dim(&self) -> usize {
+        (self.n_heads * self.head_dim) as usize
+    }
+
+    pub fn kv_dim(&self) -> usize {
+        (self.n_kv_heads * self.head_dim) as usize
     }
 }
@@ -0,0 +1,330 @@
+//! Model pool — loaded models shared by path under one memory budget.
+//!
+//! Callers ask for a model by path and role; the first request loads it,
+//! later ones (from any agent) get the same instance. Each entry carries
+//! the RAM estimate it was admitted with. Before a load that would exceed
+//! the budget, idle models are unloaded least recently used first. A model
+//! still serving a request is never evicted: dropping the pool's handle
+//! would not free its memory until the request ends anyway.
+//!
+//! The pool is generic over what it holds, so the provider can keep an
+//! engine or a batching worker per model.
+
+use bizclaw_core::error::{BizClawError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// What a pooled model is used for. The same file can be loaded once per
+/// role (a chat model that also serves embeddings).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelRole {
+    Chat,
+    Embedding,
+}
+
+/// Models kept loaded and shared across callers.
+pub struct ModelPool<T> {
+    state: Mutex<PoolState<T>>,
+    /// Held while loading, so two callers never load (or make room) at once
+    loading: Mutex<()>,
+}
+
+struct PoolState<T> {
+    /// Bytes all loaded models may use together (0 = no limit)
+    budget_bytes: u64,
+    entries: Vec<Entry<T>>,
+    clock: u64,
+    loads: u64,
+    evictions: u64,
+}
+
+struct Entry<T> {
+    path: PathBuf,
+    role: ModelRole,
+    model: Arc<T>,
+    bytes: u64,
+    loaded_at: Instant,
+    last_used: u64,
+    last_used_at: Instant,
+    uses: u64,
+}
+
+/// Snapshot of the pool for status pages.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStatus {
+    pub budget_bytes: u64,
+    pub used_bytes: u64,
+    pub loads: u64,
+    pub evictions: u64,
+    /// Most recently used first
+    pub models: Vec<PooledModel>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PooledModel {
+    pub path: String,
+    pub role: ModelRole,
+    /// RAM estimate the model was admitted with
+    pub bytes: u64,
+    /// Serving a request right now
+    pub in_use: bool,
+    pub uses: u64,
+    pub loaded_secs: u64,
+    pub idle_secs: u64,
+}
+
+impl<T> ModelPool<T> {
+    pub fn new(budget_bytes: u64) -> Self {
+        Self {
+            state: Mutex::new(PoolState {
+                budget_bytes,
+                entries: Vec::new(),
+                clock: 0,
+                loads: 0,
+                evictions: 0,
+            }),
+            loading: Mutex::new(()),
+        }
+    }
+
+    /// Change the budget; takes effect at the next load.
+    pub fn set_budget(&self, budget_bytes: u64) {
+        self.lock().budget_bytes = budget_bytes;
+    }
+
+    /// A loaded model, marking it used.
+    pub fn get(&self, path: &Path, role: ModelRole) -> Option<Arc<T>> {
+        let mut state = self.lock();
+        state.clock += 1;
+        let clock = state.clock;
+        let entry = state
+            .entries
+            .iter_mut()
+            .find(|e| e.path == path && e.role == role)?;
+        entry.last_used = clock;
+        entry.last_used_at = Instant::now();
+        entry.uses += 1;
+        Some(entry.model.clone())
+    }
+
+    /// A loaded model, without counting as a use.
+    pub fn peek(&self, path: &Path, role: ModelRole) -> Option<Arc<T>> {
+        self.lock()
+            .entries
+            .iter()
+            .find(|e| e.path == path && e.role == role)
+            .map(|e| e.model.clone())
+    }
+
+    /// The pooled model, loading it with `load` if needed. `bytes` is the
+    /// model's estimated RAM; idle models are unloaded until it fits.
+    /// Blocks while another model loads.
+    pub fn get_or_load(
+        &self,
+        path: &Path,
+        role: ModelRole,
+        bytes: u64,
+        load: impl FnOnce() -> Result<T>,
+    ) -> Result<Arc<T>> {
+        if let Some(model) = self.get(path, role) {
+            return Ok(model);
+        }
+        let _loading = self.loading.lock().unwrap_or_else(|e| e.into_inner());
+        // Someone may have loaded it while we waited
+        if let Some(model) = self.get(path, role) {
+            return Ok(model);
+        }
+
+        self.make_room(path, bytes)?;
+        let model = Arc::new(load()?);
+        let mut state = self.lock();
+        state.clock += 1;
+        state.loads += 1;
+        let clock = state.clock;
+        state.entries.push(Entry {
+            path: path.to_path_buf(),
+            role,
+            model: model.clone(),
+            bytes,
+            loaded_at: Instant::now(),
+            last_used: clock,
+            last_used_at: Instant::now(),
+            uses: 1,
+        });
+        tracing::info!(
+            "Model pool: loaded {} ({:?}, ~{} MB)",
+            path.display(),
+            role,
+            bytes / 1024 / 1024
+        );
+        Ok(model)
+    }
+
+    /// Unload idle models, least recently used first, until `bytes` more
+    /// fit in the budget.
+    fn make_room(&self, path: &Path, bytes: u64) -> Result<()> {
+        let mut state = self.lock();
+        let budget = state.budget_bytes;
+        if budget == 0 {
+            return Ok(());
+        }
+        if bytes > budget {
+            return Err(BizClawError::Brain(format!(
+                "{} needs ~{} MB, more than the {} MB model budget",
+                path.display(),
+                bytes / 1024 / 1024,
+                budget / 1024 / 1024
+            )));
+        }
+        while state.used_bytes() + bytes > budget {
+            let Some(victim) = state
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| Arc::strong_count(&e.model) == 1)
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(i, _)| i)
+            else {
+                return Err(BizClawError::Brain(format!(
+                    "Cannot load {} (~{} MB): {} MB of the {} MB model budget is serving requests",
+                    path.display(),
+                    bytes / 1024 / 1024,
+                    state.used_bytes() / 1024 / 1024,
+                    budget / 1024 / 1024
+                )));
+            };
+            let entry = state.entries.remove(victim);
+            state.evictions += 1;
+            tracing::info!(
+                "Model pool: unloaded {} ({:?}) to make room",
+                entry.path.display(),
+                entry.role
+            );
+        }
+        Ok(())
+    }
+
+    /// Drop a model from the pool. Its memory is freed once the requests
+    /// using it finish. Returns whether it was loaded.
+    pub fn unload(&self, path: &Path, role: ModelRole) -> bool {
+        let mut state = self.lock();
+        let before = state.entries.len();
+        state
+            .entries
+            .retain(|e| !(e.path == path && e.role == role));
+        before != state.entries.len()
+    }
+
+    pub fn status(&self) -> PoolStatus {
+        let state = self.lock();
+        let mut models: Vec<(u64, PooledModel)> = state
+            .entries
+            .iter()
+            .map(|e| {
+                let model = PooledModel {
+                    path: e.path.display().to_string(),
+                    role: e.role,
+                    bytes: e.bytes,
+                    in_use: Arc::strong_count(&e.model) > 1,
+                    uses: e.uses,
+                    loaded_secs: e.loaded_at.elapsed().as_secs(),
+                    idle_secs: e.last_used_at.elapsed().as_secs(),
+                };
+                (e.last_used, model)
+            })
+            .collect();
+        models.sort_by_key(|(last_used, _)| std::cmp::Reverse(*last_used));
+        PoolStatus {
+            budget_bytes: state.budget_bytes,
+            used_bytes: state.used_bytes(),
+            loads: state.loads,
+            evictions: state.evictions,
+            models: models.into_iter().map(|(_, m)| m).collect(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T> PoolState<T> {
+    fn used_bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.bytes).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(pool: &ModelPool<String>, name: &str, bytes: u64) -> Result<Arc<String>> {
+        pool.get_or_load(Path::new(name), ModelRole::Chat, bytes, || {
+            Ok(name.to_string())
+        })
+    }
+
+    #[test]
+    fn test_shared_and_lazy() {
+        let pool = ModelPool::new(0);
+        let mut calls = 0;
+        for _ in 0..3 {
+            let model = pool
+                .get_or_load(Path::new("a.gguf"), ModelRole::Chat, 10, || {
+                    calls += 1;
+                    Ok("a".to_string())
+                })
+                .unwrap();
+            assert_eq!(*model, "a");
+        }
+        assert_eq!(calls, 1);
+        // Another role is another model
+        pool.get_or_load(Path::new("a.gguf"), ModelRole::Embedding, 5, || {
+            Ok("a-embed".to_string())
+        })
+        .unwrap();
+        let status = pool.status();
+        assert_eq!((status.loads, status.used_bytes), (2, 15));
+        assert_eq!(status.models[0].role, ModelRole::Embedding);
+        assert_eq!(status.models[1].uses, 3);
+
+        assert!(
+            pool.get_or_load(Path::new("b"), ModelRole::Chat, 1, || {
+                Err(BizClawError::Brain("broken".into()))
+            })
+            .is_err()
+        );
+        assert_eq!(pool.status().models.len(), 2);
+    }
+
+    #[test]
+    fn test_lru_eviction_within_budget() {
+        let pool = ModelPool::new(100);
+        load(&pool, "router", 20).unwrap();
+        load(&pool, "answer", 60).unwrap();
+        pool.get(Path::new("router"), ModelRole::Chat);
+
+        // "answer" is least recently used
+        load(&pool, "embed", 30).unwrap();
+        let names: Vec<String> = pool.status().models.into_iter().map(|m| m.path).collect();
+        assert_eq!(names, ["embed", "router"]);
+        assert_eq!(pool.status().evictions, 1);
+
+        // Models in use stay, even when that means refusing a load
+        let busy = load(&pool, "router", 20).unwrap();
+        let busy2 = load(&pool, "embed", 30).unwrap();
+        assert!(pool.status().models.iter().all(|m| m.in_use));
+        assert!(load(&pool, "answer", 60).is_err());
+        drop((busy, busy2));
+        load(&pool, "answer", 60).unwrap();
+        assert_eq!(pool.status().used_bytes, 90);
+
+        assert!(load(&pool, "huge", 101).is_err());
+        assert!(pool.unload(Path::new("answer"), ModelRole::Chat));
+        assert!(!pool.unload(Path::new("answer"), ModelRole::Chat));
+        assert_eq!(pool.status().used_bytes, 30);
+    }
+}
@@ -0,0 +1,222 @@
+//! Pre-tokenizers for byte-level BPE vocabularies.
+//!
+//! GPT-2 style tokenizers split text into words with a regex before applying
+//! merges, so merges never cross word boundaries. The regexes use look-ahead
+//! (`\s+(?!\S)`), which the `regex` crate does not support, so each pattern
+//! is matched by hand here. `\p{L}` is approximated by `char::is_alphabetic`
+//! and `\p{N}` by `char::is_numeric`.
+
+/// Pre-tokenizer regex family, from `tokenizer.ggml.pre`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreTokenizer {
+    /// GPT-2:
+    /// `'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+`
+    Gpt2,
+    /// LLaMA 3: case-insensitive contractions, one leading non-letter glued
+    /// to words, numbers in groups of up to three, newline runs kept apart.
+    Llama3,
+    /// Qwen2: like LLaMA 3 but every digit is its own pre-token.
+    Qwen2,
+}
+
+impl PreTokenizer {
+    /// Map a `tokenizer.ggml.pre` name; unknown names use the GPT-2 pattern.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "llama3" | "llama-bpe" | "smaug-bpe" | "falcon3" => Self::Llama3,
+            "qwen2" | "deepseek-r1-qwen" => Self::Qwen2,
+            _ => Self::Gpt2,
+        }
+    }
+
+    /// Split `text` into pre-tokens. The pieces concatenate back to `text`.
+    pub fn split<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let mut pieces = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let len = self.match_len(&chars, i).max(1);
+            let start = chars[i].0;
+            let end = chars.get(i + len).map_or(text.len(), |c| c.0);
+            pieces.push(&text[start..end]);
+            i += len;
+        }
+        pieces
+    }
+
+    /// Length in chars of the pre-token starting at char `i`.
+    fn match_len(&self, chars: &[(usize, char)], i: usize) -> usize {
+        let at = |j: usize| chars.get(j).map(|c| c.1);
+        let run = |from: usize, pred: fn(char) -> bool| {
+            chars[from.min(chars.len())..]
+                .iter()
+                .take_while(|c| pred(c.1))
+                .count()
+        };
+        let c = chars[i].1;
+
+        if let Some(n) = contraction(chars, i, *self != Self::Gpt2) {
+            return n;
+        }
+
+        if *self == Self::Gpt2 {
+            // ` ?\p{L}+`, ` ?\p{N}+`, ` ?[^\s\p{L}\p{N}]+`
+            for class in [is_letter, is_number, is_punct] {
+                let lead = usize::from(c == ' ' && at(i + 1).is_some_and(class));
+                let n = run(i + lead, class);
+                if n > 0 {
+                    return lead + n;
+                }
+            }
+        } else {
+            // `[^\r\n\p{L}\p{N}]?\p{L}+`
+            if is_letter(c) {
+                return run(i, is_letter);
+            }
+            if !is_newline(c) && !is_number(c) && at(i + 1).is_some_and(is_letter) {
+                return 1 + run(i + 1, is_letter);
+            }
+            // `\p{N}{1,3}` (Qwen2: `\p{N}`)
+            if is_number(c) {
+                let max = if *self == Self::Qwen2 { 1 } else { 3 };
+                return run(i, is_number).min(max);
+            }
+            // ` ?[^\s\p{L}\p{N}]+[\r\n]*`
+            let lead = usize::from(c == ' ' && at(i + 1).is_some_and(is_punct));
+            let n = run(i + lead, is_punct);
+            if n > 0 {
+                return lead + n + run(i + lead + n, is_newline);
+            }
+            // `\s*[\r\n]+`: whitespace up to and including its last newline
+            let ws = run(i, char::is_whitespace);
+            if let Some(last) = (i..i + ws).rev().find(|&j| is_newline(chars[j].1)) {
+                return last + 1 - i;
+            }
+        }
+
+        // `\s+(?!\S)|\s+`: leave the last space of a run for the next word
+        let ws = run(i, char::is_whitespace);
+        if ws > 1 && i + ws < chars.len() {
+            ws - 1
+        } else {
+            ws
+        }
+    }
+}
+
+/// `'s|'t|'re|'ve|'m|'ll|'d`, optionally case-insensitive.
+fn contraction(chars: &[(usize, char)], i: usize, ignore_case: bool) -> Option<usize> {
+    if chars[i].1 != '\'' {
+        return None;
+    }
+    let lower = |j: usize| {
+        chars.get(j).map(|c| {
+            if ignore_case {
+                c.1.to_ascii_lowercase()
+            } else {
+                c.1
+            }
+        })
+    };
+    match (lower(i + 1)?, lower(i + 2)) {
+        ('r', Some('e')) | ('v', Some('e')) | ('l', Some('l')) => Some(3),
+        ('s' | 't' | 'm' | 'd', _) => Some(2),
+        _ => None,
+    }
+}
+
+fn is_letter(c: char) -> bool {
+    c.is_alphabetic()
+}
+
+fn is_number(c: char) -> bool {
+    c.is_numeric()
+}
+
+fn is_punct(c: char) -> bool {
+    !c.is_whitespace() && !c.is_alphabetic() && !c.is_numeric()
+}
+
+fn is_newline(c: char) -> bool {
+    c == '\r' || c == '\n'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected splits produced by the reference regexes (Python `regex`).
+    const ENGLISH: &str = "Hello world's BEST 12345 items!!\n\n  ok";
+    const VIETNAMESE: &str = "Xin chào Việt Nam, tôi tên là Bình.";
+    const SPACING: &str = "  x\t\"quoted\"  \n y...\r\n";
+    const CAPS: &str = "I'M HERE'S 2024-10-17";
+
+    #[test]
+    fn test_gpt2_split() {
+        let pre = PreTokenizer::Gpt2;
+        assert_eq!(
+            pre.split(ENGLISH),
+            [
+                "Hello", " world", "'s", " BEST", " 12345", " items", "!!", "\n\n ", " ok"
+            ]
+        );
+        assert_eq!(
+            pre.split(VIETNAMESE),
+            [
+                "Xin", " chào", " Việt", " Nam", ",", " tôi", " tên", " là", " Bình", "."
+            ]
+        );
+        assert_eq!(
+            pre.split(SPACING),
+            [
+                " ", " x", "\t", "\"", "quoted", "\"", "  \n", " y", "...", "\r\n"
+            ]
+        );
+        assert_eq!(
+            pre.split(CAPS),
+            [
+                "I", "'", "M", " HERE", "'", "S", " 2024", "-", "10", "-", "17"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_llama3_split() {
+        let pre = PreTokenizer::from_name("llama-bpe");
+        assert_eq!(pre, PreTokenizer::Llama3);
+        assert_eq!(
+            pre.split(ENGLISH),
+            [
+                "Hello", " world", "'s", " BEST", " ", "123", "45", " items", "!!\n\n", " ", " ok"
+            ]
+        );
+        assert_eq!(
+            pre.split(SPACING),
+            [" ", " x", "\t", "\"quoted", "\"", "  \n", " y", "...\r\n"]
+        );
+        assert_eq!(
+            pre.split(CAPS),
+            [
+                "I", "'M", " HERE", "'S", " ", "202", "4", "-", "10", "-", "17"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_qwen2_split() {
+        let pre = PreTokenizer::from_name("qwen2");
+        assert_eq!(
+            pre.split(CAPS),
+            [
+                "I", "'M", " HERE", "'S", " ", "2", "0", "2", "4", "-", "1", "0", "-", "1", "7"
+            ]
+        );
+        assert_eq!(
+            pre.split(VIETNAMESE),
+            [
+                "Xin", " chào", " Việt", " Nam", ",", " tôi", " tên", " là", " Bình", "."
+            ]
+        );
+        assert_eq!(pre.split(ENGLISH).concat(), ENGLISH);
+    }
+}
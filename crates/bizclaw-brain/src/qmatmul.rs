@@ -0,0 +1,562 @@
+//! Fused quantized matmul — dot products directly on quantized weight blocks.
+//!
+//! Activations are quantized once per matmul (Q8_0 for the 32-element block
+//! formats, Q8_K for K-quants). Each weight row is then unpacked one block at
+//! a time into i8 values and reduced with an integer SIMD dot product, so no
+//! dequantized copy of the weight matrix is ever materialised.
+
+use crate::gguf::GgmlType;
+use crate::quant::{self, QK_K, f16_at, scale_min_k4, unpack_q3_k_scales};
+use crate::simd::dot_i8_simd;
+use bizclaw_core::error::{BizClawError, Result};
+
+/// Number of elements in a Q8_0 activation block.
+pub const QK8_0: usize = 32;
+
+/// Activation block for 32-element weight formats (Q4_0, Q5_0, Q8_0).
+#[derive(Debug, Clone, Copy)]
+pub struct BlockQ8_0 {
+    pub d: f32,
+    pub qs: [i8; QK8_0],
+}
+
+/// Activation block for K-quant weights; `bsums` holds the sum of each 16 values.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockQ8K {
+    pub d: f32,
+    pub qs: [i8; QK_K],
+    pub bsums: [i16; 16],
+}
+
+/// Input vector prepared for a particular weight type.
+pub enum QuantizedInput<'a> {
+    /// Plain f32 activations, used with weights that have no integer kernel.
+    F32(&'a [f32]),
+    Q8_0(Vec<BlockQ8_0>),
+    Q8K(Vec<BlockQ8K>),
+}
+
+impl<'a> QuantizedInput<'a> {
+    /// Quantize `input` into the activation format expected by `weight_type`.
+    pub fn for_weight(input: &'a [f32], weight_type: GgmlType) -> Self {
+        let fits = |bs: usize| input.len().is_multiple_of(bs);
+        match weight_type {
+            GgmlType::Q4_0 | GgmlType::Q5_0 | GgmlType::Q8_0 if fits(QK8_0) => {
+                QuantizedInput::Q8_0(quantize_q8_0(input))
+            }
+            GgmlType::Q2K | GgmlType::Q3K | GgmlType::Q4K | GgmlType::Q5K | GgmlType::Q6K
+                if fits(QK_K) =>
+            {
+                QuantizedInput::Q8K(quantize_q8_k(input))
+            }
+            _ => QuantizedInput::F32(input),
+        }
+    }
+}
+
+/// Quantize f32 values to Q8_0 blocks (symmetric, one scale per 32 values).
+pub fn quantize_q8_0(input: &[f32]) -> Vec<BlockQ8_0> {
+    input
+        .chunks_exact(QK8_0)
+        .map(|chunk| {
+            let amax = chunk.iter().fold(0.0f32, |m, &v| m.max(v.abs()));
+            let d = amax / 127.0;
+            let id = if d > 0.0 { 1.0 / d } else { 0.0 };
+            let mut qs = [0i8; QK8_0];
+            for (q, &v) in qs.iter_mut().zip(chunk) {
+                *q = (v * id).round() as i8;
+            }
+            BlockQ8_0 { d, qs }
+        })
+        .collect()
+}
+
+/// Quantize f32 values to Q8_K super-blocks (one scale per 256 values).
+pub fn quantize_q8_k(input: &[f32]) -> Vec<BlockQ8K> {
+    input
+        .chunks_exact(QK_K)
+        .map(|chunk| {
+            let amax = chunk.iter().fold(0.0f32, |m, &v| m.max(v.abs()));
+            let d = amax / 127.0;
+            let id = if d > 0.0 { 1.0 / d } else { 0.0 };
+            let mut qs = [0i8; QK_K];
+            for (q, &v) in qs.iter_mut().zip(chunk) {
+                *q = (v * id).round() as i8;
+            }
+            let mut bsums = [0i16; 16];
+            for (sum, group) in bsums.iter_mut().zip(qs.chunks_exact(16)) {
+                *sum = group.iter().map(|&q| q as i16).sum();
+            }
+            BlockQ8K { d, qs, bsums }
+        })
+        .collect()
+}
+
+/// Dot product of one quantized weight row with a prepared input vector.
+///
+/// `row` must hold exactly `cols` elements of `weight_type`.
+pub fn vec_dot_row(row: &[u8], weight_type: GgmlType, input: &QuantizedInput) -> Result<f32> {
+    match (weight_type, input) {
+        (GgmlType::Q4_0, QuantizedInput::Q8_0(act)) => Ok(vec_dot_q4_0_q8_0(row, act)),
+        (GgmlType::Q5_0, QuantizedInput::Q8_0(act)) => Ok(vec_dot_q5_0_q8_0(row, act)),
+        (GgmlType::Q8_0, QuantizedInput::Q8_0(act)) => Ok(vec_dot_q8_0_q8_0(row, act)),
+        (GgmlType::Q2K, QuantizedInput::Q8K(act)) => Ok(vec_dot_q2_k_q8_k(row, act)),
+        (GgmlType::Q3K, QuantizedInput::Q8K(act)) => Ok(vec_dot_q3_k_q8_k(row, act)),
+        (GgmlType::Q4K, QuantizedInput::Q8K(act)) => Ok(vec_dot_q4_k_q8_k(row, act)),
+        (GgmlType::Q5K, QuantizedInput::Q8K(act)) => Ok(vec_dot_q5_k_q8_k(row, act)),
+        (GgmlType::Q6K, QuantizedInput::Q8K(act)) => Ok(vec_dot_q6_k_q8_k(row, act)),
+        (_, QuantizedInput::F32(x)) => vec_dot_dequant_f32(row, weight_type, x),
+        (other, _) => Err(BizClawError::Brain(format!(
+            "No fused kernel for {other:?} with this activation format"
+        ))),
+    }
+}
+
+/// Fallback: dequantize one chunk of the row at a time and dot with f32 input.
+fn vec_dot_dequant_f32(row: &[u8], weight_type: GgmlType, input: &[f32]) -> Result<f32> {
+    const CHUNK: usize = QK_K;
+    let block_size = weight_type.block_size();
+    let type_size = weight_type.type_size();
+    let mut buf = [0.0f32; CHUNK];
+    let mut sum = 0.0f32;
+    let mut col = 0;
+    while col < input.len() {
+        let n = CHUNK.min(input.len() - col);
+        let byte_start = col / block_size * type_size;
+        quant::dequantize_row(&row[byte_start..], &mut buf[..n], n, weight_type)?;
+        sum += crate::simd::dot_product_simd(&buf[..n], &input[col..col + n]);
+        col += n;
+    }
+    Ok(sum)
+}
+
+/// Q4_0 weights · Q8_0 activations.
+pub fn vec_dot_q4_0_q8_0(row: &[u8], act: &[BlockQ8_0]) -> f32 {
+    let mut w = [0i8; QK8_0];
+    let mut sum = 0.0f32;
+    for (block, a) in row.chunks_exact(18).zip(act) {
+        for (i, &byte) in block[2..18].iter().enumerate() {
+            w[i] = (byte & 0x0F) as i8 - 8;
+            w[i + 16] = (byte >> 4) as i8 - 8;
+        }
+        sum += f16_at(block, 0) * a.d * dot_i8_simd(&w, &a.qs) as f32;
+    }
+    sum
+}
+
+/// Q5_0 weights · Q8_0 activations.
+pub fn vec_dot_q5_0_q8_0(row: &[u8], act: &[BlockQ8_0]) -> f32 {
+    let mut w = [0i8; QK8_0];
+    let mut sum = 0.0f32;
+    for (block, a) in row.chunks_exact(22).zip(act) {
+        let qh = u32::from_le_bytes([block[2], block[3], block[4], block[5]]);
+        for (j, &byte) in block[6..22].iter().enumerate() {
+            let xh_0 = (((qh >> j) << 4) & 0x10) as u8;
+            let xh_1 = ((qh >> (j + 12)) & 0x10) as u8;
+            w[j] = ((byte & 0x0F) | xh_0) as i8 - 16;
+            w[j + 16] = ((byte >> 4) | xh_1) as i8 - 16;
+        }
+        sum += f16_at(block, 0) * a.d * dot_i8_simd(&w, &a.qs) as f32;
+    }
+    sum
+}
+
+/// Q8_0 weights · Q8_0 activations.
+pub fn vec_dot_q8_0_q8_0(row: &[u8], act: &[BlockQ8_0]) -> f32 {
+    let mut w = [0i8; QK8_0];
+    let mut sum = 0.0f32;
+    for (block, a) in row.chunks_exact(34).zip(act) {
+        for (q, &byte) in w.iter_mut().zip(&block[2..34]) {
+            *q = byte as i8;
+        }
+        sum += f16_at(block, 0) * a.d * dot_i8_simd(&w, &a.qs) as f32;
+    }
+    sum
+}
+
+/// Q2_K weights · Q8_K activations.
+pub fn vec_dot_q2_k_q8_k(row: &[u8], act: &[BlockQ8K]) -> f32 {
+    let mut w = [0i8; QK_K];
+    let mut sum = 0.0f32;
+    for (block, a) in row.chunks_exact(84).zip(act) {
+        let scales = &block[0..16];
+        let mut y = 0;
+        for n in 0..2 {
+            let q = &block[16 + n * 32..16 + (n + 1) * 32];
+            for shift in [0, 2, 4, 6] {
+                for &byte in q {
+                    w[y] = ((byte >> shift) & 3) as i8;
+                    y += 1;
+                }
+            }
+        }
+
+        let mut sumi = 0i32;
+        let mut summ = 0i32;
+        for (g, &sc) in scales.iter().enumerate() {
+            let r = g * 16..g * 16 + 16;
+            sumi += (sc & 0x0F) as i32 * dot_i8_simd(&w[r.clone()], &a.qs[r]);
+            summ += (sc >> 4) as i32 * a.bsums[g] as i32;
+        }
+        sum += a.d * (f16_at(block, 80) * sumi as f32 - f16_at(block, 82) * summ as f32);
+    }
+    sum
+}
+
+/// Q3_K weights · Q8_K activations.
+pub fn vec_dot_q3_k_q8_k(row: &[u8], act: &[BlockQ8K]) -> f32 {
+    let mut w = [0i8; QK_K];
+    let mut sum = 0.0f32;
+    for (block, a) in row.chunks_exact(110).zip(act) {
+        let hmask = &block[0..32];
+        let scales = unpack_q3_k_scales(&block[96..108]);
+        let mut y = 0;
+        let mut m = 1u8;
+        for n in 0..2 {
+            let q = &block[32 + n * 32..32 + (n + 1) * 32];
+            for shift in [0, 2, 4, 6] {
+                for (l, &byte) in q.iter().enumerate() {
+                    let high = if hmask[l] & m != 0 { 0 } else { 4 };
+                    w[y] = ((byte >> shift) & 3) as i8 - high;
+                    y += 1;
+                }
+                m <<= 1;
+            }
+        }
+
+        let mut sumi = 0i32;
+        for (g, &sc) in scales.iter().enumerate() {
+            let r = g * 16..g * 16 + 16;
+            sumi += (sc as i32 - 32) * dot_i8_simd(&w[r.clone()], &a.qs[r]);
+        }
+        sum += a.d * f16_at(block, 108) * sumi as f32;
+    }
+    sum
+}
+
+/// Q4_K weights · Q8_K activations.
+pub fn vec_dot_q4_k_q8_k(row: &[u8], act: &[BlockQ8K]) -> f32 {
+    let mut w = [0i8; QK_K];
+    let mut sum = 0.0f32;
+    for (block, a) in row.chunks_exact(144).zip(act) {
+        let scales = &block[4..16];
+        for (chunk, q) in block[16..144].chunks_exact(32).enumerate() {
+            let base = chunk * 64;
+            for (l, &byte) in q.iter().enumerate() {
+                w[base + l] = (byte & 0x0F) as i8;
+                w[base + 32 + l] = (byte >> 4) as i8;
+            }
+        }
+        sum += k4_sub_block_sum(block, scales, &w, a);
+    }
+    sum
+}
+
+/// Q5_K weights · Q8_K activations.
+pub fn vec_dot_q5_k_q8_k(row: &[u8], act: &[BlockQ8K]) -> f32 {
+    let mut w = [0i8; QK_K];
+    let mut sum = 0.0f32;
+    for (block, a) in row.chunks_exact(176).zip(act) {
+        let scales = &block[4..16];
+        let qh = &block[16..48];
+        let mut u1 = 1u8;
+        let mut u2 = 2u8;
+        for (chunk, ql) in block[48..176].chunks_exact(32).enumerate() {
+            let base = chunk * 64;
+            for (l, &byte) in ql.iter().enumerate() {
+                let h1 = if qh[l] & u1 != 0 { 16 } else { 0 };
+                let h2 = if qh[l] & u2 != 0 { 16 } else { 0 };
+                w[base + l] = ((byte & 0x0F) + h1) as i8;
+                w[base + 32 + l] = ((byte >> 4) + h2) as i8;
+            }
+            u1 <<= 2;
+            u2 <<= 2;
+        }
+        sum += k4_sub_block_sum(block, scales, &w, a);
+    }
+    sum
+}
+
+/// Shared Q4_K/Q5_K reduction: 8 sub-blocks of 32 with 6-bit scales and mins.
+#[inline]
+fn k4_sub_block_sum(block: &[u8], scales: &[u8], w: &[i8; QK_K], a: &BlockQ8K) -> f32 {
+    let mut sumi = 0i32;
+    let mut summ = 0i32;
+    for s in 0..8 {
+        let (sc, m) = scale_min_k4(s, scales);
+        let r = s * 32..s * 32 + 32;
+        sumi += sc as i32 * dot_i8_simd(&w[r.clone()], &a.qs[r]);
+        summ += m as i32 * (a.bsums[2 * s] as i32 + a.bsums[2 * s + 1] as i32);
+    }
+    a.d * (f16_at(block, 0) * sumi as f32 - f16_at(block, 2) * summ as f32)
+}
+
+/// Q6_K weights · Q8_K activations.
+pub fn vec_dot_q6_k_q8_k(row: &[u8], act: &[BlockQ8K]) -> f32 {
+    let mut w = [0i8; QK_K];
+    let mut sum = 0.0f32;
+    for (block, a) in row.chunks_exact(210).zip(act) {
+        for n in 0..2 {
+            let ql = &block[n * 64..n * 64 + 64];
+            let qh = &block[128 + n * 32..128 + n * 32 + 32];
+            let y = &mut w[n * 128..n * 128 + 128];
+            for l in 0..32 {
+                y[l] = ((ql[l] & 0x0F) | ((qh[l] & 3) << 4)) as i8 - 32;
+                y[l + 32] = ((ql[l + 32] & 0x0F) | (((qh[l] >> 2) & 3) << 4)) as i8 - 32;
+                y[l + 64] = ((ql[l] >> 4) | (((qh[l] >> 4) & 3) << 4)) as i8 - 32;
+                y[l + 96] = ((ql[l + 32] >> 4) | (((qh[l] >> 6) & 3) << 4)) as i8 - 32;
+            }
+        }
+
+        let mut sumi = 0i32;
+        for (g, &sc) in block[192..208].iter().enumerate() {
+            let r = g * 16..g * 16 + 16;
+            sumi += (sc as i8) as i32 * dot_i8_simd(&w[r.clone()], &a.qs[r]);
+        }
+        sum += a.d * f16_at(block, 208) * sumi as f32;
+    }
+    sum
+}
+
+/// Quantized matrix-vector multiply: output[rows] = weight[rows x cols] @ input[cols].
+///
+/// `weight` is the raw GGUF tensor data; rows are computed in parallel.
+pub fn matmul(
+    output: &mut [f32],
+    weight: &[u8],
+    weight_type: GgmlType,
+    input: &[f32],
+    rows: usize,
+    cols: usize,
+) -> Result<()> {
+    let block_size = weight_type.block_size();
+    if !cols.is_multiple_of(block_size) || input.len() != cols || output.len() < rows {
+        return Err(BizClawError::Brain(format!(
+            "{weight_type:?} matmul: bad shape rows={rows} cols={cols} input={} output={}",
+            input.len(),
+            output.len()
+        )));
+    }
+    let row_bytes = cols / block_size * weight_type.type_size();
+    if weight.len() < rows * row_bytes {
+        return Err(BizClawError::Brain(format!(
+            "{weight_type:?} matmul: weight data too small ({}B for {rows}x{cols})",
+            weight.len()
+        )));
+    }
+
+    let prepared = QuantizedInput::for_weight(input, weight_type);
+    crate::thread_pool::matmul_quantized_parallel(
+        &mut output[..rows],
+        weight,
+        row_bytes,
+        weight_type,
+        &prepared,
+    )
+}
+
+/// Batched `matmul`: output[n x rows] = input[n x cols] @ weight[rows x cols]^T.
+///
+/// Each weight row is read once and dotted against all `n_tokens` inputs,
+/// which is what makes prompt prefill cheaper than `n_tokens` single calls.
+pub fn matmul_batch(
+    output: &mut [f32],
+    weight: &[u8],
+    weight_type: GgmlType,
+    input: &[f32],
+    n_tokens: usize,
+    rows: usize,
+    cols: usize,
+) -> Result<()> {
+    let block_size = weight_type.block_size();
+    if !cols.is_multiple_of(block_size)
+        || input.len() != n_tokens * cols
+        || output.len() < n_tokens * rows
+    {
+        return Err(BizClawError::Brain(format!(
+            "{weight_type:?} batched matmul: bad shape n={n_tokens} rows={rows} cols={cols} input={} output={}",
+            input.len(),
+            output.len()
+        )));
+    }
+    let row_bytes = cols / block_size * weight_type.type_size();
+    if weight.len() < rows * row_bytes {
+        return Err(BizClawError::Brain(format!(
+            "{weight_type:?} batched matmul: weight data too small ({}B for {rows}x{cols})",
+            weight.len()
+        )));
+    }
+
+    let prepared: Vec<QuantizedInput> = input
+        .chunks_exact(cols)
+        .map(|x| QuantizedInput::for_weight(x, weight_type))
+        .collect();
+
+    // Row-major scratch [rows x n], then transpose to token-major output.
+    let mut scratch = vec![0.0f32; rows * n_tokens];
+    crate::thread_pool::matmul_quantized_batch_parallel(
+        &mut scratch,
+        weight,
+        row_bytes,
+        weight_type,
+        &prepared,
+    )?;
+    for (r, per_token) in scratch.chunks_exact(n_tokens).enumerate() {
+        for (t, &val) in per_token.iter().enumerate() {
+            output[t * rows + r] = val;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    /// Random weight bytes with sane f16 scales at the given offsets.
+    fn random_blocks(
+        rng: &mut rand::rngs::StdRng,
+        n_blocks: usize,
+        ty: GgmlType,
+        scale_offsets: &[usize],
+    ) -> Vec<u8> {
+        let ts = ty.type_size();
+        let mut data: Vec<u8> = (0..n_blocks * ts).map(|_| rng.r#gen()).collect();
+        for b in 0..n_blocks {
+            for &off in scale_offsets {
+                let v = half::f16::from_f32(rng.gen_range(0.001..0.05));
+                data[b * ts + off..b * ts + off + 2].copy_from_slice(&v.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    fn reference_matmul(weight: &[u8], ty: GgmlType, input: &[f32], rows: usize) -> Vec<f32> {
+        let cols = input.len();
+        let mut dense = vec![0.0f32; rows * cols];
+        quant::dequantize_row(weight, &mut dense, rows * cols, ty).unwrap();
+        let mut out = vec![0.0f32; rows];
+        crate::tensor::matmul(&mut out, &dense, input, rows, cols);
+        out
+    }
+
+    fn check_type(ty: GgmlType, scale_offsets: &[usize]) {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let (rows, cols) = (8, 512);
+        let weight = random_blocks(&mut rng, rows * cols / ty.block_size(), ty, scale_offsets);
+        let input: Vec<f32> = (0..cols).map(|_| rng.gen_range(-1.0..1.0)).collect();
+
+        let expected = reference_matmul(&weight, ty, &input, rows);
+        let mut output = vec![0.0f32; rows];
+        matmul(&mut output, &weight, ty, &input, rows, cols).unwrap();
+
+        for (r, (&got, &want)) in output.iter().zip(&expected).enumerate() {
+            let scale = expected.iter().fold(1.0f32, |m, v| m.max(v.abs()));
+            assert!(
+                (got - want).abs() / scale < 0.02,
+                "{ty:?} row {r}: fused={got} reference={want}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fused_q4_0() {
+        check_type(GgmlType::Q4_0, &[0]);
+    }
+
+    #[test]
+    fn test_fused_q5_0() {
+        check_type(GgmlType::Q5_0, &[0]);
+    }
+
+    #[test]
+    fn test_fused_q8_0() {
+        check_type(GgmlType::Q8_0, &[0]);
+    }
+
+    #[test]
+    fn test_fused_q2_k() {
+        check_type(GgmlType::Q2K, &[80, 82]);
+    }
+
+    #[test]
+    fn test_fused_q3_k() {
+        check_type(GgmlType::Q3K, &[108]);
+    }
+
+    #[test]
+    fn test_fused_q4_k() {
+        check_type(GgmlType::Q4K, &[0, 2]);
+    }
+
+    #[test]
+    fn test_fused_q5_k() {
+        check_type(GgmlType::Q5K, &[0, 2]);
+    }
+
+    #[test]
+    fn test_fused_q6_k() {
+        check_type(GgmlType::Q6K, &[208]);
+    }
+
+    #[test]
+    fn test_fallback_q4_1() {
+        check_type(GgmlType::Q4_1, &[0, 2]);
+    }
+
+    #[test]
+    fn test_fallback_f32() {
+        let weight: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let mut output = vec![0.0f32; 2];
+        matmul(&mut output, &weight, GgmlType::F32, &[1.0, 1.0, 1.0], 2, 3).unwrap();
+        assert_eq!(output, vec![6.0, 15.0]);
+    }
+
+    #[test]
+    fn test_matmul_batch_matches_single() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let (n, rows, cols) = (5, 6, 256);
+        let ty = GgmlType::Q4K;
+        let weight = random_blocks(&mut rng, rows * cols / ty.block_size(), ty, &[0, 2]);
+        let input: Vec<f32> = (0..n * cols).map(|_| rng.gen_range(-1.0..1.0)).collect();
+
+        let mut batched = vec![0.0f32; n * rows];
+        matmul_batch(&mut batched, &weight, ty, &input, n, rows, cols).unwrap();
+
+        for t in 0..n {
+            let mut single = vec![0.0f32; rows];
+            matmul(
+                &mut single,
+                &weight,
+                ty,
+                &input[t * cols..(t + 1) * cols],
+                rows,
+                cols,
+            )
+            .unwrap();
+            assert_eq!(&batched[t * rows..(t + 1) * rows], &single[..]);
+        }
+    }
+
+    #[test]
+    fn test_quantize_q8_k_bsums() {
+        let input: Vec<f32> = (0..QK_K).map(|i| (i % 16) as f32 - 8.0).collect();
+        let blocks = quantize_q8_k(&input);
+        let b = &blocks[0];
+        for g in 0..16 {
+            let s: i16 = b.qs[g * 16..g * 16 + 16].iter().map(|&q| q as i16).sum();
+            assert_eq!(b.bsums[g], s);
+        }
+    }
+
+    #[test]
+    fn test_matmul_rejects_bad_shape() {
+        let weight = vec![0u8; 18];
+        let mut output = vec![0.0f32; 1];
+        assert!(matmul(&mut output, &weight, GgmlType::Q4_0, &[0.0; 16], 1, 16).is_err());
+    }
+}
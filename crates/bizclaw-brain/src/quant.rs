@@ -1,22 +1,100 @@
 //! Quantization kernels — dequantize quantized weight blocks to f32.
 //!
-//! Supports Q4_0, Q4_K_M, Q6_K, Q8_0 formats used by GGUF models.
+//! Supports every GGML block format that `GgmlType::from_u32` accepts:
+//! F32, F16, Q4_0, Q4_1, Q5_0, Q5_1, Q8_0, Q8_1 and the K-quants
+//! Q2_K, Q3_K, Q4_K, Q5_K, Q6_K, Q8_K. Block layouts follow ggml exactly.
 
-use bizclaw_core::error::Result;
+use crate::gguf::GgmlType;
+use bizclaw_core::error::{BizClawError, Result};
+
+/// Number of elements in a K-quant super-block.
+pub const QK_K: usize = 256;
+
+/// Read a little-endian f16 at `offset` and widen to f32.
+#[inline(always)]
+pub(crate) fn f16_at(block: &[u8], offset: usize) -> f32 {
+    half::f16::from_le_bytes([block[offset], block[offset + 1]]).to_f32()
+}
+
+/// Read a little-endian f32 at `offset`.
+#[inline(always)]
+fn f32_at(block: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes([
+        block[offset],
+        block[offset + 1],
+        block[offset + 2],
+        block[offset + 3],
+    ])
+}
 
 /// Dequantize Q4_0 block (18 bytes → 32 f32 values).
 /// Format: scale (f16, 2 bytes) + 16 bytes of 4-bit quantized values.
+/// Low nibbles hold elements 0..16, high nibbles hold elements 16..32.
 pub fn dequantize_q4_0(block: &[u8], output: &mut [f32]) {
     debug_assert!(block.len() >= 18);
     debug_assert!(output.len() >= 32);
 
-    let scale = half::f16::from_le_bytes([block[0], block[1]]).to_f32();
+    let scale = f16_at(block, 0);
 
     for (i, &byte) in block[2..18].iter().enumerate() {
         let lo = (byte & 0x0F) as f32 - 8.0;
-        let hi = ((byte >> 4) & 0x0F) as f32 - 8.0;
-        output[i * 2] = lo * scale;
-        output[i * 2 + 1] = hi * scale;
+        let hi = (byte >> 4) as f32 - 8.0;
+        output[i] = lo * scale;
+        output[i + 16] = hi * scale;
+    }
+}
+
+/// Dequantize Q4_1 block (20 bytes → 32 f32 values).
+/// Format: scale (f16) + min (f16) + 16 bytes of 4-bit quantized values.
+pub fn dequantize_q4_1(block: &[u8], output: &mut [f32]) {
+    debug_assert!(block.len() >= 20);
+    debug_assert!(output.len() >= 32);
+
+    let scale = f16_at(block, 0);
+    let min = f16_at(block, 2);
+
+    for (i, &byte) in block[4..20].iter().enumerate() {
+        output[i] = (byte & 0x0F) as f32 * scale + min;
+        output[i + 16] = (byte >> 4) as f32 * scale + min;
+    }
+}
+
+/// Dequantize Q5_0 block (22 bytes → 32 f32 values).
+/// Format: scale (f16) + 4 bytes of high bits + 16 bytes of low nibbles.
+pub fn dequantize_q5_0(block: &[u8], output: &mut [f32]) {
+    debug_assert!(block.len() >= 22);
+    debug_assert!(output.len() >= 32);
+
+    let scale = f16_at(block, 0);
+    let qh = u32::from_le_bytes([block[2], block[3], block[4], block[5]]);
+
+    for (j, &byte) in block[6..22].iter().enumerate() {
+        let xh_0 = (((qh >> j) << 4) & 0x10) as u8;
+        let xh_1 = ((qh >> (j + 12)) & 0x10) as u8;
+        let x0 = ((byte & 0x0F) | xh_0) as i32 - 16;
+        let x1 = ((byte >> 4) | xh_1) as i32 - 16;
+        output[j] = x0 as f32 * scale;
+        output[j + 16] = x1 as f32 * scale;
+    }
+}
+
+/// Dequantize Q5_1 block (24 bytes → 32 f32 values).
+/// Format: scale (f16) + min (f16) + 4 bytes of high bits + 16 bytes of low nibbles.
+pub fn dequantize_q5_1(block: &[u8], output: &mut [f32]) {
+    debug_assert!(block.len() >= 24);
+    debug_assert!(output.len() >= 32);
+
+    let scale = f16_at(block, 0);
+    let min = f16_at(block, 2);
+    let qh = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    for (j, &byte) in block[8..24].iter().enumerate() {
+        let xh_0 = (((qh >> j) << 4) & 0x10) as u8;
+        let xh_1 = ((qh >> (j + 12)) & 0x10) as u8;
+        let x0 = (byte & 0x0F) | xh_0;
+        let x1 = (byte >> 4) | xh_1;
+        output[j] = x0 as f32 * scale + min;
+        output[j + 16] = x1 as f32 * scale + min;
     }
 }
 
@@ -26,59 +104,300 @@ pub fn dequantize_q8_0(block: &[u8], output: &mut [f32]) {
     debug_assert!(block.len() >= 34);
     debug_assert!(output.len() >= 32);
 
-    let scale = half::f16::from_le_bytes([block[0], block[1]]).to_f32();
+    let scale = f16_at(block, 0);
 
     for (out, &byte) in output.iter_mut().zip(&block[2..34]) {
         *out = byte as i8 as f32 * scale;
     }
 }
 
+/// Dequantize Q8_1 block (36 bytes → 32 f32 values).
+/// Format: scale (f16) + sum (f16, unused here) + 32 bytes of 8-bit values.
+pub fn dequantize_q8_1(block: &[u8], output: &mut [f32]) {
+    debug_assert!(block.len() >= 36);
+    debug_assert!(output.len() >= 32);
+
+    let scale = f16_at(block, 0);
+
+    for (out, &byte) in output.iter_mut().zip(&block[4..36]) {
+        *out = byte as i8 as f32 * scale;
+    }
+}
+
+/// Dequantize Q2_K super-block (84 bytes → 256 f32 values).
+/// Format: 16 bytes of 4-bit scale/min pairs + 64 bytes of 2-bit values
+/// + d (f16) + dmin (f16).
+pub fn dequantize_q2_k(block: &[u8], output: &mut [f32]) {
+    debug_assert!(block.len() >= 84);
+    debug_assert!(output.len() >= QK_K);
+
+    let scales = &block[0..16];
+    let d = f16_at(block, 80);
+    let min = f16_at(block, 82);
+
+    let mut y = 0;
+    let mut is = 0;
+    for n in 0..2 {
+        let q = &block[16 + n * 32..16 + (n + 1) * 32];
+        for shift in [0, 2, 4, 6] {
+            for half in 0..2 {
+                let sc = scales[is];
+                is += 1;
+                let dl = d * (sc & 0x0F) as f32;
+                let ml = min * (sc >> 4) as f32;
+                for &byte in &q[half * 16..half * 16 + 16] {
+                    output[y] = dl * ((byte >> shift) & 3) as f32 - ml;
+                    y += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Dequantize Q3_K super-block (110 bytes → 256 f32 values).
+/// Format: 32 bytes of high-bit mask + 64 bytes of 2-bit values
+/// + 12 bytes of packed 6-bit scales + d (f16).
+pub fn dequantize_q3_k(block: &[u8], output: &mut [f32]) {
+    debug_assert!(block.len() >= 110);
+    debug_assert!(output.len() >= QK_K);
+
+    let hmask = &block[0..32];
+    let d_all = f16_at(block, 108);
+    let scales = unpack_q3_k_scales(&block[96..108]);
+
+    let mut y = 0;
+    let mut is = 0;
+    let mut m = 1u8;
+    for n in 0..2 {
+        let q = &block[32 + n * 32..32 + (n + 1) * 32];
+        for shift in [0, 2, 4, 6] {
+            for half in 0..2 {
+                let dl = d_all * (scales[is] as i32 - 32) as f32;
+                is += 1;
+                for l in half * 16..half * 16 + 16 {
+                    let low = ((q[l] >> shift) & 3) as i32;
+                    let high = if hmask[l] & m != 0 { 0 } else { 4 };
+                    output[y] = dl * (low - high) as f32;
+                    y += 1;
+                }
+            }
+            m <<= 1;
+        }
+    }
+}
+
+/// Unpack the 16 packed 6-bit scales of a Q3_K super-block (still offset by 32).
+pub(crate) fn unpack_q3_k_scales(raw: &[u8]) -> [i8; 16] {
+    const KMASK1: u32 = 0x0303_0303;
+    const KMASK2: u32 = 0x0f0f_0f0f;
+
+    let mut aux = [
+        u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]),
+        u32::from_le_bytes([raw[4], raw[5], raw[6], raw[7]]),
+        u32::from_le_bytes([raw[8], raw[9], raw[10], raw[11]]),
+        0,
+    ];
+    let tmp = aux[2];
+    aux[2] = ((aux[0] >> 4) & KMASK2) | (((tmp >> 4) & KMASK1) << 4);
+    aux[3] = ((aux[1] >> 4) & KMASK2) | (((tmp >> 6) & KMASK1) << 4);
+    aux[0] = (aux[0] & KMASK2) | ((tmp & KMASK1) << 4);
+    aux[1] = (aux[1] & KMASK2) | (((tmp >> 2) & KMASK1) << 4);
+
+    let mut scales = [0i8; 16];
+    for (i, word) in aux.iter().enumerate() {
+        for (k, b) in word.to_le_bytes().iter().enumerate() {
+            scales[i * 4 + k] = *b as i8;
+        }
+    }
+    scales
+}
+
+/// Unpack the j-th 6-bit (scale, min) pair used by Q4_K and Q5_K.
+#[inline(always)]
+pub(crate) fn scale_min_k4(j: usize, q: &[u8]) -> (u8, u8) {
+    if j < 4 {
+        (q[j] & 63, q[j + 4] & 63)
+    } else {
+        (
+            (q[j + 4] & 0x0F) | ((q[j - 4] >> 6) << 4),
+            (q[j + 4] >> 4) | ((q[j] >> 6) << 4),
+        )
+    }
+}
+
+/// Dequantize Q4_K super-block (144 bytes → 256 f32 values).
+/// Format: d (f16) + dmin (f16) + 12 bytes of packed 6-bit scales/mins
+/// + 128 bytes of 4-bit values.
+pub fn dequantize_q4_k(block: &[u8], output: &mut [f32]) {
+    debug_assert!(block.len() >= 144);
+    debug_assert!(output.len() >= QK_K);
+
+    let d = f16_at(block, 0);
+    let min = f16_at(block, 2);
+    let scales = &block[4..16];
+
+    let mut y = 0;
+    for (chunk, q) in block[16..144].chunks_exact(32).enumerate() {
+        let (sc1, m1) = scale_min_k4(chunk * 2, scales);
+        let (sc2, m2) = scale_min_k4(chunk * 2 + 1, scales);
+        let (d1, m1) = (d * sc1 as f32, min * m1 as f32);
+        let (d2, m2) = (d * sc2 as f32, min * m2 as f32);
+        for &byte in q {
+            output[y] = d1 * (byte & 0x0F) as f32 - m1;
+            output[y + 32] = d2 * (byte >> 4) as f32 - m2;
+            y += 1;
+        }
+        y += 32;
+    }
+}
+
+/// Dequantize Q5_K super-block (176 bytes → 256 f32 values).
+/// Format: d (f16) + dmin (f16) + 12 bytes of packed 6-bit scales/mins
+/// + 32 bytes of high bits + 128 bytes of low nibbles.
+pub fn dequantize_q5_k(block: &[u8], output: &mut [f32]) {
+    debug_assert!(block.len() >= 176);
+    debug_assert!(output.len() >= QK_K);
+
+    let d = f16_at(block, 0);
+    let min = f16_at(block, 2);
+    let scales = &block[4..16];
+    let qh = &block[16..48];
+
+    let mut y = 0;
+    let mut u1 = 1u8;
+    let mut u2 = 2u8;
+    for (chunk, ql) in block[48..176].chunks_exact(32).enumerate() {
+        let (sc1, m1) = scale_min_k4(chunk * 2, scales);
+        let (sc2, m2) = scale_min_k4(chunk * 2 + 1, scales);
+        let (d1, m1) = (d * sc1 as f32, min * m1 as f32);
+        let (d2, m2) = (d * sc2 as f32, min * m2 as f32);
+        for (l, &byte) in ql.iter().enumerate() {
+            let h1 = if qh[l] & u1 != 0 { 16 } else { 0 };
+            let h2 = if qh[l] & u2 != 0 { 16 } else { 0 };
+            output[y] = d1 * ((byte & 0x0F) + h1) as f32 - m1;
+            output[y + 32] = d2 * ((byte >> 4) + h2) as f32 - m2;
+            y += 1;
+        }
+        y += 32;
+        u1 <<= 2;
+        u2 <<= 2;
+    }
+}
+
+/// Dequantize Q6_K super-block (210 bytes → 256 f32 values).
+/// Format: 128 bytes of low nibbles + 64 bytes of 2-bit high parts
+/// + 16 signed 8-bit scales + d (f16).
+pub fn dequantize_q6_k(block: &[u8], output: &mut [f32]) {
+    debug_assert!(block.len() >= 210);
+    debug_assert!(output.len() >= QK_K);
+
+    let d = f16_at(block, 208);
+
+    for n in 0..2 {
+        let ql = &block[n * 64..n * 64 + 64];
+        let qh = &block[128 + n * 32..128 + n * 32 + 32];
+        let sc = &block[192 + n * 8..192 + n * 8 + 8];
+        let y = &mut output[n * 128..n * 128 + 128];
+        for l in 0..32 {
+            let is = l / 16;
+            let q1 = ((ql[l] & 0x0F) | ((qh[l] & 3) << 4)) as i32 - 32;
+            let q2 = ((ql[l + 32] & 0x0F) | (((qh[l] >> 2) & 3) << 4)) as i32 - 32;
+            let q3 = ((ql[l] >> 4) | (((qh[l] >> 4) & 3) << 4)) as i32 - 32;
+            let q4 = ((ql[l + 32] >> 4) | (((qh[l] >> 6) & 3) << 4)) as i32 - 32;
+            y[l] = d * (sc[is] as i8) as f32 * q1 as f32;
+            y[l + 32] = d * (sc[is + 2] as i8) as f32 * q2 as f32;
+            y[l + 64] = d * (sc[is + 4] as i8) as f32 * q3 as f32;
+            y[l + 96] = d * (sc[is + 6] as i8) as f32 * q4 as f32;
+        }
+    }
+}
+
+/// Dequantize Q8_K super-block (292 bytes → 256 f32 values).
+/// Format: d (f32) + 256 bytes of 8-bit values + 16 i16 block sums (unused here).
+pub fn dequantize_q8_k(block: &[u8], output: &mut [f32]) {
+    debug_assert!(block.len() >= 292);
+    debug_assert!(output.len() >= QK_K);
+
+    let d = f32_at(block, 0);
+
+    for (out, &byte) in output.iter_mut().zip(&block[4..4 + QK_K]) {
+        *out = byte as i8 as f32 * d;
+    }
+}
+
+/// Dequantizes one block of raw bytes into `block_size` f32 values.
+type BlockKernel = fn(&[u8], &mut [f32]);
+
+/// Look up the block dequantization kernel for a quantized type.
+fn block_kernel(ggml_type: GgmlType) -> Option<BlockKernel> {
+    let kernel: BlockKernel = match ggml_type {
+        GgmlType::Q4_0 => dequantize_q4_0,
+        GgmlType::Q4_1 => dequantize_q4_1,
+        GgmlType::Q5_0 => dequantize_q5_0,
+        GgmlType::Q5_1 => dequantize_q5_1,
+        GgmlType::Q8_0 => dequantize_q8_0,
+        GgmlType::Q8_1 => dequantize_q8_1,
+        GgmlType::Q2K => dequantize_q2_k,
+        GgmlType::Q3K => dequantize_q3_k,
+        GgmlType::Q4K => dequantize_q4_k,
+        GgmlType::Q5K => dequantize_q5_k,
+        GgmlType::Q6K => dequantize_q6_k,
+        GgmlType::Q8K => dequantize_q8_k,
+        _ => return None,
+    };
+    Some(kernel)
+}
+
 /// Dequantize a full row of quantized data to f32.
 /// Dispatches to the correct dequantization kernel based on type.
+///
+/// Returns an error for types without a kernel, for element counts that
+/// are not a whole number of blocks, and for truncated input data.
 pub fn dequantize_row(
     data: &[u8],
     output: &mut [f32],
     n_elements: usize,
-    ggml_type: crate::gguf::GgmlType,
+    ggml_type: GgmlType,
 ) -> Result<()> {
+    let block_size = ggml_type.block_size();
+    let type_size = ggml_type.type_size();
+
+    if !n_elements.is_multiple_of(block_size) {
+        return Err(BizClawError::Brain(format!(
+            "{ggml_type:?}: {n_elements} elements is not a multiple of block size {block_size}"
+        )));
+    }
+    let n_blocks = n_elements / block_size;
+    if data.len() < n_blocks * type_size || output.len() < n_elements {
+        return Err(BizClawError::Brain(format!(
+            "{ggml_type:?}: buffer too small for {n_elements} elements (data={}B, output={})",
+            data.len(),
+            output.len()
+        )));
+    }
+
     match ggml_type {
-        crate::gguf::GgmlType::F32 => {
+        GgmlType::F32 => {
             // Direct copy from bytes to f32
             for (out, chunk) in output.iter_mut().zip(data.chunks_exact(4)).take(n_elements) {
                 *out = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
             }
         }
-        crate::gguf::GgmlType::F16 => {
+        GgmlType::F16 => {
             for (out, chunk) in output.iter_mut().zip(data.chunks_exact(2)).take(n_elements) {
                 *out = half::f16::from_le_bytes([chunk[0], chunk[1]]).to_f32();
             }
         }
-        crate::gguf::GgmlType::Q4_0 => {
-            let block_size = 32;
-            let type_size = 18;
-            let n_blocks = n_elements / block_size;
-            for b in 0..n_blocks {
-                let block_data = &data[b * type_size..];
-                dequantize_q4_0(block_data, &mut output[b * block_size..]);
-            }
-        }
-        crate::gguf::GgmlType::Q8_0 => {
-            let block_size = 32;
-            let type_size = 34;
-            let n_blocks = n_elements / block_size;
-            for b in 0..n_blocks {
-                let block_data = &data[b * type_size..];
-                dequantize_q8_0(block_data, &mut output[b * block_size..]);
-            }
-        }
-        _ => {
-            // For unsupported types, fill with zeros
-            tracing::warn!(
-                "Unsupported quantization type: {:?}, filling with zeros",
-                ggml_type
-            );
-            for v in output.iter_mut().take(n_elements) {
-                *v = 0.0;
+        other => {
+            let kernel = block_kernel(other).ok_or_else(|| {
+                BizClawError::Brain(format!("Unsupported quantization type: {other:?}"))
+            })?;
+            for (block, out) in data
+                .chunks_exact(type_size)
+                .zip(output.chunks_exact_mut(block_size))
+                .take(n_blocks)
+            {
+                kernel(block, out);
             }
         }
     }
@@ -89,6 +408,10 @@ pub fn dequantize_row(
 mod tests {
     use super::*;
 
+    fn f16_bytes(v: f32) -> [u8; 2] {
+        half::f16::from_f32(v).to_le_bytes()
+    }
+
     #[test]
     fn test_dequantize_q8_0() {
         // Scale = 1.0 (as f16), values = [1, 2, 3, ...]
@@ -104,4 +427,212 @@ mod tests {
         assert!((output[0] - 1.0).abs() < 0.01);
         assert!((output[1] - 2.0).abs() < 0.01);
     }
+
+    #[test]
+    fn test_dequantize_q4_0_nibble_order() {
+        // Byte i holds element i in the low nibble and element i+16 in the high nibble.
+        let mut block = vec![0u8; 18];
+        block[..2].copy_from_slice(&f16_bytes(0.5));
+        for i in 0..16 {
+            block[2 + i] = (i as u8) | (((15 - i) as u8) << 4);
+        }
+        let mut output = vec![0.0f32; 32];
+        dequantize_q4_0(&block, &mut output);
+        for i in 0..16 {
+            assert_eq!(output[i], (i as f32 - 8.0) * 0.5);
+            assert_eq!(output[i + 16], ((15 - i) as f32 - 8.0) * 0.5);
+        }
+    }
+
+    #[test]
+    fn test_dequantize_q4_1() {
+        let mut block = vec![0u8; 20];
+        block[..2].copy_from_slice(&f16_bytes(2.0));
+        block[2..4].copy_from_slice(&f16_bytes(-1.0));
+        for i in 0..16 {
+            block[4 + i] = (i as u8) | (0x0F << 4);
+        }
+        let mut output = vec![0.0f32; 32];
+        dequantize_q4_1(&block, &mut output);
+        for i in 0..16 {
+            assert_eq!(output[i], i as f32 * 2.0 - 1.0);
+            assert_eq!(output[i + 16], 15.0 * 2.0 - 1.0);
+        }
+    }
+
+    #[test]
+    fn test_dequantize_q5_0_high_bits() {
+        let mut block = vec![0u8; 22];
+        block[..2].copy_from_slice(&f16_bytes(1.0));
+        // High bit set for element 0 (bit 0) and element 16 (bit 16).
+        block[2..6].copy_from_slice(&0x0001_0001u32.to_le_bytes());
+        block[6] = 0x21; // element 0 low nibble = 1, element 16 low nibble = 2
+        let mut output = vec![0.0f32; 32];
+        dequantize_q5_0(&block, &mut output);
+        assert_eq!(output[0], (16 + 1 - 16) as f32);
+        assert_eq!(output[16], (16 + 2 - 16) as f32);
+        // Elements without high bit and zero nibble → -16.
+        assert_eq!(output[1], -16.0);
+        assert_eq!(output[17], -16.0);
+    }
+
+    #[test]
+    fn test_dequantize_q5_1() {
+        let mut block = vec![0u8; 24];
+        block[..2].copy_from_slice(&f16_bytes(0.5));
+        block[2..4].copy_from_slice(&f16_bytes(3.0));
+        block[4..8].copy_from_slice(&0x8000_0000u32.to_le_bytes()); // element 31
+        block[8 + 15] = 0xF0; // element 31 low nibble = 15
+        let mut output = vec![0.0f32; 32];
+        dequantize_q5_1(&block, &mut output);
+        assert_eq!(output[31], 31.0 * 0.5 + 3.0);
+        assert_eq!(output[0], 3.0);
+    }
+
+    #[test]
+    fn test_dequantize_q8_1() {
+        let mut block = vec![0u8; 36];
+        block[..2].copy_from_slice(&f16_bytes(0.25));
+        block[4] = (-8i8) as u8;
+        block[35] = 100;
+        let mut output = vec![0.0f32; 32];
+        dequantize_q8_1(&block, &mut output);
+        assert_eq!(output[0], -2.0);
+        assert_eq!(output[31], 25.0);
+    }
+
+    #[test]
+    fn test_dequantize_q2_k() {
+        let mut block = vec![0u8; 84];
+        // Sub-block 0: scale 3, min 1. Sub-block 1: scale 2, min 0.
+        block[0] = 0x13;
+        block[1] = 0x02;
+        // First byte of qs: value 3 in bits 0..2 → element 0 of sub-block 0.
+        block[16] = 0b0000_0011;
+        // Byte 16 of qs → element 0 of sub-block 1 (value 2).
+        block[16 + 16] = 0b0000_0010;
+        block[80..82].copy_from_slice(&f16_bytes(0.5));
+        block[82..84].copy_from_slice(&f16_bytes(0.25));
+        let mut output = vec![0.0f32; QK_K];
+        dequantize_q2_k(&block, &mut output);
+        assert_eq!(output[0], 0.5 * 3.0 * 3.0 - 0.25);
+        assert_eq!(output[1], -0.25);
+        assert_eq!(output[16], 0.5 * 2.0 * 2.0);
+    }
+
+    #[test]
+    fn test_dequantize_q3_k() {
+        let mut block = vec![0u8; 110];
+        // All scales = 32 + 1 → effective scale 1. Low nibble of bytes 0..8 and
+        // bits 4..5 of bytes 8..12 encode the 6-bit value 33 = 0b10_0001.
+        for b in &mut block[96..104] {
+            *b = 0x11;
+        }
+        for b in &mut block[104..108] {
+            *b = 0b1010_1010;
+        }
+        block[108..110].copy_from_slice(&f16_bytes(1.0));
+        // Element 0: low bits 2, high mask set → 2. Element 1: low bits 1, mask clear → -3.
+        block[32] = 2;
+        block[33] = 1;
+        block[0] = 1;
+        let mut output = vec![0.0f32; QK_K];
+        dequantize_q3_k(&block, &mut output);
+        assert_eq!(output[0], 2.0);
+        assert_eq!(output[1], -3.0);
+        assert_eq!(output[2], -4.0);
+    }
+
+    #[test]
+    fn test_dequantize_q4_k() {
+        let mut block = vec![0u8; 144];
+        block[..2].copy_from_slice(&f16_bytes(1.0));
+        block[2..4].copy_from_slice(&f16_bytes(0.5));
+        // Sub-block 0: scale 2, min 4. Sub-block 1: scale 3, min 0.
+        block[4] = 2;
+        block[5] = 3;
+        block[8] = 4;
+        // Sub-block 4 (j >= 4): scale from q[j+4] low nibble, min from high nibble.
+        block[4 + 8] = 0x21;
+        block[16] = 0x57; // element 0 = 7, element 32 = 5
+        let mut output = vec![0.0f32; QK_K];
+        dequantize_q4_k(&block, &mut output);
+        assert_eq!(output[0], 2.0 * 7.0 - 0.5 * 4.0);
+        assert_eq!(output[32], 3.0 * 5.0);
+        // Element 128 lives in sub-block 4: scale 1, min 2 → 0 * 1 - 0.5 * 2.
+        assert_eq!(output[128], -1.0);
+    }
+
+    #[test]
+    fn test_dequantize_q5_k() {
+        let mut block = vec![0u8; 176];
+        block[..2].copy_from_slice(&f16_bytes(1.0));
+        block[2..4].copy_from_slice(&f16_bytes(1.0));
+        block[4] = 1; // sub-block 0 scale
+        block[5] = 1; // sub-block 1 scale
+        block[16] = 0b0000_0011; // qh[0]: high bit for elements 0 and 32
+        block[48] = 0x21; // element 0 low = 1, element 32 low = 2
+        let mut output = vec![0.0f32; QK_K];
+        dequantize_q5_k(&block, &mut output);
+        assert_eq!(output[0], 17.0);
+        assert_eq!(output[32], 18.0);
+        assert_eq!(output[1], 0.0);
+    }
+
+    #[test]
+    fn test_dequantize_q6_k() {
+        let mut block = vec![0u8; 210];
+        block[208..210].copy_from_slice(&f16_bytes(0.5));
+        for s in &mut block[192..208] {
+            *s = 2;
+        }
+        block[192] = (-1i8) as u8; // scale for elements 0..16
+        // Element 0: low nibble 0xF, high bits 0b11 → 63 - 32 = 31.
+        block[0] = 0x0F;
+        block[128] = 0b11;
+        let mut output = vec![0.0f32; QK_K];
+        dequantize_q6_k(&block, &mut output);
+        assert_eq!(output[0], -0.5 * 31.0);
+        // Element 16 uses scale index 1 (=2) with q = -32.
+        assert_eq!(output[16], 0.5 * 2.0 * -32.0);
+    }
+
+    #[test]
+    fn test_dequantize_q8_k() {
+        let mut block = vec![0u8; 292];
+        block[..4].copy_from_slice(&0.125f32.to_le_bytes());
+        block[4] = 8;
+        block[4 + 255] = (-16i8) as u8;
+        let mut output = vec![0.0f32; QK_K];
+        dequantize_q8_k(&block, &mut output);
+        assert_eq!(output[0], 1.0);
+        assert_eq!(output[255], -2.0);
+    }
+
+    #[test]
+    fn test_dequantize_row_multiple_blocks() {
+        let mut data = vec![0u8; 68];
+        data[..2].copy_from_slice(&f16_bytes(1.0));
+        data[34..36].copy_from_slice(&f16_bytes(2.0));
+        data[2] = 5;
+        data[36] = 5;
+        let mut output = vec![0.0f32; 64];
+        dequantize_row(&data, &mut output, 64, GgmlType::Q8_0).unwrap();
+        assert_eq!(output[0], 5.0);
+        assert_eq!(output[32], 10.0);
+    }
+
+    #[test]
+    fn test_dequantize_row_rejects_unknown_type() {
+        let data = vec![0u8; 64];
+        let mut output = vec![1.0f32; 32];
+        assert!(dequantize_row(&data, &mut output, 32, GgmlType::IQ4NL).is_err());
+    }
+
+    #[test]
+    fn test_dequantize_row_rejects_truncated_data() {
+        let data = vec![0u8; 100];
+        let mut output = vec![0.0f32; QK_K];
+        assert!(dequantize_row(&data, &mut output, QK_K, GgmlType::Q4K).is_err());
+    }
 }
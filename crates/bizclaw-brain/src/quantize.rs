@@ -0,0 +1,462 @@
+//! Quantization — f32 to GGML block formats, and whole-model requantization.
+//!
+//! The block quantizers are the inverse of the kernels in
+//! [`quant`](crate::quant) and follow ggml's reference implementations.
+//! [`quantize_model`] rewrites a GGUF file tensor by tensor: projection
+//! matrices get the target type, embeddings and 1-D tensors (norms, biases)
+//! their own configurable types, and all metadata is carried over.
+
+use crate::gguf::{GgmlType, GgufValue};
+use crate::gguf_writer::{GgufWriter, TensorSpec};
+use crate::mmap::MmapModel;
+use crate::quant::{self, QK_K};
+use bizclaw_core::error::{BizClawError, Result};
+use rayon::prelude::*;
+use std::path::Path;
+
+/// Quantize one Q4_0 block (32 values → 18 bytes).
+fn quantize_block_q4_0(x: &[f32], out: &mut Vec<u8>) {
+    // The value with the largest magnitude maps to -8
+    let max = x
+        .iter()
+        .fold(0.0f32, |m, &v| if v.abs() > m.abs() { v } else { m });
+    let d = max / -8.0;
+    let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+    out.extend_from_slice(&half::f16::from_f32(d).to_le_bytes());
+    let q = |v: f32| ((v * id + 8.5) as u8).min(15);
+    for j in 0..16 {
+        out.push(q(x[j]) | (q(x[j + 16]) << 4));
+    }
+}
+
+/// Quantize one Q8_0 block (32 values → 34 bytes).
+fn quantize_block_q8_0(x: &[f32], out: &mut Vec<u8>) {
+    let amax = x.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+    let d = amax / 127.0;
+    let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+    out.extend_from_slice(&half::f16::from_f32(d).to_le_bytes());
+    out.extend(x.iter().map(|v| (v * id).round() as i8 as u8));
+}
+
+/// Quantize one Q4_K super-block (256 values → 144 bytes).
+///
+/// Each 32-value sub-block gets an affine `scale * q - min` fit over its
+/// range; scales and mins are then quantized to 6 bits against the
+/// super-block's `d` and `dmin`.
+fn quantize_block_q4_k(x: &[f32], out: &mut Vec<u8>) {
+    let mut scales = [0.0f32; 8];
+    let mut mins = [0.0f32; 8];
+    for (j, sub) in x.chunks_exact(32).enumerate() {
+        let lo = sub.iter().fold(f32::INFINITY, |m, &v| m.min(v)).min(0.0);
+        let hi = sub.iter().fold(f32::NEG_INFINITY, |m, &v| m.max(v));
+        scales[j] = (hi - lo) / 15.0;
+        mins[j] = -lo;
+    }
+    let max_scale = scales.iter().fold(0.0f32, |m, &v| m.max(v));
+    let max_min = mins.iter().fold(0.0f32, |m, &v| m.max(v));
+    let inv = |max: f32| if max > 0.0 { 63.0 / max } else { 0.0 };
+    let (inv_scale, inv_min) = (inv(max_scale), inv(max_min));
+
+    let mut ls = [0u8; 8];
+    let mut lm = [0u8; 8];
+    for j in 0..8 {
+        ls[j] = ((inv_scale * scales[j]).round() as u8).min(63);
+        lm[j] = ((inv_min * mins[j]).round() as u8).min(63);
+    }
+    let d = half::f16::from_f32(max_scale / 63.0);
+    let dmin = half::f16::from_f32(max_min / 63.0);
+
+    // Packing inverse to `quant::scale_min_k4`
+    let mut packed = [0u8; 12];
+    for j in 0..8 {
+        if j < 4 {
+            packed[j] = ls[j];
+            packed[j + 4] = lm[j];
+        } else {
+            packed[j + 4] = (ls[j] & 0x0F) | ((lm[j] & 0x0F) << 4);
+            packed[j - 4] |= (ls[j] >> 4) << 6;
+            packed[j] |= (lm[j] >> 4) << 6;
+        }
+    }
+
+    // Quantize against the rounded (scale, min) the kernel will see
+    let mut q = [0u8; QK_K];
+    for (j, sub) in x.chunks_exact(32).enumerate() {
+        let (sc, m) = quant::scale_min_k4(j, &packed);
+        let dl = d.to_f32() * sc as f32;
+        let ml = dmin.to_f32() * m as f32;
+        for (qi, &v) in q[j * 32..(j + 1) * 32].iter_mut().zip(sub) {
+            *qi = if dl > 0.0 {
+                ((v + ml) / dl).round().clamp(0.0, 15.0) as u8
+            } else {
+                0
+            };
+        }
+    }
+
+    out.extend_from_slice(&d.to_le_bytes());
+    out.extend_from_slice(&dmin.to_le_bytes());
+    out.extend_from_slice(&packed);
+    for chunk in q.chunks_exact(64) {
+        for l in 0..32 {
+            out.push(chunk[l] | (chunk[l + 32] << 4));
+        }
+    }
+}
+
+/// Parse a CLI type name: `f32`, `f16`, `q4_0`, `q8_0` or `q4_k`.
+pub fn parse_type(name: &str) -> Option<GgmlType> {
+    match name.to_ascii_lowercase().as_str() {
+        "f32" => Some(GgmlType::F32),
+        "f16" => Some(GgmlType::F16),
+        "q4_0" => Some(GgmlType::Q4_0),
+        "q8_0" => Some(GgmlType::Q8_0),
+        "q4_k" | "q4_k_s" => Some(GgmlType::Q4K),
+        _ => None,
+    }
+}
+
+/// Types [`quantize_row`] can produce.
+pub fn can_quantize(ty: GgmlType) -> bool {
+    matches!(
+        ty,
+        GgmlType::F32 | GgmlType::F16 | GgmlType::Q4_0 | GgmlType::Q8_0 | GgmlType::Q4K
+    )
+}
+
+/// Encode `values` as `ty`. The length must be a multiple of the block size.
+pub fn quantize_row(values: &[f32], ty: GgmlType) -> Result<Vec<u8>> {
+    let block = ty.block_size();
+    if !values.len().is_multiple_of(block) {
+        return Err(BizClawError::Brain(format!(
+            "{ty:?}: {} values is not a multiple of block size {block}",
+            values.len()
+        )));
+    }
+    let mut out = Vec::with_capacity(values.len() / block * ty.type_size());
+    match ty {
+        GgmlType::F32 => out.extend(values.iter().flat_map(|v| v.to_le_bytes())),
+        GgmlType::F16 => out.extend(
+            values
+                .iter()
+                .flat_map(|&v| half::f16::from_f32(v).to_le_bytes()),
+        ),
+        GgmlType::Q4_0 => values
+            .chunks_exact(block)
+            .for_each(|x| quantize_block_q4_0(x, &mut out)),
+        GgmlType::Q8_0 => values
+            .chunks_exact(block)
+            .for_each(|x| quantize_block_q8_0(x, &mut out)),
+        GgmlType::Q4K => values
+            .chunks_exact(block)
+            .for_each(|x| quantize_block_q4_k(x, &mut out)),
+        other => {
+            return Err(BizClawError::Brain(format!(
+                "Quantizing to {other:?} is not supported"
+            )));
+        }
+    }
+    Ok(out)
+}
+
+/// Storage types chosen by [`quantize_model`].
+#[derive(Debug, Clone, Copy)]
+pub struct QuantizeOptions {
+    /// Type for 2-D weight matrices.
+    pub ty: GgmlType,
+    /// Type for token/position embedding tables.
+    pub embedding_type: GgmlType,
+    /// Type for 1-D tensors (norm weights, biases).
+    pub norm_type: GgmlType,
+}
+
+impl QuantizeOptions {
+    pub fn new(ty: GgmlType) -> Self {
+        Self {
+            ty,
+            embedding_type: GgmlType::Q8_0,
+            norm_type: GgmlType::F32,
+        }
+    }
+
+    /// Type the tensor's role asks for.
+    fn wanted_type(&self, name: &str, dims: &[u64]) -> GgmlType {
+        // Vectors, also when stored with trailing unit dimensions
+        if dims.iter().filter(|&&d| d > 1).count() < 2 {
+            self.norm_type
+        } else if name.starts_with("token_embd.")
+            || name.starts_with("token_types.")
+            || name.starts_with("position_embd.")
+        {
+            self.embedding_type
+        } else {
+            self.ty
+        }
+    }
+}
+
+/// `wanted`, or Q8_0 then F16 when rows of `row_len` values do not fill
+/// whole blocks of it (as llama.cpp does for odd shapes).
+fn fitting_type(wanted: GgmlType, row_len: usize) -> GgmlType {
+    [wanted, GgmlType::Q8_0, GgmlType::F16]
+        .into_iter()
+        .find(|t| row_len.is_multiple_of(t.block_size()))
+        .unwrap_or(GgmlType::F32)
+}
+
+/// What [`quantize_model`] did.
+#[derive(Debug, Clone, Default)]
+pub struct QuantizeReport {
+    pub tensors: usize,
+    /// Tensors that could not take the wanted type because of their shape.
+    pub fallbacks: usize,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+}
+
+/// llama.cpp `general.file_type` for a model mostly stored as `ty`.
+fn file_type(ty: GgmlType) -> u32 {
+    match ty {
+        GgmlType::F32 => 0,
+        GgmlType::F16 => 1,
+        GgmlType::Q4_0 => 2,
+        GgmlType::Q8_0 => 7,
+        // Q4_K_S: every matrix in Q4_K
+        GgmlType::Q4K => 14,
+        _ => 0,
+    }
+}
+
+/// Requantize the GGUF model at `input` into `output`.
+///
+/// Tensors are converted one at a time, rows in parallel, so peak memory is
+/// about one tensor in f32. Metadata is preserved except `general.file_type`
+/// and `general.quantization_version`, which describe the new file.
+pub fn quantize_model(
+    input: &Path,
+    output: &Path,
+    options: &QuantizeOptions,
+) -> Result<QuantizeReport> {
+    for ty in [options.ty, options.embedding_type, options.norm_type] {
+        if !can_quantize(ty) {
+            return Err(BizClawError::Brain(format!(
+                "Quantizing to {ty:?} is not supported"
+            )));
+        }
+    }
+    if input.canonicalize().ok() == output.canonicalize().ok() {
+        return Err(BizClawError::Brain(
+            "Input and output must be different files".into(),
+        ));
+    }
+    let model = MmapModel::load(input)?;
+
+    let mut metadata: Vec<(String, GgufValue)> = model
+        .gguf
+        .metadata
+        .iter()
+        .filter(|(k, _)| {
+            !matches!(
+                k.as_str(),
+                "general.file_type" | "general.quantization_version"
+            )
+        })
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    metadata.sort_by(|a, b| a.0.cmp(&b.0));
+    metadata.push((
+        "general.file_type".into(),
+        GgufValue::U32(file_type(options.ty)),
+    ));
+    metadata.push(("general.quantization_version".into(), GgufValue::U32(2)));
+
+    let mut report = QuantizeReport {
+        tensors: model.gguf.tensors.len(),
+        input_bytes: model.file_size() as u64,
+        ..Default::default()
+    };
+    let specs: Vec<TensorSpec> = model
+        .gguf
+        .tensors
+        .iter()
+        .map(|t| {
+            let wanted = options.wanted_type(&t.name, &t.dims);
+            let ggml_type = fitting_type(wanted, t.dims.first().copied().unwrap_or(1) as usize);
+            if ggml_type != wanted {
+                report.fallbacks += 1;
+            }
+            TensorSpec {
+                name: t.name.clone(),
+                dims: t.dims.clone(),
+                ggml_type,
+            }
+        })
+        .collect();
+
+    let file = std::fs::File::create(output)?;
+    let mut writer = GgufWriter::new(std::io::BufWriter::new(file), &metadata, specs.clone())?;
+    for (i, (info, spec)) in model.gguf.tensors.iter().zip(&specs).enumerate() {
+        let data = model.tensor_data(i)?;
+        if info.ggml_type == spec.ggml_type {
+            writer.write_tensor(data)?;
+            continue;
+        }
+        let row_len = info.dims.first().copied().unwrap_or(1) as usize;
+        let n_rows = info.n_elements() as usize / row_len.max(1);
+        let src_row = info.size_bytes() as usize / n_rows.max(1);
+        let rows: Vec<Vec<u8>> = data
+            .par_chunks(src_row)
+            .take(n_rows)
+            .map(|row| {
+                let mut values = vec![0.0f32; row_len];
+                quant::dequantize_row(row, &mut values, row_len, info.ggml_type)?;
+                quantize_row(&values, spec.ggml_type)
+            })
+            .collect::<Result<_>>()?;
+        writer.write_tensor(&rows.concat())?;
+        tracing::debug!("{}: {:?} → {:?}", info.name, info.ggml_type, spec.ggml_type);
+    }
+    writer.finish()?;
+
+    report.output_bytes = std::fs::metadata(output)?.len();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forward::{TransformerWeights, forward_batch};
+    use crate::kv_cache::{KvPrecision, KvStorage};
+    use crate::model::ModelParams;
+    use crate::test_model::TinyLlama;
+
+    /// Last-token logits of the model at `path` for `tokens`.
+    fn logits(path: &Path, tokens: &[u32]) -> Vec<f32> {
+        let model = MmapModel::load(path).unwrap();
+        let params = ModelParams::from_gguf(&model.gguf).unwrap();
+        let weights = TransformerWeights::from_gguf(&model, &params).unwrap();
+        let mut cache = KvStorage::new(
+            KvPrecision::F32,
+            params.n_layers as usize,
+            params.max_seq_len as usize,
+            params.n_kv_heads as usize,
+            params.head_dim as usize,
+        );
+        let mut logits = vec![0.0f32; params.vocab_size as usize];
+        forward_batch(
+            &model,
+            &weights,
+            &params,
+            &mut cache,
+            tokens,
+            0,
+            &mut logits,
+        )
+        .unwrap();
+        logits
+    }
+
+    fn roundtrip(values: &[f32], ty: GgmlType) -> Vec<f32> {
+        let bytes = quantize_row(values, ty).unwrap();
+        assert_eq!(bytes.len(), values.len() / ty.block_size() * ty.type_size());
+        let mut out = vec![0.0f32; values.len()];
+        quant::dequantize_row(&bytes, &mut out, values.len(), ty).unwrap();
+        out
+    }
+
+    fn max_error(a: &[f32], b: &[f32]) -> f32 {
+        a.iter()
+            .zip(b)
+            .fold(0.0f32, |m, (x, y)| m.max((x - y).abs()))
+    }
+
+    #[test]
+    fn test_quantize_roundtrip() {
+        let values: Vec<f32> = (0..512)
+            .map(|i| ((i * 37 % 101) as f32 - 50.0) / 25.0)
+            .collect();
+        // Error bounds: half a quantization step of each format (range 4)
+        for (ty, bound) in [
+            (GgmlType::F16, 1e-3),
+            (GgmlType::Q8_0, 2.0 / 127.0),
+            (GgmlType::Q4_0, 2.0 / 8.0 + 1e-3),
+            (GgmlType::Q4K, 4.0 / 15.0),
+        ] {
+            let err = max_error(&values, &roundtrip(&values, ty));
+            assert!(err <= bound, "{ty:?}: {err}");
+        }
+        // Exact zeros stay zero
+        for ty in [GgmlType::Q4_0, GgmlType::Q8_0, GgmlType::Q4K] {
+            assert!(roundtrip(&[0.0; 256], ty).iter().all(|&v| v == 0.0));
+        }
+        assert!(quantize_row(&[0.0; 33], GgmlType::Q8_0).is_err());
+        assert_eq!(parse_type("Q4_K"), Some(GgmlType::Q4K));
+        assert_eq!(parse_type("q5_1"), None);
+    }
+
+    #[test]
+    fn test_quantize_model() {
+        // Rows of 256 so Q4_K applies everywhere except the tiny FFN input
+        let src = TinyLlama {
+            dim: 256,
+            hidden_dim: 96,
+            n_heads: 4,
+            n_kv_heads: 4,
+            ..Default::default()
+        }
+        .write();
+        let tokens = [1, 40, 41, 42];
+        let reference = logits(&src.path, &tokens);
+
+        for ty in [GgmlType::Q8_0, GgmlType::Q4_0, GgmlType::Q4K] {
+            let out = std::env::temp_dir().join(format!(
+                "bizclaw-quantized-{}-{ty:?}.gguf",
+                std::process::id()
+            ));
+            let report = quantize_model(&src.path, &out, &QuantizeOptions::new(ty)).unwrap();
+            assert!(report.output_bytes < report.input_bytes);
+
+            let model = MmapModel::load(&out).unwrap();
+            let types: std::collections::HashMap<&str, GgmlType> = model
+                .gguf
+                .tensors
+                .iter()
+                .map(|t| (t.name.as_str(), t.ggml_type))
+                .collect();
+            assert_eq!(types["blk.0.attn_q.weight"], ty);
+            assert_eq!(types["blk.0.attn_norm.weight"], GgmlType::F32);
+            assert_eq!(types["token_embd.weight"], GgmlType::Q8_0);
+            // ffn_down rows are 96 long: too short for Q4_K blocks
+            let down = if ty == GgmlType::Q4K {
+                assert_eq!(report.fallbacks, 2);
+                GgmlType::Q8_0
+            } else {
+                ty
+            };
+            assert_eq!(types["blk.0.ffn_down.weight"], down);
+            assert_eq!(model.gguf.get_u32("general.file_type"), Some(file_type(ty)));
+            assert_eq!(
+                model.gguf.metadata.len(),
+                MmapModel::load(&src.path).unwrap().gguf.metadata.len() + 2
+            );
+            drop(model);
+
+            // The quantized model still loads and computes nearly the same logits
+            crate::BrainEngine::load(&out).unwrap();
+            let quantized = logits(&out, &tokens);
+            let best = |l: &[f32]| {
+                l.iter()
+                    .enumerate()
+                    .max_by(|a, b| a.1.total_cmp(b.1))
+                    .unwrap()
+                    .0
+            };
+            let err = max_error(&reference, &quantized);
+            assert!(err < 0.5, "{ty:?}: {err}");
+            if ty == GgmlType::Q8_0 {
+                assert_eq!(best(&quantized), best(&reference));
+            }
+            let _ = std::fs::remove_file(&out);
+        }
+    }
+}
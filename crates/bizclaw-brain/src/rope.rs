@@ -1,6 +1,201 @@
 //! Rotary Position Embeddings (RoPE).
 //!
 //! Applied to query and key vectors to encode position information.
+//! GGUF files use one of two layouts for the rotated pairs, depending on the
+//! architecture (see [`RopeStyle`]).
+//!
+//! Models fine-tuned for a longer context than they were trained on stretch
+//! the rotation frequencies ([`RopeScaling`], from `<arch>.rope.scaling.*`).
+//! [`Rope`] holds the resulting per-pair frequencies for a model.
+
+use std::f32::consts::PI;
+
+/// Which dimensions of a head are rotated together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RopeStyle {
+    /// Adjacent pairs `(2i, 2i + 1)` — LLaMA/Mistral GGUFs (the converter
+    /// permutes Q/K weights into this layout).
+    Normal,
+    /// Pairs `(i, i + d/2)` across the two halves — Qwen2, Phi-3, Gemma.
+    Neox,
+}
+
+/// Frequency scaling for contexts past the original training length.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RopeScaling {
+    #[default]
+    None,
+    /// Position interpolation: every frequency divided by `factor`.
+    Linear { factor: f32 },
+    /// NTK-aware: the base raised so the lowest frequency is divided by
+    /// `factor` while the highest barely changes.
+    Ntk { factor: f32 },
+    /// YaRN: frequencies below the `beta_slow` band are interpolated, those
+    /// above `beta_fast` kept, with a linear ramp in between; cos/sin are
+    /// scaled by `attn_factor * (1 + 0.1 ln(factor))`.
+    Yarn {
+        factor: f32,
+        /// Context length the model was trained with
+        original_context: u32,
+        /// Rotations over the original context above which a pair is kept
+        beta_fast: f32,
+        /// Rotations below which a pair is fully interpolated
+        beta_slow: f32,
+        attn_factor: f32,
+    },
+}
+
+impl RopeScaling {
+    /// Read `<arch>.rope.scaling.*` (and the older `rope.scale_linear`).
+    /// `type` is `none`, `linear`, `yarn` or `ntk`; a missing or unknown
+    /// type, or a factor of at most 1, disables scaling.
+    pub fn from_gguf(gguf: &crate::gguf::GgufFile, arch: &str, context_length: u32) -> Self {
+        let key = |name: &str| format!("{arch}.rope.{name}");
+        let factor = gguf
+            .get_f32(&key("scaling.factor"))
+            .or_else(|| gguf.get_f32(&key("scale_linear")))
+            .unwrap_or(1.0);
+        let kind = gguf
+            .metadata
+            .get(&key("scaling.type"))
+            .and_then(|v| v.as_str())
+            .unwrap_or(if gguf.get_f32(&key("scale_linear")).is_some() {
+                "linear"
+            } else {
+                "none"
+            });
+        if factor <= 1.0 {
+            return Self::None;
+        }
+        match kind {
+            "linear" => Self::Linear { factor },
+            "ntk" | "dynamic" => Self::Ntk { factor },
+            "yarn" => Self::Yarn {
+                factor,
+                original_context: gguf
+                    .get_u32(&key("scaling.original_context_length"))
+                    .unwrap_or((context_length as f32 / factor) as u32),
+                beta_fast: gguf.get_f32(&key("scaling.yarn_beta_fast")).unwrap_or(32.0),
+                beta_slow: gguf.get_f32(&key("scaling.yarn_beta_slow")).unwrap_or(1.0),
+                attn_factor: gguf.get_f32(&key("scaling.attn_factor")).unwrap_or(1.0),
+            },
+            other => {
+                if other != "none" {
+                    tracing::warn!("Unknown RoPE scaling type {other:?}, ignoring");
+                }
+                Self::None
+            }
+        }
+    }
+}
+
+/// RoPE of one model: the angle per position of every rotated pair, with
+/// scaling applied.
+#[derive(Debug, Clone)]
+pub struct Rope {
+    inv_freq: Vec<f32>,
+    /// Factor on cos/sin (YaRN attention temperature, 1 otherwise)
+    mscale: f32,
+    style: RopeStyle,
+}
+
+impl Rope {
+    /// Frequencies `theta^(-2i/rope_dim)` for each pair `i`, scaled.
+    pub fn new(rope_dim: usize, theta: f32, scaling: RopeScaling, style: RopeStyle) -> Self {
+        let half = rope_dim / 2;
+        let base = match scaling {
+            RopeScaling::Ntk { factor } if rope_dim > 2 => {
+                theta * factor.powf(rope_dim as f32 / (rope_dim as f32 - 2.0))
+            }
+            _ => theta,
+        };
+        let mut inv_freq: Vec<f32> = (0..half)
+            .map(|i| 1.0 / base.powf(2.0 * i as f32 / rope_dim as f32))
+            .collect();
+        let mut mscale = 1.0;
+
+        match scaling {
+            RopeScaling::Linear { factor } => inv_freq.iter_mut().for_each(|f| *f /= factor),
+            RopeScaling::Yarn {
+                factor,
+                original_context,
+                beta_fast,
+                beta_slow,
+                attn_factor,
+            } => {
+                // Pair index completing `rotations` turns over the original
+                // context
+                let corr_dim = |rotations: f32| {
+                    rope_dim as f32 * (original_context as f32 / (rotations * 2.0 * PI)).ln()
+                        / (2.0 * theta.ln())
+                };
+                let low = corr_dim(beta_fast).floor().max(0.0);
+                let high = corr_dim(beta_slow).ceil().min(rope_dim as f32 - 1.0);
+                for (i, f) in inv_freq.iter_mut().enumerate() {
+                    let ramp = ((i as f32 - low) / (high - low).max(0.001)).clamp(0.0, 1.0);
+                    // ramp 0: keep (high frequency), 1: interpolate
+                    *f *= (1.0 - ramp) + ramp / factor;
+                }
+                mscale = attn_factor * (1.0 + 0.1 * factor.ln());
+            }
+            RopeScaling::None | RopeScaling::Ntk { .. } => {}
+        }
+        Self {
+            inv_freq,
+            mscale,
+            style,
+        }
+    }
+
+    /// RoPE for `params`' architecture and scaling.
+    pub fn from_params(params: &crate::model::ModelParams) -> Self {
+        Self::new(
+            params.rope_dim as usize,
+            params.rope_theta,
+            params.rope_scaling,
+            params.arch.rope_style(),
+        )
+    }
+
+    /// Angle per position of each rotated pair.
+    pub fn inv_freq(&self) -> &[f32] {
+        &self.inv_freq
+    }
+
+    pub fn mscale(&self) -> f32 {
+        self.mscale
+    }
+
+    /// Rotate the first `2 * inv_freq.len()` dimensions of each of the
+    /// `n_heads` heads in `vec` to position `pos`.
+    pub fn apply(&self, vec: &mut [f32], pos: usize, n_heads: usize, head_dim: usize) {
+        self.rotate_by(vec, pos as f32, self.mscale, n_heads, head_dim);
+    }
+
+    /// Move already-rotated vectors by `delta` positions (negative moves
+    /// them back). Rotations compose, so this equals applying RoPE at the
+    /// new position to the unrotated vector.
+    pub fn shift(&self, vec: &mut [f32], delta: isize, n_heads: usize, head_dim: usize) {
+        self.rotate_by(vec, delta as f32, 1.0, n_heads, head_dim);
+    }
+
+    fn rotate_by(&self, vec: &mut [f32], pos: f32, scale: f32, n_heads: usize, head_dim: usize) {
+        let half = self.inv_freq.len();
+        for head in vec.chunks_exact_mut(head_dim).take(n_heads) {
+            for (i, &freq) in self.inv_freq.iter().enumerate() {
+                let (sin, cos) = (pos * freq).sin_cos();
+                let (sin, cos) = (sin * scale, cos * scale);
+                let (a, b) = match self.style {
+                    RopeStyle::Normal => (2 * i, 2 * i + 1),
+                    RopeStyle::Neox => (i, i + half),
+                };
+                let (x0, x1) = (head[a], head[b]);
+                head[a] = x0 * cos - x1 * sin;
+                head[b] = x0 * sin + x1 * cos;
+            }
+        }
+    }
+}
 
 /// Apply RoPE to a vector in-place.
 /// `pos` is the token position, `dim` is the embedding dimension,
@@ -20,6 +215,20 @@ pub fn apply_rope(vec: &mut [f32], pos: usize, head_dim: usize, rope_theta: f32)
     }
 }
 
+/// Apply RoPE to the first `rope_dim` dimensions of every head (`rope_dim`
+/// equals `head_dim` unless the model uses partial rotary embeddings).
+pub fn apply_rope_heads(
+    vec: &mut [f32],
+    pos: usize,
+    n_heads: usize,
+    head_dim: usize,
+    rope_dim: usize,
+    rope_theta: f32,
+    style: RopeStyle,
+) {
+    Rope::new(rope_dim, rope_theta, RopeScaling::None, style).apply(vec, pos, n_heads, head_dim);
+}
+
 /// Apply RoPE to all heads in a layer.
 pub fn apply_rope_multi_head(
     vec: &mut [f32],
@@ -49,4 +258,186 @@ mod tests {
             assert!((a - b).abs() < 1e-5);
         }
     }
+
+    #[test]
+    fn test_rope_styles() {
+        let input: Vec<f32> = (1..=8).map(|v| v as f32).collect();
+
+        let mut neox = input.clone();
+        apply_rope_heads(&mut neox, 3, 2, 4, 4, 10000.0, RopeStyle::Neox);
+        let mut reference = input.clone();
+        apply_rope_multi_head(&mut reference, 3, 2, 4, 10000.0);
+        assert_eq!(neox, reference);
+
+        // Normal style is Neox on a head with its pairs interleaved
+        let interleave = |v: &[f32]| vec![v[0], v[2], v[1], v[3], v[4], v[6], v[5], v[7]];
+        let mut normal = interleave(&input);
+        apply_rope_heads(&mut normal, 3, 2, 4, 4, 10000.0, RopeStyle::Normal);
+        for (a, b) in interleave(&normal).iter().zip(&neox) {
+            assert!((a - b).abs() < 1e-5, "{a} vs {b}");
+        }
+
+        // Partial rotary leaves the tail of each head alone
+        let mut partial = input.clone();
+        apply_rope_heads(&mut partial, 3, 2, 4, 2, 10000.0, RopeStyle::Neox);
+        assert_eq!(partial[2..4], input[2..4]);
+        assert_eq!(partial[6..8], input[6..8]);
+        assert_ne!(partial[..2], input[..2]);
+    }
+
+    fn base_freqs(d: usize) -> Vec<f32> {
+        (0..d / 2)
+            .map(|i| 1.0 / 10000f32.powf(2.0 * i as f32 / d as f32))
+            .collect()
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= 1e-6 + 1e-4 * b.abs()
+    }
+
+    #[test]
+    fn test_linear_and_ntk_frequencies() {
+        let base = base_freqs(64);
+        let plain = Rope::new(64, 10000.0, RopeScaling::None, RopeStyle::Neox);
+        assert_eq!(plain.inv_freq(), &base[..]);
+        assert_eq!(plain.mscale(), 1.0);
+
+        let linear = Rope::new(
+            64,
+            10000.0,
+            RopeScaling::Linear { factor: 4.0 },
+            RopeStyle::Neox,
+        );
+        for (f, b) in linear.inv_freq().iter().zip(&base) {
+            assert!(close(*f, b / 4.0));
+        }
+
+        // NTK keeps the fastest pair, slows the slowest by exactly `factor`,
+        // and interpolates geometrically in between
+        let ntk = Rope::new(
+            64,
+            10000.0,
+            RopeScaling::Ntk { factor: 4.0 },
+            RopeStyle::Neox,
+        );
+        let f = ntk.inv_freq();
+        assert_eq!(f[0], 1.0);
+        assert!(close(f[31], base[31] / 4.0));
+        let ratio = |i: usize| base[i] / f[i];
+        assert!((1..31).all(|i| ratio(i) > ratio(i - 1) && ratio(i) < 4.0));
+    }
+
+    #[test]
+    fn test_yarn_frequencies() {
+        let scaling = RopeScaling::Yarn {
+            factor: 4.0,
+            original_context: 4096,
+            beta_fast: 32.0,
+            beta_slow: 1.0,
+            attn_factor: 1.0,
+        };
+        let base = base_freqs(128);
+        let yarn = Rope::new(128, 10000.0, scaling, RopeStyle::Neox);
+        let f = yarn.inv_freq();
+
+        // Pairs turning more than 32 times over 4096 positions (i <= 20)
+        // are kept; fewer than once (i >= 46) are interpolated by 4
+        for i in 0..=20 {
+            assert!(close(f[i], base[i]), "pair {i}");
+        }
+        for i in 46..64 {
+            assert!(close(f[i], base[i] / 4.0), "pair {i}");
+        }
+        let ratio = |i: usize| base[i] / f[i];
+        assert!((22..46).all(|i| ratio(i) > ratio(i - 1)));
+        assert!(close(yarn.mscale(), 1.0 + 0.1 * 4f32.ln()));
+
+        // The magnitude factor applies to every rotation
+        let mut v = vec![1.0, 0.0, 0.0, 0.0];
+        Rope::new(4, 10000.0, scaling, RopeStyle::Neox).apply(&mut v, 0, 1, 4);
+        assert!(close(v[0], yarn.mscale()));
+    }
+
+    #[test]
+    fn test_scaling_from_gguf() {
+        use crate::gguf::{GgufFile, GgufValue};
+
+        let gguf = |entries: &[(&str, GgufValue)]| GgufFile {
+            version: 3,
+            metadata: entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+            tensors: Vec::new(),
+            data_offset: 0,
+            alignment: 32,
+        };
+        let text = |s: &str| GgufValue::String(s.into());
+
+        let yarn = gguf(&[
+            ("qwen2.rope.scaling.type", text("yarn")),
+            ("qwen2.rope.scaling.factor", GgufValue::F32(4.0)),
+            (
+                "qwen2.rope.scaling.original_context_length",
+                GgufValue::U32(32768),
+            ),
+        ]);
+        assert_eq!(
+            RopeScaling::from_gguf(&yarn, "qwen2", 131072),
+            RopeScaling::Yarn {
+                factor: 4.0,
+                original_context: 32768,
+                beta_fast: 32.0,
+                beta_slow: 1.0,
+                attn_factor: 1.0,
+            }
+        );
+
+        let linear = gguf(&[
+            ("llama.rope.scaling.type", text("linear")),
+            ("llama.rope.scaling.factor", GgufValue::F32(2.0)),
+        ]);
+        assert_eq!(
+            RopeScaling::from_gguf(&linear, "llama", 4096),
+            RopeScaling::Linear { factor: 2.0 }
+        );
+        let legacy = gguf(&[("llama.rope.scale_linear", GgufValue::F32(2.0))]);
+        assert_eq!(
+            RopeScaling::from_gguf(&legacy, "llama", 4096),
+            RopeScaling::Linear { factor: 2.0 }
+        );
+
+        let unscaled = gguf(&[
+            ("llama.rope.scaling.type", text("yarn")),
+            ("llama.rope.scaling.factor", GgufValue::F32(1.0)),
+        ]);
+        assert_eq!(
+            RopeScaling::from_gguf(&unscaled, "llama", 4096),
+            RopeScaling::None
+        );
+        assert_eq!(
+            RopeScaling::from_gguf(&gguf(&[]), "llama", 4096),
+            RopeScaling::None
+        );
+    }
+
+    #[test]
+    fn test_shift_moves_rotated_vectors() {
+        let rope = Rope::new(
+            8,
+            10000.0,
+            RopeScaling::Linear { factor: 2.0 },
+            RopeStyle::Normal,
+        );
+        let input: Vec<f32> = (1..=16).map(|v| v as f32 * 0.1).collect();
+        let mut at_9 = input.clone();
+        rope.apply(&mut at_9, 9, 2, 8);
+        let mut at_3 = input.clone();
+        rope.apply(&mut at_3, 3, 2, 8);
+
+        rope.shift(&mut at_9, -6, 2, 8);
+        for (a, b) in at_9.iter().zip(&at_3) {
+            assert!((a - b).abs() < 1e-5, "{a} vs {b}");
+        }
+    }
 }
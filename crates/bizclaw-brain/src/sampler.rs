@@ -1,6 +1,9 @@
-//! Temperature + Top-p/Top-k sampling for token generation.
+//! Token sampling: penalties, logit bias, temperature, then top-k, typical,
+//! top-p and min-p filtering — or Mirostat v2 — for token generation.
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
 
 /// Sampler configuration.
 #[derive(Debug, Clone)]
@@ -8,8 +11,27 @@ pub struct SamplerConfig {
     pub temperature: f32,
     pub top_p: f32,
     pub top_k: u32,
+    /// Drop tokens less likely than `min_p` times the best one (0 disables).
+    pub min_p: f32,
+    /// Locally typical sampling mass (1.0 disables).
+    pub typical_p: f32,
     pub repeat_penalty: f32,
     pub repeat_last_n: usize,
+    /// Subtracted once from every token seen in the last `repeat_last_n`.
+    pub presence_penalty: f32,
+    /// Subtracted per occurrence in the last `repeat_last_n`.
+    pub frequency_penalty: f32,
+    /// Use Mirostat v2 (targets a surprise of `mirostat_tau` bits per token)
+    /// instead of top-k/typical/top-p/min-p.
+    pub mirostat: bool,
+    pub mirostat_tau: f32,
+    pub mirostat_eta: f32,
+    /// Added to the logit of a token before sampling.
+    pub logit_bias: HashMap<u32, f32>,
+    /// Tokens that are never sampled.
+    pub banned_tokens: Vec<u32>,
+    /// Seed for reproducible sampling; re-applied on every [`Sampler::reset`].
+    pub seed: Option<u64>,
 }
 
 impl Default for SamplerConfig {
@@ -18,8 +40,18 @@ impl Default for SamplerConfig {
             temperature: 0.7,
             top_p: 0.9,
             top_k: 40,
+            min_p: 0.0,
+            typical_p: 1.0,
             repeat_penalty: 1.1,
             repeat_last_n: 64,
+            presence_penalty: 0.0,
+            frequency_penalty: 0.0,
+            mirostat: false,
+            mirostat_tau: 5.0,
+            mirostat_eta: 0.1,
+            logit_bias: HashMap::new(),
+            banned_tokens: Vec::new(),
+            seed: None,
         }
     }
 }
@@ -27,65 +59,77 @@ impl Default for SamplerConfig {
 /// Token sampler — selects next token from logits.
 pub struct Sampler {
     config: SamplerConfig,
+    rng: StdRng,
+    /// Mirostat v2 maximum surprise, adapted after every token.
+    mu: f32,
 }
 
 impl Sampler {
     pub fn new(config: SamplerConfig) -> Self {
-        Self { config }
+        let mut sampler = Self {
+            rng: StdRng::from_entropy(),
+            mu: 0.0,
+            config,
+        };
+        sampler.reset();
+        sampler
+    }
+
+    pub fn config(&self) -> &SamplerConfig {
+        &self.config
+    }
+
+    /// Start a new generation: re-seed the RNG (if seeded) and reset the
+    /// Mirostat state.
+    pub fn reset(&mut self) {
+        if let Some(seed) = self.config.seed {
+            self.rng = StdRng::seed_from_u64(seed);
+        }
+        self.mu = 2.0 * self.config.mirostat_tau;
     }
 
     /// Sample a token from logits.
-    pub fn sample(&self, logits: &mut [f32], last_tokens: &[u32]) -> u32 {
-        // Apply repeat penalty
-        if self.config.repeat_penalty != 1.0 {
-            let n = last_tokens.len().min(self.config.repeat_last_n);
-            for &token_id in &last_tokens[last_tokens.len().saturating_sub(n)..] {
-                let idx = token_id as usize;
-                if idx < logits.len() {
-                    if logits[idx] > 0.0 {
-                        logits[idx] /= self.config.repeat_penalty;
-                    } else {
-                        logits[idx] *= self.config.repeat_penalty;
-                    }
-                }
+    pub fn sample(&mut self, logits: &mut [f32], last_tokens: &[u32]) -> u32 {
+        self.apply_penalties(logits, last_tokens);
+
+        for (&token, &bias) in &self.config.logit_bias {
+            if let Some(logit) = logits.get_mut(token as usize) {
+                *logit += bias;
+            }
+        }
+        for &token in &self.config.banned_tokens {
+            if let Some(logit) = logits.get_mut(token as usize) {
+                *logit = f32::NEG_INFINITY;
             }
         }
 
+        // If temperature is 0, return argmax (greedy)
+        if self.config.temperature <= 0.0 {
+            return argmax(logits);
+        }
+
         // Apply temperature
-        if self.config.temperature > 0.0 && self.config.temperature != 1.0 {
+        if self.config.temperature != 1.0 {
             let inv_temp = 1.0 / self.config.temperature;
             for logit in logits.iter_mut() {
                 *logit *= inv_temp;
             }
         }
 
-        // If temperature is 0, return argmax (greedy)
-        if self.config.temperature <= 0.0 {
-            return argmax(logits);
+        if self.config.mirostat {
+            return self.sample_mirostat_v2(logits);
         }
 
-        // Create sorted indices
-        let mut indices: Vec<(usize, f32)> =
-            logits.iter().enumerate().map(|(i, &v)| (i, v)).collect();
-        indices.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-
-        // Top-K filtering
+        // Top-K: partial selection, then sort only the survivors
         let top_k = if self.config.top_k > 0 {
-            (self.config.top_k as usize).min(indices.len())
+            self.config.top_k as usize
         } else {
-            indices.len()
+            logits.len()
         };
-        indices.truncate(top_k);
+        let mut probs = softmax(top_candidates(logits, top_k));
 
-        // Softmax
-        let max_logit = indices[0].1;
-        let mut probs: Vec<(usize, f32)> = indices
-            .iter()
-            .map(|&(i, v)| (i, (v - max_logit).exp()))
-            .collect();
-        let sum: f32 = probs.iter().map(|&(_, p)| p).sum();
-        for p in probs.iter_mut() {
-            p.1 /= sum;
+        if self.config.typical_p < 1.0 {
+            typical_filter(&mut probs, self.config.typical_p);
         }
 
         // Top-P (nucleus) sampling
@@ -100,19 +144,74 @@ impl Sampler {
                 }
             }
             probs.truncate(cutoff);
+            normalize(&mut probs);
+        }
+
+        // Min-P, relative to the most likely survivor
+        if self.config.min_p > 0.0
+            && let Some(&(_, best)) = probs.first()
+        {
+            let threshold = best * self.config.min_p;
+            probs.retain(|&(_, p)| p >= threshold);
+            normalize(&mut probs);
+        }
 
-            // Re-normalize
-            let sum: f32 = probs.iter().map(|&(_, p)| p).sum();
-            for p in probs.iter_mut() {
-                p.1 /= sum;
+        self.pick(&probs)
+    }
+
+    /// Repeat, presence and frequency penalties over the recent window, once
+    /// per distinct token.
+    fn apply_penalties(&self, logits: &mut [f32], last_tokens: &[u32]) {
+        let cfg = &self.config;
+        if cfg.repeat_penalty == 1.0 && cfg.presence_penalty == 0.0 && cfg.frequency_penalty == 0.0
+        {
+            return;
+        }
+        let n = last_tokens.len().min(cfg.repeat_last_n);
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+        for &token in &last_tokens[last_tokens.len() - n..] {
+            *counts.entry(token).or_default() += 1;
+        }
+        for (token, count) in counts {
+            let Some(logit) = logits.get_mut(token as usize) else {
+                continue;
+            };
+            if *logit > 0.0 {
+                *logit /= cfg.repeat_penalty;
+            } else {
+                *logit *= cfg.repeat_penalty;
             }
+            *logit -= cfg.frequency_penalty * count as f32 + cfg.presence_penalty;
         }
+    }
 
-        // Random sampling
-        let mut rng = rand::thread_rng();
-        let r: f32 = rng.r#gen();
+    /// Mirostat v2: drop tokens whose surprise exceeds `mu`, sample, then
+    /// move `mu` towards the target surprise.
+    fn sample_mirostat_v2(&mut self, logits: &[f32]) -> u32 {
+        let mut probs = softmax(top_candidates(logits, logits.len()));
+        let keep = probs
+            .iter()
+            .position(|&(_, p)| -p.log2() > self.mu)
+            .unwrap_or(probs.len())
+            .max(1);
+        probs.truncate(keep);
+        normalize(&mut probs);
+
+        let token = self.pick(&probs);
+        let p = probs
+            .iter()
+            .find(|&&(i, _)| i == token as usize)
+            .map_or(1.0, |&(_, p)| p);
+        let surprise = -p.log2();
+        self.mu -= self.config.mirostat_eta * (surprise - self.config.mirostat_tau);
+        token
+    }
+
+    /// Draw from a normalized distribution.
+    fn pick(&mut self, probs: &[(usize, f32)]) -> u32 {
+        let r: f32 = self.rng.r#gen();
         let mut cumulative = 0.0;
-        for &(idx, prob) in &probs {
+        for &(idx, prob) in probs {
             cumulative += prob;
             if r < cumulative {
                 return idx as u32;
@@ -124,6 +223,72 @@ impl Sampler {
     }
 }
 
+/// The `k` largest finite logits, sorted descending. Uses partial selection
+/// so only the survivors are sorted, not the whole vocabulary.
+fn top_candidates(logits: &[f32], k: usize) -> Vec<(usize, f32)> {
+    let mut candidates: Vec<(usize, f32)> = logits
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| **v > f32::NEG_INFINITY)
+        .map(|(i, &v)| (i, v))
+        .collect();
+    if candidates.is_empty() {
+        return vec![(argmax(logits) as usize, 0.0)];
+    }
+    let descending = |a: &(usize, f32), b: &(usize, f32)| b.1.total_cmp(&a.1);
+    if k < candidates.len() {
+        candidates.select_nth_unstable_by(k - 1, descending);
+        candidates.truncate(k);
+    }
+    candidates.sort_unstable_by(descending);
+    candidates
+}
+
+/// Softmax over logits sorted descending, keeping token ids.
+fn softmax(mut candidates: Vec<(usize, f32)>) -> Vec<(usize, f32)> {
+    let max_logit = candidates[0].1;
+    for c in candidates.iter_mut() {
+        c.1 = (c.1 - max_logit).exp();
+    }
+    normalize(&mut candidates);
+    candidates
+}
+
+fn normalize(probs: &mut [(usize, f32)]) {
+    let sum: f32 = probs.iter().map(|&(_, p)| p).sum();
+    if sum > 0.0 {
+        for p in probs.iter_mut() {
+            p.1 /= sum;
+        }
+    }
+}
+
+/// Locally typical sampling: keep the tokens whose surprise is closest to
+/// the distribution's entropy until they cover `mass`. Leaves `probs`
+/// renormalized and sorted by probability again.
+fn typical_filter(probs: &mut Vec<(usize, f32)>, mass: f32) {
+    let entropy: f32 = probs
+        .iter()
+        .filter(|&&(_, p)| p > 0.0)
+        .map(|&(_, p)| -p * p.ln())
+        .sum();
+    let distance = |p: f32| (-p.ln() - entropy).abs();
+    probs.sort_by(|a, b| distance(a.1).total_cmp(&distance(b.1)));
+
+    let mut cumulative = 0.0;
+    let mut cutoff = probs.len();
+    for (i, &(_, p)) in probs.iter().enumerate() {
+        cumulative += p;
+        if cumulative >= mass {
+            cutoff = i + 1;
+            break;
+        }
+    }
+    probs.truncate(cutoff);
+    probs.sort_by(|a, b| b.1.total_cmp(&a.1));
+    normalize(probs);
+}
+
 /// Return the index of the maximum value (greedy decoding).
 fn argmax(values: &[f32]) -> u32 {
     values
@@ -133,3 +298,118 @@ fn argmax(values: &[f32]) -> u32 {
         .map(|(i, _)| i as u32)
         .unwrap_or(0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampler(config: SamplerConfig) -> Sampler {
+        Sampler::new(SamplerConfig {
+            repeat_penalty: 1.0,
+            seed: Some(7),
+            ..config
+        })
+    }
+
+    fn logits() -> Vec<f32> {
+        vec![2.0, 1.5, 1.0, 0.0, -1.0, -2.0, 3.0, 0.5]
+    }
+
+    fn draws(s: &mut Sampler, n: usize) -> Vec<u32> {
+        (0..n).map(|_| s.sample(&mut logits(), &[])).collect()
+    }
+
+    #[test]
+    fn test_seed_is_reproducible() {
+        let config = SamplerConfig {
+            temperature: 1.5,
+            top_p: 1.0,
+            top_k: 0,
+            ..Default::default()
+        };
+        let mut a = sampler(config.clone());
+        let mut b = sampler(config);
+        let first = draws(&mut a, 32);
+        assert_eq!(first, draws(&mut b, 32));
+        assert!(first.iter().any(|&t| t != first[0]), "{first:?}");
+
+        a.reset();
+        assert_eq!(draws(&mut a, 32), first);
+    }
+
+    #[test]
+    fn test_top_k_and_min_p_restrict_candidates() {
+        let mut top2 = sampler(SamplerConfig {
+            temperature: 2.0,
+            top_p: 1.0,
+            top_k: 2,
+            ..Default::default()
+        });
+        assert!(draws(&mut top2, 50).iter().all(|t| [6, 0].contains(t)));
+
+        // p(token) >= 0.5 * p(best) keeps logits within ln 2 of the max
+        let mut min_p = sampler(SamplerConfig {
+            temperature: 1.0,
+            top_p: 1.0,
+            top_k: 0,
+            min_p: 0.5,
+            ..Default::default()
+        });
+        assert!(draws(&mut min_p, 50).iter().all(|t| [6, 0].contains(t)));
+    }
+
+    #[test]
+    fn test_typical_filter() {
+        // Entropy sits between the two likely tokens; a tiny mass keeps the
+        // single most typical one.
+        let mut probs = vec![(0, 0.5), (1, 0.45), (2, 0.05)];
+        typical_filter(&mut probs, 0.1);
+        assert_eq!(probs.len(), 1);
+        assert_eq!(probs[0].1, 1.0);
+
+        let mut all = vec![(0, 0.5), (1, 0.45), (2, 0.05)];
+        typical_filter(&mut all, 1.0);
+        assert_eq!(all.iter().map(|p| p.0).collect::<Vec<_>>(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_penalties_bias_and_bans() {
+        let mut s = sampler(SamplerConfig {
+            temperature: 0.0,
+            presence_penalty: 0.8,
+            frequency_penalty: 0.2,
+            ..Default::default()
+        });
+        // 6 (3.0) seen twice: 3.0 - 0.8 - 0.4 = 1.8 < 2.0
+        assert_eq!(s.sample(&mut logits(), &[6, 6]), 0);
+        assert_eq!(s.sample(&mut logits(), &[6]), 6);
+
+        let mut s = sampler(SamplerConfig {
+            temperature: 0.0,
+            logit_bias: HashMap::from([(3, 5.0)]),
+            banned_tokens: vec![6],
+            ..Default::default()
+        });
+        assert_eq!(s.sample(&mut logits(), &[]), 3);
+        s.config.logit_bias.clear();
+        assert_eq!(s.sample(&mut logits(), &[]), 0);
+    }
+
+    #[test]
+    fn test_mirostat_v2_adapts_mu() {
+        let mut s = sampler(SamplerConfig {
+            temperature: 1.0,
+            mirostat: true,
+            mirostat_tau: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(s.mu, 2.0);
+        draws(&mut s, 200);
+        // Low target surprise squeezes sampling towards the best tokens
+        assert!(s.mu < 4.0, "mu = {}", s.mu);
+        assert!(draws(&mut s, 50).iter().all(|t| [6, 0, 1].contains(t)));
+
+        s.reset();
+        assert_eq!(s.mu, 2.0);
+    }
+}
@@ -0,0 +1,250 @@
+//! On-disk KV caches for chat sessions.
+//!
+//! Each session is stored as two files: `<key>.bckv` (an [`Fp16KvCache`]
+//! holding exactly the cached positions) and `<key>.json` (the model key and
+//! the token ids those positions belong to). The token list is small, so
+//! callers can check how much of a new prompt a stored cache covers before
+//! loading the cache itself. Old sessions are evicted least-recently-used
+//! first once the directory exceeds its disk budget.
+
+use crate::kv_cache::Fp16KvCache;
+use bizclaw_core::error::{BizClawError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Serialize, Deserialize)]
+struct SessionMeta {
+    /// Identifies the model the cache was computed with.
+    model: String,
+    tokens: Vec<u32>,
+}
+
+/// Directory of persisted session caches with an LRU disk budget.
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+    budget_bytes: u64,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>, budget_bytes: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            budget_bytes,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Tokens cached for `session`, if stored for the same model.
+    pub fn tokens(&self, session: &str, model: &str) -> Option<Vec<u32>> {
+        let text = std::fs::read_to_string(self.meta_path(session)).ok()?;
+        let meta: SessionMeta = serde_json::from_str(&text).ok()?;
+        (meta.model == model && self.cache_path(session).exists()).then_some(meta.tokens)
+    }
+
+    /// Load the cache for `session` and mark it as recently used.
+    pub fn load(&self, session: &str) -> Result<Fp16KvCache> {
+        let path = self.cache_path(session);
+        let cache = Fp16KvCache::load_from(&path)
+            .map_err(|e| BizClawError::Brain(format!("session cache {}: {e}", path.display())))?;
+        touch(&path);
+        Ok(cache)
+    }
+
+    /// Store `cache` (covering `tokens`) for `session`, then evict old
+    /// sessions beyond the budget.
+    pub fn save(
+        &self,
+        session: &str,
+        model: &str,
+        tokens: &[u32],
+        cache: &Fp16KvCache,
+    ) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        cache.save(&self.cache_path(session))?;
+        let meta = SessionMeta {
+            model: model.to_string(),
+            tokens: tokens.to_vec(),
+        };
+        std::fs::write(self.meta_path(session), serde_json::to_vec(&meta)?)?;
+        self.evict(Some(session));
+        Ok(())
+    }
+
+    /// Forget a stored session.
+    pub fn remove(&self, session: &str) {
+        let _ = std::fs::remove_file(self.cache_path(session));
+        let _ = std::fs::remove_file(self.meta_path(session));
+    }
+
+    /// Bytes currently used by stored sessions.
+    pub fn usage(&self) -> u64 {
+        self.entries().iter().map(|e| e.bytes).sum()
+    }
+
+    /// Delete least-recently-used sessions until within budget, never
+    /// touching `keep`.
+    fn evict(&self, keep: Option<&str>) {
+        let keep = keep.map(file_stem);
+        let mut entries = self.entries();
+        let mut total: u64 = entries.iter().map(|e| e.bytes).sum();
+        entries.sort_by_key(|e| e.used);
+        for entry in entries {
+            if total <= self.budget_bytes {
+                break;
+            }
+            if keep.as_deref() == Some(entry.stem.as_str()) {
+                continue;
+            }
+            tracing::debug!("Evicting session cache {}", entry.stem);
+            let _ = std::fs::remove_file(self.dir.join(format!("{}.bckv", entry.stem)));
+            let _ = std::fs::remove_file(self.dir.join(format!("{}.json", entry.stem)));
+            total = total.saturating_sub(entry.bytes);
+        }
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        let Ok(dir) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        dir.flatten()
+            .filter_map(|e| {
+                let path = e.path();
+                if path.extension()? != "bckv" {
+                    return None;
+                }
+                let stem = path.file_stem()?.to_string_lossy().into_owned();
+                let meta = e.metadata().ok()?;
+                let sidecar = std::fs::metadata(path.with_extension("json"))
+                    .map(|m| m.len())
+                    .unwrap_or(0);
+                Some(Entry {
+                    stem,
+                    bytes: meta.len() + sidecar,
+                    used: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                })
+            })
+            .collect()
+    }
+
+    fn cache_path(&self, session: &str) -> PathBuf {
+        self.dir.join(format!("{}.bckv", file_stem(session)))
+    }
+
+    fn meta_path(&self, session: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", file_stem(session)))
+    }
+}
+
+struct Entry {
+    stem: String,
+    bytes: u64,
+    used: SystemTime,
+}
+
+/// Filesystem-safe name for a session id: readable prefix plus a hash, so
+/// ids differing only in unsafe characters do not collide.
+fn file_stem(session: &str) -> String {
+    let safe: String = session
+        .chars()
+        .take(48)
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    // FNV-1a
+    let hash = session.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{safe}-{hash:016x}")
+}
+
+fn touch(path: &Path) {
+    if let Ok(file) = std::fs::File::options().append(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn temp_store(budget: u64) -> SessionStore {
+        let dir =
+            std::env::temp_dir().join(format!("bizclaw-sessions-{}-{budget}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        SessionStore::new(dir, budget)
+    }
+
+    fn cache(n_tokens: usize) -> Fp16KvCache {
+        let mut cache = Fp16KvCache::new(1, n_tokens, 1, 4);
+        for pos in 0..n_tokens {
+            cache.store_key(0, pos, &[pos as f32; 4]);
+        }
+        cache
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let store = temp_store(1 << 20);
+        store
+            .save("chat/1", "model-a", &[1, 2, 3], &cache(3))
+            .unwrap();
+
+        assert_eq!(store.tokens("chat/1", "model-a"), Some(vec![1, 2, 3]));
+        assert_eq!(store.tokens("chat/1", "model-b"), None);
+        assert_eq!(store.tokens("chat/2", "model-a"), None);
+        assert_ne!(file_stem("chat/1"), file_stem("chat_1"));
+
+        let loaded = store.load("chat/1").unwrap();
+        let mut keys = [0.0f32; 12];
+        loaded.load_keys(0, 3, &mut keys);
+        assert_eq!(keys[8], 2.0);
+
+        store.remove("chat/1");
+        assert_eq!(store.tokens("chat/1", "model-a"), None);
+        let _ = std::fs::remove_dir_all(store.dir());
+    }
+
+    #[test]
+    fn test_lru_eviction_by_budget() {
+        // Each entry: 20-byte header + 2 * 64 * 4 * 2 bytes of fp16 data + sidecar
+        let one = {
+            let probe = temp_store(u64::MAX);
+            probe.save("probe", "m", &[0; 64], &cache(64)).unwrap();
+            let bytes = probe.usage();
+            let _ = std::fs::remove_dir_all(probe.dir());
+            bytes
+        };
+        let store = temp_store(one * 2 + one / 2);
+        let past = SystemTime::now() - Duration::from_secs(60);
+
+        store.save("a", "m", &[0; 64], &cache(64)).unwrap();
+        store.save("b", "m", &[0; 64], &cache(64)).unwrap();
+        // Make "a" older, then use it so "b" becomes least recently used
+        for name in ["a", "b"] {
+            let f = std::fs::File::options()
+                .append(true)
+                .open(store.cache_path(name))
+                .unwrap();
+            f.set_modified(past).unwrap();
+        }
+        store.load("a").unwrap();
+
+        store.save("c", "m", &[0; 64], &cache(64)).unwrap();
+        assert!(store.tokens("a", "m").is_some());
+        assert!(store.tokens("b", "m").is_none());
+        assert!(store.tokens("c", "m").is_some());
+        assert!(store.usage() <= one * 2 + one / 2);
+        let _ = std::fs::remove_dir_all(store.dir());
+    }
+}
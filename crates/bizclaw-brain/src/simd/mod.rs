@@ -38,6 +38,32 @@ pub fn dot_product_simd(a: &[f32], b: &[f32]) -> f32 {
     }
 }
 
+/// Accelerated i8 dot product for quantized kernels — dispatches like `dot_product_simd`.
+/// `b` must stay within -127..=127 (Q8 activations always do).
+#[inline]
+pub fn dot_i8_simd(a: &[i8], b: &[i8]) -> i32 {
+    debug_assert_eq!(a.len(), b.len());
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        return neon::dot_i8_neon(a, b);
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+    {
+        return avx2::dot_i8_avx2(a, b);
+    }
+
+    // Fallback (SSE2 has no signed byte multiply-add)
+    #[cfg(not(any(
+        target_arch = "aarch64",
+        all(target_arch = "x86_64", target_feature = "avx2")
+    )))]
+    {
+        crate::tensor::dot_product_i8(a, b)
+    }
+}
+
 /// Accelerated matmul using SIMD dot product.
 /// output[rows] = mat[rows x cols] @ vec[cols]
 pub fn matmul_simd(output: &mut [f32], mat: &[f32], vec: &[f32], rows: usize, cols: usize) {
@@ -72,6 +98,15 @@ mod tests {
         assert!((result - 36.0).abs() < 1e-4, "got {result}");
     }
 
+    #[test]
+    fn test_dot_i8_simd() {
+        let a: Vec<i8> = (0..70).map(|i| (i as i8).wrapping_mul(7)).collect();
+        let b: Vec<i8> = (0..70)
+            .map(|i| if i % 2 == 0 { -127 } else { 127 })
+            .collect();
+        assert_eq!(dot_i8_simd(&a, &b), crate::tensor::dot_product_i8(&a, &b));
+    }
+
     #[test]
     fn test_matmul_simd() {
         let mat = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
@@ -0,0 +1,226 @@
+//! Speculative decoding with a small draft model.
+//!
+//! The draft model greedily proposes a few tokens; the target model runs all
+//! of them through one batched forward pass, which yields the target's
+//! logits after every proposed token. The decode loop then samples from those
+//! logits as usual: while the sampled token equals the draft's guess the next
+//! row is already computed, and on the first mismatch the remaining rows are
+//! dropped and the KV cache rolls back to the last accepted position (later
+//! positions are simply overwritten). The output is exactly what the target
+//! model alone would have produced; only the number of forward passes changes.
+
+use crate::kv_cache::{KvPrecision, KvStorage};
+use crate::{forward, mmap, model, tokenizer};
+use bizclaw_core::error::{BizClawError, Result};
+use std::path::{Path, PathBuf};
+
+/// Running totals of proposed and accepted draft tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DraftStats {
+    pub proposed: u64,
+    pub accepted: u64,
+}
+
+impl DraftStats {
+    /// Fraction of proposed tokens the target model accepted.
+    pub fn acceptance_rate(&self) -> f64 {
+        if self.proposed == 0 {
+            return 0.0;
+        }
+        self.accepted as f64 / self.proposed as f64
+    }
+}
+
+/// A draft model sharing the target's vocabulary.
+pub struct DraftModel {
+    mmap_model: mmap::MmapModel,
+    params: model::ModelParams,
+    weights: forward::TransformerWeights,
+    kv_cache: KvStorage,
+    /// Tokens whose keys/values are in `kv_cache`, from position 0
+    cached_tokens: Vec<u32>,
+    path: PathBuf,
+    pub(crate) stats: DraftStats,
+}
+
+impl DraftModel {
+    /// Load a draft model; its vocabulary must match `target_pieces` (the
+    /// target's token bytes) exactly, or token ids would mean different text.
+    pub fn load(path: &Path, precision: KvPrecision, target_pieces: &[Vec<u8>]) -> Result<Self> {
+        let mmap_model = mmap::MmapModel::load(path)?;
+        let params = model::ModelParams::from_gguf(&mmap_model.gguf)?;
+        let tokenizer = tokenizer::BpeTokenizer::from_gguf(&mmap_model.gguf.metadata)?;
+        let same_vocab = tokenizer.vocab_size() == target_pieces.len()
+            && params.vocab_size as usize == target_pieces.len()
+            && target_pieces
+                .iter()
+                .enumerate()
+                .all(|(id, piece)| tokenizer.token_bytes(id as u32) == *piece);
+        if !same_vocab {
+            return Err(BizClawError::Brain(format!(
+                "Draft model {} does not share the target model's vocabulary",
+                path.display()
+            )));
+        }
+
+        let weights = forward::TransformerWeights::from_gguf(&mmap_model, &params)?;
+        let kv_cache = KvStorage::new(
+            precision,
+            params.n_layers as usize,
+            params.max_seq_len as usize,
+            params.n_kv_heads as usize,
+            params.head_dim as usize,
+        );
+        Ok(Self {
+            mmap_model,
+            params,
+            weights,
+            kv_cache,
+            cached_tokens: Vec::new(),
+            path: path.to_path_buf(),
+            stats: DraftStats::default(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn stats(&self) -> DraftStats {
+        self.stats
+    }
+
+    /// Greedily propose up to `k` tokens continuing `context`. Only the part
+    /// of `context` not already in the draft's KV cache is prefilled.
+    pub fn propose(&mut self, context: &[u32], k: usize, batch_size: usize) -> Result<Vec<u32>> {
+        let n_ctx = self.params.max_seq_len as usize;
+        if context.is_empty() || k == 0 || context.len() >= n_ctx {
+            return Ok(Vec::new());
+        }
+        let k = k.min(n_ctx - context.len());
+
+        let reuse = self
+            .cached_tokens
+            .iter()
+            .zip(context)
+            .take_while(|(a, b)| a == b)
+            .count()
+            .min(context.len() - 1);
+        self.cached_tokens.truncate(reuse);
+
+        let mut logits = vec![0.0f32; self.params.vocab_size as usize];
+        let mut pos = reuse;
+        for chunk in context[reuse..].chunks(batch_size.max(1)) {
+            forward::forward_batch(
+                &self.mmap_model,
+                &self.weights,
+                &self.params,
+                &mut self.kv_cache,
+                chunk,
+                pos,
+                &mut logits,
+            )?;
+            self.cached_tokens.extend_from_slice(chunk);
+            pos += chunk.len();
+        }
+
+        let mut proposal = Vec::with_capacity(k);
+        loop {
+            let token = argmax(&logits);
+            proposal.push(token);
+            if proposal.len() == k {
+                break;
+            }
+            forward::forward(
+                &self.mmap_model,
+                &self.weights,
+                &self.params,
+                &mut self.kv_cache,
+                token,
+                pos,
+                &mut logits,
+            )?;
+            self.cached_tokens.push(token);
+            pos += 1;
+        }
+        Ok(proposal)
+    }
+}
+
+/// Target logits computed ahead for draft tokens not yet confirmed.
+#[derive(Default)]
+pub struct Verified {
+    tokens: Vec<u32>,
+    /// Row `i` holds the target's logits after `tokens[i]`.
+    logits: Vec<f32>,
+    next: usize,
+}
+
+impl Verified {
+    pub fn new(tokens: Vec<u32>, logits: Vec<f32>) -> Self {
+        Self {
+            tokens,
+            logits,
+            next: 0,
+        }
+    }
+
+    /// The draft token the next sample must equal for the rows to stay valid.
+    pub fn expected(&self) -> Option<u32> {
+        self.tokens.get(self.next).copied()
+    }
+
+    /// Accept the expected token, copying the logits that follow it.
+    pub fn accept(&mut self, logits: &mut [f32]) {
+        let vocab = logits.len();
+        logits.copy_from_slice(&self.logits[self.next * vocab..(self.next + 1) * vocab]);
+        self.next += 1;
+    }
+
+    /// Draft tokens still unconfirmed (dropped on rejection).
+    pub fn remaining(&self) -> usize {
+        self.tokens.len() - self.next
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+fn argmax(values: &[f32]) -> u32 {
+    values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i as u32)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verified_queue() {
+        let mut verified = Verified::new(vec![7, 8], vec![0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(verified.expected(), Some(7));
+        let mut logits = [0.0f32; 2];
+        verified.accept(&mut logits);
+        assert_eq!(logits, [0.0, 1.0]);
+        assert_eq!(verified.expected(), Some(8));
+        assert_eq!(verified.remaining(), 1);
+        verified.clear();
+        assert_eq!(verified.expected(), None);
+        assert_eq!(verified.remaining(), 0);
+    }
+
+    #[test]
+    fn test_draft_stats() {
+        let stats = DraftStats {
+            proposed: 8,
+            accepted: 6,
+        };
+        assert_eq!(stats.acceptance_rate(), 0.75);
+        assert_eq!(DraftStats::default().acceptance_rate(), 0.0);
+    }
+}
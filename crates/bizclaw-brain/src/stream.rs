@@ -0,0 +1,209 @@
+//! Streaming generation support — UTF-8 safe text pieces, cancellation, timing.
+//!
+//! Tokens do not line up with characters: a single emoji may be split over
+//! several byte-fallback tokens (`<0xF0>`, `<0x9F>`, ...). `Utf8StreamDecoder`
+//! buffers incomplete sequences so every emitted piece is valid UTF-8.
+
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Cloneable flag used to stop a running generation from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation. Generation stops before the next token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Why generation stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// Model emitted end-of-sequence.
+    #[default]
+    Eos,
+    /// Hit `max_tokens` or the end of the context window.
+    MaxTokens,
+    /// `CancelToken::cancel` was called.
+    Cancelled,
+    /// The output grammar matched completely and allows nothing more.
+    GrammarComplete,
+}
+
+/// Timing and token counts for one generation.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GenerationStats {
+    pub prompt_tokens: usize,
+    /// Prompt tokens served from the KV cache instead of being prefilled.
+    pub cached_tokens: usize,
+    pub generated_tokens: usize,
+    /// Time spent on prompt prefill.
+    pub prefill_time: Duration,
+    /// Per-token decode latency (sampling + forward pass), in order.
+    pub token_times: Vec<Duration>,
+    pub stop_reason: StopReason,
+    /// Tokens proposed by the draft model (speculative decoding).
+    pub draft_tokens: usize,
+    /// Draft tokens the target model accepted.
+    pub accepted_draft_tokens: usize,
+    /// Times the context filled up and older tokens were discarded.
+    pub context_shifts: usize,
+}
+
+impl GenerationStats {
+    /// Latency until the first generated token was available.
+    pub fn time_to_first_token(&self) -> Option<Duration> {
+        self.token_times.first().map(|t| self.prefill_time + *t)
+    }
+
+    /// Decode throughput (excludes prefill).
+    pub fn tokens_per_second(&self) -> f64 {
+        let total: Duration = self.token_times.iter().sum();
+        if total.is_zero() {
+            return 0.0;
+        }
+        self.token_times.len() as f64 / total.as_secs_f64()
+    }
+
+    /// Prefill throughput (tokens actually prefilled, not reused from cache).
+    pub fn prefill_tokens_per_second(&self) -> f64 {
+        if self.prefill_time.is_zero() {
+            return 0.0;
+        }
+        (self.prompt_tokens - self.cached_tokens) as f64 / self.prefill_time.as_secs_f64()
+    }
+}
+
+/// Incremental decoder turning token bytes into valid UTF-8 pieces.
+#[derive(Debug, Default)]
+pub struct Utf8StreamDecoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8StreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed the bytes of one token. Returns the text that is now complete
+    /// (possibly empty if a multi-byte character is still unfinished).
+    pub fn push(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let mut out = String::new();
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(s) => {
+                    out.push_str(s);
+                    self.pending.clear();
+                    return out;
+                }
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    out.push_str(std::str::from_utf8(&self.pending[..valid]).unwrap_or_default());
+                    match e.error_len() {
+                        // Incomplete trailing sequence — wait for more bytes.
+                        None => {
+                            self.pending.drain(..valid);
+                            return out;
+                        }
+                        // Invalid bytes can never become valid: replace and go on.
+                        Some(bad) => {
+                            out.push(char::REPLACEMENT_CHARACTER);
+                            self.pending.drain(..valid + bad);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Flush whatever is left at end of generation (lossy).
+    pub fn finish(&mut self) -> String {
+        let out = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_ascii_passthrough() {
+        let mut d = Utf8StreamDecoder::new();
+        assert_eq!(d.push(b"Hello"), "Hello");
+        assert_eq!(d.push(b" world"), " world");
+        assert_eq!(d.finish(), "");
+    }
+
+    #[test]
+    fn test_decoder_split_multibyte() {
+        let bytes = "Xin chào 🦀".as_bytes();
+        let mut d = Utf8StreamDecoder::new();
+        let mut out = String::new();
+        for b in bytes {
+            let piece = d.push(&[*b]);
+            assert!(std::str::from_utf8(piece.as_bytes()).is_ok());
+            out.push_str(&piece);
+        }
+        out.push_str(&d.finish());
+        assert_eq!(out, "Xin chào 🦀");
+    }
+
+    #[test]
+    fn test_decoder_holds_incomplete_sequence() {
+        let mut d = Utf8StreamDecoder::new();
+        // "é" = C3 A9
+        assert_eq!(d.push(&[b'a', 0xC3]), "a");
+        assert_eq!(d.push(&[0xA9]), "é");
+    }
+
+    #[test]
+    fn test_decoder_invalid_bytes_replaced() {
+        let mut d = Utf8StreamDecoder::new();
+        assert_eq!(d.push(&[0xFF, b'a']), "\u{FFFD}a");
+        assert_eq!(d.push(&[0xE2, 0x82]), "");
+        assert_eq!(d.finish(), "\u{FFFD}");
+    }
+
+    #[test]
+    fn test_cancel_token_shared() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        clone.cancel();
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn test_stats_rates() {
+        let stats = GenerationStats {
+            prompt_tokens: 10,
+            generated_tokens: 4,
+            prefill_time: Duration::from_millis(500),
+            token_times: vec![Duration::from_millis(250); 4],
+            stop_reason: StopReason::MaxTokens,
+            ..Default::default()
+        };
+        assert_eq!(
+            stats.time_to_first_token(),
+            Some(Duration::from_millis(750))
+        );
+        assert!((stats.tokens_per_second() - 4.0).abs() < 1e-9);
+        assert!((stats.prefill_tokens_per_second() - 20.0).abs() < 1e-9);
+    }
+}
@@ -40,6 +40,16 @@ pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
     a.iter().zip(b.iter()).map(|(&x, &y)| x * y).sum()
 }
 
+/// Integer dot product of two i8 vectors (used by quantized matmul).
+#[inline]
+pub fn dot_product_i8(a: &[i8], b: &[i8]) -> i32 {
+    debug_assert_eq!(a.len(), b.len());
+    a.iter()
+        .zip(b.iter())
+        .map(|(&x, &y)| x as i32 * y as i32)
+        .sum()
+}
+
 /// Softmax — converts logits to probabilities.
 pub fn softmax(values: &mut [f32]) {
     if values.is_empty() {
@@ -58,6 +68,17 @@ pub fn softmax(values: &mut [f32]) {
     }
 }
 
+/// LayerNorm in place: (x - mean) / sqrt(var + eps) * weight + bias.
+pub fn layernorm(values: &mut [f32], weight: &[f32], bias: Option<&[f32]>, eps: f32) {
+    let n = values.len() as f32;
+    let mean = values.iter().sum::<f32>() / n;
+    let var = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / n;
+    let inv_std = 1.0 / (var + eps).sqrt();
+    for (i, v) in values.iter_mut().enumerate() {
+        *v = (*v - mean) * inv_std * weight[i] + bias.map_or(0.0, |b| b[i]);
+    }
+}
+
 /// SiLU (Swish) activation: silu(x) = x * sigmoid(x) = x / (1 + exp(-x))
 pub fn silu(values: &mut [f32]) {
     for v in values.iter_mut() {
@@ -65,6 +86,15 @@ pub fn silu(values: &mut [f32]) {
     }
 }
 
+/// GELU activation (tanh approximation), as used by Gemma's GeGLU FFN.
+pub fn gelu(values: &mut [f32]) {
+    const SQRT_2_OVER_PI: f32 = 0.797_884_6;
+    for v in values.iter_mut() {
+        let x = *v;
+        *v = 0.5 * x * (1.0 + (SQRT_2_OVER_PI * (x + 0.044715 * x * x * x)).tanh());
+    }
+}
+
 /// Element-wise multiply: a[i] *= b[i]
 pub fn elementwise_mul(a: &mut [f32], b: &[f32]) {
     debug_assert_eq!(a.len(), b.len());
@@ -91,6 +121,25 @@ pub fn copy(dst: &mut [f32], src: &[f32]) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_gelu() {
+        let mut v = vec![0.0, 1.0, -1.0, 3.0];
+        gelu(&mut v);
+        let expected = [0.0, 0.8412, -0.1588, 2.9964];
+        for (a, b) in v.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-3, "{a} vs {b}");
+        }
+    }
+
+    #[test]
+    fn test_layernorm() {
+        let mut values = [1.0, 2.0, 3.0, 4.0];
+        layernorm(&mut values, &[1.0; 4], Some(&[0.5; 4]), 0.0);
+        let std = 1.25f32.sqrt();
+        assert!((values[0] - (0.5 - 1.5 / std)).abs() < 1e-6);
+        assert!((values.iter().sum::<f32>() - 2.0).abs() < 1e-5);
+    }
+
     #[test]
     fn test_softmax() {
         let mut v = vec![1.0, 2.0, 3.0];
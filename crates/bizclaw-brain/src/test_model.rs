@@ -0,0 +1,509 @@
+//! Tiny random LLaMA-family models written as real GGUF v3 files, for tests.
+//!
+//! The models are far too small to say anything sensible, but they exercise
+//! the whole load → tokenize → forward → sample path exactly like a real file.
+//! `bert` and `nomic-bert` produce encoder-only embedding models instead.
+
+use crate::gguf::GgmlType;
+use rand::{Rng, SeedableRng};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const ALIGNMENT: usize = 32;
+
+/// Shape of a tiny LLaMA model.
+#[derive(Debug, Clone)]
+pub struct TinyLlama {
+    pub dim: usize,
+    pub hidden_dim: usize,
+    pub n_layers: usize,
+    pub n_heads: usize,
+    pub n_kv_heads: usize,
+    pub context_length: usize,
+    /// Storage type of the projection matrices (embeddings and norms stay F32).
+    pub weight_type: GgmlType,
+    /// `tokenizer.chat_template` metadata, if any.
+    pub chat_template: Option<String>,
+    pub seed: u64,
+    /// `general.architecture`; `phi3` gets fused QKV/gate-up tensors and
+    /// `gemma` ties the output weights to the embeddings.
+    pub architecture: &'static str,
+    /// Add Q/K/V biases (as Qwen2 has), drawn from a separate RNG so the
+    /// other weights stay identical to the bias-free model.
+    pub attn_bias: bool,
+    /// `attention.key_length` when the head size is not `dim / n_heads`.
+    pub key_length: Option<usize>,
+    pub sliding_window: Option<usize>,
+    /// `{arch}.pooling_type` for embedding models.
+    pub pooling: Option<crate::embedding::Pooling>,
+}
+
+impl Default for TinyLlama {
+    fn default() -> Self {
+        Self {
+            dim: 64,
+            hidden_dim: 128,
+            n_layers: 2,
+            n_heads: 4,
+            n_kv_heads: 2,
+            context_length: 128,
+            weight_type: GgmlType::F32,
+            chat_template: None,
+            seed: 42,
+            architecture: "llama",
+            attn_bias: false,
+            key_length: None,
+            sliding_window: None,
+            pooling: None,
+        }
+    }
+}
+
+/// A GGUF file in the temp dir, deleted on drop.
+pub struct TempModel {
+    pub path: PathBuf,
+}
+
+impl Drop for TempModel {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A LoRA adapter GGUF (f32 factors) for an `arch` model, from
+/// `(base tensor, rank, lora_a, lora_b)` entries.
+pub fn write_adapter(
+    arch: &str,
+    alpha: f32,
+    factors: &[(&str, usize, Vec<f32>, Vec<f32>)],
+) -> TempModel {
+    use crate::gguf::GgufValue;
+    use crate::gguf_writer::{GgufWriter, TensorSpec};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "bizclaw-lora-{}-{}.gguf",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let metadata = vec![
+        (
+            "general.architecture".into(),
+            GgufValue::String(arch.into()),
+        ),
+        ("general.type".into(), GgufValue::String("adapter".into())),
+        ("adapter.type".into(), GgufValue::String("lora".into())),
+        ("adapter.lora.alpha".into(), GgufValue::F32(alpha)),
+    ];
+    let mut specs = Vec::new();
+    let mut data = Vec::new();
+    for (name, rank, a, b) in factors {
+        let cols = a.len() / rank;
+        let rows = b.len() / rank;
+        for (suffix, dims, values) in [("lora_a", [cols, *rank], a), ("lora_b", [*rank, rows], b)] {
+            specs.push(TensorSpec {
+                name: format!("{name}.{suffix}"),
+                dims: dims.iter().map(|&d| d as u64).collect(),
+                ggml_type: GgmlType::F32,
+            });
+            data.push(encode(values, GgmlType::F32));
+        }
+    }
+    let file = std::fs::File::create(&path).unwrap();
+    let mut writer = GgufWriter::new(file, &metadata, specs).unwrap();
+    for bytes in data {
+        writer.write_tensor(&bytes).unwrap();
+    }
+    writer.finish().unwrap();
+    TempModel { path }
+}
+
+/// Control tokens appended after the regular vocabulary.
+pub const CONTROL_TOKENS: [&str; 2] = ["<|im_start|>", "<|im_end|>"];
+
+/// Vocabulary: specials, the SentencePiece space marker, printable ASCII,
+/// the ChatML control tokens and a byte-fallback newline (which grammars
+/// such as the Hermes tool-call format need).
+pub fn vocab() -> Vec<String> {
+    let mut v = vec!["<unk>".to_string(), "<s>".into(), "</s>".into(), "▁".into()];
+    v.extend((b' '..=b'~').map(|b| (b as char).to_string()));
+    v.extend(CONTROL_TOKENS.iter().map(|t| t.to_string()));
+    v.push("<0x0A>".into());
+    v
+}
+
+/// GGUF token types matching `vocab()`: 2 = unknown, 3 = control,
+/// 6 = byte, 1 = normal.
+fn token_types(vocab: &[String]) -> Vec<i32> {
+    vocab
+        .iter()
+        .enumerate()
+        .map(|(i, t)| match i {
+            0 => 2,
+            1 | 2 => 3,
+            _ if CONTROL_TOKENS.contains(&t.as_str()) => 3,
+            _ if t.starts_with("<0x") => 6,
+            _ => 1,
+        })
+        .collect()
+}
+
+enum Value {
+    U32(u32),
+    F32(f32),
+    Str(String),
+    StrArray(Vec<String>),
+    F32Array(Vec<f32>),
+    I32Array(Vec<i32>),
+}
+
+impl TinyLlama {
+    /// Write the model to a fresh temp file.
+    pub fn write(&self) -> TempModel {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "bizclaw-tiny-{}-{}.gguf",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        self.write_to(&path);
+        TempModel { path }
+    }
+
+    /// Write the model to `path`.
+    pub fn write_to(&self, path: &Path) {
+        let tokens = vocab();
+        let vocab_size = tokens.len();
+        let head_dim = self.key_length.unwrap_or(self.dim / self.n_heads);
+        let q_dim = self.n_heads * head_dim;
+        let kv_dim = self.n_kv_heads * head_dim;
+        let arch = self.architecture;
+        let encoder = matches!(arch, "bert" | "nomic-bert");
+        let key = |name: &str| format!("{arch}.{name}");
+        let mut metadata = vec![
+            ("general.architecture".into(), Value::Str(arch.into())),
+            ("general.name".into(), Value::Str(format!("tiny-{arch}"))),
+            (key("embedding_length"), Value::U32(self.dim as u32)),
+            (
+                key("feed_forward_length"),
+                Value::U32(self.hidden_dim as u32),
+            ),
+            (key("block_count"), Value::U32(self.n_layers as u32)),
+            (key("attention.head_count"), Value::U32(self.n_heads as u32)),
+            (
+                key("attention.head_count_kv"),
+                Value::U32(self.n_kv_heads as u32),
+            ),
+            (
+                key("context_length"),
+                Value::U32(self.context_length as u32),
+            ),
+            (
+                key(if encoder {
+                    "attention.layer_norm_epsilon"
+                } else {
+                    "attention.layer_norm_rms_epsilon"
+                }),
+                Value::F32(1e-5),
+            ),
+            (
+                "tokenizer.ggml.model".into(),
+                Value::Str(if encoder { "bert" } else { "llama" }.into()),
+            ),
+            (
+                "tokenizer.ggml.scores".into(),
+                Value::F32Array(vec![0.0; vocab_size]),
+            ),
+            (
+                "tokenizer.ggml.token_type".into(),
+                Value::I32Array(token_types(&tokens)),
+            ),
+            ("tokenizer.ggml.tokens".into(), Value::StrArray(tokens)),
+            ("tokenizer.ggml.bos_token_id".into(), Value::U32(1)),
+            ("tokenizer.ggml.eos_token_id".into(), Value::U32(2)),
+        ];
+        if let Some(template) = &self.chat_template {
+            metadata.push((
+                "tokenizer.chat_template".into(),
+                Value::Str(template.clone()),
+            ));
+        }
+        if let Some(k) = self.key_length {
+            metadata.push((key("attention.key_length"), Value::U32(k as u32)));
+        }
+        if let Some(w) = self.sliding_window {
+            metadata.push((key("attention.sliding_window"), Value::U32(w as u32)));
+        }
+        if let Some(pooling) = self.pooling {
+            let value = match pooling {
+                crate::embedding::Pooling::Mean => 1,
+                crate::embedding::Pooling::Cls => 2,
+                crate::embedding::Pooling::Last => 3,
+            };
+            metadata.push((key("pooling_type"), Value::U32(value)));
+        }
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(self.seed);
+        let mut bias_rng = rand::rngs::StdRng::seed_from_u64(self.seed + 1);
+        let mut tensors: Vec<(String, GgmlType, [usize; 2])> = Vec::new();
+        let mut data: Vec<Vec<u8>> = Vec::new();
+        let mut add = |name: String, ty: GgmlType, rows: usize, cols: usize, values: Vec<f32>| {
+            tensors.push((name, ty, [cols, rows]));
+            data.push(encode(&values, ty));
+        };
+        let mut matrix = |rows: usize, cols: usize| -> Vec<f32> {
+            let s = 1.0 / (cols as f32).sqrt();
+            (0..rows * cols).map(|_| rng.gen_range(-s..s)).collect()
+        };
+
+        let (d, h) = (self.dim, self.hidden_dim);
+        let ty = self.weight_type;
+        add(
+            "token_embd.weight".into(),
+            GgmlType::F32,
+            vocab_size,
+            d,
+            matrix(vocab_size, d),
+        );
+        if encoder {
+            // Post-norm encoder; LayerNorm biases and BERT's linear biases
+            // come from the bias RNG
+            let mut bias = |width: usize| -> Vec<f32> {
+                (0..width).map(|_| bias_rng.gen_range(-0.1..0.1)).collect()
+            };
+            add(
+                "token_types.weight".into(),
+                GgmlType::F32,
+                2,
+                d,
+                matrix(2, d),
+            );
+            if arch == "bert" {
+                let n_ctx = self.context_length;
+                add(
+                    "position_embd.weight".into(),
+                    GgmlType::F32,
+                    n_ctx,
+                    d,
+                    matrix(n_ctx, d),
+                );
+            }
+            let mut norms = vec!["token_embd_norm".to_string()];
+            for l in 0..self.n_layers {
+                let mut linears = Vec::new();
+                if arch == "nomic-bert" {
+                    linears.push(("attn_qkv", q_dim + 2 * kv_dim, d));
+                } else {
+                    linears.extend([
+                        ("attn_q", q_dim, d),
+                        ("attn_k", kv_dim, d),
+                        ("attn_v", kv_dim, d),
+                    ]);
+                }
+                linears.push(("attn_output", d, q_dim));
+                linears.push(("ffn_up", h, d));
+                if arch == "nomic-bert" {
+                    linears.push(("ffn_gate", h, d));
+                }
+                linears.push(("ffn_down", d, h));
+                for (name, rows, cols) in linears {
+                    let w = matrix(rows, cols);
+                    add(format!("blk.{l}.{name}.weight"), ty, rows, cols, w);
+                    if arch == "bert" {
+                        add(
+                            format!("blk.{l}.{name}.bias"),
+                            GgmlType::F32,
+                            1,
+                            rows,
+                            bias(rows),
+                        );
+                    }
+                }
+                norms.push(format!("blk.{l}.attn_output_norm"));
+                norms.push(format!("blk.{l}.layer_output_norm"));
+            }
+            for name in norms {
+                add(format!("{name}.weight"), GgmlType::F32, 1, d, vec![1.0; d]);
+                add(format!("{name}.bias"), GgmlType::F32, 1, d, bias(d));
+            }
+        } else {
+            for l in 0..self.n_layers {
+                add(
+                    format!("blk.{l}.attn_norm.weight"),
+                    GgmlType::F32,
+                    1,
+                    d,
+                    vec![1.0; d],
+                );
+                let (wq, wk, wv) = (matrix(q_dim, d), matrix(kv_dim, d), matrix(kv_dim, d));
+                if arch == "phi3" {
+                    let qkv = [wq, wk, wv].concat();
+                    add(
+                        format!("blk.{l}.attn_qkv.weight"),
+                        ty,
+                        q_dim + 2 * kv_dim,
+                        d,
+                        qkv,
+                    );
+                } else {
+                    add(format!("blk.{l}.attn_q.weight"), ty, q_dim, d, wq);
+                    add(format!("blk.{l}.attn_k.weight"), ty, kv_dim, d, wk);
+                    add(format!("blk.{l}.attn_v.weight"), ty, kv_dim, d, wv);
+                }
+                if self.attn_bias {
+                    for (name, width) in [("attn_q", q_dim), ("attn_k", kv_dim), ("attn_v", kv_dim)]
+                    {
+                        let bias = (0..width).map(|_| bias_rng.gen_range(-0.5..0.5)).collect();
+                        add(
+                            format!("blk.{l}.{name}.bias"),
+                            GgmlType::F32,
+                            1,
+                            width,
+                            bias,
+                        );
+                    }
+                }
+                add(
+                    format!("blk.{l}.attn_output.weight"),
+                    ty,
+                    d,
+                    q_dim,
+                    matrix(d, q_dim),
+                );
+                add(
+                    format!("blk.{l}.ffn_norm.weight"),
+                    GgmlType::F32,
+                    1,
+                    d,
+                    vec![1.0; d],
+                );
+                let (gate, up) = (matrix(h, d), matrix(h, d));
+                if arch == "phi3" {
+                    add(
+                        format!("blk.{l}.ffn_up.weight"),
+                        ty,
+                        2 * h,
+                        d,
+                        [gate, up].concat(),
+                    );
+                } else {
+                    add(format!("blk.{l}.ffn_gate.weight"), ty, h, d, gate);
+                    add(format!("blk.{l}.ffn_up.weight"), ty, h, d, up);
+                }
+                add(format!("blk.{l}.ffn_down.weight"), ty, d, h, matrix(d, h));
+            }
+            add(
+                "output_norm.weight".into(),
+                GgmlType::F32,
+                1,
+                d,
+                vec![1.0; d],
+            );
+            if arch != "gemma" {
+                add(
+                    "output.weight".into(),
+                    ty,
+                    vocab_size,
+                    d,
+                    matrix(vocab_size, d),
+                );
+            }
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(b"GGUF");
+        out.extend_from_slice(&3u32.to_le_bytes());
+        out.extend_from_slice(&(tensors.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(metadata.len() as u64).to_le_bytes());
+        for (key, value) in &metadata {
+            write_str(&mut out, key);
+            write_value(&mut out, value);
+        }
+
+        let mut offset = 0usize;
+        for ((name, ty, dims), bytes) in tensors.iter().zip(&data) {
+            write_str(&mut out, name);
+            out.extend_from_slice(&2u32.to_le_bytes());
+            for &d in dims {
+                out.extend_from_slice(&(d as u64).to_le_bytes());
+            }
+            out.extend_from_slice(&(*ty as u32).to_le_bytes());
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            offset = (offset + bytes.len()).next_multiple_of(ALIGNMENT);
+        }
+
+        for bytes in &data {
+            out.resize(out.len().next_multiple_of(ALIGNMENT), 0);
+            out.extend_from_slice(bytes);
+        }
+
+        let mut file = std::fs::File::create(path).expect("create tiny model");
+        file.write_all(&out).expect("write tiny model");
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u64).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::U32(v) => {
+            out.extend_from_slice(&4u32.to_le_bytes());
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        Value::F32(v) => {
+            out.extend_from_slice(&6u32.to_le_bytes());
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        Value::Str(s) => {
+            out.extend_from_slice(&8u32.to_le_bytes());
+            write_str(out, s);
+        }
+        Value::StrArray(items) => {
+            out.extend_from_slice(&9u32.to_le_bytes());
+            out.extend_from_slice(&8u32.to_le_bytes());
+            out.extend_from_slice(&(items.len() as u64).to_le_bytes());
+            for s in items {
+                write_str(out, s);
+            }
+        }
+        Value::I32Array(items) => {
+            out.extend_from_slice(&9u32.to_le_bytes());
+            out.extend_from_slice(&5u32.to_le_bytes());
+            out.extend_from_slice(&(items.len() as u64).to_le_bytes());
+            for v in items {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        Value::F32Array(items) => {
+            out.extend_from_slice(&9u32.to_le_bytes());
+            out.extend_from_slice(&6u32.to_le_bytes());
+            out.extend_from_slice(&(items.len() as u64).to_le_bytes());
+            for v in items {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Encode f32 values as `ty` (F32 or Q8_0).
+fn encode(values: &[f32], ty: GgmlType) -> Vec<u8> {
+    match ty {
+        GgmlType::F32 => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        GgmlType::Q8_0 => {
+            let mut out = Vec::with_capacity(values.len() / 32 * 34);
+            for block in values.chunks_exact(32) {
+                let amax = block.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+                let d = amax / 127.0;
+                let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+                out.extend_from_slice(&half::f16::from_f32(d).to_le_bytes());
+                out.extend(block.iter().map(|v| (v * id).round() as i8 as u8));
+            }
+            out
+        }
+        other => panic!("tiny model: unsupported weight type {other:?}"),
+    }
+}
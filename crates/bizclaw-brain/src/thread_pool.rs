@@ -16,6 +16,49 @@ pub fn matmul_parallel(output: &mut [f32], mat: &[f32], vec_in: &[f32], rows: us
     });
 }
 
+/// Parallel quantized matrix-vector multiply.
+/// `weight` holds `output.len()` rows of `row_bytes` raw quantized bytes each;
+/// rows are split across threads and reduced with fused block dot products.
+pub fn matmul_quantized_parallel(
+    output: &mut [f32],
+    weight: &[u8],
+    row_bytes: usize,
+    weight_type: crate::gguf::GgmlType,
+    input: &crate::qmatmul::QuantizedInput,
+) -> bizclaw_core::error::Result<()> {
+    output
+        .par_iter_mut()
+        .enumerate()
+        .with_min_len(16)
+        .try_for_each(|(i, out)| {
+            let row = &weight[i * row_bytes..(i + 1) * row_bytes];
+            *out = crate::qmatmul::vec_dot_row(row, weight_type, input)?;
+            Ok(())
+        })
+}
+
+/// Batched variant of `matmul_quantized_parallel`: `output` is laid out
+/// [rows x n_inputs], and each weight row is dotted against every input.
+pub fn matmul_quantized_batch_parallel(
+    output: &mut [f32],
+    weight: &[u8],
+    row_bytes: usize,
+    weight_type: crate::gguf::GgmlType,
+    inputs: &[crate::qmatmul::QuantizedInput],
+) -> bizclaw_core::error::Result<()> {
+    output
+        .par_chunks_mut(inputs.len())
+        .enumerate()
+        .with_min_len(4)
+        .try_for_each(|(i, out)| {
+            let row = &weight[i * row_bytes..(i + 1) * row_bytes];
+            for (o, input) in out.iter_mut().zip(inputs) {
+                *o = crate::qmatmul::vec_dot_row(row, weight_type, input)?;
+            }
+            Ok(())
+        })
+}
+
 /// Get the number of available threads.
 pub fn num_threads() -> usize {
     rayon::current_num_threads()
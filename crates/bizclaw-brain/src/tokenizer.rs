@@ -1,11 +1,44 @@
-//! BPE (Byte Pair Encoding) tokenizer for LLaMA models.
+//! Tokenizers for LLaMA-family GGUF models.
 //!
-//! Reads vocabulary and merge rules from GGUF metadata and converts
-//! text to/from token IDs.
+//! Reads the vocabulary from GGUF metadata and picks the algorithm named by
+//! `tokenizer.ggml.model`:
+//! - `llama` — SentencePiece BPE: spaces become `▁`, adjacent pieces merge
+//!   highest-score first, and characters missing from the vocabulary fall
+//!   back to `<0xNN>` byte tokens.
+//! - `gpt2` — byte-level BPE (LLaMA 3, Qwen2): text is split by the
+//!   pre-tokenizer named in `tokenizer.ggml.pre`, bytes are mapped to
+//!   printable characters, and `tokenizer.ggml.merges` apply lowest-rank first.
+//! - `bert` — WordPiece for encoder embedding models: lowercased words,
+//!   longest vocabulary match first (word starts carry `▁`, as llama.cpp's
+//!   converter writes them).
+//!
+//! The BPE variants merge with a priority queue over a linked list of symbols, so
+//! encoding is O(n log n) in the length of each word.
 
 use crate::gguf::GgufValue;
+use crate::pre_tokenizer::PreTokenizer;
 use bizclaw_core::error::{BizClawError, Result};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::sync::OnceLock;
+
+/// GGUF `tokenizer.ggml.token_type` values.
+const TOKEN_TYPE_CONTROL: u32 = 3;
+const TOKEN_TYPE_USER_DEFINED: u32 = 4;
+
+/// SentencePiece's space marker.
+const SPACE_MARKER: char = '\u{2581}';
+
+/// Vocabulary type, from `tokenizer.ggml.model`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenizerKind {
+    /// SentencePiece with byte fallback (LLaMA 1/2, Mistral, Gemma).
+    Spm,
+    /// GPT-2 style byte-level BPE (LLaMA 3, Qwen2, Phi-3.5 variants).
+    Gpt2,
+    /// WordPiece (BERT-style embedding models).
+    Wpm,
+}
 
 /// BPE tokenizer for LLaMA-family models.
 pub struct BpeTokenizer {
@@ -13,165 +46,341 @@ pub struct BpeTokenizer {
     vocab: Vec<String>,
     /// String → Token ID mapping.
     token_to_id: HashMap<String, u32>,
-    /// Token scores (used for BPE merge priority).
+    /// Token scores (SentencePiece merge priority).
     scores: Vec<f32>,
+    pub kind: TokenizerKind,
+    /// Word splitting before byte-level BPE.
+    pre: PreTokenizer,
+    /// Byte-level merges: (left, right) token IDs → rank.
+    merges: HashMap<(u32, u32), u32>,
     /// Special token IDs.
     pub bos_id: u32,
     pub eos_id: u32,
     pub pad_id: u32,
+    pub unk_id: u32,
+    /// Whether prompts should start with BOS (`tokenizer.ggml.add_bos_token`).
+    pub add_bos: bool,
+    /// Whether embedding inputs end with EOS (`tokenizer.ggml.add_eos_token`;
+    /// the SEP token for WordPiece).
+    pub add_eos: bool,
+    /// Whether SentencePiece text gets a leading `▁`
+    /// (`tokenizer.ggml.add_space_prefix`).
+    add_space_prefix: bool,
+    /// Control/user-defined tokens matched verbatim in prompt text, longest first.
+    special_tokens: Vec<(String, u32)>,
+    /// Control tokens — they carry no text when decoded.
+    control_ids: HashSet<u32>,
 }
 
 impl BpeTokenizer {
     /// Create a tokenizer from GGUF metadata.
     pub fn from_gguf(metadata: &HashMap<String, GgufValue>) -> Result<Self> {
+        let array = |key: &str| match metadata.get(key) {
+            Some(GgufValue::Array(arr)) => Some(arr),
+            _ => None,
+        };
+        let get_u32 = |key: &str| metadata.get(key).and_then(|v| v.as_u32());
+        let get_bool = |key: &str| metadata.get(key).and_then(|v| v.as_bool());
+
         // Extract vocabulary tokens
-        let tokens = metadata
-            .get("tokenizer.ggml.tokens")
-            .and_then(|v| match v {
-                GgufValue::Array(arr) => Some(arr),
-                _ => None,
-            })
+        let tokens = array("tokenizer.ggml.tokens")
             .ok_or_else(|| BizClawError::Brain("Missing tokenizer.ggml.tokens".into()))?;
-
         let vocab: Vec<String> = tokens
             .iter()
             .filter_map(|v| v.as_str().map(|s| s.to_string()))
             .collect();
-
         if vocab.is_empty() {
             return Err(BizClawError::Brain("Empty vocabulary".into()));
         }
 
-        // Extract scores
-        let scores: Vec<f32> = metadata
-            .get("tokenizer.ggml.scores")
-            .and_then(|v| match v {
-                GgufValue::Array(arr) => Some(arr.iter().filter_map(|v| v.as_f32()).collect()),
-                _ => None,
-            })
+        let scores: Vec<f32> = array("tokenizer.ggml.scores")
+            .map(|arr| arr.iter().filter_map(|v| v.as_f32()).collect())
             .unwrap_or_else(|| vec![0.0; vocab.len()]);
 
-        // Build reverse mapping
-        let token_to_id: HashMap<String, u32> = vocab
-            .iter()
-            .enumerate()
-            .map(|(i, t)| (t.clone(), i as u32))
-            .collect();
+        let model = metadata
+            .get("tokenizer.ggml.model")
+            .and_then(|v| v.as_str())
+            .unwrap_or("llama");
+        let kind = match model {
+            "gpt2" => TokenizerKind::Gpt2,
+            "llama" => TokenizerKind::Spm,
+            "bert" => TokenizerKind::Wpm,
+            other => {
+                tracing::warn!("Unknown tokenizer model '{other}', using SentencePiece");
+                TokenizerKind::Spm
+            }
+        };
 
-        // Extract special tokens
-        let bos_id = metadata
-            .get("tokenizer.ggml.bos_token_id")
-            .and_then(|v| v.as_u32())
-            .unwrap_or(1);
-        let eos_id = metadata
-            .get("tokenizer.ggml.eos_token_id")
-            .and_then(|v| v.as_u32())
-            .unwrap_or(2);
-        let pad_id = metadata
-            .get("tokenizer.ggml.padding_token_id")
-            .and_then(|v| v.as_u32())
-            .unwrap_or(0);
+        let mut tokenizer = Self::new(vocab, scores, kind);
+        if let Some(pre) = metadata.get("tokenizer.ggml.pre").and_then(|v| v.as_str()) {
+            tokenizer.pre = PreTokenizer::from_name(pre);
+        }
+        if let Some(merges) = array("tokenizer.ggml.merges") {
+            tokenizer.set_merges(merges.iter().filter_map(|v| v.as_str()));
+        }
+        if kind == TokenizerKind::Gpt2 && tokenizer.merges.is_empty() {
+            return Err(BizClawError::Brain(
+                "Byte-level BPE vocabulary without tokenizer.ggml.merges".into(),
+            ));
+        }
+
+        tokenizer.bos_id = get_u32("tokenizer.ggml.bos_token_id").unwrap_or(1);
+        tokenizer.eos_id = get_u32("tokenizer.ggml.eos_token_id").unwrap_or(2);
+        tokenizer.pad_id = get_u32("tokenizer.ggml.padding_token_id").unwrap_or(0);
+        if let Some(unk) = get_u32("tokenizer.ggml.unknown_token_id") {
+            tokenizer.unk_id = unk;
+        }
+        if kind == TokenizerKind::Wpm {
+            // BERT wraps inputs in CLS ... SEP
+            if let Some(cls) = get_u32("tokenizer.ggml.cls_token_id") {
+                tokenizer.bos_id = cls;
+            }
+            if let Some(sep) = get_u32("tokenizer.ggml.seperator_token_id") {
+                tokenizer.eos_id = sep;
+            }
+        }
+        if let Some(add_bos) = get_bool("tokenizer.ggml.add_bos_token") {
+            tokenizer.add_bos = add_bos;
+        }
+        if let Some(add_eos) = get_bool("tokenizer.ggml.add_eos_token") {
+            tokenizer.add_eos = add_eos;
+        }
+        if let Some(prefix) = get_bool("tokenizer.ggml.add_space_prefix") {
+            tokenizer.add_space_prefix = prefix;
+        }
 
         tracing::info!(
-            "Tokenizer loaded: vocab_size={}, bos={}, eos={}",
-            vocab.len(),
-            bos_id,
-            eos_id
+            "Tokenizer loaded: {:?}, vocab_size={}, merges={}, bos={}, eos={}",
+            kind,
+            tokenizer.vocab.len(),
+            tokenizer.merges.len(),
+            tokenizer.bos_id,
+            tokenizer.eos_id
         );
 
-        Ok(Self {
-            vocab,
-            token_to_id,
-            scores,
-            bos_id,
-            eos_id,
-            pad_id,
-        })
+        let token_types: Option<Vec<u32>> = array("tokenizer.ggml.token_type")
+            .map(|arr| arr.iter().filter_map(|v| v.as_u32()).collect());
+        tokenizer.index_special_tokens(token_types.as_deref());
+        Ok(tokenizer)
     }
 
-    /// Create a simple fallback tokenizer (for testing without a model).
-    pub fn fallback() -> Self {
-        let vocab: Vec<String> = vec!["<pad>".into(), "<bos>".into(), "<eos>".into(), " ".into()];
+    /// A tokenizer over `vocab` with default special tokens and no merges.
+    fn new(vocab: Vec<String>, scores: Vec<f32>, kind: TokenizerKind) -> Self {
         let token_to_id: HashMap<String, u32> = vocab
             .iter()
             .enumerate()
             .map(|(i, t)| (t.clone(), i as u32))
             .collect();
+        let unk_id = token_to_id.get("<unk>").copied().unwrap_or(0);
         Self {
-            scores: vec![0.0; vocab.len()],
             vocab,
             token_to_id,
+            scores,
+            kind,
+            pre: PreTokenizer::Gpt2,
+            merges: HashMap::new(),
             bos_id: 1,
             eos_id: 2,
             pad_id: 0,
+            unk_id,
+            add_bos: kind != TokenizerKind::Gpt2,
+            add_eos: kind == TokenizerKind::Wpm,
+            add_space_prefix: kind == TokenizerKind::Spm,
+            special_tokens: Vec::new(),
+            control_ids: HashSet::new(),
+        }
+    }
+
+    /// Index `"left right"` merge rules by token ID pair; the position in
+    /// the list is the rank. Rules naming unknown pieces are skipped.
+    fn set_merges<'a>(&mut self, rules: impl Iterator<Item = &'a str>) {
+        self.merges = rules
+            .enumerate()
+            .filter_map(|(rank, rule)| {
+                let (left, right) = rule.split_once(' ')?;
+                let pair = (self.token_id(left)?, self.token_id(right)?);
+                Some((pair, rank as u32))
+            })
+            .collect();
+    }
+
+    /// Collect control/user-defined tokens. Without `token_type` metadata,
+    /// BOS/EOS and `<|...|>`-style pieces are treated as control tokens.
+    fn index_special_tokens(&mut self, token_types: Option<&[u32]>) {
+        let mut special = Vec::new();
+        let mut control = HashSet::new();
+        for (id, piece) in self.vocab.iter().enumerate() {
+            let id = id as u32;
+            let ty = match token_types {
+                Some(types) => types.get(id as usize).copied().unwrap_or(1),
+                None if id == self.bos_id
+                    || id == self.eos_id
+                    || (piece.starts_with("<|") && piece.ends_with("|>")) =>
+                {
+                    TOKEN_TYPE_CONTROL
+                }
+                None => 1,
+            };
+            if ty == TOKEN_TYPE_CONTROL || ty == TOKEN_TYPE_USER_DEFINED {
+                if !piece.is_empty() {
+                    special.push((piece.clone(), id));
+                }
+                if ty == TOKEN_TYPE_CONTROL {
+                    control.insert(id);
+                }
+            }
         }
+        special.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        self.special_tokens = special;
+        self.control_ids = control;
+    }
+
+    /// Create a simple fallback tokenizer (for testing without a model).
+    pub fn fallback() -> Self {
+        let vocab: Vec<String> = vec!["<pad>".into(), "<bos>".into(), "<eos>".into(), " ".into()];
+        let mut tokenizer = Self::new(vocab, vec![0.0; 4], TokenizerKind::Spm);
+        tokenizer.index_special_tokens(None);
+        tokenizer
     }
 
-    /// Encode text into token IDs using BPE.
+    /// Encode text, mapping special tokens that appear verbatim (e.g.
+    /// `<|im_start|>`, `<s>`) to their IDs instead of spelling them out.
+    /// Used for chat-template output. Each run of plain text between special
+    /// tokens is encoded on its own, as llama.cpp does.
+    pub fn encode_with_special(&self, text: &str) -> Vec<u32> {
+        let mut tokens = Vec::new();
+        let mut plain_start = 0;
+        let mut i = 0;
+        while i < text.len() {
+            let hit = self
+                .special_tokens
+                .iter()
+                .find(|(piece, _)| text[i..].starts_with(piece.as_str()));
+            if let Some((piece, id)) = hit {
+                tokens.extend(self.encode(&text[plain_start..i]));
+                tokens.push(*id);
+                i += piece.len();
+                plain_start = i;
+            } else {
+                i += text[i..].chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        tokens.extend(self.encode(&text[plain_start..]));
+        tokens
+    }
+
+    /// Look up the ID of an exact vocabulary piece.
+    pub fn token_id(&self, piece: &str) -> Option<u32> {
+        self.token_to_id.get(piece).copied()
+    }
+
+    /// Encode text into token IDs. Special-token text is spelled out.
     pub fn encode(&self, text: &str) -> Vec<u32> {
         if text.is_empty() {
             return vec![];
         }
+        let mut tokens = Vec::new();
+        match self.kind {
+            TokenizerKind::Spm => self.encode_spm(text, &mut tokens),
+            TokenizerKind::Gpt2 => self.encode_byte_level(text, &mut tokens),
+            TokenizerKind::Wpm => self.encode_wpm(text, &mut tokens),
+        }
+        tokens
+    }
 
-        // Step 1: UTF-8 byte-level encoding — each byte becomes a token
-        let mut tokens: Vec<u32> = Vec::new();
-        for byte in text.bytes() {
-            // Try to find the byte as a token
-            let byte_str = format!("{}", byte as char);
-            if let Some(&id) = self.token_to_id.get(&byte_str) {
+    fn encode_spm(&self, text: &str, tokens: &mut Vec<u32>) {
+        let mut word = String::with_capacity(text.len() + 3);
+        if self.add_space_prefix {
+            word.push(SPACE_MARKER);
+        }
+        word.extend(
+            text.chars()
+                .map(|c| if c == ' ' { SPACE_MARKER } else { c }),
+        );
+
+        let pieces = merge_symbols(&word, |_, _, joined| {
+            let id = self.token_id(joined)?;
+            Some(-self.scores.get(id as usize).copied().unwrap_or(0.0))
+        });
+        for piece in pieces {
+            if let Some(id) = self.token_id(piece) {
                 tokens.push(id);
-            } else {
-                // Fallback: try hex representation
-                let hex = format!("<0x{:02X}>", byte);
-                if let Some(&id) = self.token_to_id.get(&hex) {
-                    tokens.push(id);
-                } else {
-                    // Last resort: use pad token
-                    tokens.push(self.pad_id);
-                }
+                continue;
+            }
+            // Byte fallback
+            for byte in piece.bytes() {
+                let id = self.token_id(&format!("<0x{byte:02X}>"));
+                tokens.push(id.unwrap_or(self.unk_id));
             }
         }
+    }
 
-        // Step 2: BPE merges — iteratively merge the best pair
-        loop {
-            if tokens.len() < 2 {
-                break;
+    fn encode_byte_level(&self, text: &str, tokens: &mut Vec<u32>) {
+        let table = byte_chars();
+        for word in self.pre.split(text) {
+            let word: String = word.bytes().map(|b| table[b as usize]).collect();
+            // LLaMA 3 keeps whole words that are in the vocabulary as-is
+            if self.pre == PreTokenizer::Llama3
+                && let Some(id) = self.token_id(&word)
+            {
+                tokens.push(id);
+                continue;
             }
-
-            // Find the best merge (highest score pair)
-            let mut best_score = f32::NEG_INFINITY;
-            let mut best_idx = usize::MAX;
-            let mut best_id = 0u32;
-
-            for i in 0..tokens.len() - 1 {
-                let merged = format!(
-                    "{}{}",
-                    self.decode_token(tokens[i]),
-                    self.decode_token(tokens[i + 1])
-                );
-                if let Some(&id) = self.token_to_id.get(&merged) {
-                    let score = if (id as usize) < self.scores.len() {
-                        self.scores[id as usize]
-                    } else {
-                        0.0
-                    };
-                    if score > best_score {
-                        best_score = score;
-                        best_idx = i;
-                        best_id = id;
-                    }
+            let pieces = merge_symbols(&word, |left, right, _| {
+                let pair = (self.token_id(left)?, self.token_id(right)?);
+                self.merges.get(&pair).map(|&rank| rank as f32)
+            });
+            for piece in pieces {
+                match self.token_id(piece) {
+                    Some(id) => tokens.push(id),
+                    None => tokens.extend(piece.chars().map(|c| {
+                        self.token_id(c.encode_utf8(&mut [0; 4]))
+                            .unwrap_or(self.unk_id)
+                    })),
                 }
             }
+        }
+    }
 
-            if best_idx == usize::MAX {
-                break; // No more merges possible
+    fn encode_wpm(&self, text: &str, tokens: &mut Vec<u32>) {
+        for word in wpm_words(text) {
+            let word = format!("{SPACE_MARKER}{word}");
+            let start = tokens.len();
+            let mut rest = word.as_str();
+            while !rest.is_empty() {
+                // Longest vocabulary piece at the start of `rest`
+                let hit = rest
+                    .char_indices()
+                    .map(|(i, c)| i + c.len_utf8())
+                    .rev()
+                    .find_map(|end| self.token_id(&rest[..end]).map(|id| (end, id)));
+                match hit {
+                    Some((end, id)) => {
+                        tokens.push(id);
+                        rest = &rest[end..];
+                    }
+                    None => {
+                        // A word that cannot be spelled becomes a single UNK
+                        tokens.truncate(start);
+                        tokens.push(self.unk_id);
+                        break;
+                    }
+                }
             }
-
-            // Apply the merge
-            tokens[best_idx] = best_id;
-            tokens.remove(best_idx + 1);
         }
+    }
 
+    /// Token IDs for an embedding input: BOS/CLS, the text, then EOS/SEP
+    /// as the vocabulary asks. Special-token text is spelled out.
+    pub fn encode_for_embedding(&self, text: &str) -> Vec<u32> {
+        let mut tokens = Vec::new();
+        if self.add_bos {
+            tokens.push(self.bos_id);
+        }
+        tokens.extend(self.encode(text));
+        if self.add_eos {
+            tokens.push(self.eos_id);
+        }
         tokens
     }
 
@@ -183,13 +392,43 @@ impl BpeTokenizer {
             .unwrap_or("<unk>")
     }
 
+    /// Raw output bytes of a token: byte-fallback tokens (`<0xNN>`) become
+    /// the byte itself, `▁` becomes a space, byte-level pieces map back to
+    /// their bytes, and BOS/EOS/PAD and control tokens produce nothing.
+    ///
+    /// A token may hold only part of a UTF-8 character, so callers streaming
+    /// text should feed these through `stream::Utf8StreamDecoder`.
+    pub fn token_bytes(&self, id: u32) -> Vec<u8> {
+        if self.is_special(id) || self.control_ids.contains(&id) {
+            return Vec::new();
+        }
+        let piece = self.decode_token(id);
+        if self.kind == TokenizerKind::Gpt2 {
+            // Characters outside the byte table (user-defined pieces) pass through
+            let mut bytes = Vec::with_capacity(piece.len());
+            for c in piece.chars() {
+                match char_byte(c) {
+                    Some(b) => bytes.push(b),
+                    None => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                }
+            }
+            return bytes;
+        }
+        if let Some(hex) = piece
+            .strip_prefix("<0x")
+            .and_then(|rest| rest.strip_suffix('>'))
+            && hex.len() == 2
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            return vec![byte];
+        }
+        piece.replace(SPACE_MARKER, " ").into_bytes()
+    }
+
     /// Decode a sequence of token IDs to text.
     pub fn decode(&self, tokens: &[u32]) -> String {
-        tokens
-            .iter()
-            .map(|&id| self.decode_token(id))
-            .collect::<Vec<&str>>()
-            .join("")
+        let bytes: Vec<u8> = tokens.iter().flat_map(|&id| self.token_bytes(id)).collect();
+        String::from_utf8_lossy(&bytes).into_owned()
     }
 
     /// Get vocabulary size.
@@ -202,3 +441,490 @@ impl BpeTokenizer {
         id == self.bos_id || id == self.eos_id || id == self.pad_id
     }
 }
+
+/// BERT pre-tokenization: lowercase, split on whitespace, and make ASCII
+/// punctuation and CJK ideographs words of their own.
+fn wpm_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        let alone = c.is_ascii_punctuation() || is_cjk(c);
+        if (c.is_whitespace() || alone) && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        if alone {
+            words.push(c.to_string());
+        } else if !c.is_whitespace() {
+            current.extend(c.to_lowercase());
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x4E00..=0x9FFF
+            | 0x3400..=0x4DBF
+            | 0x20000..=0x2A6DF
+            | 0x2A700..=0x2CEAF
+            | 0xF900..=0xFAFF
+            | 0x2F800..=0x2FA1F
+    )
+}
+
+/// A span of the word being merged, linked to its neighbours.
+#[derive(Clone, Copy)]
+struct Symbol {
+    start: usize,
+    end: usize,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// A queued merge of the symbol at `left` with its right neighbour.
+struct Candidate {
+    cost: f32,
+    left: usize,
+    /// Byte length of the merged span, to detect stale entries.
+    len: usize,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap pops the greatest: lowest cost first, then leftmost
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then(other.left.cmp(&self.left))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+/// Start from one symbol per char of `word` and repeatedly merge the
+/// adjacent pair with the lowest `cost(left, right, joined)` (`None` = not
+/// mergeable), leftmost first on ties. Returns the final pieces in order.
+fn merge_symbols(word: &str, cost: impl Fn(&str, &str, &str) -> Option<f32>) -> Vec<&str> {
+    let mut symbols: Vec<Symbol> = word
+        .char_indices()
+        .map(|(i, c)| Symbol {
+            start: i,
+            end: i + c.len_utf8(),
+            prev: None,
+            next: None,
+        })
+        .collect();
+    let n = symbols.len();
+    for (i, symbol) in symbols.iter_mut().enumerate() {
+        symbol.prev = i.checked_sub(1);
+        symbol.next = (i + 1 < n).then_some(i + 1);
+    }
+
+    let mut queue = BinaryHeap::new();
+    let enqueue = |queue: &mut BinaryHeap<Candidate>, symbols: &[Symbol], left: usize| {
+        let l = symbols[left];
+        if let Some(right) = l.next {
+            let r = symbols[right];
+            let joined = &word[l.start..r.end];
+            if let Some(cost) = cost(&word[l.start..l.end], &word[r.start..r.end], joined) {
+                queue.push(Candidate {
+                    cost,
+                    left,
+                    len: joined.len(),
+                });
+            }
+        }
+    };
+    for i in 0..n.saturating_sub(1) {
+        enqueue(&mut queue, &symbols, i);
+    }
+
+    while let Some(candidate) = queue.pop() {
+        let left = symbols[candidate.left];
+        // Skip entries made stale by earlier merges on either side
+        let Some(right) = left.next else { continue };
+        if left.start == left.end || symbols[right].end - left.start != candidate.len {
+            continue;
+        }
+        let r = symbols[right];
+        symbols[candidate.left].end = r.end;
+        symbols[candidate.left].next = r.next;
+        if let Some(after) = r.next {
+            symbols[after].prev = Some(candidate.left);
+        }
+        symbols[right].start = symbols[right].end;
+
+        if let Some(before) = left.prev {
+            enqueue(&mut queue, &symbols, before);
+        }
+        enqueue(&mut queue, &symbols, candidate.left);
+    }
+
+    let mut pieces = Vec::new();
+    let mut at = (n > 0).then_some(0);
+    while let Some(i) = at {
+        pieces.push(&word[symbols[i].start..symbols[i].end]);
+        at = symbols[i].next;
+    }
+    pieces
+}
+
+/// GPT-2's reversible byte → printable character table: printable Latin-1
+/// bytes map to themselves, the rest to U+0100 onwards.
+fn byte_chars() -> &'static [char; 256] {
+    static TABLE: OnceLock<[char; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = ['\0'; 256];
+        let mut next = 0x100;
+        for b in 0..=255u8 {
+            table[b as usize] = if is_printable_byte(b) {
+                char::from(b)
+            } else {
+                next += 1;
+                char::from_u32(next - 1).unwrap_or('\0')
+            };
+        }
+        table
+    })
+}
+
+fn is_printable_byte(b: u8) -> bool {
+    matches!(b, b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF)
+}
+
+/// Inverse of [`byte_chars`].
+fn char_byte(c: char) -> Option<u8> {
+    static INVERSE: OnceLock<HashMap<char, u8>> = OnceLock::new();
+    INVERSE
+        .get_or_init(|| {
+            byte_chars()
+                .iter()
+                .enumerate()
+                .map(|(b, &c)| (c, b as u8))
+                .collect()
+        })
+        .get(&c)
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenizer(pieces: &[&str]) -> BpeTokenizer {
+        let vocab: Vec<String> = pieces.iter().map(|s| s.to_string()).collect();
+        let scores = vec![0.0; vocab.len()];
+        let mut tok = BpeTokenizer::new(vocab, scores, TokenizerKind::Spm);
+        tok.index_special_tokens(None);
+        tok
+    }
+
+    #[test]
+    fn test_token_bytes() {
+        let tok = tokenizer(&[
+            "<unk>", "<s>", "</s>", "▁Hello", "<0xE2>", "<0x82>", "<0xAC>",
+        ]);
+        assert_eq!(tok.token_bytes(3), b" Hello");
+        assert_eq!(tok.token_bytes(4), vec![0xE2]);
+        assert!(tok.token_bytes(1).is_empty());
+        assert!(tok.token_bytes(2).is_empty());
+    }
+
+    #[test]
+    fn test_decode_joins_byte_tokens() {
+        let tok = tokenizer(&[
+            "<unk>", "<s>", "</s>", "▁Hello", "<0xE2>", "<0x82>", "<0xAC>",
+        ]);
+        assert_eq!(tok.decode(&[1, 3, 4, 5, 6, 2]), " Hello€");
+    }
+
+    #[test]
+    fn test_encode_with_special() {
+        let tok = tokenizer(&[
+            "<unk>",
+            "<s>",
+            "</s>",
+            "<|im_start|>",
+            "<|im_end|>",
+            "a",
+            "b",
+            "▁",
+        ]);
+        // Text after a special token gets the SentencePiece space prefix
+        assert_eq!(
+            tok.encode_with_special("<|im_start|>ab<|im_end|>"),
+            vec![3, 7, 5, 6, 4]
+        );
+        assert_eq!(tok.encode_with_special("<s>a"), vec![1, 7, 5]);
+        // Plain encode spells special tokens out instead
+        assert!(!tok.encode("<|im_end|>").contains(&4));
+        // Control tokens decode to nothing
+        assert!(tok.token_bytes(4).is_empty());
+    }
+
+    fn metadata(entries: Vec<(&str, GgufValue)>) -> HashMap<String, GgufValue> {
+        entries
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect()
+    }
+
+    fn strings(items: &[&str]) -> GgufValue {
+        GgufValue::Array(
+            items
+                .iter()
+                .map(|s| GgufValue::String(s.to_string()))
+                .collect(),
+        )
+    }
+
+    /// SentencePiece fixture; expected ids from a naive reference merge.
+    fn spm_fixture() -> BpeTokenizer {
+        let pieces = [
+            ("<unk>", 0.0),
+            ("<s>", 0.0),
+            ("</s>", 0.0),
+            ("<0xE1>", 0.0),
+            ("<0xBB>", 0.0),
+            ("<0x87>", 0.0),
+            ("▁", -1.0),
+            ("H", -2.0),
+            ("e", -2.0),
+            ("l", -2.0),
+            ("o", -2.0),
+            ("w", -2.0),
+            ("r", -2.0),
+            ("d", -2.0),
+            ("V", -2.0),
+            ("i", -2.0),
+            ("t", -2.0),
+            ("ll", -3.0),
+            ("He", -4.0),
+            ("Hell", -5.0),
+            ("Hello", -6.0),
+            ("▁Hello", -7.0),
+            ("▁w", -9.0),
+            ("or", -4.0),
+            ("▁wor", -8.0),
+            ("ld", -5.0),
+            ("▁world", -6.0),
+            ("▁V", -10.0),
+            ("▁Vi", -10.0),
+            ("wo", -3.5),
+            ("orld", -20.0),
+        ];
+        let names: Vec<&str> = pieces.iter().map(|p| p.0).collect();
+        let scores = pieces.iter().map(|p| GgufValue::F32(p.1)).collect();
+        BpeTokenizer::from_gguf(&metadata(vec![
+            ("tokenizer.ggml.model", GgufValue::String("llama".into())),
+            ("tokenizer.ggml.tokens", strings(&names)),
+            ("tokenizer.ggml.scores", GgufValue::Array(scores)),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_spm_fixture() {
+        let tok = spm_fixture();
+        assert_eq!(tok.kind, TokenizerKind::Spm);
+        // "wo" outscores "or", so "▁world" is never formed
+        assert_eq!(tok.encode("Hello world"), vec![21, 6, 29, 12, 25]);
+        assert_eq!(tok.encode("world"), vec![6, 29, 12, 25]);
+        // "ệ" is not in the vocabulary: byte fallback
+        assert_eq!(tok.encode("Việt"), vec![28, 3, 4, 5, 16]);
+        assert_eq!(tok.decode(&[1, 28, 3, 4, 5, 16]), " Việt");
+        assert_eq!(tok.decode(&tok.encode("Hello world")), " Hello world");
+    }
+
+    /// Byte-level BPE fixture: the 256 byte characters, then merged pieces
+    /// and two control tokens. Expected ids come from a reference
+    /// implementation (Python `regex` pre-tokenizer + rank-ordered merges).
+    fn gpt2_fixture() -> BpeTokenizer {
+        let table = byte_chars();
+        let mut vocab: Vec<String> = table.iter().map(|c| c.to_string()).collect();
+        vocab.extend(
+            [
+                "Ġw",
+                "or",
+                "Ġwor",
+                "ld",
+                "Ġworld",
+                "He",
+                "ll",
+                "Hell",
+                "Hello",
+                "á»",
+                "á»ĩ",
+                "Vi",
+                "Viá»ĩ",
+                "Viá»ĩt",
+                "ĠViá»ĩt",
+                "ĠN",
+                "am",
+                "ĠNam",
+                "ch",
+                "Ãł",
+                "chÃł",
+                "ĠchÃł",
+                "oĠ",
+                "<|endoftext|>",
+                "<|im_start|>",
+            ]
+            .map(String::from),
+        );
+        let merges = [
+            "Ġ w",
+            "o r",
+            "Ġw or",
+            "l d",
+            "Ġwor ld",
+            "H e",
+            "l l",
+            "He ll",
+            "Hell o",
+            "á »",
+            "á» ĩ",
+            "V i",
+            "Vi á»ĩ",
+            "Viá»ĩ t",
+            "Ġ Viá»ĩt",
+            "Ġ N",
+            "a m",
+            "ĠN am",
+            "c h",
+            "Ã ł",
+            "ch Ãł",
+            "Ġ chÃł",
+            "o Ġ",
+        ];
+        let types = (0..vocab.len())
+            .map(|i| GgufValue::I32(if i >= 279 { 3 } else { 1 }))
+            .collect();
+        let names: Vec<&str> = vocab.iter().map(String::as_str).collect();
+        BpeTokenizer::from_gguf(&metadata(vec![
+            ("tokenizer.ggml.model", GgufValue::String("gpt2".into())),
+            ("tokenizer.ggml.pre", GgufValue::String("qwen2".into())),
+            ("tokenizer.ggml.tokens", strings(&names)),
+            ("tokenizer.ggml.merges", strings(&merges)),
+            ("tokenizer.ggml.token_type", GgufValue::Array(types)),
+            ("tokenizer.ggml.bos_token_id", GgufValue::U32(279)),
+            ("tokenizer.ggml.eos_token_id", GgufValue::U32(279)),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_gpt2_fixture() {
+        let tok = gpt2_fixture();
+        assert_eq!(tok.kind, TokenizerKind::Gpt2);
+        assert!(!tok.add_bos);
+        assert_eq!(tok.encode("Hello world"), vec![264, 260]);
+        assert_eq!(
+            tok.encode("Xin chào Việt Nam"),
+            vec![88, 105, 110, 277, 111, 270, 273]
+        );
+        // Double space and per-digit splitting from the Qwen2 pre-tokenizer
+        assert_eq!(
+            tok.encode("Hello  world 42"),
+            vec![264, 32, 260, 32, 52, 50]
+        );
+        assert_eq!(
+            tok.encode_with_special("<|im_start|>Hello world<|endoftext|>"),
+            vec![280, 264, 260, 279]
+        );
+        for text in [
+            "Xin chào Việt Nam",
+            "Tiếng Việt có dấu: ắ ằ ẳ ẵ ặ 😀",
+            "a\r\n\tb",
+        ] {
+            assert_eq!(tok.decode(&tok.encode(text)), text);
+        }
+    }
+
+    #[test]
+    fn test_byte_table_round_trips() {
+        let table = byte_chars();
+        assert_eq!(table[b' ' as usize], 'Ġ');
+        assert_eq!(table[b'\n' as usize], 'Ċ');
+        for b in 0..=255u8 {
+            assert_eq!(char_byte(table[b as usize]), Some(b));
+        }
+    }
+
+    #[test]
+    fn test_merge_queue_matches_naive() {
+        use rand::{Rng, SeedableRng};
+        // Random words and pair costs over a tiny alphabet, checked against
+        // the quadratic "find best pair, merge, repeat" definition
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let mut costs: HashMap<String, f32> = HashMap::new();
+        for _ in 0..40 {
+            let len = rng.gen_range(2..5);
+            let piece: String = (0..len).map(|_| rng.gen_range('a'..='d')).collect();
+            costs.insert(piece, rng.gen_range(0..8) as f32);
+        }
+        let cost = |_: &str, _: &str, joined: &str| costs.get(joined).copied();
+
+        for _ in 0..200 {
+            let len = rng.gen_range(0..12);
+            let word: String = (0..len).map(|_| rng.gen_range('a'..='d')).collect();
+            let mut naive: Vec<String> = word.chars().map(String::from).collect();
+            loop {
+                let best = (0..naive.len().saturating_sub(1))
+                    .filter_map(|i| {
+                        cost("", "", &(naive[i].clone() + &naive[i + 1])).map(|c| (c, i))
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+                let Some((_, i)) = best else { break };
+                let right = naive.remove(i + 1);
+                naive[i].push_str(&right);
+            }
+            assert_eq!(merge_symbols(&word, cost), naive, "word {word:?}");
+        }
+    }
+
+    #[test]
+    fn test_wordpiece() {
+        let pieces = [
+            "[PAD]", "[UNK]", "[CLS]", "[SEP]", "▁hello", "▁hel", "lo", "▁world", "▁vi", "ệt",
+            "▁,", "▁!", "▁中", "▁文",
+        ];
+        let types = (0..pieces.len())
+            .map(|i| GgufValue::I32(if (2..4).contains(&i) { 3 } else { 1 }))
+            .collect();
+        let tok = BpeTokenizer::from_gguf(&metadata(vec![
+            ("tokenizer.ggml.model", GgufValue::String("bert".into())),
+            ("tokenizer.ggml.tokens", strings(&pieces)),
+            ("tokenizer.ggml.token_type", GgufValue::Array(types)),
+            ("tokenizer.ggml.unknown_token_id", GgufValue::U32(1)),
+            ("tokenizer.ggml.cls_token_id", GgufValue::U32(2)),
+            ("tokenizer.ggml.seperator_token_id", GgufValue::U32(3)),
+            ("tokenizer.ggml.padding_token_id", GgufValue::U32(0)),
+        ]))
+        .unwrap();
+        assert_eq!(tok.kind, TokenizerKind::Wpm);
+        // Lowercased, punctuation split off, longest match first
+        assert_eq!(tok.encode("Hello, World!"), vec![4, 10, 7, 11]);
+        assert_eq!(tok.encode("Việt"), vec![8, 9]);
+        // Unspellable words collapse to one UNK
+        assert_eq!(tok.encode("hello xyz world"), vec![4, 1, 7]);
+        assert_eq!(tok.encode("中文"), vec![12, 13]);
+        assert_eq!(tok.encode_for_embedding("hello"), vec![2, 4, 3]);
+    }
+}
@@ -0,0 +1,384 @@
+//! Native tool calling for local models.
+//!
+//! Tool definitions are put into the prompt the way each model family was
+//! trained to see them, and the reply is parsed back into [`ToolCall`]s.
+//! Where the call syntax is plain text, a grammar keeps the call JSON valid
+//! against each tool's parameter schema while leaving ordinary answers free.
+
+use crate::chat_template::ChatFormat;
+use crate::gbnf::Grammar;
+use crate::json_schema;
+use bizclaw_core::error::Result;
+use bizclaw_core::types::{FunctionCall, Message, Role, ToolCall, ToolDefinition};
+use serde_json::Value;
+
+/// How a model family expects tools to be offered and called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolCallStyle {
+    /// `<tool_call>{"name": ..., "arguments": ...}</tool_call>` — Hermes, Qwen
+    /// and the default for formats without their own convention.
+    Hermes,
+    /// A bare `{"name": ..., "parameters": ...}` reply — Llama-3.1+.
+    Llama3,
+    /// `[TOOL_CALLS] [{"name": ..., "arguments": ...}]` — Mistral.
+    Mistral,
+}
+
+/// Reply text with any tool calls split out.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedReply {
+    pub content: String,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl ToolCallStyle {
+    pub fn for_format(format: ChatFormat) -> Self {
+        match format {
+            ChatFormat::Llama3 => Self::Llama3,
+            ChatFormat::Mistral => Self::Mistral,
+            _ => Self::Hermes,
+        }
+    }
+
+    /// Instructions plus tool signatures for the prompt.
+    pub fn tool_prompt(self, tools: &[ToolDefinition]) -> String {
+        match self {
+            Self::Hermes => {
+                let sigs: Vec<String> = tools.iter().map(|t| tool_json(t).to_string()).collect();
+                format!(
+                    "# Tools\n\nYou may call one or more functions to assist with the user query.\n\n\
+                     You are provided with function signatures within <tools></tools> XML tags:\n\
+                     <tools>\n{}\n</tools>\n\n\
+                     For each function call, return a json object with function name and arguments \
+                     within <tool_call></tool_call> XML tags:\n\
+                     <tool_call>\n{{\"name\": <function-name>, \"arguments\": <args-json-object>}}\n</tool_call>",
+                    sigs.join("\n")
+                )
+            }
+            Self::Llama3 => {
+                let sigs: Vec<String> = tools
+                    .iter()
+                    .map(|t| serde_json::to_string_pretty(&tool_json(t)).unwrap_or_default())
+                    .collect();
+                format!(
+                    "Given the following functions, please respond with a JSON for a function call \
+                     with its proper arguments that best answers the given prompt.\n\n\
+                     Respond in the format {{\"name\": function name, \"parameters\": dictionary of \
+                     argument name and its value}}. Do not use variables.\n\n{}",
+                    sigs.join("\n\n")
+                )
+            }
+            Self::Mistral => format!(
+                "You can call the following functions. To call them, reply with [TOOL_CALLS] \
+                 followed by a JSON list such as [{{\"name\": \"fn\", \"arguments\": {{}}}}].\n\n{}",
+                tools_list(tools)
+            ),
+        }
+    }
+
+    /// Add the tool prompt to a conversation: the system message for Hermes
+    /// and Mistral, the first user message for Llama-3 (as its template does).
+    pub fn inject(self, messages: &[Message], tools: &[ToolDefinition]) -> Vec<Message> {
+        let prompt = self.tool_prompt(tools);
+        let mut out = messages.to_vec();
+        if self == Self::Llama3
+            && let Some(user) = out.iter_mut().find(|m| m.role == Role::User)
+        {
+            user.content = format!("{prompt}\n\n{}", user.content);
+            return out;
+        }
+        match out.first_mut() {
+            Some(sys) if sys.role == Role::System => {
+                sys.content = format!("{}\n\n{prompt}", sys.content);
+            }
+            _ => out.insert(0, Message::system(prompt)),
+        }
+        out
+    }
+
+    /// Grammar allowing either a plain answer or well-formed calls to the
+    /// given tools. `None` when the style calls tools through control tokens
+    /// the grammar cannot see (Mistral's `[TOOL_CALLS]`).
+    pub fn grammar(self, tools: &[ToolDefinition]) -> Result<Option<Grammar>> {
+        // Plain answers must not start like a call, so the two never mix
+        let (text_start, calls, args_key) = match self {
+            Self::Hermes => (
+                "[^<]",
+                r#"tool-call ("\n" tool-call)*
+tool-call ::= "<tool_call>\n" tool-json "\n</tool_call>""#,
+                "arguments",
+            ),
+            Self::Llama3 => ("[^{]", "tool-json", "parameters"),
+            Self::Mistral => return Ok(None),
+        };
+        if tools.is_empty() {
+            return Ok(None);
+        }
+
+        let choices: Vec<String> = (0..tools.len()).map(|i| format!("tool-{i}")).collect();
+        let mut src = format!(
+            r#"root ::= tool-text | tool-calls
+tool-text ::= {text_start} .*
+tool-calls ::= {calls}
+tool-json ::= "{{" space "\"name\"" space ":" space ({}) "}}" space
+"#,
+            choices.join(" | ")
+        );
+        let arg_rules: Vec<String> = choices.iter().map(|c| format!("{c}-args")).collect();
+        for ((choice, args), tool) in choices.iter().zip(&arg_rules).zip(tools) {
+            let name = serde_json::to_string(&tool.name).unwrap_or_default();
+            src.push_str(&format!(
+                "{choice} ::= {} space \",\" space \"\\\"{args_key}\\\"\" space \":\" space {args}\n",
+                gbnf_literal(&name)
+            ));
+        }
+        let schemas: Vec<(&str, &Value)> = arg_rules
+            .iter()
+            .zip(tools)
+            .map(|(rule, tool)| (rule.as_str(), &tool.parameters))
+            .collect();
+        src.push_str(&json_schema::schemas_to_gbnf(&schemas)?);
+        Grammar::parse(&src).map(Some)
+    }
+
+    /// Split generated text into answer text and tool calls. Calls naming
+    /// unknown tools, or with unparseable JSON, are left in the text.
+    pub fn parse(self, text: &str, tools: &[ToolDefinition]) -> ParsedReply {
+        match self {
+            Self::Hermes => parse_hermes(text, tools),
+            Self::Llama3 | Self::Mistral => {
+                let body = text.trim_start();
+                let body = body
+                    .strip_prefix("[TOOL_CALLS]")
+                    .or_else(|| body.strip_prefix("<|python_tag|>"))
+                    .unwrap_or(body);
+                match parse_json_calls(body, tools) {
+                    Some(tool_calls) => ParsedReply {
+                        content: String::new(),
+                        tool_calls,
+                    },
+                    None => ParsedReply {
+                        content: text.to_string(),
+                        tool_calls: Vec::new(),
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// OpenAI-style function entry, as chat templates expect it.
+fn tool_json(tool: &ToolDefinition) -> Value {
+    serde_json::json!({
+        "type": "function",
+        "function": {
+            "name": tool.name,
+            "description": tool.description,
+            "parameters": tool.parameters,
+        }
+    })
+}
+
+/// JSON list of all tool entries (Mistral's `[AVAILABLE_TOOLS]` payload).
+pub fn tools_list(tools: &[ToolDefinition]) -> String {
+    Value::Array(tools.iter().map(tool_json).collect()).to_string()
+}
+
+fn gbnf_literal(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn parse_hermes(text: &str, tools: &[ToolDefinition]) -> ParsedReply {
+    const OPEN: &str = "<tool_call>";
+    const CLOSE: &str = "</tool_call>";
+    let mut reply = ParsedReply::default();
+    let mut rest = text;
+    while let Some(start) = rest.find(OPEN) {
+        let after = &rest[start + OPEN.len()..];
+        // A missing close tag usually means generation stopped right after the JSON
+        let (inner, next) = match after.find(CLOSE) {
+            Some(end) => (&after[..end], &after[end + CLOSE.len()..]),
+            None => (after, ""),
+        };
+        reply.content.push_str(&rest[..start]);
+        match serde_json::from_str::<Value>(inner.trim())
+            .ok()
+            .and_then(|v| call_from_json(&v, tools))
+        {
+            Some(call) => reply.tool_calls.push(call),
+            None => reply
+                .content
+                .push_str(&rest[start..rest.len() - next.len()]),
+        }
+        rest = next;
+    }
+    reply.content.push_str(rest);
+    reply.content = reply.content.trim().to_string();
+    reply
+}
+
+/// One or more JSON calls: a list, or objects separated by whitespace or `;`.
+fn parse_json_calls(body: &str, tools: &[ToolDefinition]) -> Option<Vec<ToolCall>> {
+    let body = body.trim();
+    if !body.starts_with('{') && !body.starts_with('[') {
+        return None;
+    }
+    let mut values = Vec::new();
+    for part in body.split(';') {
+        for value in serde_json::Deserializer::from_str(part).into_iter::<Value>() {
+            match value.ok()? {
+                Value::Array(items) => values.extend(items),
+                v => values.push(v),
+            }
+        }
+    }
+    let calls: Option<Vec<ToolCall>> = values.iter().map(|v| call_from_json(v, tools)).collect();
+    calls.filter(|c| !c.is_empty())
+}
+
+fn call_from_json(value: &Value, tools: &[ToolDefinition]) -> Option<ToolCall> {
+    let name = value.get("name")?.as_str()?;
+    if !tools.iter().any(|t| t.name == name) {
+        return None;
+    }
+    let arguments = match value.get("arguments").or_else(|| value.get("parameters")) {
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+        None => "{}".to_string(),
+    };
+    Some(ToolCall {
+        id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+        r#type: "function".into(),
+        function: FunctionCall {
+            name: name.to_string(),
+            arguments,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tools() -> Vec<ToolDefinition> {
+        vec![
+            ToolDefinition {
+                name: "get_weather".into(),
+                description: "Weather lookup".into(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}, "days": {"type": "integer"}},
+                    "required": ["city"]
+                }),
+            },
+            ToolDefinition {
+                name: "now".into(),
+                description: "Current time".into(),
+                parameters: json!({"type": "object"}),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_parse_hermes() {
+        let text = "Let me check.\n<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Hanoi\"}}\n</tool_call>\n<tool_call>\n{\"name\": \"now\", \"arguments\": {}}";
+        let reply = ToolCallStyle::Hermes.parse(text, &tools());
+        assert_eq!(reply.content, "Let me check.");
+        assert_eq!(reply.tool_calls.len(), 2);
+        assert_eq!(reply.tool_calls[0].function.name, "get_weather");
+        assert_eq!(
+            reply.tool_calls[0].function.arguments,
+            r#"{"city":"Hanoi"}"#
+        );
+        assert_eq!(reply.tool_calls[1].function.name, "now");
+        assert_ne!(reply.tool_calls[0].id, reply.tool_calls[1].id);
+
+        // Unknown tools and broken JSON stay as text
+        let text = "<tool_call>{\"name\": \"rm_rf\", \"arguments\": {}}</tool_call> ok";
+        let reply = ToolCallStyle::Hermes.parse(text, &tools());
+        assert!(reply.tool_calls.is_empty());
+        assert_eq!(reply.content, text);
+        let reply = ToolCallStyle::Hermes.parse("<tool_call>{\"name\": </tool_call>", &tools());
+        assert!(reply.tool_calls.is_empty());
+    }
+
+    #[test]
+    fn test_parse_llama3_and_mistral() {
+        let reply = ToolCallStyle::Llama3.parse(
+            "{\"name\": \"get_weather\", \"parameters\": {\"city\": \"Hue\"}}",
+            &tools(),
+        );
+        assert!(reply.content.is_empty());
+        assert_eq!(reply.tool_calls[0].function.arguments, r#"{"city":"Hue"}"#);
+
+        let reply = ToolCallStyle::Llama3.parse("The weather is {mild} today.", &tools());
+        assert!(reply.tool_calls.is_empty());
+        assert_eq!(reply.content, "The weather is {mild} today.");
+
+        let reply = ToolCallStyle::Mistral.parse(
+            "[TOOL_CALLS] [{\"name\": \"now\", \"arguments\": {}}, {\"name\": \"get_weather\", \"arguments\": \"{\\\"city\\\": \\\"Hue\\\"}\"}]",
+            &tools(),
+        );
+        assert_eq!(reply.tool_calls.len(), 2);
+        assert_eq!(reply.tool_calls[1].function.arguments, r#"{"city": "Hue"}"#);
+    }
+
+    #[test]
+    fn test_inject() {
+        let msgs = vec![Message::system("Be brief."), Message::user("Weather?")];
+        let hermes = ToolCallStyle::Hermes.inject(&msgs, &tools());
+        assert_eq!(hermes.len(), 2);
+        assert!(hermes[0].content.starts_with("Be brief.\n\n# Tools\n"));
+        assert!(hermes[0].content.contains(
+            "<tools>\n{\"function\":{\"description\":\"Weather lookup\",\"name\":\"get_weather\""
+        ));
+
+        let llama3 = ToolCallStyle::Llama3.inject(&msgs, &tools());
+        assert_eq!(llama3[0].content, "Be brief.");
+        assert!(
+            llama3[1]
+                .content
+                .starts_with("Given the following functions")
+        );
+        assert!(llama3[1].content.ends_with("\n\nWeather?"));
+
+        let no_system = ToolCallStyle::Mistral.inject(&msgs[1..], &tools());
+        assert_eq!(no_system[0].role, Role::System);
+        assert!(no_system[0].content.contains("[TOOL_CALLS]"));
+    }
+
+    #[test]
+    fn test_grammar() {
+        let accepts =
+            |g: &Grammar, text: &str| g.start().accept_str(text).is_some_and(|s| s.is_complete());
+
+        let g = ToolCallStyle::Hermes.grammar(&tools()).unwrap().unwrap();
+        assert!(accepts(&g, "Sunny, 31°C."));
+        assert!(accepts(
+            &g,
+            "<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Hanoi\", \"days\": 2}}\n</tool_call>"
+        ));
+        assert!(accepts(
+            &g,
+            "<tool_call>\n{\"name\": \"now\", \"arguments\": {}}\n</tool_call>\n<tool_call>\n{\"name\": \"now\", \"arguments\": {}}\n</tool_call>"
+        ));
+        assert!(!accepts(
+            &g,
+            "<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"days\": 2}}\n</tool_call>"
+        ));
+        assert!(!accepts(
+            &g,
+            "<tool_call>\n{\"name\": \"rm_rf\", \"arguments\": {}}\n</tool_call>"
+        ));
+        assert!(!accepts(&g, ""));
+
+        let g = ToolCallStyle::Llama3.grammar(&tools()).unwrap().unwrap();
+        assert!(accepts(&g, "{\"name\": \"now\", \"parameters\": {}}"));
+        assert!(!accepts(&g, "{\"name\": \"now\", \"arguments\": {}}"));
+        assert!(accepts(&g, "It is noon."));
+
+        assert!(ToolCallStyle::Mistral.grammar(&tools()).unwrap().is_none());
+        assert!(ToolCallStyle::Hermes.grammar(&[]).unwrap().is_none());
+    }
+}
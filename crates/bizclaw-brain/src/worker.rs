@@ -0,0 +1,1076 @@
+//! Brain worker — one thread owning the model, serving many chats at once.
+//!
+//! Requests arrive over a queue and wait there until a KV slot is free.
+//! Each step decodes one token for every active sequence and spends the
+//! rest of the `batch_size` token budget on prompt prefill (in chunks,
+//! rotating between prefilling sequences), all in a single
+//! [`forward_seqs`](crate::forward::forward_seqs) pass. Sequences join and
+//! leave between steps (continuous batching), so a long prompt never stalls
+//! the chats already generating.
+//!
+//! Waiting requests are admitted round-robin across clients, so one busy
+//! chat cannot starve the others. Slots are allocated on demand up to
+//! `max_slots` and keep their KV cache when a request finishes; a new
+//! request goes to the slot sharing the longest prompt prefix with it.
+
+use crate::forward::{self, SeqBatch};
+use crate::gbnf::{DetachedState, Grammar};
+use crate::kv_cache::KvStorage;
+use crate::sampler::{Sampler, SamplerConfig};
+use crate::session_cache::SessionStore;
+use crate::stream::Utf8StreamDecoder;
+use crate::tool_calls::{ParsedReply, ToolCallStyle};
+use crate::{
+    BrainEngine, CancelToken, ChatReply, GenerationStats, LoadedModel, StopReason, common_prefix,
+    context_keep, json_schema, reuse_prefix, sample_constrained, shift_context,
+};
+use bizclaw_core::error::{BizClawError, Result};
+use bizclaw_core::types::{Message, ToolDefinition};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+
+/// Worker limits.
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    /// Sequences decoded together (each holds a full-context KV cache)
+    pub max_slots: usize,
+    /// Requests allowed to wait for a slot before new ones are rejected
+    pub max_queue: usize,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self {
+            max_slots: 2,
+            max_queue: 32,
+        }
+    }
+}
+
+/// One chat turn for the worker.
+#[derive(Debug, Clone)]
+pub struct WorkerRequest {
+    pub messages: Vec<Message>,
+    pub tools: Vec<ToolDefinition>,
+    pub max_tokens: u32,
+    pub sampler: SamplerConfig,
+    /// Fairness key: a client's requests run in order, clients take turns.
+    pub client: String,
+    /// Session whose KV cache is restored and saved (see `session_cache_dir`).
+    pub session: Option<String>,
+    /// LoRA adapter to run with ([`BrainEngine::set_lora`]); requests for
+    /// different adapters are not batched together.
+    pub lora: Option<PathBuf>,
+    pub lora_scale: f32,
+    pub cancel: CancelToken,
+}
+
+/// Streamed result of a [`WorkerRequest`].
+#[derive(Debug, Clone)]
+pub enum WorkerEvent {
+    /// Next piece of generated text (always valid UTF-8).
+    Delta(String),
+    /// The finished turn; always the last event.
+    Done(ChatReply),
+}
+
+/// Receiving end of a submitted request.
+pub type WorkerReceiver = UnboundedReceiver<Result<WorkerEvent>>;
+
+/// Queue and throughput counters, updated after every step.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WorkerMetrics {
+    /// Requests waiting for a slot
+    pub queue_depth: usize,
+    pub peak_queue_depth: usize,
+    /// Sequences currently prefilling or decoding
+    pub active: usize,
+    /// KV slots allocated so far
+    pub slots: usize,
+    pub max_slots: usize,
+    pub submitted: u64,
+    pub completed: u64,
+    /// Turned away because the queue was full
+    pub rejected: u64,
+    pub cancelled: u64,
+    pub failed: u64,
+    /// Batched forward passes
+    pub steps: u64,
+    /// Tokens run through the model over all steps
+    pub batched_tokens: u64,
+    pub generated_tokens: u64,
+}
+
+impl WorkerMetrics {
+    /// Average tokens per forward pass.
+    pub fn mean_batch(&self) -> f64 {
+        if self.steps == 0 {
+            return 0.0;
+        }
+        self.batched_tokens as f64 / self.steps as f64
+    }
+}
+
+/// Handle to a running worker. Dropping it lets the worker finish the
+/// requests it has and exit.
+pub struct BrainWorker {
+    jobs: mpsc::Sender<Job>,
+    metrics: Arc<Mutex<WorkerMetrics>>,
+    max_queue: usize,
+    model_info: String,
+}
+
+struct Job {
+    request: WorkerRequest,
+    events: UnboundedSender<Result<WorkerEvent>>,
+    queued_at: Instant,
+}
+
+impl BrainWorker {
+    /// Move the engine's loaded model onto a worker thread.
+    pub fn spawn(engine: BrainEngine, config: WorkerConfig) -> Result<Self> {
+        let model_info = engine
+            .model_info()
+            .ok_or_else(|| BizClawError::Brain("Model not loaded".into()))?;
+        if engine.draft.is_some() {
+            tracing::warn!("Brain worker: speculative decoding is not used with multiple slots");
+        }
+        let metrics = Arc::new(Mutex::new(WorkerMetrics {
+            max_slots: config.max_slots.max(1),
+            ..Default::default()
+        }));
+        let scheduler = Scheduler::new(engine, config.max_slots, metrics.clone())?;
+        let (jobs, rx) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("brain-worker".into())
+            .spawn(move || scheduler.run(rx))?;
+        Ok(Self {
+            jobs,
+            metrics,
+            max_queue: config.max_queue,
+            model_info,
+        })
+    }
+
+    /// Queue a request. Fails at once if `max_queue` requests are waiting.
+    pub fn submit(&self, request: WorkerRequest) -> Result<WorkerReceiver> {
+        {
+            let mut metrics = lock(&self.metrics);
+            if metrics.queue_depth >= self.max_queue {
+                metrics.rejected += 1;
+                return Err(BizClawError::Brain(format!(
+                    "Brain queue full ({} requests waiting)",
+                    metrics.queue_depth
+                )));
+            }
+            metrics.submitted += 1;
+            metrics.queue_depth += 1;
+            metrics.peak_queue_depth = metrics.peak_queue_depth.max(metrics.queue_depth);
+        }
+        let (events, rx) = unbounded_channel();
+        let job = Job {
+            request,
+            events,
+            queued_at: Instant::now(),
+        };
+        if self.jobs.send(job).is_err() {
+            let mut metrics = lock(&self.metrics);
+            metrics.queue_depth -= 1;
+            metrics.failed += 1;
+            return Err(BizClawError::Brain("Brain worker has stopped".into()));
+        }
+        Ok(rx)
+    }
+
+    pub fn metrics(&self) -> WorkerMetrics {
+        lock(&self.metrics).clone()
+    }
+
+    /// Description of the loaded model, as [`BrainEngine::model_info`].
+    pub fn model_info(&self) -> &str {
+        &self.model_info
+    }
+}
+
+fn lock(metrics: &Mutex<WorkerMetrics>) -> std::sync::MutexGuard<'_, WorkerMetrics> {
+    metrics.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Round-robin queue: one FIFO per client, clients served in turn.
+struct FairQueue<T> {
+    /// Clients with waiting items, next to serve first
+    turns: VecDeque<String>,
+    pending: HashMap<String, VecDeque<T>>,
+}
+
+impl<T> FairQueue<T> {
+    fn new() -> Self {
+        Self {
+            turns: VecDeque::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    fn push(&mut self, client: &str, item: T) {
+        let queue = self.pending.entry(client.to_string()).or_default();
+        if queue.is_empty() {
+            self.turns.push_back(client.to_string());
+        }
+        queue.push_back(item);
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let client = self.turns.pop_front()?;
+        let queue = self.pending.get_mut(&client)?;
+        let item = queue.pop_front();
+        if queue.is_empty() {
+            self.pending.remove(&client);
+        } else {
+            self.turns.push_back(client);
+        }
+        item
+    }
+
+    /// The item `pop` would return.
+    fn peek(&self) -> Option<&T> {
+        self.pending.get(self.turns.front()?)?.front()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+}
+
+/// A KV cache and the sequence (if any) currently using it.
+struct Slot {
+    kv_cache: KvStorage,
+    /// Tokens whose keys/values are in `kv_cache`, from position 0
+    cached_tokens: Vec<u32>,
+    /// Session `cached_tokens` belong to
+    session: Option<String>,
+    /// Scheduler clock when the slot was last assigned
+    last_used: u64,
+    seq: Option<Sequence>,
+}
+
+/// A request being prefilled or decoded.
+struct Sequence {
+    events: UnboundedSender<Result<WorkerEvent>>,
+    cancel: CancelToken,
+    tools: Vec<ToolDefinition>,
+    style: ToolCallStyle,
+    grammar: Option<Grammar>,
+    grammar_state: Option<DetachedState>,
+    sampler: Sampler,
+    /// Prompt followed by the generated tokens; the last generated token is
+    /// not in the KV cache yet
+    tokens: Vec<u32>,
+    /// How many of `tokens` have been fed to the model. Equals the slot's
+    /// cached length until a context shift drops some of them.
+    fed: usize,
+    prompt_len: usize,
+    max_gen: usize,
+    decoder: Utf8StreamDecoder,
+    text: String,
+    stats: GenerationStats,
+    started: Instant,
+    token_start: Instant,
+}
+
+impl Sequence {
+    fn emit(&mut self, piece: String) {
+        if !piece.is_empty() {
+            self.text.push_str(&piece);
+            let _ = self.events.send(Ok(WorkerEvent::Delta(piece)));
+        }
+    }
+}
+
+struct Scheduler {
+    model: LoadedModel,
+    slots: Vec<Slot>,
+    max_slots: usize,
+    /// Token budget of one forward pass
+    batch_size: usize,
+    max_tokens: u32,
+    json_mode: bool,
+    context_shift: bool,
+    /// Tokens a context shift keeps at the start of a slot
+    context_keep: usize,
+    sessions: Option<SessionStore>,
+    queue: FairQueue<Job>,
+    metrics: Arc<Mutex<WorkerMetrics>>,
+    clock: u64,
+}
+
+impl Scheduler {
+    fn new(
+        engine: BrainEngine,
+        max_slots: usize,
+        metrics: Arc<Mutex<WorkerMetrics>>,
+    ) -> Result<Self> {
+        let BrainEngine {
+            config,
+            model,
+            sessions,
+            ..
+        } = engine;
+        let mut model = model.ok_or_else(|| BizClawError::Brain("Model not loaded".into()))?;
+
+        // The engine's cache becomes the first slot, prefix and all
+        let precision = model.kv_cache.precision();
+        let first = Slot {
+            kv_cache: std::mem::replace(&mut model.kv_cache, KvStorage::new(precision, 0, 0, 0, 0)),
+            cached_tokens: std::mem::take(&mut model.cached_tokens),
+            session: model.cache_session.take(),
+            last_used: 0,
+            seq: None,
+        };
+        Ok(Self {
+            context_keep: context_keep(&config, &model.tokenizer),
+            model,
+            slots: vec![first],
+            max_slots: max_slots.max(1),
+            batch_size: config.batch_size.max(1) as usize,
+            max_tokens: config.max_tokens,
+            json_mode: config.json_mode,
+            context_shift: config.context_shift,
+            sessions,
+            queue: FairQueue::new(),
+            metrics,
+            clock: 0,
+        })
+    }
+
+    fn run(mut self, jobs: mpsc::Receiver<Job>) {
+        let mut open = true;
+        while open || !self.is_idle() {
+            // Block only when there is nothing to work on
+            if open && self.is_idle() {
+                match jobs.recv() {
+                    Ok(job) => self.enqueue(job),
+                    Err(_) => open = false,
+                }
+            }
+            while open {
+                match jobs.try_recv() {
+                    Ok(job) => self.enqueue(job),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => open = false,
+                }
+            }
+            self.drop_cancelled();
+            self.admit();
+            self.step();
+        }
+    }
+
+    fn enqueue(&mut self, job: Job) {
+        let client = job.request.client.clone();
+        self.queue.push(&client, job);
+    }
+
+    fn is_idle(&self) -> bool {
+        self.queue.is_empty() && self.slots.iter().all(|s| s.seq.is_none())
+    }
+
+    fn n_ctx(&self) -> usize {
+        self.model.params.max_seq_len as usize
+    }
+
+    /// Move waiting requests into free slots, fairly across clients.
+    fn admit(&mut self) {
+        loop {
+            let active = self.slots.iter().filter(|s| s.seq.is_some()).count();
+            let Some(next) = self.queue.peek() else {
+                break;
+            };
+            // A batch shares one set of weights: a request for another
+            // adapter waits for the running sequences to finish
+            let request = &next.request;
+            let same_lora = self
+                .model
+                .lora_is(request.lora.as_deref(), request.lora_scale);
+            if active == self.max_slots || (active > 0 && !same_lora) {
+                break;
+            }
+            let Some(job) = self.queue.pop() else {
+                break;
+            };
+            lock(&self.metrics).queue_depth -= 1;
+            if job.request.cancel.is_cancelled() || job.events.is_closed() {
+                lock(&self.metrics).cancelled += 1;
+                continue;
+            }
+            tracing::debug!(
+                "Brain worker: {} admitted after {:?} in queue",
+                job.request.client,
+                job.queued_at.elapsed()
+            );
+            let events = job.events.clone();
+            if let Err(e) = self.start(job.request, job.events) {
+                lock(&self.metrics).failed += 1;
+                let _ = events.send(Err(e));
+            }
+        }
+        self.publish();
+    }
+
+    /// Tokenize the request and place it in a slot.
+    fn start(
+        &mut self,
+        request: WorkerRequest,
+        events: UnboundedSender<Result<WorkerEvent>>,
+    ) -> Result<()> {
+        if self
+            .model
+            .set_lora(request.lora.as_deref(), request.lora_scale)?
+        {
+            for slot in &mut self.slots {
+                slot.cached_tokens.clear();
+                slot.session = None;
+            }
+        }
+
+        let model = &self.model;
+        let prompt = model
+            .chat_template
+            .render(&request.messages, &request.tools, true);
+        let mut tokens = model.tokenizer.encode_with_special(&prompt);
+        if model.tokenizer.add_bos && tokens.first() != Some(&model.tokenizer.bos_id) {
+            tokens.insert(0, model.tokenizer.bos_id);
+        }
+        let n_ctx = self.n_ctx();
+        if tokens.is_empty() || tokens.len() >= n_ctx {
+            return Err(BizClawError::Brain(format!(
+                "Prompt too long: {} tokens (context {n_ctx})",
+                tokens.len()
+            )));
+        }
+
+        let style = model.chat_template.tool_style();
+        let grammar = if request.tools.is_empty() {
+            self.json_mode.then(json_schema::json_grammar)
+        } else {
+            style.grammar(&request.tools).unwrap_or_else(|e| {
+                tracing::warn!("Tool calls unconstrained: {e}");
+                None
+            })
+        };
+        let grammar_state = grammar.as_ref().map(|g| g.start().detach());
+
+        let index = self.pick_slot(&tokens);
+        let slot = &mut self.slots[index];
+        let reused = reuse_prefix(
+            &mut slot.kv_cache,
+            &mut slot.cached_tokens,
+            &mut slot.session,
+            &self.model.model_key,
+            self.sessions.as_ref(),
+            request.session.as_deref(),
+            &tokens,
+        );
+        let max_gen = request.max_tokens.min(self.max_tokens) as usize;
+        let max_gen = if self.context_shift {
+            max_gen
+        } else {
+            max_gen.min(n_ctx - tokens.len())
+        };
+        self.clock += 1;
+        let slot = &mut self.slots[index];
+        slot.last_used = self.clock;
+        slot.seq = Some(Sequence {
+            events,
+            cancel: request.cancel,
+            tools: request.tools,
+            style,
+            grammar,
+            grammar_state,
+            sampler: Sampler::new(request.sampler),
+            prompt_len: tokens.len(),
+            stats: GenerationStats {
+                prompt_tokens: tokens.len(),
+                cached_tokens: reused,
+                stop_reason: StopReason::MaxTokens,
+                ..Default::default()
+            },
+            tokens,
+            fed: reused,
+            max_gen,
+            decoder: Utf8StreamDecoder::new(),
+            text: String::new(),
+            started: Instant::now(),
+            token_start: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// An idle slot to run `tokens` in: one whose whole cache is a prefix of
+    /// the prompt (the same conversation), else an empty one, else a new
+    /// slot while under `max_slots`, else the idle slot sharing the longest
+    /// prefix (least recently used on ties).
+    fn pick_slot(&mut self, tokens: &[u32]) -> usize {
+        let idle = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.seq.is_none())
+            .map(|(i, s)| (i, common_prefix(&s.cached_tokens, tokens), s));
+
+        let continuing = idle
+            .clone()
+            .filter(|(_, prefix, s)| *prefix > 0 && *prefix == s.cached_tokens.len())
+            .max_by_key(|(_, prefix, _)| *prefix);
+        if let Some((i, _, _)) = continuing {
+            return i;
+        }
+        if let Some((i, _, _)) = idle.clone().find(|(_, _, s)| s.cached_tokens.is_empty()) {
+            return i;
+        }
+        if self.slots.len() < self.max_slots {
+            let p = &self.model.params;
+            self.slots.push(Slot {
+                kv_cache: KvStorage::new(
+                    self.slots[0].kv_cache.precision(),
+                    p.n_layers as usize,
+                    p.max_seq_len as usize,
+                    p.n_kv_heads as usize,
+                    p.head_dim as usize,
+                ),
+                cached_tokens: Vec::new(),
+                session: None,
+                last_used: 0,
+                seq: None,
+            });
+            return self.slots.len() - 1;
+        }
+        idle.max_by(|a, b| a.1.cmp(&b.1).then(b.2.last_used.cmp(&a.2.last_used)))
+            .map(|(i, _, _)| i)
+            .expect("admit only runs with a free slot")
+    }
+
+    /// End sequences whose caller cancelled or went away.
+    fn drop_cancelled(&mut self) {
+        for i in 0..self.slots.len() {
+            if let Some(seq) = &self.slots[i].seq
+                && (seq.cancel.is_cancelled() || seq.events.is_closed())
+            {
+                lock(&self.metrics).cancelled += 1;
+                self.finish(i, StopReason::Cancelled);
+            }
+        }
+    }
+
+    /// One batched forward pass over every active sequence, then sampling
+    /// for those whose input is fully in the cache.
+    fn step(&mut self) {
+        // A decoding sequence whose slot is full makes room first
+        let n_ctx = self.n_ctx();
+        for slot in &mut self.slots {
+            let Some(seq) = &mut slot.seq else { continue };
+            if seq.fed >= seq.prompt_len && slot.cached_tokens.len() >= n_ctx {
+                shift_context(
+                    &self.model.params,
+                    &mut slot.kv_cache,
+                    &mut slot.cached_tokens,
+                    self.context_keep,
+                );
+                seq.stats.context_shifts += 1;
+            }
+        }
+
+        // Decoding sequences feed their last token; prefill shares the rest
+        // of the budget, starting with a different sequence every step.
+        // Ranges index the sequence's tokens.
+        let mut ranges = vec![None; self.slots.len()];
+        let mut budget = self.batch_size;
+        let mut prefilling = Vec::new();
+        for (i, slot) in self.slots.iter().enumerate() {
+            let Some(seq) = &slot.seq else { continue };
+            if seq.fed >= seq.prompt_len {
+                ranges[i] = Some(seq.fed..seq.fed + 1);
+                budget = budget.saturating_sub(1);
+            } else {
+                prefilling.push(i);
+            }
+        }
+        if !prefilling.is_empty() {
+            let start = self.clock as usize % prefilling.len();
+            prefilling.rotate_left(start);
+        }
+        for i in prefilling {
+            if budget == 0 && ranges.iter().any(Option::is_some) {
+                break;
+            }
+            let (fed, remaining) = self.slots[i]
+                .seq
+                .as_ref()
+                .map_or((0, 0), |s| (s.fed, s.prompt_len - s.fed));
+            let take = remaining.min(budget.max(1));
+            ranges[i] = Some(fed..fed + take);
+            budget = budget.saturating_sub(take);
+        }
+
+        let order: Vec<usize> = (0..ranges.len()).filter(|&i| ranges[i].is_some()).collect();
+        if order.is_empty() {
+            return;
+        }
+        self.clock += 1;
+
+        let vocab = self.model.params.vocab_size as usize;
+        let mut logits = vec![0.0f32; order.len() * vocab];
+        let mut batch = Vec::with_capacity(order.len());
+        for (slot, range) in self.slots.iter_mut().zip(&ranges) {
+            if let (Some(range), Some(seq)) = (range, &slot.seq) {
+                batch.push(SeqBatch {
+                    kv_cache: &mut slot.kv_cache,
+                    tokens: &seq.tokens[range.clone()],
+                    start_pos: slot.cached_tokens.len(),
+                });
+            }
+        }
+        let n_tokens: usize = batch.iter().map(|b| b.tokens.len()).sum();
+        let result = forward::forward_seqs(
+            &self.model.mmap_model,
+            &self.model.weights,
+            &self.model.params,
+            &mut batch,
+            &mut logits,
+        );
+        drop(batch);
+        {
+            let mut metrics = lock(&self.metrics);
+            metrics.steps += 1;
+            metrics.batched_tokens += n_tokens as u64;
+        }
+
+        if let Err(e) = result {
+            tracing::warn!("Brain worker: forward pass failed: {e}");
+            for &i in &order {
+                if let Some(seq) = self.slots[i].seq.take() {
+                    let _ = seq.events.send(Err(BizClawError::Brain(e.to_string())));
+                }
+                // The cache may be half written
+                self.slots[i].cached_tokens.clear();
+                lock(&self.metrics).failed += 1;
+            }
+            self.publish();
+            return;
+        }
+
+        for (row, &i) in order.iter().enumerate() {
+            let range = ranges[i].clone().unwrap_or_default();
+            let slot = &mut self.slots[i];
+            let Some(seq) = &mut slot.seq else { continue };
+            slot.cached_tokens
+                .extend_from_slice(&seq.tokens[range.clone()]);
+            seq.fed = range.end;
+            if seq.fed < seq.tokens.len() {
+                continue; // still prefilling
+            }
+            if seq.stats.generated_tokens == 0 {
+                seq.stats.prefill_time = seq.started.elapsed();
+            }
+            let row_logits = &mut logits[row * vocab..(row + 1) * vocab];
+            match self.advance(i, row_logits) {
+                Ok(Some(reason)) => self.finish(i, reason),
+                Ok(None) => {}
+                Err(e) => {
+                    lock(&self.metrics).failed += 1;
+                    if let Some(seq) = self.slots[i].seq.take() {
+                        let _ = seq.events.send(Err(e));
+                    }
+                }
+            }
+        }
+        self.publish();
+    }
+
+    /// Sample the next token of slot `i`'s sequence and stream it. Returns
+    /// why the sequence stops, if it does.
+    fn advance(&mut self, i: usize, logits: &mut [f32]) -> Result<Option<StopReason>> {
+        let n_ctx = self.n_ctx();
+        let context_shift = self.context_shift;
+        let model = &self.model;
+        let Some(seq) = &mut self.slots[i].seq else {
+            return Ok(None);
+        };
+
+        let (token, state) = match (&seq.grammar, seq.grammar_state.take()) {
+            (Some(grammar), Some(detached)) => {
+                let state = grammar.resume(detached);
+                let token = sample_constrained(
+                    &mut seq.sampler,
+                    &model.stop_ids,
+                    &model.token_pieces,
+                    &state,
+                    logits,
+                    &seq.tokens,
+                )?;
+                (token, Some(state))
+            }
+            _ => (seq.sampler.sample(logits, &seq.tokens), None),
+        };
+        if model.stop_ids.contains(&token) {
+            return Ok(Some(StopReason::Eos));
+        }
+
+        let piece = &model.token_pieces[token as usize];
+        let mut finished = false;
+        if let Some(state) = state {
+            let state = state
+                .accept_bytes(piece)
+                .ok_or_else(|| BizClawError::Brain("grammar rejected sampled token".into()))?;
+            finished = state.is_finished();
+            seq.grammar_state = Some(state.detach());
+        }
+
+        seq.tokens.push(token);
+        seq.stats.generated_tokens += 1;
+        seq.stats.token_times.push(seq.token_start.elapsed());
+        seq.token_start = Instant::now();
+        let text = seq.decoder.push(piece);
+        seq.emit(text);
+
+        Ok(if finished {
+            Some(StopReason::GrammarComplete)
+        } else if seq.stats.generated_tokens >= seq.max_gen
+            || (!context_shift && seq.tokens.len() >= n_ctx)
+        {
+            Some(StopReason::MaxTokens)
+        } else {
+            None
+        })
+    }
+
+    /// Complete slot `i`'s sequence: flush text, parse tool calls, send the
+    /// reply and persist the session cache.
+    fn finish(&mut self, i: usize, reason: StopReason) {
+        let slot = &mut self.slots[i];
+        let Some(mut seq) = slot.seq.take() else {
+            return;
+        };
+        let rest = seq.decoder.finish();
+        seq.emit(rest);
+        seq.stats.stop_reason = reason;
+
+        let parsed = if seq.tools.is_empty() {
+            ParsedReply {
+                content: std::mem::take(&mut seq.text),
+                tool_calls: Vec::new(),
+            }
+        } else {
+            seq.style.parse(&seq.text, &seq.tools)
+        };
+        {
+            let mut metrics = lock(&self.metrics);
+            metrics.generated_tokens += seq.stats.generated_tokens as u64;
+            if reason != StopReason::Cancelled {
+                metrics.completed += 1;
+            }
+        }
+        let _ = seq.events.send(Ok(WorkerEvent::Done(ChatReply {
+            content: parsed.content,
+            tool_calls: parsed.tool_calls,
+            stats: seq.stats,
+        })));
+
+        if let (Some(store), Some(session)) = (&self.sessions, &slot.session) {
+            let snapshot = slot.kv_cache.snapshot(slot.cached_tokens.len());
+            if let Err(e) = store.save(
+                session,
+                &self.model.model_key,
+                &slot.cached_tokens,
+                &snapshot,
+            ) {
+                tracing::warn!("Failed to save session cache for {session}: {e}");
+            }
+        }
+    }
+
+    fn publish(&self) {
+        let mut metrics = lock(&self.metrics);
+        metrics.active = self.slots.iter().filter(|s| s.seq.is_some()).count();
+        metrics.slots = self.slots.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BrainConfig;
+    use crate::test_model::TinyLlama;
+
+    fn request(client: &str, text: &str) -> WorkerRequest {
+        WorkerRequest {
+            messages: vec![Message::user(text)],
+            tools: Vec::new(),
+            max_tokens: 8,
+            sampler: SamplerConfig {
+                temperature: 0.0,
+                ..Default::default()
+            },
+            client: client.into(),
+            session: None,
+            lora: None,
+            lora_scale: 1.0,
+            cancel: CancelToken::new(),
+        }
+    }
+
+    fn engine(file: &crate::test_model::TempModel) -> BrainEngine {
+        let mut engine = BrainEngine::new(BrainConfig {
+            temperature: 0.0,
+            batch_size: 8,
+            ..Default::default()
+        });
+        engine.load_model(&file.path).unwrap();
+        engine
+    }
+
+    /// Text and stats of a finished request.
+    fn collect(rx: &mut WorkerReceiver) -> (String, ChatReply) {
+        let mut text = String::new();
+        loop {
+            match rx.try_recv().expect("request finished").unwrap() {
+                WorkerEvent::Delta(piece) => text.push_str(&piece),
+                WorkerEvent::Done(reply) => return (text, reply),
+            }
+        }
+    }
+
+    fn job(request: WorkerRequest) -> (Job, WorkerReceiver) {
+        let (events, rx) = unbounded_channel();
+        let job = Job {
+            request,
+            events,
+            queued_at: Instant::now(),
+        };
+        (job, rx)
+    }
+
+    #[test]
+    fn test_fair_queue() {
+        let mut queue = FairQueue::new();
+        for item in ["a1", "a2", "a3"] {
+            queue.push("a", item);
+        }
+        queue.push("b", "b1");
+        queue.push("c", "c1");
+        assert_eq!(queue.peek(), Some(&"a1"));
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(order, ["a1", "b1", "c1", "a2", "a3"]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_scheduler_batches_fairly_and_matches_engine() {
+        let file = TinyLlama::default().write();
+        let prompts = [("alice", "hello"), ("alice", "again"), ("bob", "hi there")];
+        let expected: Vec<String> = prompts
+            .iter()
+            .map(|(_, text)| {
+                let mut engine = engine(&file);
+                engine
+                    .chat(&[Message::user(*text)], &[], 8)
+                    .unwrap()
+                    .content
+            })
+            .collect();
+
+        let metrics = Arc::new(Mutex::new(WorkerMetrics::default()));
+        let mut scheduler = Scheduler::new(engine(&file), 2, metrics.clone()).unwrap();
+        let mut receivers = Vec::new();
+        for (client, text) in prompts {
+            let (job, rx) = job(request(client, text));
+            scheduler.enqueue(job);
+            receivers.push(rx);
+        }
+        lock(&metrics).queue_depth = 3;
+
+        // Bob is admitted before Alice's second request
+        scheduler.admit();
+        assert_eq!(lock(&metrics).active, 2);
+        assert_eq!(lock(&metrics).queue_depth, 1);
+        assert_eq!(scheduler.queue.turns, ["alice"]);
+
+        while !scheduler.is_idle() {
+            scheduler.admit();
+            scheduler.step();
+        }
+        for (rx, expected) in receivers.iter_mut().zip(&expected) {
+            let (streamed, reply) = collect(rx);
+            assert_eq!(&reply.content, expected);
+            assert_eq!(streamed, reply.content);
+            assert!(reply.stats.generated_tokens > 0);
+        }
+
+        let metrics = lock(&metrics).clone();
+        assert_eq!(metrics.completed, 3);
+        assert_eq!(metrics.slots, 2);
+        assert_eq!(metrics.active, 0);
+        assert!(metrics.mean_batch() > 1.0);
+    }
+
+    #[test]
+    fn test_requests_with_other_adapter_wait_for_batch() {
+        let file = TinyLlama::default().write();
+        let vocab = crate::test_model::vocab().len();
+        let adapter = crate::test_model::write_adapter(
+            "llama",
+            0.0,
+            &[("output.weight", 1, vec![1.0; 64], vec![1.0; vocab])],
+        );
+        let base = engine(&file)
+            .chat(&[Message::user("hello")], &[], 8)
+            .unwrap()
+            .content;
+
+        let metrics = Arc::new(Mutex::new(WorkerMetrics::default()));
+        let mut scheduler = Scheduler::new(engine(&file), 2, metrics.clone()).unwrap();
+        let mut adapted = request("alice", "hello");
+        adapted.lora = Some(adapter.path.clone());
+        let (first, mut adapted_rx) = job(adapted);
+        let (second, mut base_rx) = job(request("bob", "hello"));
+        scheduler.enqueue(first);
+        scheduler.enqueue(second);
+        lock(&metrics).queue_depth = 2;
+
+        scheduler.admit();
+        assert_eq!(lock(&metrics).active, 1);
+        while !scheduler.is_idle() {
+            scheduler.admit();
+            scheduler.step();
+        }
+        collect(&mut adapted_rx);
+        // Bob ran on the base weights again, not on a stale cache
+        let (_, reply) = collect(&mut base_rx);
+        assert_eq!(reply.content, base);
+        assert_eq!(reply.stats.cached_tokens, 0);
+        assert_eq!(lock(&metrics).completed, 2);
+    }
+
+    #[test]
+    fn test_slot_reuse_for_same_conversation() {
+        let file = TinyLlama::default().write();
+        let metrics = Arc::new(Mutex::new(WorkerMetrics::default()));
+        let mut scheduler = Scheduler::new(engine(&file), 2, metrics.clone()).unwrap();
+
+        let run = |scheduler: &mut Scheduler, request: WorkerRequest| {
+            let (job, mut rx) = job(request);
+            scheduler.enqueue(job);
+            lock(&scheduler.metrics).queue_depth += 1;
+            while !scheduler.is_idle() {
+                scheduler.admit();
+                scheduler.step();
+            }
+            collect(&mut rx).1
+        };
+
+        let first = run(&mut scheduler, request("alice", "hello"));
+        assert_eq!(first.stats.cached_tokens, 0);
+        let other = run(&mut scheduler, request("bob", "something else"));
+        assert!(other.stats.cached_tokens <= 1);
+
+        // Alice's follow-up lands in her old slot and reuses the prompt
+        let mut follow_up = request("alice", "hello");
+        follow_up.messages.push(Message::assistant(&first.content));
+        follow_up.messages.push(Message::user("more"));
+        let second = run(&mut scheduler, follow_up);
+        assert!(second.stats.cached_tokens > first.stats.prompt_tokens / 2);
+        assert_eq!(lock(&metrics).slots, 2);
+    }
+
+    #[test]
+    fn test_context_shift_matches_engine() {
+        let file = TinyLlama {
+            context_length: 48,
+            ..Default::default()
+        }
+        .write();
+        let mut engine = engine(&file);
+        let mut sampler = engine.config().sampler_config();
+        sampler.banned_tokens = engine.model.as_ref().unwrap().stop_ids.clone();
+        engine.set_sampler(sampler.clone());
+        let expected = engine.chat(&[Message::user("hello")], &[], 64).unwrap();
+        assert!(expected.stats.context_shifts > 0);
+
+        let metrics = Arc::new(Mutex::new(WorkerMetrics::default()));
+        let mut scheduler = Scheduler::new(engine, 2, metrics.clone()).unwrap();
+        let mut request = request("alice", "hello");
+        request.max_tokens = 64;
+        request.sampler = sampler;
+        let (job, mut rx) = job(request);
+        scheduler.enqueue(job);
+        lock(&metrics).queue_depth = 1;
+        while !scheduler.is_idle() {
+            scheduler.admit();
+            scheduler.step();
+        }
+        let (text, reply) = collect(&mut rx);
+        assert_eq!(text, expected.content);
+        assert_eq!(reply.stats.generated_tokens, 64);
+        assert_eq!(reply.stats.context_shifts, expected.stats.context_shifts);
+    }
+
+    #[test]
+    fn test_worker_thread() {
+        let file = TinyLlama::default().write();
+        let worker = BrainWorker::spawn(
+            engine(&file),
+            WorkerConfig {
+                max_slots: 2,
+                max_queue: 4,
+            },
+        )
+        .unwrap();
+        assert!(worker.model_info().contains("layers"));
+
+        let cancelled = request("bob", "stop me");
+        cancelled.cancel.cancel();
+        let mut cancelled_rx = worker.submit(cancelled).unwrap();
+        let mut rx = worker.submit(request("alice", "hello")).unwrap();
+
+        let mut text = String::new();
+        let reply = loop {
+            match rx.blocking_recv().unwrap().unwrap() {
+                WorkerEvent::Delta(piece) => text.push_str(&piece),
+                WorkerEvent::Done(reply) => break reply,
+            }
+        };
+        assert_eq!(text, reply.content);
+        // Cancelled while queued: dropped without a reply
+        assert!(cancelled_rx.blocking_recv().is_none());
+
+        let metrics = worker.metrics();
+        assert_eq!(metrics.submitted, 2);
+        assert_eq!(metrics.completed, 1);
+        assert_eq!(metrics.cancelled, 1);
+        assert_eq!(metrics.queue_depth, 0);
+        assert_eq!(metrics.max_slots, 2);
+
+        let full = BrainWorker::spawn(
+            engine(&file),
+            WorkerConfig {
+                max_slots: 1,
+                max_queue: 0,
+            },
+        )
+        .unwrap();
+        assert!(full.submit(request("alice", "hello")).is_err());
+        assert_eq!(full.metrics().rejected, 1);
+
+        assert!(
+            BrainWorker::spawn(
+                BrainEngine::new(BrainConfig::default()),
+                WorkerConfig::default()
+            )
+            .is_err()
+        );
+    }
+}
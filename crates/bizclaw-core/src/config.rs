@@ -1,6 +1,7 @@
 //! BizClaw configuration system.
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use crate::error::Result;
@@ -35,6 +36,9 @@ pub struct BizClawConfig {
     pub identity: Identity,
     #[serde(default)]
     pub channel: ChannelConfig,
+    /// Timeouts and retries for HTTP providers.
+    #[serde(default)]
+    pub http: HttpConfig,
     /// MCP server configurations.
     #[serde(default)]
     pub mcp_servers: Vec<McpServerEntry>,
@@ -69,6 +73,7 @@ impl Default for BizClawConfig {
             secrets: SecretsConfig::default(),
             identity: Identity::default(),
             channel: ChannelConfig::default(),
+            http: HttpConfig::default(),
             mcp_servers: vec![],
         }
     }
@@ -138,18 +143,102 @@ pub struct BrainConfig {
     pub max_tokens: u32,
     #[serde(default = "default_context_length")]
     pub context_length: u32,
+    /// Prompt tokens processed per batched forward pass during prefill.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: u32,
+    /// Requests decoded together, each with its own KV cache (1 serves one
+    /// at a time and allows speculative decoding).
+    #[serde(default = "default_max_slots")]
+    pub max_slots: u32,
+    /// Requests that may wait for a free slot before new ones are refused.
+    #[serde(default = "default_max_queue")]
+    pub max_queue: u32,
+    /// Drop older tokens and keep generating when the context fills up.
+    #[serde(default = "bool_true")]
+    pub context_shift: bool,
+    /// Leading tokens a context shift always keeps (BOS is kept anyway).
+    #[serde(default)]
+    pub context_keep: u32,
+    /// RAM all loaded local models may use together (MB, 0 = no limit).
+    /// Least recently used models are unloaded to stay under it.
+    #[serde(default)]
+    pub memory_budget_mb: u64,
     #[serde(default = "default_cache_dir")]
     pub cache_dir: String,
+    /// Disk budget for persisted per-session KV caches (MB, 0 disables).
+    #[serde(default = "default_session_cache_mb")]
+    pub session_cache_mb: u64,
+    /// KV cache precision: "f32", "f16" or "q8_0".
+    #[serde(default = "default_kv_precision")]
+    pub kv_precision: String,
+    /// Draft model for speculative decoding (same vocabulary, empty disables).
+    #[serde(default)]
+    pub draft_model_path: String,
+    /// Tokens the draft model proposes per step.
+    #[serde(default = "default_draft_tokens")]
+    pub draft_tokens: u32,
+    /// GGUF embedding model (bert, nomic-bert, ...); empty embeds with the
+    /// chat model.
+    #[serde(default)]
+    pub embedding_model_path: String,
+    /// GGUF LoRA adapter applied on top of the model (empty disables).
+    #[serde(default)]
+    pub lora_path: String,
+    /// Strength of the LoRA update (1.0 = as trained).
+    #[serde(default = "default_lora_scale")]
+    pub lora_scale: f32,
     #[serde(default = "bool_true")]
     pub auto_download: bool,
     #[serde(default = "default_temperature")]
     pub temperature: f32,
     #[serde(default = "default_top_p")]
     pub top_p: f32,
+    #[serde(default = "default_top_k")]
+    pub top_k: u32,
+    /// Drop tokens less likely than `min_p` times the best one (0 disables).
     #[serde(default)]
-    pub json_mode: bool,
+    pub min_p: f32,
+    /// Locally typical sampling mass (1.0 disables).
+    #[serde(default = "default_typical_p")]
+    pub typical_p: f32,
+    #[serde(default = "default_repeat_penalty")]
+    pub repeat_penalty: f32,
+    #[serde(default)]
+    pub presence_penalty: f32,
+    #[serde(default)]
+    pub frequency_penalty: f32,
+    /// Sample with Mirostat v2 instead of top-k/typical/top-p/min-p.
+    #[serde(default)]
+    pub mirostat: bool,
+    #[serde(default = "default_mirostat_tau")]
+    pub mirostat_tau: f32,
+    #[serde(default = "default_mirostat_eta")]
+    pub mirostat_eta: f32,
+    /// Bias added to a token's logit, keyed by token id
+    /// (`[brain.logit_bias]` with `"13" = -2.0`).
     #[serde(default)]
-    pub fallback: Option<BrainFallback>,
+    pub logit_bias: HashMap<String, f32>,
+    /// Token ids that are never sampled.
+    #[serde(default)]
+    pub banned_tokens: Vec<u32>,
+    /// Seed for reproducible sampling.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub json_mode: bool,
+    /// Providers tried in order when the default one fails
+    /// (`[brain.fallback]` for one, `[[brain.fallback]]` for several).
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub fallback: Vec<BrainFallback>,
+    /// Consecutive failures after which a provider is skipped.
+    #[serde(default = "default_fallback_failures")]
+    pub fallback_failures: u32,
+    /// Seconds a skipped provider waits before one probe request.
+    #[serde(default = "default_fallback_cooldown_secs")]
+    pub fallback_cooldown_secs: u64,
+    /// Time limit per provider attempt when fallbacks are set (0 = none).
+    #[serde(default = "default_fallback_timeout_secs")]
+    pub fallback_timeout_secs: u64,
 }
 
 fn bool_true() -> bool {
@@ -167,12 +256,57 @@ fn default_max_tokens() -> u32 {
 fn default_context_length() -> u32 {
     2048
 }
+fn default_batch_size() -> u32 {
+    64
+}
+fn default_max_slots() -> u32 {
+    2
+}
+fn default_max_queue() -> u32 {
+    32
+}
 fn default_cache_dir() -> String {
     "~/.bizclaw/cache".into()
 }
+fn default_session_cache_mb() -> u64 {
+    512
+}
+fn default_kv_precision() -> String {
+    "f32".into()
+}
+fn default_draft_tokens() -> u32 {
+    4
+}
+fn default_lora_scale() -> f32 {
+    1.0
+}
 fn default_top_p() -> f32 {
     0.9
 }
+fn default_top_k() -> u32 {
+    40
+}
+fn default_typical_p() -> f32 {
+    1.0
+}
+fn default_repeat_penalty() -> f32 {
+    1.1
+}
+fn default_mirostat_tau() -> f32 {
+    5.0
+}
+fn default_mirostat_eta() -> f32 {
+    0.1
+}
+fn default_fallback_failures() -> u32 {
+    3
+}
+fn default_fallback_cooldown_secs() -> u64 {
+    30
+}
+fn default_fallback_timeout_secs() -> u64 {
+    60
+}
 
 impl Default for BrainConfig {
     fn default() -> Self {
@@ -182,20 +316,95 @@ impl Default for BrainConfig {
             threads: default_threads(),
             max_tokens: default_max_tokens(),
             context_length: default_context_length(),
+            batch_size: default_batch_size(),
+            max_slots: default_max_slots(),
+            max_queue: default_max_queue(),
+            context_shift: true,
+            context_keep: 0,
+            memory_budget_mb: 0,
             cache_dir: default_cache_dir(),
+            session_cache_mb: default_session_cache_mb(),
+            kv_precision: default_kv_precision(),
+            draft_model_path: String::new(),
+            draft_tokens: default_draft_tokens(),
+            embedding_model_path: String::new(),
+            lora_path: String::new(),
+            lora_scale: default_lora_scale(),
             auto_download: true,
             temperature: default_temperature(),
             top_p: default_top_p(),
+            top_k: default_top_k(),
+            min_p: 0.0,
+            typical_p: default_typical_p(),
+            repeat_penalty: default_repeat_penalty(),
+            presence_penalty: 0.0,
+            frequency_penalty: 0.0,
+            mirostat: false,
+            mirostat_tau: default_mirostat_tau(),
+            mirostat_eta: default_mirostat_eta(),
+            logit_bias: HashMap::new(),
+            banned_tokens: vec![],
+            seed: None,
             json_mode: false,
-            fallback: None,
+            fallback: vec![],
+            fallback_failures: default_fallback_failures(),
+            fallback_cooldown_secs: default_fallback_cooldown_secs(),
+            fallback_timeout_secs: default_fallback_timeout_secs(),
         }
     }
 }
 
+impl BrainConfig {
+    /// Cache directory with `~` expanded.
+    pub fn cache_path(&self) -> PathBuf {
+        PathBuf::from(shellexpand::tilde(&self.cache_dir).as_ref())
+    }
+
+    /// Draft model path with `~` expanded, if one is set.
+    pub fn draft_model_file(&self) -> Option<PathBuf> {
+        (!self.draft_model_path.is_empty())
+            .then(|| PathBuf::from(shellexpand::tilde(&self.draft_model_path).as_ref()))
+    }
+
+    /// Embedding model path with `~` expanded, if one is set.
+    pub fn embedding_model_file(&self) -> Option<PathBuf> {
+        (!self.embedding_model_path.is_empty())
+            .then(|| PathBuf::from(shellexpand::tilde(&self.embedding_model_path).as_ref()))
+    }
+
+    /// LoRA adapter path with `~` expanded, if one is set.
+    pub fn lora_file(&self) -> Option<PathBuf> {
+        (!self.lora_path.is_empty())
+            .then(|| PathBuf::from(shellexpand::tilde(&self.lora_path).as_ref()))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BrainFallback {
     pub provider: String,
+    /// Empty keeps the model the request asked for.
+    #[serde(default)]
     pub model: String,
+    /// Key for this provider; empty uses its environment variable.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub api_key: String,
+}
+
+/// A single fallback table or an array of them.
+fn one_or_many<'de, D>(deserializer: D) -> std::result::Result<Vec<BrainFallback>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(BrainFallback),
+        Many(Vec<BrainFallback>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(fallback) => vec![fallback],
+        OneOrMany::Many(fallbacks) => fallbacks,
+    })
 }
 
 /// Memory configuration.
@@ -327,6 +536,55 @@ impl Default for RuntimeConfig {
     }
 }
 
+/// HTTP provider configuration: timeouts and retries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpConfig {
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Until the response headers arrive, and between reads of the body
+    /// (0 = no limit)
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// Retries after a 429, 5xx or connection error
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// First backoff delay; doubled on every retry, with jitter
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+    /// Longest wait before a retry. A `Retry-After` beyond this fails
+    /// straight away with a rate-limit error.
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+fn default_request_timeout_secs() -> u64 {
+    300
+}
+fn default_max_retries() -> u32 {
+    3
+}
+fn default_backoff_ms() -> u64 {
+    500
+}
+fn default_max_backoff_secs() -> u64 {
+    30
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: default_connect_timeout_secs(),
+            request_timeout_secs: default_request_timeout_secs(),
+            max_retries: default_max_retries(),
+            backoff_ms: default_backoff_ms(),
+            max_backoff_secs: default_max_backoff_secs(),
+        }
+    }
+}
+
 /// Tunnel configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TunnelConfig {
@@ -628,6 +886,87 @@ mod tests {
         assert_eq!(config.gateway.port, 3000);
     }
 
+    #[test]
+    fn test_brain_sampler_settings() {
+        let config: BizClawConfig = toml::from_str(
+            r#"
+            [brain]
+            min_p = 0.05
+            banned_tokens = [2]
+            seed = 42
+
+            [brain.logit_bias]
+            "13" = -2.5
+            "29871" = 1.0
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.brain.logit_bias["13"], -2.5);
+        assert_eq!(config.brain.banned_tokens, [2]);
+
+        // Round-trips through TOML
+        let text = toml::to_string(&config).unwrap();
+        let again: BizClawConfig = toml::from_str(&text).unwrap();
+        assert_eq!(again.brain.logit_bias, config.brain.logit_bias);
+        assert_eq!(again.brain.seed, Some(42));
+        assert!((again.brain.min_p - 0.05).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_brain_model_paths_expand_tilde() {
+        let mut brain = BrainConfig::default();
+        assert_eq!(brain.draft_model_file(), None);
+        brain.draft_model_path = "~/models/draft.gguf".into();
+        let draft = brain.draft_model_file().unwrap();
+        assert!(!draft.starts_with("~"));
+        assert!(draft.ends_with("models/draft.gguf"));
+        brain.embedding_model_path = "~/models/embed.gguf".into();
+        let embedding = brain.embedding_model_file().unwrap();
+        assert!(!embedding.starts_with("~"));
+        assert!(embedding.ends_with("models/embed.gguf"));
+    }
+
+    #[test]
+    fn test_fallback_one_or_many() {
+        let config: BizClawConfig = toml::from_str(
+            r#"
+            [brain.fallback]
+            provider = "openai"
+            model = "gpt-4o-mini"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.brain.fallback.len(), 1);
+        assert_eq!(config.brain.fallback[0].model, "gpt-4o-mini");
+
+        let config: BizClawConfig = toml::from_str(
+            r#"
+            [[brain.fallback]]
+            provider = "anthropic"
+            model = "claude-3-5-haiku-20241022"
+            api_key = "sk-ant"
+
+            [[brain.fallback]]
+            provider = "ollama"
+        "#,
+        )
+        .unwrap();
+        let providers: Vec<&str> = config
+            .brain
+            .fallback
+            .iter()
+            .map(|f| f.provider.as_str())
+            .collect();
+        assert_eq!(providers, ["anthropic", "ollama"]);
+        assert_eq!(config.brain.fallback[0].api_key, "sk-ant");
+        assert_eq!(config.brain.fallback_failures, 3);
+
+        // Round-trips as an array of tables
+        let text = toml::to_string(&config).unwrap();
+        let again: BizClawConfig = toml::from_str(&text).unwrap();
+        assert_eq!(again.brain.fallback.len(), 2);
+    }
+
     #[test]
     fn test_home_dir() {
         let home = BizClawConfig::home_dir();
@@ -20,6 +20,12 @@ pub enum BizClawError {
     #[error("API key not configured for provider: {0}")]
     ApiKeyMissing(String),
 
+    #[error("Context too long: {0}")]
+    ContextTooLong(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
     // Channel errors
     #[error("Channel error: {0}")]
     Channel(String),
@@ -0,0 +1,15 @@
+//! Embedder trait — text to vectors for semantic search.
+
+use async_trait::async_trait;
+
+use crate::error::Result;
+
+/// Embedder trait — every embedding backend implements this.
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Embedder identifier (e.g., "brain").
+    fn name(&self) -> &str;
+
+    /// Embed each text into one vector; all vectors have the same length.
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
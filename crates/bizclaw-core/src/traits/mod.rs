@@ -3,6 +3,7 @@
 //! Swap implementations with a config change, zero code changes.
 
 pub mod channel;
+pub mod embedding;
 pub mod identity;
 pub mod memory;
 pub mod observer;
@@ -13,6 +14,7 @@ pub mod tool;
 pub mod tunnel;
 
 pub use channel::Channel;
+pub use embedding::Embedder;
 pub use memory::MemoryBackend;
 pub use provider::Provider;
 pub use security::SecurityPolicy;
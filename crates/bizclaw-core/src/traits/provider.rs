@@ -1,9 +1,15 @@
 //! LLM Provider trait — swappable AI backends.
 
 use async_trait::async_trait;
+use futures::Stream;
+use std::collections::HashMap;
+use std::pin::Pin;
 
 use crate::error::Result;
-use crate::types::{Message, ModelInfo, ProviderResponse, ToolDefinition};
+use crate::types::{Message, ModelInfo, ProviderResponse, StreamEvent, ToolDefinition};
+
+/// Events of a streamed chat completion, ending with `Finish`.
+pub type ProviderStream = Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>>;
 
 /// Configuration for generation parameters.
 #[derive(Debug, Clone)]
@@ -13,6 +19,25 @@ pub struct GenerateParams {
     pub max_tokens: u32,
     pub top_p: f32,
     pub stop: Vec<String>,
+    /// Conversation these params belong to, so local backends can keep
+    /// per-session state such as the KV cache.
+    pub session_id: Option<String>,
+    /// Sampling extensions; `None`/empty keeps the backend's own settings.
+    pub top_k: Option<u32>,
+    pub min_p: Option<f32>,
+    pub typical_p: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    /// Sample with Mirostat v2 targeting this surprise (bits per token).
+    pub mirostat_tau: Option<f32>,
+    /// Token id → bias added to its logit.
+    pub logit_bias: HashMap<u32, f32>,
+    pub banned_tokens: Vec<u32>,
+    pub seed: Option<u64>,
+    /// LoRA adapter for local backends (e.g. one per agent); `None` keeps
+    /// the backend's configured adapter.
+    pub lora_adapter: Option<String>,
+    pub lora_scale: Option<f32>,
 }
 
 impl Default for GenerateParams {
@@ -23,6 +48,18 @@ impl Default for GenerateParams {
             max_tokens: 4096,
             top_p: 0.9,
             stop: vec![],
+            session_id: None,
+            top_k: None,
+            min_p: None,
+            typical_p: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            mirostat_tau: None,
+            logit_bias: HashMap::new(),
+            banned_tokens: vec![],
+            seed: None,
+            lora_adapter: None,
+            lora_scale: None,
         }
     }
 }
@@ -41,6 +78,21 @@ pub trait Provider: Send + Sync {
         params: &GenerateParams,
     ) -> Result<ProviderResponse>;
 
+    /// Stream a chat completion as it is generated.
+    ///
+    /// The default sends the whole `chat` response at once, for backends
+    /// that cannot stream.
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderStream> {
+        let response = self.chat(messages, tools, params).await?;
+        let events = response.into_events().into_iter().map(Ok);
+        Ok(Box::pin(futures::stream::iter(events)))
+    }
+
     /// List available models for this provider.
     async fn list_models(&self) -> Result<Vec<ModelInfo>>;
 
@@ -115,6 +115,11 @@ pub struct ProviderResponse {
     pub tool_calls: Vec<super::ToolCall>,
     pub finish_reason: Option<String>,
     pub usage: Option<Usage>,
+    /// Provider and model that answered, when a fallback chain picked one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
 }
 
 impl ProviderResponse {
@@ -124,6 +129,8 @@ impl ProviderResponse {
             tool_calls: vec![],
             finish_reason: Some("stop".into()),
             usage: None,
+            provider: None,
+            model: None,
         }
     }
 
@@ -133,18 +140,153 @@ impl ProviderResponse {
             tool_calls,
             finish_reason: Some("tool_calls".into()),
             usage: None,
+            provider: None,
+            model: None,
         }
     }
+
+    /// The response as the events a streaming provider would have sent.
+    pub fn into_events(self) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        if let Some(name) = self.provider {
+            events.push(StreamEvent::Provider {
+                name,
+                model: self.model.unwrap_or_default(),
+            });
+        }
+        if let Some(content) = self.content.filter(|c| !c.is_empty()) {
+            events.push(StreamEvent::Delta(content));
+        }
+        for (index, call) in self.tool_calls.into_iter().enumerate() {
+            events.push(StreamEvent::ToolCallDelta {
+                index,
+                id: Some(call.id),
+                name: Some(call.function.name),
+                arguments: call.function.arguments,
+            });
+        }
+        if let Some(usage) = self.usage {
+            events.push(StreamEvent::Usage(usage));
+        }
+        events.push(StreamEvent::Finish(
+            self.finish_reason.unwrap_or_else(|| "stop".into()),
+        ));
+        events
+    }
 }
 
 /// Token usage statistics.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
 }
 
+/// One piece of a streamed chat completion.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    /// Next piece of generated text.
+    Delta(String),
+    /// Part of a tool call. Fragments with the same `index` belong to one
+    /// call; `id` and `name` usually come only with the first, `arguments`
+    /// is JSON text to append.
+    ToolCallDelta {
+        index: usize,
+        id: Option<String>,
+        name: Option<String>,
+        arguments: String,
+    },
+    /// Token counts, usually sent near the end.
+    Usage(Usage),
+    /// Why generation stopped ("stop", "length", "tool_calls", ...).
+    Finish(String),
+    /// Provider and model answering, sent first by a fallback chain.
+    Provider { name: String, model: String },
+}
+
+/// Builds a [`ProviderResponse`] from stream events.
+#[derive(Debug, Default)]
+pub struct StreamAccumulator {
+    content: String,
+    /// (index, id, name, arguments) in arrival order
+    tool_calls: Vec<(usize, String, String, String)>,
+    finish_reason: Option<String>,
+    usage: Option<Usage>,
+    provider: Option<(String, String)>,
+}
+
+impl StreamAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, event: StreamEvent) {
+        match event {
+            StreamEvent::Delta(text) => self.content.push_str(&text),
+            StreamEvent::ToolCallDelta {
+                index,
+                id,
+                name,
+                arguments,
+            } => {
+                let call = match self.tool_calls.iter_mut().find(|c| c.0 == index) {
+                    Some(call) => call,
+                    None => {
+                        self.tool_calls
+                            .push((index, String::new(), String::new(), String::new()));
+                        self.tool_calls.last_mut().expect("just pushed")
+                    }
+                };
+                if let Some(id) = id {
+                    call.1 = id;
+                }
+                if let Some(name) = name {
+                    call.2.push_str(&name);
+                }
+                call.3.push_str(&arguments);
+            }
+            StreamEvent::Usage(usage) => self.usage = Some(usage),
+            StreamEvent::Finish(reason) => self.finish_reason = Some(reason),
+            StreamEvent::Provider { name, model } => self.provider = Some((name, model)),
+        }
+    }
+
+    pub fn finish(self) -> ProviderResponse {
+        let tool_calls = self
+            .tool_calls
+            .into_iter()
+            .map(|(_, id, name, arguments)| super::ToolCall {
+                id: if id.is_empty() {
+                    uuid::Uuid::new_v4().to_string()
+                } else {
+                    id
+                },
+                r#type: "function".into(),
+                function: super::FunctionCall {
+                    name,
+                    arguments: if arguments.is_empty() {
+                        "{}".into()
+                    } else {
+                        arguments
+                    },
+                },
+            })
+            .collect();
+        ProviderResponse {
+            content: (!self.content.is_empty()).then_some(self.content),
+            tool_calls,
+            finish_reason: self.finish_reason,
+            usage: self.usage,
+            provider: self.provider.as_ref().map(|(name, _)| name.clone()),
+            model: self
+                .provider
+                .map(|(_, model)| model)
+                .filter(|m| !m.is_empty()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -185,4 +327,50 @@ mod tests {
         assert_eq!(resp.content, Some("hello".into()));
         assert!(resp.tool_calls.is_empty());
     }
+
+    #[test]
+    fn test_stream_accumulator() {
+        let mut acc = StreamAccumulator::new();
+        let call =
+            |index, id: Option<&str>, name: Option<&str>, args: &str| StreamEvent::ToolCallDelta {
+                index,
+                id: id.map(String::from),
+                name: name.map(String::from),
+                arguments: args.into(),
+            };
+        for event in [
+            StreamEvent::Delta("Checking ".into()),
+            StreamEvent::Delta("weather".into()),
+            call(0, Some("call_1"), Some("weather"), ""),
+            call(1, Some("call_2"), Some("time"), "{}"),
+            call(0, None, None, "{\"city\":"),
+            call(0, None, None, "\"Hanoi\"}"),
+            StreamEvent::Finish("tool_calls".into()),
+        ] {
+            acc.push(event);
+        }
+        let resp = acc.finish();
+        assert_eq!(resp.content.as_deref(), Some("Checking weather"));
+        assert_eq!(resp.tool_calls.len(), 2);
+        assert_eq!(resp.tool_calls[0].id, "call_1");
+        assert_eq!(
+            resp.tool_calls[0].function.arguments,
+            "{\"city\":\"Hanoi\"}"
+        );
+        assert_eq!(resp.tool_calls[1].function.name, "time");
+        assert_eq!(resp.finish_reason.as_deref(), Some("tool_calls"));
+
+        // A response replayed as events comes back the same
+        let mut acc = StreamAccumulator::new();
+        for event in resp.clone().into_events() {
+            acc.push(event);
+        }
+        let again = acc.finish();
+        assert_eq!(again.content, resp.content);
+        assert_eq!(
+            again.tool_calls[0].function.arguments,
+            resp.tool_calls[0].function.arguments
+        );
+        assert_eq!(again.finish_reason, resp.finish_reason);
+    }
 }
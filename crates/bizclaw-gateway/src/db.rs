@@ -219,6 +219,7 @@ impl GatewayDb {
                provider_type=?2, api_key=?3, base_url=?4, models_json=?5, updated_at=datetime('now')",
             params![name, provider_type, api_key, base_url, models_json],
         ).map_err(|e| format!("Upsert provider: {e}"))?;
+        drop(conn);
 
         self.get_provider(name)
     }
@@ -300,6 +301,7 @@ impl GatewayDb {
                role=?2, description=?3, provider=?4, model=?5, system_prompt=?6, updated_at=datetime('now')",
             params![name, role, description, provider, model, system_prompt],
         ).map_err(|e| format!("Upsert agent: {e}"))?;
+        drop(conn);
 
         self.get_agent(name)
     }
@@ -464,6 +464,13 @@ pub async fn list_providers(State(state): State<Arc<AppState>>) -> Json<serde_js
     }))
 }
 
+/// Circuit breaker state of each provider in the fallback chain.
+pub async fn provider_health() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "breakers": bizclaw_providers::fallback::breaker_status(),
+    }))
+}
+
 /// List available channels with config status.
 pub async fn list_channels(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
     let cfg = state.full_config.lock().unwrap();
@@ -595,14 +602,96 @@ pub async fn brain_scan_models(State(state): State<Arc<AppState>>) -> Json<serde
             .cmp(b["name"].as_str().unwrap_or(""))
     });
 
+    // Estimate each model's RAM from its header and flag what won't fit
+    let options = {
+        let cfg = state.full_config.lock().unwrap();
+        bizclaw_brain::inspect::InspectOptions {
+            context: 0,
+            batch_size: cfg.brain.batch_size as usize,
+            kv_precision: bizclaw_brain::kv_cache::KvPrecision::parse(&cfg.brain.kv_precision)
+                .unwrap_or_default(),
+        }
+    };
+    let host = bizclaw_brain::inspect::host_memory();
+    let pool = bizclaw_providers::brain::pool_status();
+    let found_models = match tokio::task::spawn_blocking(move || {
+        for model in &mut found_models {
+            let path = std::path::PathBuf::from(model["path"].as_str().unwrap_or_default());
+            model["loaded"] = pool
+                .models
+                .iter()
+                .any(|m| std::path::Path::new(&m.path) == path)
+                .into();
+            match bizclaw_brain::inspect::inspect(&path, &options) {
+                Ok(report) => {
+                    model["architecture"] = report.architecture.into();
+                    model["weight_type"] = report.weight_type.into();
+                    model["context_length"] = report.ram.context_length.into();
+                    model["estimated_ram_bytes"] = report.ram.total_bytes.into();
+                    model["fits"] = host.map(|h| report.ram.fits(&h)).into();
+                    model["unsupported"] = report.unsupported.into();
+                }
+                Err(e) => model["inspect_error"] = e.to_string().into(),
+            }
+        }
+        found_models
+    })
+    .await
+    {
+        Ok(models) => models,
+        Err(e) => {
+            return Json(
+                serde_json::json!({"ok": false, "error": format!("Model scan failed: {e}")}),
+            );
+        }
+    };
+
     Json(serde_json::json!({
         "ok": true,
         "models": found_models,
+        "host_memory": host,
         "models_dir": models_dir.display().to_string(),
         "scan_dirs": scan_dirs.iter().filter(|d| d.exists()).map(|d| d.display().to_string()).collect::<Vec<_>>(),
     }))
 }
 
+/// Local models loaded in the shared pool and their memory use.
+pub async fn brain_pool_status() -> Json<serde_json::Value> {
+    let status = bizclaw_providers::brain::pool_status();
+    Json(serde_json::json!({
+        "ok": true,
+        "pool": status,
+        "host_memory": bizclaw_brain::inspect::host_memory(),
+    }))
+}
+
+/// Unload a pooled model (`{"path": ..., "role": "chat" | "embedding"}`).
+pub async fn brain_pool_unload(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    let Some(path) = body["path"].as_str() else {
+        return Json(serde_json::json!({"ok": false, "error": "path is required"}));
+    };
+    let role = match body.get("role") {
+        Some(role) => match serde_json::from_value(role.clone()) {
+            Ok(role) => role,
+            Err(_) => {
+                return Json(
+                    serde_json::json!({"ok": false, "error": "role must be chat or embedding"}),
+                );
+            }
+        },
+        None => bizclaw_brain::pool::ModelRole::Chat,
+    };
+    let unloaded = bizclaw_providers::brain::unload_model(std::path::Path::new(path), role);
+    Json(serde_json::json!({
+        "ok": unloaded,
+        "message": if unloaded {
+            format!("Unloaded {path}")
+        } else {
+            format!("{path} is not loaded")
+        },
+    }))
+}
+
 /// Generate Zalo QR code for login.
 pub async fn zalo_qr_code(State(_state): State<Arc<AppState>>) -> Json<serde_json::Value> {
     use bizclaw_channels::zalo::client::auth::{ZaloAuth, ZaloCredentials};
@@ -102,6 +102,10 @@ pub fn build_router(state: AppState) -> Router {
         .route("/api/v1/config/update", post(super::routes::update_config))
         .route("/api/v1/config/full", get(super::routes::get_full_config))
         .route("/api/v1/providers", get(super::routes::list_providers))
+        .route(
+            "/api/v1/providers/health",
+            get(super::routes::provider_health),
+        )
         .route("/api/v1/channels", get(super::routes::list_channels))
         .route(
             "/api/v1/channels/update",
@@ -112,6 +116,11 @@ pub fn build_router(state: AppState) -> Router {
             "/api/v1/brain/models",
             get(super::routes::brain_scan_models),
         )
+        .route("/api/v1/brain/pool", get(super::routes::brain_pool_status))
+        .route(
+            "/api/v1/brain/pool/unload",
+            post(super::routes::brain_pool_unload),
+        )
         .route("/api/v1/zalo/qr", post(super::routes::zalo_qr_code))
         // Scheduler API
         .route(
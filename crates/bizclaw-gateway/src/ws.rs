@@ -2,8 +2,8 @@
 //!
 //! Architecture:
 //! - If Agent Engine is available → uses it for FULL processing (tools + memory + all providers)
-//! - Streaming mode → direct provider HTTP for UX, then saves to Agent memory
-//! - Fallback → raw HTTP calls to Ollama/OpenAI if Agent unavailable
+//! - Streaming mode → provider deltas are forwarded as they are generated
+//! - Fallback → the configured provider directly if Agent unavailable
 //!
 //! Protocol:
 //! → Client sends: {"type":"chat","content":"...","stream":true}
@@ -19,6 +19,9 @@ use axum::{
     },
     response::IntoResponse,
 };
+use bizclaw_core::traits::provider::GenerateParams;
+use bizclaw_core::types::{Message as ChatMessage, StreamEvent};
+use futures::StreamExt;
 use std::sync::Arc;
 
 /// WebSocket upgrade handler.
@@ -29,14 +32,6 @@ pub async fn ws_handler(
     ws.on_upgrade(move |socket| handle_socket(socket, state))
 }
 
-/// Resolve Ollama URL from config or env.
-fn ollama_url(_state: &AppState) -> String {
-    if let Ok(url) = std::env::var("OLLAMA_HOST") {
-        return url;
-    }
-    "http://localhost:11434".to_string()
-}
-
 /// Get the active model from config.
 fn active_model(state: &AppState) -> String {
     let config = state.full_config.lock().unwrap();
@@ -98,9 +93,9 @@ async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
 
     let mut request_counter: u64 = 0;
     // Fallback history for direct mode (when Agent engine is not available)
-    let mut fallback_history: Vec<serde_json::Value> = vec![
-        serde_json::json!({"role": "system", "content": "Bạn là BizClaw AI Assistant. Trả lời ngắn gọn, hữu ích bằng tiếng Việt. Nếu user nói tiếng Anh thì trả lời tiếng Anh."}),
-    ];
+    let mut fallback_history = vec![ChatMessage::system(
+        "Bạn là BizClaw AI Assistant. Trả lời ngắn gọn, hữu ích bằng tiếng Việt. Nếu user nói tiếng Anh thì trả lời tiếng Anh.",
+    )];
 
     // Message loop
     while let Some(msg) = socket.recv().await {
@@ -150,12 +145,35 @@ async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
                             )
                             .await;
 
+                            let mut chunk_idx: u64 = 0;
                             let result = {
                                 let mut agent = state.agent.lock().await;
                                 if let Some(agent) = agent.as_mut() {
                                     // Connect knowledge base for RAG
                                     agent.set_knowledge(state.knowledge.clone());
-                                    Some(agent.process(&content).await)
+                                    if stream {
+                                        // Forward provider deltas while the agent runs
+                                        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+                                        let process = agent.process_stream(&content, tx);
+                                        tokio::pin!(process);
+                                        let result = loop {
+                                            tokio::select! {
+                                                result = &mut process => break result,
+                                                Some(piece) = rx.recv() => {
+                                                    send_chunk(&mut socket, &request_id, &piece, chunk_idx).await;
+                                                    chunk_idx += 1;
+                                                }
+                                            }
+                                        };
+                                        while let Ok(piece) = rx.try_recv() {
+                                            send_chunk(&mut socket, &request_id, &piece, chunk_idx)
+                                                .await;
+                                            chunk_idx += 1;
+                                        }
+                                        Some(result)
+                                    } else {
+                                        Some(agent.process(&content).await)
+                                    }
                                 } else {
                                     None
                                 }
@@ -170,30 +188,12 @@ async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
                             match result {
                                 Some(Ok(response)) => {
                                     if stream {
-                                        // Emit as rapid chunks for streaming UX
-                                        let chunk_size = 8; // chars per chunk
-                                        let chars: Vec<char> = response.chars().collect();
-                                        let mut idx: u64 = 0;
-                                        for chunk in chars.chunks(chunk_size) {
-                                            let text: String = chunk.iter().collect();
-                                            let _ = send_json(
-                                                &mut socket,
-                                                &serde_json::json!({
-                                                    "type": "chat_chunk",
-                                                    "request_id": &request_id,
-                                                    "content": &text,
-                                                    "index": idx,
-                                                }),
-                                            )
-                                            .await;
-                                            idx += 1;
-                                        }
                                         let _ = send_json(
                                             &mut socket,
                                             &serde_json::json!({
                                                 "type": "chat_done",
                                                 "request_id": &request_id,
-                                                "total_tokens": idx,
+                                                "total_tokens": chunk_idx,
                                                 "full_content": &response,
                                                 "mode": "agent",
                                                 "context": ctx_stats,
@@ -245,8 +245,7 @@ async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
                             // STREAMING / DIRECT MODE
                             // ═══════════════════════════════════════════
                             // Add user message to fallback history
-                            fallback_history
-                                .push(serde_json::json!({"role": "user", "content": &content}));
+                            fallback_history.push(ChatMessage::user(&content));
 
                             // Keep history manageable (last 20 messages + system)
                             if fallback_history.len() > 21 {
@@ -258,61 +257,19 @@ async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
                                 fallback_history.extend(tail);
                             }
 
-                            // Route to provider
-                            let result = match provider.as_str() {
-                                "ollama" | "brain" => {
-                                    chat_ollama(
-                                        &mut socket,
-                                        &state,
-                                        &request_id,
-                                        &fallback_history,
-                                        &model,
-                                        stream,
-                                    )
-                                    .await
-                                }
-                                "openai" => {
-                                    chat_openai(
-                                        &mut socket,
-                                        &state,
-                                        &request_id,
-                                        &fallback_history,
-                                        &model,
-                                        stream,
-                                    )
-                                    .await
-                                }
-                                _ => {
-                                    // Fallback: try Ollama first, then OpenAI
-                                    let r = chat_ollama(
-                                        &mut socket,
-                                        &state,
-                                        &request_id,
-                                        &fallback_history,
-                                        &model,
-                                        stream,
-                                    )
-                                    .await;
-                                    if r.is_err() {
-                                        chat_openai(
-                                            &mut socket,
-                                            &state,
-                                            &request_id,
-                                            &fallback_history,
-                                            "gpt-4o-mini",
-                                            stream,
-                                        )
-                                        .await
-                                    } else {
-                                        r
-                                    }
-                                }
-                            };
+                            let result = chat_direct(
+                                &mut socket,
+                                &state,
+                                &request_id,
+                                &fallback_history,
+                                stream,
+                            )
+                            .await;
 
                             match result {
                                 Ok(response) => {
                                     // Add assistant response to fallback history
-                                    fallback_history.push(serde_json::json!({"role": "assistant", "content": &response}));
+                                    fallback_history.push(ChatMessage::assistant(&response));
 
                                     // Also save to Agent memory if available
                                     if has_agent {
@@ -400,273 +357,100 @@ async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
 }
 
 // ═══════════════════════════════════════════════════════════
-// OLLAMA PROVIDER
+// DIRECT PROVIDER
 // ═══════════════════════════════════════════════════════════
 
-async fn chat_ollama(
+/// Chat through the configured provider without the agent (no tools or
+/// memory). In stream mode chunks are sent as the provider produces them.
+async fn chat_direct(
     socket: &mut WebSocket,
     state: &AppState,
     request_id: &str,
-    messages: &[serde_json::Value],
-    model: &str,
+    messages: &[ChatMessage],
     stream: bool,
 ) -> Result<String, String> {
-    let url = ollama_url(state);
-    let client = reqwest::Client::new();
-
-    if stream {
-        // Streaming response
-        let _ = send_json(
-            socket,
-            &serde_json::json!({
-                "type": "chat_start",
-                "request_id": request_id,
-                "provider": "ollama",
-                "model": model,
-            }),
-        )
-        .await;
-
-        let body = serde_json::json!({
-            "model": model,
-            "messages": messages,
-            "stream": true,
-        });
-
-        let resp = client
-            .post(format!("{url}/api/chat"))
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| format!("Ollama connection failed ({}): {}", url, e))?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(format!("Ollama error {status}: {text}"));
-        }
-
-        let mut full_content = String::new();
-        let mut chunk_idx: u64 = 0;
-
-        // Read streaming NDJSON response
-        let bytes = resp.bytes().await.map_err(|e| e.to_string())?;
-        let text = String::from_utf8_lossy(&bytes);
-
-        for line in text.lines() {
-            if line.trim().is_empty() {
-                continue;
-            }
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(line)
-                && let Some(content) = json["message"]["content"].as_str()
-                && !content.is_empty()
-            {
-                full_content.push_str(content);
-                let _ = send_json(
-                    socket,
-                    &serde_json::json!({
-                        "type": "chat_chunk",
-                        "request_id": request_id,
-                        "content": content,
-                        "index": chunk_idx,
-                    }),
-                )
-                .await;
-                chunk_idx += 1;
-            }
-        }
-
-        let _ = send_json(
-            socket,
-            &serde_json::json!({
-                "type": "chat_done",
-                "request_id": request_id,
-                "total_tokens": chunk_idx,
-                "full_content": &full_content,
-            }),
-        )
-        .await;
+    let config = state.full_config.lock().unwrap().clone();
+    let provider = bizclaw_providers::create_provider(&config).map_err(|e| e.to_string())?;
+    let params = GenerateParams {
+        model: config.default_model.clone(),
+        temperature: config.default_temperature,
+        max_tokens: config.brain.max_tokens,
+        ..Default::default()
+    };
 
-        Ok(full_content)
-    } else {
-        // Non-streaming
-        let body = serde_json::json!({
-            "model": model,
-            "messages": messages,
-            "stream": false,
-        });
-
-        let resp = client
-            .post(format!("{url}/api/chat"))
-            .json(&body)
-            .send()
+    if !stream {
+        let response = provider
+            .chat(messages, &[], &params)
             .await
-            .map_err(|e| format!("Ollama connection failed: {e}"))?;
-
-        let json: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
-        let content = json["message"]["content"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
-
+            .map_err(|e| e.to_string())?;
+        let content = response.content.unwrap_or_default();
         let _ = send_json(
             socket,
             &serde_json::json!({
                 "type": "chat_response",
                 "request_id": request_id,
                 "content": &content,
-                "provider": "ollama",
-                "model": model,
+                "provider": response.provider.as_deref().unwrap_or(provider.name()),
+                "model": response.model.as_deref().unwrap_or(&params.model),
             }),
         )
         .await;
-
-        Ok(content)
+        return Ok(content);
     }
-}
-
-// ═══════════════════════════════════════════════════════════
-// OPENAI PROVIDER
-// ═══════════════════════════════════════════════════════════
-
-async fn chat_openai(
-    socket: &mut WebSocket,
-    state: &AppState,
-    request_id: &str,
-    messages: &[serde_json::Value],
-    model: &str,
-    stream: bool,
-) -> Result<String, String> {
-    let api_key = {
-        let config = state.full_config.lock().unwrap();
-        config.api_key.clone()
-    };
-    let api_key = if api_key.is_empty() {
-        std::env::var("OPENAI_API_KEY").map_err(|_| {
-            "OpenAI API key not configured. Set in Settings → API Key or OPENAI_API_KEY env var"
-                .to_string()
-        })?
-    } else {
-        api_key
-    };
-
-    let client = reqwest::Client::new();
-
-    if stream {
-        // Streaming SSE mode
-        let _ = send_json(
-            socket,
-            &serde_json::json!({
-                "type": "chat_start",
-                "request_id": request_id,
-                "provider": "openai",
-                "model": model,
-            }),
-        )
-        .await;
 
-        let body = serde_json::json!({
-            "model": model,
-            "messages": messages,
-            "stream": true,
-        });
-
-        let resp = client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {api_key}"))
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| format!("OpenAI request failed: {e}"))?;
-
-        if !resp.status().is_success() {
-            let text = resp.text().await.unwrap_or_default();
-            return Err(format!("OpenAI error: {text}"));
-        }
-
-        // Read SSE stream
-        let bytes = resp.bytes().await.map_err(|e| e.to_string())?;
-        let text = String::from_utf8_lossy(&bytes);
-        let mut full_content = String::new();
-        let mut chunk_idx: u64 = 0;
-
-        for line in text.lines() {
-            let line = line.trim();
-            if line.is_empty() || line == "data: [DONE]" {
-                continue;
-            }
-            if let Some(data) = line.strip_prefix("data: ")
-                && let Ok(json) = serde_json::from_str::<serde_json::Value>(data)
-                && let Some(content) = json["choices"][0]["delta"]["content"].as_str()
-                && !content.is_empty()
-            {
-                full_content.push_str(content);
-                let _ = send_json(
-                    socket,
-                    &serde_json::json!({
-                        "type": "chat_chunk",
-                        "request_id": request_id,
-                        "content": content,
-                        "index": chunk_idx,
-                    }),
-                )
-                .await;
+    let _ = send_json(
+        socket,
+        &serde_json::json!({
+            "type": "chat_start",
+            "request_id": request_id,
+            "provider": provider.name(),
+            "model": &params.model,
+        }),
+    )
+    .await;
+
+    let mut events = provider
+        .chat_stream(messages, &[], &params)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut full_content = String::new();
+    let mut chunk_idx: u64 = 0;
+    let mut usage = None;
+    let mut answered_by = provider.name().to_string();
+    let mut answered_model = params.model.clone();
+    while let Some(event) = events.next().await {
+        match event.map_err(|e| e.to_string())? {
+            StreamEvent::Delta(text) => {
+                full_content.push_str(&text);
+                send_chunk(socket, request_id, &text, chunk_idx).await;
                 chunk_idx += 1;
             }
+            StreamEvent::Usage(u) => usage = Some(u),
+            StreamEvent::Provider { name, model } => {
+                answered_by = name;
+                if !model.is_empty() {
+                    answered_model = model;
+                }
+            }
+            StreamEvent::ToolCallDelta { .. } | StreamEvent::Finish(_) => {}
         }
-
-        let _ = send_json(
-            socket,
-            &serde_json::json!({
-                "type": "chat_done",
-                "request_id": request_id,
-                "total_tokens": chunk_idx,
-                "full_content": &full_content,
-            }),
-        )
-        .await;
-
-        Ok(full_content)
-    } else {
-        // Non-streaming mode
-        let body = serde_json::json!({
-            "model": model,
-            "messages": messages,
-        });
-
-        let resp = client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {api_key}"))
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| format!("OpenAI request failed: {e}"))?;
-
-        if !resp.status().is_success() {
-            let text = resp.text().await.unwrap_or_default();
-            return Err(format!("OpenAI error: {text}"));
-        }
-
-        let json: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
-        let content = json["choices"][0]["message"]["content"]
-            .as_str()
-            .unwrap_or("")
-            .to_string();
-
-        let _ = send_json(
-            socket,
-            &serde_json::json!({
-                "type": "chat_response",
-                "request_id": request_id,
-                "content": &content,
-                "provider": "openai",
-                "model": model,
-            }),
-        )
-        .await;
-
-        Ok(content)
     }
+
+    let _ = send_json(
+        socket,
+        &serde_json::json!({
+            "type": "chat_done",
+            "request_id": request_id,
+            "total_tokens": chunk_idx,
+            "full_content": &full_content,
+            "usage": usage,
+            "provider": answered_by,
+            "model": answered_model,
+        }),
+    )
+    .await;
+
+    Ok(full_content)
 }
 
 // ═══════════════════════════════════════════════════════════
@@ -682,6 +466,19 @@ async fn send_json(socket: &mut WebSocket, value: &serde_json::Value) -> Result<
         })
 }
 
+async fn send_chunk(socket: &mut WebSocket, request_id: &str, content: &str, index: u64) {
+    let _ = send_json(
+        socket,
+        &serde_json::json!({
+            "type": "chat_chunk",
+            "request_id": request_id,
+            "content": content,
+            "index": index,
+        }),
+    )
+    .await;
+}
+
 async fn send_error(socket: &mut WebSocket, message: &str) {
     let error = serde_json::json!({
         "type": "error",
@@ -1,13 +1,15 @@
 //! # BizClaw Knowledge Base
 //!
 //! Ultra-lightweight personal RAG (Retrieval-Augmented Generation).
-//! Designed for 512MB RAM devices — no vector DB, no embeddings.
+//! Designed for 512MB RAM devices — no vector DB; embeddings are optional.
 //!
 //! ## Design (PicoClaw/ZeroClaw-inspired)
 //! - **SQLite FTS5** for full-text search (built-in, zero setup)
 //! - **BM25 scoring** — relevance ranking without embeddings
 //! - **Chunking** — split documents into ~500 char chunks
 //! - **File-based** — documents stored as-is, index in SQLite
+//! - **Optional embeddings** — chunk vectors in a side table, blended with
+//!   BM25 by `search_hybrid` when an embedder is configured
 //! - RAM: ~2MB for 1000 document chunks
 //!
 //! ## How it works
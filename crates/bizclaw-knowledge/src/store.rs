@@ -1,5 +1,6 @@
 //! Knowledge store — SQLite FTS5 for fast full-text search.
-//! No vector DB, no embeddings — just BM25 relevance scoring.
+//! BM25 relevance scoring by default; chunk embeddings are optional and
+//! stored next to the index for hybrid search, still without a vector DB.
 //! This is intentionally lightweight for 512MB RAM devices.
 
 use rusqlite::{Connection, params};
@@ -38,6 +39,12 @@ impl KnowledgeStore {
                 tokenize='unicode61'
             );
 
+            -- Optional chunk embeddings (little-endian f32), keyed by chunk rowid
+            CREATE TABLE IF NOT EXISTS chunk_vectors (
+                chunk_rowid INTEGER PRIMARY KEY,
+                embedding BLOB NOT NULL
+            );
+
             -- Metadata for quick stats
             CREATE TABLE IF NOT EXISTS kb_meta (
                 key TEXT PRIMARY KEY,
@@ -139,6 +146,88 @@ impl KnowledgeStore {
         }
     }
 
+    /// Chunks without an embedding yet, as `(rowid, content)`.
+    pub fn pending_embeddings(&self, limit: usize) -> Vec<(i64, String)> {
+        let mut stmt = match self.conn.prepare(
+            "SELECT rowid, content FROM chunks
+             WHERE rowid NOT IN (SELECT chunk_rowid FROM chunk_vectors)
+             LIMIT ?1",
+        ) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!("⚠️ Pending embeddings query error: {e}");
+                return Vec::new();
+            }
+        };
+        stmt.query_map(params![limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map(|rows| rows.filter_map(|r| r.ok()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Store the embedding of chunk `rowid`.
+    pub fn set_embedding(&self, rowid: i64, embedding: &[f32]) -> Result<(), String> {
+        let blob: Vec<u8> = embedding.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO chunk_vectors (chunk_rowid, embedding) VALUES (?1, ?2)",
+                params![rowid, blob],
+            )
+            .map_err(|e| format!("Insert embedding error: {e}"))?;
+        Ok(())
+    }
+
+    /// Hybrid search: cosine similarity to `query_vec` over embedded chunks,
+    /// blended 70/30 with the BM25 rank. `score` is the blended similarity
+    /// negated, so lower still means more relevant as with [`search`](Self::search).
+    pub fn search_hybrid(&self, query: &str, query_vec: &[f32], limit: usize) -> Vec<SearchResult> {
+        let limit = limit.min(10);
+        let mut stmt = match self.conn.prepare(
+            "SELECT c.rowid, c.chunk_idx, c.content, d.name, v.embedding
+             FROM chunk_vectors v
+             JOIN chunks c ON c.rowid = v.chunk_rowid
+             JOIN documents d ON d.id = CAST(c.doc_id AS INTEGER)",
+        ) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!("⚠️ Hybrid search query error: {e}");
+                return self.search(query, limit);
+            }
+        };
+        let rows = stmt.query_map([], |row| {
+            let blob: Vec<u8> = row.get(4)?;
+            let embedding: Vec<f32> = blob
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            Ok(SearchResult {
+                doc_name: row.get(3)?,
+                chunk_idx: row.get::<_, String>(1)?.parse().unwrap_or(0),
+                content: row.get(2)?,
+                score: cosine_similarity(query_vec, &embedding) as f64,
+            })
+        });
+        let mut results: Vec<SearchResult> = match rows {
+            Ok(rows) => rows.filter_map(|r| r.ok()).collect(),
+            Err(e) => {
+                tracing::warn!("⚠️ Hybrid search error: {e}");
+                return self.search(query, limit);
+            }
+        };
+
+        // BM25 hits add a rank-based bonus: 1.0 for the best, falling off
+        let keyword = self.search(query, 10);
+        for r in &mut results {
+            let rank = keyword
+                .iter()
+                .position(|k| k.doc_name == r.doc_name && k.chunk_idx == r.chunk_idx);
+            let bonus = rank.map_or(0.0, |i| 1.0 / (i + 1) as f64);
+            r.score = -(0.7 * r.score + 0.3 * bonus);
+        }
+        results.sort_by(|a, b| a.score.total_cmp(&b.score));
+        results.truncate(limit);
+        results
+    }
+
     /// List all documents.
     pub fn list_documents(&self) -> Vec<(i64, String, String, i64)> {
         let mut stmt = self
@@ -155,6 +244,14 @@ impl KnowledgeStore {
 
     /// Remove a document and its chunks.
     pub fn remove_document(&self, doc_id: i64) -> Result<(), String> {
+        self.conn
+            .execute(
+                "DELETE FROM chunk_vectors WHERE chunk_rowid IN
+                 (SELECT rowid FROM chunks WHERE CAST(doc_id AS INTEGER) = ?1)",
+                params![doc_id],
+            )
+            .map_err(|e| format!("Delete embeddings error: {e}"))?;
+
         self.conn
             .execute(
                 "DELETE FROM chunks WHERE CAST(doc_id AS INTEGER) = ?1",
@@ -182,3 +279,53 @@ impl KnowledgeStore {
         (doc_count as usize, chunk_count as usize)
     }
 }
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 { 0.0 } else { dot / denom }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hybrid_search() {
+        let path = std::env::temp_dir().join(format!("bizclaw-kb-{}.db", std::process::id()));
+        let store = KnowledgeStore::open(&path).unwrap();
+        store
+            .add_document("pets.txt", "Kittens need vaccinations at eight weeks.", "")
+            .unwrap();
+        store
+            .add_document("billing.txt", "Invoices are due within thirty days.", "")
+            .unwrap();
+
+        let pending = store.pending_embeddings(10);
+        assert_eq!(pending.len(), 2);
+        for (rowid, content) in &pending {
+            let v = if content.contains("Kittens") {
+                [1.0, 0.0]
+            } else {
+                [0.0, 1.0]
+            };
+            store.set_embedding(*rowid, &v).unwrap();
+        }
+        assert!(store.pending_embeddings(10).is_empty());
+
+        // "cat" shares no keyword with either chunk; the vector finds it
+        assert!(store.search("cat", 3).is_empty());
+        let results = store.search_hybrid("cat", &[0.9, 0.1], 3);
+        assert_eq!(results[0].doc_name, "pets.txt");
+        assert!(results[0].score < results[1].score);
+
+        store.remove_document(1).unwrap();
+        assert_eq!(store.search_hybrid("cat", &[0.9, 0.1], 3).len(), 1);
+        drop(store);
+        let _ = std::fs::remove_file(&path);
+    }
+}
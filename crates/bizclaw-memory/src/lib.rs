@@ -8,12 +8,28 @@ pub mod vector;
 
 use bizclaw_core::config::MemoryConfig;
 use bizclaw_core::error::Result;
-use bizclaw_core::traits::MemoryBackend;
+use bizclaw_core::traits::{Embedder, MemoryBackend};
+use std::sync::Arc;
 
 /// Create a memory backend from configuration.
 pub fn create_memory(config: &MemoryConfig) -> Result<Box<dyn MemoryBackend>> {
+    create_memory_with_embedder(config, None)
+}
+
+/// Create a memory backend that uses `embedder` (if any) for hybrid search,
+/// weighted by `vector_weight` / `keyword_weight`.
+pub fn create_memory_with_embedder(
+    config: &MemoryConfig,
+    embedder: Option<Arc<dyn Embedder>>,
+) -> Result<Box<dyn MemoryBackend>> {
     match config.backend.as_str() {
-        "sqlite" => Ok(Box::new(sqlite::SqliteMemory::new()?)),
+        "sqlite" => {
+            let memory = sqlite::SqliteMemory::new()?;
+            Ok(Box::new(match embedder {
+                Some(e) => memory.with_embedder(e, config.vector_weight, config.keyword_weight),
+                None => memory,
+            }))
+        }
         "none" => Ok(Box::new(noop::NoopMemory)),
         other => Err(bizclaw_core::error::BizClawError::Memory(format!(
             "Unknown memory backend: {other}"
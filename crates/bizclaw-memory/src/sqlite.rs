@@ -1,22 +1,39 @@
 //! SQLite memory backend with FTS5 full-text search and session support.
+//!
+//! With an [`Embedder`] attached, entries are stored with their embedding
+//! and search blends cosine similarity with the keyword score.
 
 use async_trait::async_trait;
 use bizclaw_core::error::Result;
+use bizclaw_core::traits::Embedder;
 use bizclaw_core::traits::memory::{MemoryBackend, MemoryEntry, MemorySearchResult};
 use rusqlite::Connection;
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::vector;
 
 pub struct SqliteMemory {
     conn: Mutex<Connection>,
+    embedder: Option<Arc<dyn Embedder>>,
+    vector_weight: f32,
+    keyword_weight: f32,
 }
 
 impl SqliteMemory {
+    /// Open `~/.bizclaw/memory.db`.
     pub fn new() -> Result<Self> {
         let db_path = bizclaw_core::config::BizClawConfig::home_dir().join("memory.db");
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent)?;
         }
-        let conn = Connection::open(&db_path)
+        Self::open(&db_path)
+    }
+
+    /// Open or create a memory database at `db_path`.
+    pub fn open(db_path: &Path) -> Result<Self> {
+        let conn = Connection::open(db_path)
             .map_err(|e| bizclaw_core::error::BizClawError::Memory(e.to_string()))?;
 
         // Main table with session support
@@ -69,9 +86,39 @@ impl SqliteMemory {
 
         Ok(Self {
             conn: Mutex::new(conn),
+            embedder: None,
+            vector_weight: 0.0,
+            keyword_weight: 1.0,
         })
     }
 
+    /// Embed saved entries and rank searches by
+    /// `vector_weight * cosine + keyword_weight * keyword score`.
+    pub fn with_embedder(
+        mut self,
+        embedder: Arc<dyn Embedder>,
+        vector_weight: f32,
+        keyword_weight: f32,
+    ) -> Self {
+        self.embedder = Some(embedder);
+        self.vector_weight = vector_weight;
+        self.keyword_weight = keyword_weight;
+        self
+    }
+
+    /// Embed `text` with the attached embedder. Failures are logged and
+    /// treated as "no vector" so memory keeps working keyword-only.
+    async fn embed_text(&self, text: &str) -> Option<Vec<f32>> {
+        let embedder = self.embedder.as_ref()?;
+        match embedder.embed(&[text.to_string()]).await {
+            Ok(mut vectors) => vectors.pop(),
+            Err(e) => {
+                tracing::warn!("Memory embedding failed ({}): {e}", embedder.name());
+                None
+            }
+        }
+    }
+
     /// Get conversation count across all sessions.
     pub fn conversation_count(&self) -> usize {
         let conn = self.conn.lock().unwrap();
@@ -103,57 +150,9 @@ impl SqliteMemory {
         .map_err(|e| bizclaw_core::error::BizClawError::Memory(e.to_string()))?;
         Ok(())
     }
-}
-
-#[async_trait]
-impl MemoryBackend for SqliteMemory {
-    fn name(&self) -> &str {
-        "sqlite"
-    }
 
-    async fn save(&self, entry: MemoryEntry) -> Result<()> {
-        let conn = self
-            .conn
-            .lock()
-            .map_err(|e| bizclaw_core::error::BizClawError::Memory(e.to_string()))?;
-
-        // Extract session_id from metadata or use default
-        let session_id = entry
-            .metadata
-            .get("session_id")
-            .and_then(|v| v.as_str())
-            .unwrap_or("default")
-            .to_string();
-
-        conn.execute(
-            "INSERT OR REPLACE INTO memories (id, session_id, content, metadata, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            rusqlite::params![
-                entry.id,
-                session_id,
-                entry.content,
-                entry.metadata.to_string(),
-                entry.created_at.to_rfc3339(),
-                entry.updated_at.to_rfc3339(),
-            ],
-        ).map_err(|e| bizclaw_core::error::BizClawError::Memory(e.to_string()))?;
-
-        // Index in FTS5 for fast search
-        conn.execute(
-            "INSERT OR REPLACE INTO memories_fts (id, content) VALUES (?1, ?2)",
-            rusqlite::params![entry.id, entry.content],
-        )
-        .ok(); // Don't fail on FTS insert error
-
-        // Update session message count
-        conn.execute(
-            "UPDATE sessions SET message_count = message_count + 1, updated_at = datetime('now') WHERE id = ?1",
-            rusqlite::params![session_id],
-        ).ok();
-
-        Ok(())
-    }
-
-    async fn search(&self, query: &str, limit: usize) -> Result<Vec<MemorySearchResult>> {
+    /// FTS5/BM25 search, falling back to a LIKE scan.
+    fn keyword_search(&self, query: &str, limit: usize) -> Result<Vec<MemorySearchResult>> {
         let conn = self
             .conn
             .lock()
@@ -277,6 +276,151 @@ impl MemoryBackend for SqliteMemory {
         Ok(results)
     }
 
+    /// Entries ranked by cosine similarity to `query_vec`.
+    fn vector_search(&self, query_vec: &[f32], limit: usize) -> Result<Vec<MemorySearchResult>> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| bizclaw_core::error::BizClawError::Memory(e.to_string()))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, content, metadata, created_at, updated_at, embedding
+                 FROM memories WHERE embedding IS NOT NULL",
+            )
+            .map_err(|e| bizclaw_core::error::BizClawError::Memory(e.to_string()))?;
+        let rows = stmt
+            .query_map([], |row| {
+                let parse_time = |s: String| {
+                    chrono::DateTime::parse_from_rfc3339(&s)
+                        .map(|d| d.with_timezone(&chrono::Utc))
+                        .unwrap_or_default()
+                };
+                let embedding = vector::from_blob(&row.get::<_, Vec<u8>>(5)?);
+                Ok(MemorySearchResult {
+                    score: vector::cosine_similarity(query_vec, &embedding),
+                    entry: MemoryEntry {
+                        id: row.get(0)?,
+                        content: row.get(1)?,
+                        metadata: row
+                            .get::<_, String>(2)
+                            .map(|s| serde_json::from_str(&s).unwrap_or_default())
+                            .unwrap_or_default(),
+                        embedding: Some(embedding),
+                        created_at: row.get::<_, String>(3).map(parse_time).unwrap_or_default(),
+                        updated_at: row.get::<_, String>(4).map(parse_time).unwrap_or_default(),
+                    },
+                })
+            })
+            .map_err(|e| bizclaw_core::error::BizClawError::Memory(e.to_string()))?;
+
+        let mut results: Vec<MemorySearchResult> = rows.filter_map(|r| r.ok()).collect();
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        results.truncate(limit);
+        Ok(results)
+    }
+}
+
+/// Blend keyword and vector results. Keyword scores are scaled to 0..1 by
+/// the best one, so both halves of the weighted sum are comparable.
+fn merge_hybrid(
+    keyword: Vec<MemorySearchResult>,
+    semantic: Vec<MemorySearchResult>,
+    vector_weight: f32,
+    keyword_weight: f32,
+    limit: usize,
+) -> Vec<MemorySearchResult> {
+    let max_keyword = keyword.iter().map(|r| r.score).fold(0.0f32, f32::max);
+    let mut merged: HashMap<String, MemorySearchResult> = HashMap::new();
+    for mut r in semantic {
+        r.score *= vector_weight;
+        merged.insert(r.entry.id.clone(), r);
+    }
+    for r in keyword {
+        let score = if max_keyword > 0.0 {
+            keyword_weight * r.score / max_keyword
+        } else {
+            0.0
+        };
+        merged
+            .entry(r.entry.id.clone())
+            .and_modify(|m| m.score += score)
+            .or_insert(MemorySearchResult { score, ..r });
+    }
+    let mut results: Vec<MemorySearchResult> = merged.into_values().collect();
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    results.truncate(limit);
+    results
+}
+
+#[async_trait]
+impl MemoryBackend for SqliteMemory {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    async fn save(&self, entry: MemoryEntry) -> Result<()> {
+        let embedding = match &entry.embedding {
+            Some(v) => Some(v.clone()),
+            None => self.embed_text(&entry.content).await,
+        };
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| bizclaw_core::error::BizClawError::Memory(e.to_string()))?;
+
+        // Extract session_id from metadata or use default
+        let session_id = entry
+            .metadata
+            .get("session_id")
+            .and_then(|v| v.as_str())
+            .unwrap_or("default")
+            .to_string();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO memories (id, session_id, content, metadata, embedding, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                entry.id,
+                session_id,
+                entry.content,
+                entry.metadata.to_string(),
+                embedding.as_deref().map(vector::to_blob),
+                entry.created_at.to_rfc3339(),
+                entry.updated_at.to_rfc3339(),
+            ],
+        ).map_err(|e| bizclaw_core::error::BizClawError::Memory(e.to_string()))?;
+
+        // Index in FTS5 for fast search
+        conn.execute(
+            "INSERT OR REPLACE INTO memories_fts (id, content) VALUES (?1, ?2)",
+            rusqlite::params![entry.id, entry.content],
+        )
+        .ok(); // Don't fail on FTS insert error
+
+        // Update session message count
+        conn.execute(
+            "UPDATE sessions SET message_count = message_count + 1, updated_at = datetime('now') WHERE id = ?1",
+            rusqlite::params![session_id],
+        ).ok();
+
+        Ok(())
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<MemorySearchResult>> {
+        let Some(query_vec) = self.embed_text(query).await else {
+            return self.keyword_search(query, limit);
+        };
+        // Over-fetch both lists so entries strong in only one still compete
+        let keyword = self.keyword_search(query, limit * 2)?;
+        let semantic = self.vector_search(&query_vec, limit * 2)?;
+        Ok(merge_hybrid(
+            keyword,
+            semantic,
+            self.vector_weight,
+            self.keyword_weight,
+            limit,
+        ))
+    }
+
     async fn get(&self, id: &str) -> Result<Option<MemoryEntry>> {
         let conn = self
             .conn
@@ -375,3 +519,78 @@ impl MemoryBackend for SqliteMemory {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Maps texts onto fixed topic axes: "cat"/"kitten" share one.
+    struct TopicEmbedder;
+
+    #[async_trait]
+    impl Embedder for TopicEmbedder {
+        fn name(&self) -> &str {
+            "topic"
+        }
+
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|t| {
+                    let t = t.to_lowercase();
+                    let pets = (t.contains("cat") || t.contains("kitten")) as u8 as f32;
+                    let money = (t.contains("invoice") || t.contains("price")) as u8 as f32;
+                    vec![pets, money, 0.1]
+                })
+                .collect())
+        }
+    }
+
+    fn entry(id: &str, content: &str) -> MemoryEntry {
+        MemoryEntry {
+            id: id.into(),
+            content: content.into(),
+            metadata: serde_json::json!({}),
+            embedding: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search() {
+        let dir = TempDir::new().unwrap();
+        let memory = SqliteMemory::open(&dir.path().join("memory.db"))
+            .unwrap()
+            .with_embedder(Arc::new(TopicEmbedder), 0.7, 0.3);
+        memory
+            .save(entry("1", "My cat sleeps all day"))
+            .await
+            .unwrap();
+        memory
+            .save(entry("2", "Send the invoice on Monday"))
+            .await
+            .unwrap();
+
+        // No keyword overlap: only the vector half can find it
+        let results = memory.search("kitten", 5).await.unwrap();
+        assert_eq!(results[0].entry.id, "1");
+        assert!(results[0].score > results.last().unwrap().score);
+
+        let results = memory.search("invoice", 5).await.unwrap();
+        assert_eq!(results[0].entry.id, "2");
+    }
+
+    #[tokio::test]
+    async fn test_keyword_only_without_embedder() {
+        let dir = TempDir::new().unwrap();
+        let memory = SqliteMemory::open(&dir.path().join("memory.db")).unwrap();
+        memory
+            .save(entry("1", "My cat sleeps all day"))
+            .await
+            .unwrap();
+        assert!(memory.search("kitten", 5).await.unwrap().is_empty());
+        assert_eq!(memory.search("cat", 5).await.unwrap()[0].entry.id, "1");
+    }
+}
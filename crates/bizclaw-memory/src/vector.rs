@@ -1,7 +1,6 @@
 //! In-memory vector search engine for semantic memory.
 //!
 //! Uses cosine similarity for nearest-neighbor search.
-//! Phase 3: Will integrate with bizclaw-brain for embeddings.
 
 use bizclaw_core::traits::memory::{MemoryEntry, MemorySearchResult};
 
@@ -65,8 +64,20 @@ impl Default for VectorStore {
     }
 }
 
+/// Serialize an embedding for a SQLite BLOB (little-endian f32).
+pub fn to_blob(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Inverse of [`to_blob`].
+pub fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
 /// Compute cosine similarity between two vectors.
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() || a.is_empty() {
         return 0.0;
     }
@@ -112,4 +123,11 @@ mod tests {
         let sim = cosine_similarity(&a, &b);
         assert!((sim + 1.0).abs() < 1e-6);
     }
+
+    #[test]
+    fn test_blob_roundtrip() {
+        let v = vec![0.5, -1.25, 3.0];
+        assert_eq!(to_blob(&v).len(), 12);
+        assert_eq!(from_blob(&to_blob(&v)), v);
+    }
 }
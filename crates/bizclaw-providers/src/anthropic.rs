@@ -3,12 +3,15 @@
 use async_trait::async_trait;
 use bizclaw_core::config::BizClawConfig;
 use bizclaw_core::error::{BizClawError, Result};
-use bizclaw_core::traits::provider::{GenerateParams, Provider};
+use bizclaw_core::traits::provider::{GenerateParams, Provider, ProviderStream};
 use bizclaw_core::types::{Message, ModelInfo, ProviderResponse, Role, ToolDefinition};
 
+use crate::http::HttpClient;
+use crate::stream::{self, AnthropicDecoder};
+
 pub struct AnthropicProvider {
     api_key: String,
-    client: reqwest::Client,
+    http: HttpClient,
 }
 
 impl AnthropicProvider {
@@ -21,7 +24,7 @@ impl AnthropicProvider {
 
         Ok(Self {
             api_key,
-            client: reqwest::Client::new(),
+            http: HttpClient::new("Anthropic", &config.http),
         })
     }
 
@@ -63,24 +66,12 @@ impl AnthropicProvider {
 
         (system_prompt, formatted)
     }
-}
 
-#[async_trait]
-impl Provider for AnthropicProvider {
-    fn name(&self) -> &str {
-        "anthropic"
-    }
-
-    async fn chat(
-        &self,
+    fn request_body(
         messages: &[Message],
         tools: &[ToolDefinition],
         params: &GenerateParams,
-    ) -> Result<ProviderResponse> {
-        if self.api_key.is_empty() {
-            return Err(BizClawError::ApiKeyMissing("anthropic".into()));
-        }
-
+    ) -> serde_json::Value {
         let (system_prompt, formatted_messages) = Self::format_messages(messages);
 
         let model = if params.model.is_empty() {
@@ -113,30 +104,38 @@ impl Provider for AnthropicProvider {
                 .collect();
             body["tools"] = serde_json::Value::Array(tool_defs);
         }
+        body
+    }
 
-        let resp = self
-            .client
+    fn request(&self, body: &serde_json::Value) -> reqwest::RequestBuilder {
+        self.http
+            .client()
             .post("https://api.anthropic.com/v1/messages")
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| BizClawError::Http(e.to_string()))?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(BizClawError::Provider(format!(
-                "Anthropic API error {status}: {text}"
-            )));
+            .json(body)
+    }
+}
+
+#[async_trait]
+impl Provider for AnthropicProvider {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    async fn chat(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderResponse> {
+        if self.api_key.is_empty() {
+            return Err(BizClawError::ApiKeyMissing("anthropic".into()));
         }
 
-        let json: serde_json::Value = resp
-            .json()
-            .await
-            .map_err(|e| BizClawError::Http(e.to_string()))?;
+        let body = Self::request_body(messages, tools, params);
+        let json = self.http.json(self.request(&body)).await?;
 
         // Parse Anthropic response format
         let mut content_text = String::new();
@@ -189,9 +188,26 @@ impl Provider for AnthropicProvider {
             tool_calls,
             finish_reason: json["stop_reason"].as_str().map(String::from),
             usage,
+            provider: None,
+            model: None,
         })
     }
 
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderStream> {
+        if self.api_key.is_empty() {
+            return Err(BizClawError::ApiKeyMissing("anthropic".into()));
+        }
+
+        let mut body = Self::request_body(messages, tools, params);
+        body["stream"] = true.into();
+        stream::send(&self.http, self.request(&body), AnthropicDecoder::default()).await
+    }
+
     async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         Ok(vec![
             ModelInfo {
@@ -1,53 +1,574 @@
 use async_trait::async_trait;
+use bizclaw_brain::inspect::{self, InspectOptions};
+use bizclaw_brain::kv_cache::KvPrecision;
+use bizclaw_brain::pool::{ModelPool, ModelRole, PoolStatus};
+use bizclaw_brain::sampler::SamplerConfig;
+use bizclaw_brain::worker::{
+    BrainWorker, WorkerConfig, WorkerEvent, WorkerMetrics, WorkerReceiver, WorkerRequest,
+};
+use bizclaw_brain::{BrainEngine, CancelToken, ChatReply, GenerationStats, StopReason};
 use bizclaw_core::config::BizClawConfig;
-use bizclaw_core::error::Result;
-use bizclaw_core::traits::provider::{GenerateParams, Provider};
-use bizclaw_core::types::{Message, ModelInfo, ProviderResponse, ToolDefinition};
-use tokio::sync::Mutex;
+use bizclaw_core::error::{BizClawError, Result};
+use bizclaw_core::traits::Embedder;
+use bizclaw_core::traits::provider::{GenerateParams, Provider, ProviderStream};
+use bizclaw_core::types::{
+    Message, ModelInfo, ProviderResponse, StreamEvent, ToolCall, ToolDefinition, Usage,
+};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use tokio::sync::{Mutex, mpsc};
 
+/// Local models shared by every brain provider and embedder in the process.
+static POOL: OnceLock<ModelPool<Backend>> = OnceLock::new();
+
+fn pool() -> &'static ModelPool<Backend> {
+    POOL.get_or_init(|| ModelPool::new(0))
+}
+
+/// Loaded local models and their memory use.
+pub fn pool_status() -> PoolStatus {
+    pool().status()
+}
+
+/// Unload a pooled model; it is freed once its running requests finish.
+pub fn unload_model(path: &Path, role: ModelRole) -> bool {
+    pool().unload(path, role)
+}
+
+/// Chat with local GGUF models. Models come from the shared pool and load
+/// on first use, so agents naming the same file share one copy.
 pub struct BrainProvider {
-    engine: Mutex<bizclaw_brain::BrainEngine>,
+    /// Model for requests that do not name one
+    model_path: PathBuf,
+    /// Engine settings, for per-request sampler overrides
+    config: bizclaw_brain::BrainConfig,
+    worker: WorkerConfig,
+}
+
+enum Backend {
+    /// One request at a time (the only mode with speculative decoding)
+    Engine(Arc<Mutex<BrainEngine>>),
+    /// Concurrent requests batched over `brain.max_slots` KV slots
+    Worker(BrainWorker),
+}
+
+/// Events yielded by [`BrainProvider::chat_stream`].
+#[derive(Debug, Clone)]
+pub enum BrainStreamEvent {
+    /// Next piece of generated text (always valid UTF-8).
+    Delta(String),
+    /// Tool calls parsed from the finished output (sent before `Done`).
+    ToolCalls(Vec<ToolCall>),
+    /// Generation finished; carries token counts and per-token timing.
+    Done(GenerationStats),
+}
+
+/// A running local generation. Dropping the stream cancels it.
+pub struct BrainStream {
+    rx: mpsc::Receiver<Result<BrainStreamEvent>>,
+    cancel: CancelToken,
+}
+
+impl BrainStream {
+    /// Token that stops generation when cancelled (e.g. on client disconnect).
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+}
+
+impl futures::Stream for BrainStream {
+    type Item = Result<BrainStreamEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for BrainStream {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
 }
 
 impl BrainProvider {
     pub fn new(config: &BizClawConfig) -> Result<Self> {
-        let brain_config = bizclaw_brain::BrainConfig {
-            threads: config.brain.threads,
-            max_tokens: config.brain.max_tokens,
-            context_length: config.brain.context_length,
-            temperature: config.brain.temperature,
-            top_p: config.brain.top_p,
-            json_mode: config.brain.json_mode,
-        };
+        let model_path = model_path(config);
+        if !model_path.exists() {
+            tracing::info!(
+                "Brain provider: no model found at {}. Use `bizclaw brain download` to get a model.",
+                model_path.display()
+            );
+        }
+        pool().set_budget(config.brain.memory_budget_mb * 1024 * 1024);
+        Ok(Self {
+            model_path,
+            config: engine_config(config),
+            worker: WorkerConfig {
+                max_slots: config.brain.max_slots as usize,
+                max_queue: config.brain.max_queue as usize,
+            },
+        })
+    }
 
-        let mut engine = bizclaw_brain::BrainEngine::new(brain_config);
+    /// Queue and batching counters when the default model runs requests
+    /// concurrently and is loaded.
+    pub fn worker_metrics(&self) -> Option<WorkerMetrics> {
+        match pool().peek(&self.model_path, ModelRole::Chat).as_deref() {
+            Some(Backend::Worker(worker)) => Some(worker.metrics()),
+            _ => None,
+        }
+    }
 
-        // Try to load model from configured path
-        let model_dir = bizclaw_core::config::BizClawConfig::home_dir().join("models");
-        let model_path = if !config.brain.model_path.is_empty() {
-            std::path::PathBuf::from(&config.brain.model_path)
-        } else {
-            // Auto-detect: find first .gguf file in models directory
-            find_gguf_model(&model_dir).unwrap_or_else(|| model_dir.join("model.gguf"))
+    /// The request's model from the pool, loading it if needed.
+    async fn backend(&self, params: &GenerateParams) -> Result<Arc<Backend>> {
+        let path = requested_model(&self.model_path, &params.model);
+        if let Some(backend) = pool().get(&path, ModelRole::Chat) {
+            return Ok(backend);
+        }
+        let config = self.config.clone();
+        let worker = self.worker.clone();
+        tokio::task::spawn_blocking(move || pooled(&path, ModelRole::Chat, &config, &worker))
+            .await
+            .map_err(|e| BizClawError::Brain(format!("Model load task failed: {e}")))?
+    }
+
+    /// Queue a chat turn on the worker.
+    fn submit(
+        worker: &BrainWorker,
+        config: &bizclaw_brain::BrainConfig,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+        cancel: CancelToken,
+    ) -> Result<WorkerReceiver> {
+        worker.submit(WorkerRequest {
+            messages: messages.to_vec(),
+            tools: tools.to_vec(),
+            max_tokens: effective_max_tokens(params),
+            sampler: sampler_config(config, params),
+            client: params
+                .session_id
+                .clone()
+                .unwrap_or_else(|| "default".into()),
+            session: params.session_id.clone(),
+            lora: lora_path(config, params),
+            lora_scale: lora_scale(config, params),
+            cancel,
+        })
+    }
+
+    /// Stream a chat completion token by token.
+    ///
+    /// Generation runs on a blocking thread (or the batching worker); text
+    /// pieces are delivered as they are decoded, followed by any parsed
+    /// `ToolCalls` and a final `Done` event with stats.
+    pub async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<BrainStream> {
+        let backend = self.backend(params).await?;
+        let engine = match &*backend {
+            Backend::Engine(engine) => engine.clone().lock_owned().await,
+            Backend::Worker(worker) => {
+                let cancel = CancelToken::new();
+                let mut events = Self::submit(
+                    worker,
+                    &self.config,
+                    messages,
+                    tools,
+                    params,
+                    cancel.clone(),
+                )?;
+                let (tx, rx) = mpsc::channel(64);
+                tokio::spawn(async move {
+                    // Keeps the model in use (not evictable) until done
+                    let _backend = backend;
+                    while let Some(event) = events.recv().await {
+                        let event = match event {
+                            Ok(WorkerEvent::Delta(piece)) => Ok(BrainStreamEvent::Delta(piece)),
+                            Ok(WorkerEvent::Done(reply)) => {
+                                if !reply.tool_calls.is_empty() {
+                                    let calls = BrainStreamEvent::ToolCalls(reply.tool_calls);
+                                    let _ = tx.send(Ok(calls)).await;
+                                }
+                                Ok(BrainStreamEvent::Done(reply.stats))
+                            }
+                            Err(e) => Err(e),
+                        };
+                        // Dropping `events` tells the worker to stop
+                        if tx.send(event).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+                return Ok(BrainStream { rx, cancel });
+            }
         };
+        if !engine.is_loaded() {
+            return Err(no_model_error());
+        }
+
+        let mut engine = engine;
+        engine.set_lora(
+            lora_path(&self.config, params).as_deref(),
+            lora_scale(&self.config, params),
+        )?;
+        engine.set_session(params.session_id.as_deref());
+        let sampler = sampler_config(engine.config(), params);
+        engine.set_sampler(sampler);
+        let messages = messages.to_vec();
+        let tools = tools.to_vec();
+        let max_tokens = effective_max_tokens(params);
+        let cancel = CancelToken::new();
+        let (tx, rx) = mpsc::channel(64);
 
-        if model_path.exists() {
-            match engine.load_model(&model_path) {
-                Ok(()) => {
-                    tracing::info!("Brain provider: model loaded from {}", model_path.display())
+        let worker_cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            let _backend = backend;
+            let result =
+                engine.chat_stream(&messages, &tools, max_tokens, &worker_cancel, |piece| {
+                    // Receiver gone means nobody is listening any more.
+                    if tx
+                        .blocking_send(Ok(BrainStreamEvent::Delta(piece.to_string())))
+                        .is_err()
+                    {
+                        worker_cancel.cancel();
+                    }
+                });
+            match result {
+                Ok(reply) => {
+                    if !reply.tool_calls.is_empty() {
+                        let _ = tx.blocking_send(Ok(BrainStreamEvent::ToolCalls(reply.tool_calls)));
+                    }
+                    let _ = tx.blocking_send(Ok(BrainStreamEvent::Done(reply.stats)));
+                }
+                Err(e) => {
+                    let _ = tx.blocking_send(Err(e));
                 }
-                Err(e) => tracing::warn!("Brain provider: failed to load model: {e}"),
             }
-        } else {
-            tracing::info!(
-                "Brain provider: no model found at {}. Use `bizclaw brain download` to get a model.",
-                model_path.display()
-            );
+        });
+
+        Ok(BrainStream { rx, cancel })
+    }
+}
+
+/// Engine settings from the `[brain]` config section.
+fn engine_config(config: &BizClawConfig) -> bizclaw_brain::BrainConfig {
+    let kv_precision = KvPrecision::parse(&config.brain.kv_precision).unwrap_or_else(|| {
+        tracing::warn!(
+            "Brain provider: unknown kv_precision {:?}, using f32",
+            config.brain.kv_precision
+        );
+        KvPrecision::F32
+    });
+    bizclaw_brain::BrainConfig {
+        threads: config.brain.threads,
+        max_tokens: config.brain.max_tokens,
+        context_length: config.brain.context_length,
+        batch_size: config.brain.batch_size,
+        temperature: config.brain.temperature,
+        top_p: config.brain.top_p,
+        top_k: config.brain.top_k,
+        min_p: config.brain.min_p,
+        typical_p: config.brain.typical_p,
+        repeat_penalty: config.brain.repeat_penalty,
+        presence_penalty: config.brain.presence_penalty,
+        frequency_penalty: config.brain.frequency_penalty,
+        mirostat: config.brain.mirostat,
+        mirostat_tau: config.brain.mirostat_tau,
+        mirostat_eta: config.brain.mirostat_eta,
+        logit_bias: logit_bias(&config.brain.logit_bias),
+        banned_tokens: config.brain.banned_tokens.clone(),
+        seed: config.brain.seed,
+        json_mode: config.brain.json_mode,
+        session_cache_dir: (config.brain.session_cache_mb > 0)
+            .then(|| config.brain.cache_path().join("kv")),
+        session_cache_mb: config.brain.session_cache_mb,
+        kv_precision,
+        draft_model_path: config.brain.draft_model_file(),
+        draft_tokens: config.brain.draft_tokens,
+        embedding_model_path: config.brain.embedding_model_file(),
+        embedding_normalize: true,
+        lora_path: config.brain.lora_file(),
+        lora_scale: config.brain.lora_scale,
+        context_shift: config.brain.context_shift,
+        context_keep: config.brain.context_keep,
+    }
+}
+
+/// The configured chat model, or the first .gguf in `~/.bizclaw/models`.
+fn model_path(config: &BizClawConfig) -> std::path::PathBuf {
+    let model_dir = bizclaw_core::config::BizClawConfig::home_dir().join("models");
+    if !config.brain.model_path.is_empty() {
+        std::path::PathBuf::from(&config.brain.model_path)
+    } else {
+        // Auto-detect: find first .gguf file in models directory
+        find_gguf_model(&model_dir).unwrap_or_else(|| model_dir.join("model.gguf"))
+    }
+}
+
+/// Model for a request: `model` when it names a GGUF file (a path, or a
+/// file in `~/.bizclaw/models`), else `default`.
+fn requested_model(default: &Path, model: &str) -> PathBuf {
+    if !model.ends_with(".gguf") {
+        return default.to_path_buf();
+    }
+    let path = PathBuf::from(model);
+    if path.is_absolute() || path.exists() {
+        path
+    } else {
+        BizClawConfig::home_dir().join("models").join(path)
+    }
+}
+
+/// A model from the pool, loading it (and unloading idle ones to stay in
+/// budget) if needed. Blocks.
+fn pooled(
+    path: &Path,
+    role: ModelRole,
+    config: &bizclaw_brain::BrainConfig,
+    worker: &WorkerConfig,
+) -> Result<Arc<Backend>> {
+    if let Some(backend) = pool().get(path, role) {
+        return Ok(backend);
+    }
+    if !path.exists() {
+        return Err(no_model_error());
+    }
+    let bytes = estimate_bytes(path, role, config, worker.max_slots)?;
+    pool().get_or_load(path, role, bytes, || {
+        load_backend(path, role, config.clone(), worker.clone())
+    })
+}
+
+/// RAM a model takes once loaded: weights, one KV cache per slot (none for
+/// embeddings) and scratch buffers.
+fn estimate_bytes(
+    path: &Path,
+    role: ModelRole,
+    config: &bizclaw_brain::BrainConfig,
+    slots: usize,
+) -> Result<u64> {
+    let options = InspectOptions {
+        context: 0,
+        batch_size: config.batch_size as usize,
+        kv_precision: config.kv_precision,
+    };
+    let ram = inspect::inspect(path, &options)?.ram;
+    Ok(match role {
+        ModelRole::Chat => ram.total_bytes + ram.kv_cache_bytes * (slots.max(1) as u64 - 1),
+        ModelRole::Embedding => ram.total_bytes - ram.kv_cache_bytes,
+    })
+}
+
+fn load_backend(
+    path: &Path,
+    role: ModelRole,
+    config: bizclaw_brain::BrainConfig,
+    worker: WorkerConfig,
+) -> Result<Backend> {
+    let mut engine = BrainEngine::new(config);
+    if role == ModelRole::Embedding {
+        engine.load_embedding_model(path)?;
+        return Ok(Backend::Engine(Arc::new(Mutex::new(engine))));
+    }
+    engine.load_model(path)?;
+    tracing::info!("Brain provider: model loaded from {}", path.display());
+    if worker.max_slots <= 1 {
+        return Ok(Backend::Engine(Arc::new(Mutex::new(engine))));
+    }
+    if engine.config().draft_model_path.is_some() {
+        tracing::warn!(
+            "Brain provider: draft model unused with max_slots > 1 (set max_slots = 1 for speculative decoding)"
+        );
+    }
+    Ok(Backend::Worker(BrainWorker::spawn(engine, worker)?))
+}
+
+/// Local embeddings from a GGUF model, for memory and knowledge search.
+///
+/// Uses `brain.embedding_model_path`, or the chat model when that is empty.
+/// The model loads into the shared pool on the first call.
+pub struct BrainEmbedder {
+    model_path: PathBuf,
+    config: bizclaw_brain::BrainConfig,
+}
+
+impl BrainEmbedder {
+    pub fn new(config: &BizClawConfig) -> Self {
+        let brain = engine_config(config);
+        Self {
+            model_path: brain
+                .embedding_model_path
+                .clone()
+                .unwrap_or_else(|| model_path(config)),
+            config: brain,
         }
+    }
+}
 
-        Ok(Self {
-            engine: Mutex::new(engine),
+#[async_trait]
+impl Embedder for BrainEmbedder {
+    fn name(&self) -> &str {
+        "brain"
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let path = self.model_path.clone();
+        let config = self.config.clone();
+        let texts = texts.to_vec();
+        tokio::task::spawn_blocking(move || {
+            let backend = pooled(
+                &path,
+                ModelRole::Embedding,
+                &config,
+                &WorkerConfig::default(),
+            )?;
+            let Backend::Engine(engine) = &*backend else {
+                return Err(BizClawError::Brain(
+                    "Embedding model is not an engine".into(),
+                ));
+            };
+            let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+            engine.blocking_lock().embed(&texts)
+        })
+        .await
+        .map_err(|e| BizClawError::Brain(format!("Embedding task failed: {e}")))?
+    }
+}
+
+fn no_model_error() -> BizClawError {
+    BizClawError::Brain(
+        "No model loaded. Place a .gguf file in ~/.bizclaw/models/ or set brain.model_path in config.".into(),
+    )
+}
+
+/// Map a local chat turn onto the provider response shape.
+fn to_response(reply: ChatReply) -> ProviderResponse {
+    let usage = usage(&reply.stats);
+    let finish_reason = finish_reason(!reply.tool_calls.is_empty(), &reply.stats);
+    ProviderResponse {
+        content: (!reply.content.is_empty() || reply.tool_calls.is_empty())
+            .then_some(reply.content),
+        tool_calls: reply.tool_calls,
+        finish_reason: Some(finish_reason.into()),
+        usage: Some(usage),
+        provider: None,
+        model: None,
+    }
+}
+
+fn usage(stats: &GenerationStats) -> Usage {
+    Usage {
+        prompt_tokens: stats.prompt_tokens as u32,
+        completion_tokens: stats.generated_tokens as u32,
+        total_tokens: (stats.prompt_tokens + stats.generated_tokens) as u32,
+    }
+}
+
+fn finish_reason(tool_calls: bool, stats: &GenerationStats) -> &'static str {
+    if tool_calls {
+        "tool_calls"
+    } else if stats.stop_reason == StopReason::MaxTokens {
+        "length"
+    } else {
+        "stop"
+    }
+}
+
+/// Map a local stream event onto provider stream events.
+fn to_stream_events(event: BrainStreamEvent, tool_calls: &mut bool) -> Vec<StreamEvent> {
+    match event {
+        BrainStreamEvent::Delta(text) => vec![StreamEvent::Delta(text)],
+        BrainStreamEvent::ToolCalls(calls) => {
+            *tool_calls = true;
+            calls
+                .into_iter()
+                .enumerate()
+                .map(|(index, call)| StreamEvent::ToolCallDelta {
+                    index,
+                    id: Some(call.id),
+                    name: Some(call.function.name),
+                    arguments: call.function.arguments,
+                })
+                .collect()
+        }
+        BrainStreamEvent::Done(stats) => vec![
+            StreamEvent::Usage(usage(&stats)),
+            StreamEvent::Finish(finish_reason(*tool_calls, &stats).into()),
+        ],
+    }
+}
+
+/// Configured sampler settings with the request's overrides applied.
+fn sampler_config(config: &bizclaw_brain::BrainConfig, params: &GenerateParams) -> SamplerConfig {
+    let mut sampler = config.sampler_config();
+    if let Some(top_k) = params.top_k {
+        sampler.top_k = top_k;
+    }
+    if let Some(min_p) = params.min_p {
+        sampler.min_p = min_p;
+    }
+    if let Some(typical_p) = params.typical_p {
+        sampler.typical_p = typical_p;
+    }
+    if let Some(penalty) = params.presence_penalty {
+        sampler.presence_penalty = penalty;
+    }
+    if let Some(penalty) = params.frequency_penalty {
+        sampler.frequency_penalty = penalty;
+    }
+    if let Some(tau) = params.mirostat_tau {
+        sampler.mirostat = true;
+        sampler.mirostat_tau = tau;
+    }
+    sampler.logit_bias.extend(&params.logit_bias);
+    sampler.banned_tokens.extend(&params.banned_tokens);
+    if params.seed.is_some() {
+        sampler.seed = params.seed;
+    }
+    sampler
+}
+
+/// `brain.logit_bias` with its token-id keys parsed; bad keys are skipped.
+fn logit_bias(bias: &HashMap<String, f32>) -> HashMap<u32, f32> {
+    bias.iter()
+        .filter_map(|(token, &bias)| match token.trim().parse() {
+            Ok(id) => Some((id, bias)),
+            Err(_) => {
+                tracing::warn!("Brain provider: logit_bias key {token:?} is not a token id");
+                None
+            }
         })
+        .collect()
+}
+
+/// Adapter for a request: its own, else the configured one.
+fn lora_path(
+    config: &bizclaw_brain::BrainConfig,
+    params: &GenerateParams,
+) -> Option<std::path::PathBuf> {
+    match &params.lora_adapter {
+        Some(path) => Some(std::path::PathBuf::from(path)),
+        None => config.lora_path.clone(),
+    }
+}
+
+fn lora_scale(config: &bizclaw_brain::BrainConfig, params: &GenerateParams) -> f32 {
+    params.lora_scale.unwrap_or(config.lora_scale)
+}
+
+fn effective_max_tokens(params: &GenerateParams) -> u32 {
+    if params.max_tokens > 0 {
+        params.max_tokens
+    } else {
+        256
     }
 }
 
@@ -79,32 +600,76 @@ impl Provider for BrainProvider {
     async fn chat(
         &self,
         messages: &[Message],
-        _tools: &[ToolDefinition],
+        tools: &[ToolDefinition],
         params: &GenerateParams,
     ) -> Result<ProviderResponse> {
-        if !self.engine.lock().await.is_loaded() {
-            return Err(bizclaw_core::error::BizClawError::Brain(
-                "No model loaded. Place a .gguf file in ~/.bizclaw/models/ or set brain.model_path in config.".into()
-            ));
+        let backend = self.backend(params).await?;
+        let engine = match &*backend {
+            Backend::Engine(engine) => engine,
+            Backend::Worker(worker) => {
+                let mut events = Self::submit(
+                    worker,
+                    &self.config,
+                    messages,
+                    tools,
+                    params,
+                    CancelToken::new(),
+                )?;
+                while let Some(event) = events.recv().await {
+                    if let WorkerEvent::Done(reply) = event? {
+                        return Ok(to_response(reply));
+                    }
+                }
+                return Err(BizClawError::Brain("Brain worker stopped".into()));
+            }
+        };
+        let mut engine = engine.lock().await;
+        if !engine.is_loaded() {
+            return Err(no_model_error());
         }
 
-        // Format messages into a chat prompt (Llama-style)
-        let prompt = format_chat_prompt(messages);
-
-        let max_tokens = if params.max_tokens > 0 {
-            params.max_tokens
-        } else {
-            256
-        };
+        // The engine formats with the model's own chat template and parses
+        // tool calls in the family's native syntax
+        engine.set_lora(
+            lora_path(&self.config, params).as_deref(),
+            lora_scale(&self.config, params),
+        )?;
+        engine.set_session(params.session_id.as_deref());
+        let sampler = sampler_config(engine.config(), params);
+        engine.set_sampler(sampler);
+        let reply = engine.chat(messages, tools, effective_max_tokens(params))?;
+        Ok(to_response(reply))
+    }
 
-        let response = self.engine.lock().await.generate(&prompt, max_tokens)?;
-        Ok(ProviderResponse::text(response))
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderStream> {
+        let stream = BrainProvider::chat_stream(self, messages, tools, params).await?;
+        let mut tool_calls = false;
+        Ok(Box::pin(stream.flat_map(move |event| {
+            let events = match event {
+                Ok(event) => to_stream_events(event, &mut tool_calls)
+                    .into_iter()
+                    .map(Ok)
+                    .collect(),
+                Err(e) => vec![Err(e)],
+            };
+            futures::stream::iter(events)
+        })))
     }
 
     async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         let mut models = vec![];
 
-        if let Some(info) = self.engine.lock().await.model_info() {
+        let info = match pool().peek(&self.model_path, ModelRole::Chat).as_deref() {
+            Some(Backend::Engine(engine)) => engine.lock().await.model_info(),
+            Some(Backend::Worker(worker)) => Some(worker.model_info().to_string()),
+            None => None,
+        };
+        if let Some(info) = info {
             models.push(ModelInfo {
                 id: "local-model".into(),
                 name: info,
@@ -157,30 +722,175 @@ impl Provider for BrainProvider {
     }
 
     async fn health_check(&self) -> Result<bool> {
-        Ok(self.engine.lock().await.is_loaded())
+        // Models load on first use; a file to load is enough
+        Ok(pool().peek(&self.model_path, ModelRole::Chat).is_some() || self.model_path.exists())
     }
 }
 
-/// Format messages into a LLaMA-style chat prompt.
-fn format_chat_prompt(messages: &[Message]) -> String {
-    let mut prompt = String::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bizclaw_core::types::FunctionCall;
 
-    for msg in messages {
-        match msg.role {
-            bizclaw_core::types::Role::System => {
-                prompt.push_str(&format!("[INST] <<SYS>>\n{}\n<</SYS>>\n\n", msg.content));
-            }
-            bizclaw_core::types::Role::User => {
-                prompt.push_str(&format!("{} [/INST]", msg.content));
-            }
-            bizclaw_core::types::Role::Assistant => {
-                prompt.push_str(&format!(" {} </s><s>[INST] ", msg.content));
-            }
-            bizclaw_core::types::Role::Tool => {
-                prompt.push_str(&format!("Tool result: {} [/INST]", msg.content));
-            }
-        }
+    #[test]
+    fn test_to_response() {
+        let call = ToolCall {
+            id: "call_1".into(),
+            r#type: "function".into(),
+            function: FunctionCall {
+                name: "now".into(),
+                arguments: "{}".into(),
+            },
+        };
+        let stats = GenerationStats {
+            prompt_tokens: 10,
+            generated_tokens: 5,
+            ..Default::default()
+        };
+        let resp = to_response(ChatReply {
+            content: String::new(),
+            tool_calls: vec![call],
+            stats: stats.clone(),
+        });
+        assert!(resp.content.is_none());
+        assert_eq!(resp.finish_reason.as_deref(), Some("tool_calls"));
+        assert_eq!(resp.usage.unwrap().total_tokens, 15);
+
+        let resp = to_response(ChatReply {
+            content: "Hi".into(),
+            tool_calls: vec![],
+            stats: GenerationStats {
+                stop_reason: StopReason::MaxTokens,
+                ..stats
+            },
+        });
+        assert_eq!(resp.content.as_deref(), Some("Hi"));
+        assert_eq!(resp.finish_reason.as_deref(), Some("length"));
+    }
+
+    #[test]
+    fn test_to_stream_events() {
+        let call = ToolCall {
+            id: "call_1".into(),
+            r#type: "function".into(),
+            function: FunctionCall {
+                name: "now".into(),
+                arguments: "{}".into(),
+            },
+        };
+        let stats = GenerationStats {
+            prompt_tokens: 3,
+            generated_tokens: 2,
+            ..Default::default()
+        };
+        let mut tool_calls = false;
+        let events: Vec<StreamEvent> = [
+            BrainStreamEvent::Delta("ok".into()),
+            BrainStreamEvent::ToolCalls(vec![call]),
+            BrainStreamEvent::Done(stats),
+        ]
+        .into_iter()
+        .flat_map(|event| to_stream_events(event, &mut tool_calls))
+        .collect();
+        assert_eq!(events[0], StreamEvent::Delta("ok".into()));
+        assert!(matches!(
+            &events[1],
+            StreamEvent::ToolCallDelta { index: 0, id: Some(id), .. } if id == "call_1"
+        ));
+        assert_eq!(
+            events[2],
+            StreamEvent::Usage(Usage {
+                prompt_tokens: 3,
+                completion_tokens: 2,
+                total_tokens: 5,
+            })
+        );
+        assert_eq!(events[3], StreamEvent::Finish("tool_calls".into()));
+    }
+
+    #[test]
+    fn test_sampler_overrides() {
+        let config = bizclaw_brain::BrainConfig {
+            banned_tokens: vec![1],
+            ..Default::default()
+        };
+        let params = GenerateParams {
+            min_p: Some(0.05),
+            mirostat_tau: Some(3.0),
+            banned_tokens: vec![2],
+            seed: Some(9),
+            ..Default::default()
+        };
+        let sampler = sampler_config(&config, &params);
+        assert_eq!(sampler.min_p, 0.05);
+        assert!(sampler.mirostat);
+        assert_eq!(sampler.mirostat_tau, 3.0);
+        assert_eq!(sampler.banned_tokens, [1, 2]);
+        assert_eq!(sampler.seed, Some(9));
+        assert_eq!(sampler.top_k, config.top_k);
+
+        let defaults = sampler_config(&config, &GenerateParams::default());
+        assert!(!defaults.mirostat);
+        assert_eq!(defaults.seed, None);
+
+        let bias = HashMap::from([("13".to_string(), -2.0), ("x".to_string(), 1.0)]);
+        assert_eq!(logit_bias(&bias), HashMap::from([(13, -2.0)]));
+    }
+
+    #[test]
+    fn test_lora_selection() {
+        let config = bizclaw_brain::BrainConfig {
+            lora_path: Some("shop.gguf".into()),
+            lora_scale: 0.5,
+            ..Default::default()
+        };
+        let defaults = GenerateParams::default();
+        assert_eq!(
+            lora_path(&config, &defaults),
+            Some(std::path::PathBuf::from("shop.gguf"))
+        );
+        assert_eq!(lora_scale(&config, &defaults), 0.5);
+
+        let params = GenerateParams {
+            lora_adapter: Some("hr.gguf".into()),
+            lora_scale: Some(1.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            lora_path(&config, &params),
+            Some(std::path::PathBuf::from("hr.gguf"))
+        );
+        assert_eq!(lora_scale(&config, &params), 1.0);
     }
 
-    prompt
+    #[test]
+    fn test_requested_model() {
+        let default = Path::new("/models/default.gguf");
+        assert_eq!(requested_model(default, ""), default);
+        assert_eq!(requested_model(default, "gpt-4o-mini"), default);
+        assert_eq!(
+            requested_model(default, "/srv/router.gguf"),
+            Path::new("/srv/router.gguf")
+        );
+        assert_eq!(
+            requested_model(default, "qwen-3b.gguf"),
+            BizClawConfig::home_dir()
+                .join("models")
+                .join("qwen-3b.gguf")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_without_model() {
+        let mut config = BizClawConfig::default();
+        config.brain.model_path = "/nonexistent/model.gguf".into();
+        let provider = BrainProvider::new(&config).unwrap();
+        let messages = [Message::user("hi")];
+        assert!(
+            provider
+                .chat_stream(&messages, &[], &GenerateParams::default())
+                .await
+                .is_err()
+        );
+    }
 }
@@ -5,13 +5,16 @@
 use async_trait::async_trait;
 use bizclaw_core::config::BizClawConfig;
 use bizclaw_core::error::{BizClawError, Result};
-use bizclaw_core::traits::provider::{GenerateParams, Provider};
+use bizclaw_core::traits::provider::{GenerateParams, Provider, ProviderStream};
 use bizclaw_core::types::{Message, ModelInfo, ProviderResponse, ToolDefinition};
 
+use crate::http::HttpClient;
+use crate::stream::{self, OpenAiDecoder};
+
 pub struct CustomProvider {
     api_url: String,
     api_key: String,
-    client: reqwest::Client,
+    http: HttpClient,
 }
 
 impl CustomProvider {
@@ -29,23 +32,15 @@ impl CustomProvider {
         Ok(Self {
             api_url,
             api_key,
-            client: reqwest::Client::new(),
+            http: HttpClient::new("Custom", &config.http),
         })
     }
-}
-
-#[async_trait]
-impl Provider for CustomProvider {
-    fn name(&self) -> &str {
-        "custom"
-    }
 
-    async fn chat(
-        &self,
+    fn request_body(
         messages: &[Message],
         tools: &[ToolDefinition],
         params: &GenerateParams,
-    ) -> Result<ProviderResponse> {
+    ) -> serde_json::Value {
         let mut body = serde_json::json!({
             "model": params.model,
             "messages": messages,
@@ -69,35 +64,37 @@ impl Provider for CustomProvider {
                 .collect();
             body["tools"] = serde_json::Value::Array(tool_defs);
         }
+        body
+    }
 
+    fn request(&self, body: &serde_json::Value) -> reqwest::RequestBuilder {
         let mut req = self
-            .client
+            .http
+            .client()
             .post(format!("{}/chat/completions", self.api_url))
             .header("Content-Type", "application/json");
 
         if !self.api_key.is_empty() {
             req = req.header("Authorization", format!("Bearer {}", self.api_key));
         }
+        req.json(body)
+    }
+}
 
-        let resp = req.json(&body).send().await.map_err(|e| {
-            BizClawError::Http(format!(
-                "Custom provider connection failed ({}): {}",
-                self.api_url, e
-            ))
-        })?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(BizClawError::Provider(format!(
-                "Custom API error {status}: {text}"
-            )));
-        }
+#[async_trait]
+impl Provider for CustomProvider {
+    fn name(&self) -> &str {
+        "custom"
+    }
 
-        let json: serde_json::Value = resp
-            .json()
-            .await
-            .map_err(|e| BizClawError::Http(e.to_string()))?;
+    async fn chat(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderResponse> {
+        let body = Self::request_body(messages, tools, params);
+        let json = self.http.json(self.request(&body)).await?;
 
         let choice = json["choices"]
             .get(0)
@@ -132,12 +129,27 @@ impl Provider for CustomProvider {
                     completion_tokens: u["completion_tokens"].as_u64().unwrap_or(0) as u32,
                     total_tokens: u["total_tokens"].as_u64().unwrap_or(0) as u32,
                 }),
+            provider: None,
+            model: None,
         })
     }
 
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderStream> {
+        // No stream_options: not every compatible server accepts it
+        let mut body = Self::request_body(messages, tools, params);
+        body["stream"] = true.into();
+        stream::send(&self.http, self.request(&body), OpenAiDecoder::default()).await
+    }
+
     async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         let resp = self
-            .client
+            .http
+            .client()
             .get(format!("{}/models", self.api_url))
             .send()
             .await;
@@ -168,7 +180,7 @@ impl Provider for CustomProvider {
     }
 
     async fn health_check(&self) -> Result<bool> {
-        let resp = self.client.get(&self.api_url).send().await;
+        let resp = self.http.client().get(&self.api_url).send().await;
         Ok(resp.is_ok())
     }
 }
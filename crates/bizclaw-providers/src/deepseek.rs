@@ -3,12 +3,15 @@
 use async_trait::async_trait;
 use bizclaw_core::config::BizClawConfig;
 use bizclaw_core::error::{BizClawError, Result};
-use bizclaw_core::traits::provider::{GenerateParams, Provider};
+use bizclaw_core::traits::provider::{GenerateParams, Provider, ProviderStream};
 use bizclaw_core::types::{Message, ModelInfo, ProviderResponse, ToolDefinition};
 
+use crate::http::HttpClient;
+use crate::stream::{self, OpenAiDecoder};
+
 pub struct DeepSeekProvider {
     api_key: String,
-    client: reqwest::Client,
+    http: HttpClient,
 }
 
 impl DeepSeekProvider {
@@ -20,9 +23,21 @@ impl DeepSeekProvider {
         };
         Ok(Self {
             api_key,
-            client: reqwest::Client::new(),
+            http: HttpClient::new("DeepSeek", &config.http),
         })
     }
+
+    fn request_body(messages: &[Message], params: &GenerateParams) -> serde_json::Value {
+        serde_json::json!({"model": params.model, "messages": messages, "temperature": params.temperature, "max_tokens": params.max_tokens})
+    }
+
+    fn request(&self, body: &serde_json::Value) -> reqwest::RequestBuilder {
+        self.http
+            .client()
+            .post("https://api.deepseek.com/chat/completions")
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(body)
+    }
 }
 
 #[async_trait]
@@ -41,25 +56,8 @@ impl Provider for DeepSeekProvider {
             return Err(BizClawError::ApiKeyMissing("deepseek".into()));
         }
 
-        let body = serde_json::json!({"model": params.model, "messages": messages, "temperature": params.temperature, "max_tokens": params.max_tokens});
-        let resp = self
-            .client
-            .post("https://api.deepseek.com/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| BizClawError::Provider(format!("DeepSeek error: {e}")))?;
-        let status = resp.status();
-        let text = resp
-            .text()
-            .await
-            .map_err(|e| BizClawError::Provider(format!("Read: {e}")))?;
-        if !status.is_success() {
-            return Err(BizClawError::Provider(format!("DeepSeek {status}: {text}")));
-        }
-        let json: serde_json::Value = serde_json::from_str(&text)
-            .map_err(|e| BizClawError::Provider(format!("JSON: {e}")))?;
+        let body = Self::request_body(messages, params);
+        let json = self.http.json(self.request(&body)).await?;
 
         Ok(ProviderResponse {
             content: json["choices"][0]["message"]["content"]
@@ -68,9 +66,27 @@ impl Provider for DeepSeekProvider {
             tool_calls: vec![],
             finish_reason: Some("stop".into()),
             usage: None,
+            provider: None,
+            model: None,
         })
     }
 
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        _tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderStream> {
+        if self.api_key.is_empty() {
+            return Err(BizClawError::ApiKeyMissing("deepseek".into()));
+        }
+
+        let mut body = Self::request_body(messages, params);
+        body["stream"] = true.into();
+        body["stream_options"] = serde_json::json!({"include_usage": true});
+        stream::send(&self.http, self.request(&body), OpenAiDecoder::default()).await
+    }
+
     async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         Ok(vec![
             ModelInfo {
@@ -0,0 +1,818 @@
+//! Fallback chain — one provider that tries an ordered list of
+//! provider/model pairs until one answers.
+//!
+//! Failures are classified first. Rate limits, auth errors, 5xx, timeouts
+//! and connection errors (once the HTTP layer has used up its retries)
+//! move on to the next provider; a request the provider rejected as
+//! invalid is returned as is, since the next provider would most likely
+//! reject it too.
+//!
+//! Each provider/model pair has a circuit breaker, shared by every chain
+//! in the process, so one failing model does not take down the provider's
+//! others. After `brain.fallback_failures` consecutive failures the
+//! circuit opens and the pair is skipped. Once
+//! `brain.fallback_cooldown_secs` have passed it goes half-open: one probe
+//! request is let through, and its outcome closes or re-opens the circuit.
+
+use async_trait::async_trait;
+use bizclaw_core::config::BizClawConfig;
+use bizclaw_core::error::{BizClawError, Result};
+use bizclaw_core::traits::provider::{GenerateParams, Provider, ProviderStream};
+use bizclaw_core::types::{Message, ModelInfo, ProviderResponse, StreamEvent, ToolDefinition};
+use futures::StreamExt;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Circuit breakers by `provider/model` key, shared by all chains in the
+/// process.
+static BREAKERS: OnceLock<Mutex<HashMap<String, Arc<CircuitBreaker>>>> = OnceLock::new();
+
+fn breakers() -> MutexGuard<'static, HashMap<String, Arc<CircuitBreaker>>> {
+    BREAKERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Breaker key for a link: `provider/model`, or the provider alone when
+/// the link keeps the caller's model.
+fn breaker_key(provider: &str, model: Option<&str>) -> String {
+    match model {
+        Some(model) => format!("{provider}/{model}"),
+        None => provider.to_string(),
+    }
+}
+
+/// The breaker for `key`, with the given settings applied.
+fn breaker(key: &str, threshold: u32, cooldown: Duration) -> Arc<CircuitBreaker> {
+    let breaker = breakers()
+        .entry(key.to_string())
+        .or_insert_with(|| Arc::new(CircuitBreaker::new(threshold, cooldown)))
+        .clone();
+    breaker.configure(threshold, cooldown);
+    breaker
+}
+
+/// State of every circuit, for status pages.
+pub fn breaker_status() -> Vec<BreakerStatus> {
+    let mut status: Vec<BreakerStatus> = breakers()
+        .iter()
+        .map(|(key, breaker)| breaker.status(key))
+        .collect();
+    status.sort_by(|a, b| a.key.cmp(&b.key));
+    status
+}
+
+/// What kind of failure a provider error is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    RateLimited,
+    Auth,
+    /// 5xx, or the provider reports being overloaded
+    Server,
+    Timeout,
+    /// Could not connect, or the connection broke
+    Connection,
+    /// The request itself was rejected (other 4xx)
+    InvalidRequest,
+    Other,
+}
+
+impl ErrorClass {
+    pub fn of(error: &BizClawError) -> Self {
+        match error {
+            BizClawError::RateLimited(_) => Self::RateLimited,
+            BizClawError::ApiKeyMissing(_) | BizClawError::AuthFailed(_) => Self::Auth,
+            BizClawError::ContextTooLong(_) | BizClawError::InvalidRequest(_) => {
+                Self::InvalidRequest
+            }
+            BizClawError::Timeout(_) => Self::Timeout,
+            BizClawError::Http(message) => {
+                if is_timeout(message) {
+                    Self::Timeout
+                } else {
+                    Self::Connection
+                }
+            }
+            BizClawError::Provider(message) => Self::of_message(message),
+            _ => Self::Other,
+        }
+    }
+
+    /// Classify a provider error message by the HTTP status right after
+    /// "API error" ("OpenAI API error 429 Too Many Requests: ...") or, with
+    /// none, by its wording.
+    fn of_message(message: &str) -> Self {
+        let status = message
+            .split_once("API error ")
+            .and_then(|(_, rest)| rest.split(|c: char| !c.is_ascii_digit()).next())
+            .and_then(|code| code.parse::<u16>().ok())
+            .filter(|code| (400..600).contains(code));
+        let lower = message.to_lowercase();
+        match status {
+            Some(429) => Self::RateLimited,
+            Some(401 | 403) => Self::Auth,
+            Some(408) => Self::Timeout,
+            Some(500..=599) => Self::Server,
+            Some(_) => Self::InvalidRequest,
+            None if lower.contains("rate limit") => Self::RateLimited,
+            None if lower.contains("overloaded") => Self::Server,
+            None if is_timeout(&lower) => Self::Timeout,
+            None => Self::Other,
+        }
+    }
+
+    /// Whether the next provider in the chain should be tried.
+    pub fn fails_over(self) -> bool {
+        self != Self::InvalidRequest
+    }
+}
+
+fn is_timeout(message: &str) -> bool {
+    let lower = message.to_lowercase();
+    lower.contains("timed out") || lower.contains("timeout")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests go through
+    Closed,
+    /// Provider skipped until the cooldown passes
+    Open,
+    /// One probe request is deciding whether to close again
+    HalfOpen,
+}
+
+/// Tracks consecutive failures of one provider/model pair.
+pub struct CircuitBreaker {
+    inner: Mutex<BreakerInner>,
+}
+
+struct BreakerInner {
+    threshold: u32,
+    cooldown: Duration,
+    state: CircuitState,
+    failures: u32,
+    /// When the circuit opened, or when the current probe started
+    since: Instant,
+    last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+    /// `provider/model`, or the provider alone for a link without a model
+    pub key: String,
+    pub state: CircuitState,
+    pub failures: u32,
+    /// Seconds in the current open or half-open state
+    pub state_secs: u64,
+    pub last_error: Option<String>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            inner: Mutex::new(BreakerInner {
+                threshold: threshold.max(1),
+                cooldown,
+                state: CircuitState::Closed,
+                failures: 0,
+                since: Instant::now(),
+                last_error: None,
+            }),
+        }
+    }
+
+    fn configure(&self, threshold: u32, cooldown: Duration) {
+        let mut inner = self.lock();
+        inner.threshold = threshold.max(1);
+        inner.cooldown = cooldown;
+    }
+
+    /// Whether a request may go to the provider now. Past the cooldown an
+    /// open circuit turns half-open and lets this one request through as
+    /// the probe; a probe that never reported back is replaced after
+    /// another cooldown.
+    pub fn allow(&self) -> bool {
+        let mut inner = self.lock();
+        match inner.state {
+            CircuitState::Closed => true,
+            CircuitState::Open | CircuitState::HalfOpen => {
+                if inner.since.elapsed() < inner.cooldown {
+                    return false;
+                }
+                inner.state = CircuitState::HalfOpen;
+                inner.since = Instant::now();
+                true
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.lock();
+        inner.state = CircuitState::Closed;
+        inner.failures = 0;
+    }
+
+    pub fn record_failure(&self, error: &str) {
+        let mut inner = self.lock();
+        inner.failures += 1;
+        inner.last_error = Some(error.to_string());
+        if inner.state == CircuitState::HalfOpen || inner.failures >= inner.threshold {
+            inner.state = CircuitState::Open;
+            inner.since = Instant::now();
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.lock().state
+    }
+
+    fn status(&self, key: &str) -> BreakerStatus {
+        let inner = self.lock();
+        BreakerStatus {
+            key: key.to_string(),
+            state: inner.state,
+            failures: inner.failures,
+            state_secs: match inner.state {
+                CircuitState::Closed => 0,
+                _ => inner.since.elapsed().as_secs(),
+            },
+            last_error: inner.last_error.clone(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BreakerInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// One provider/model pair of the chain.
+struct Link {
+    /// Configured provider name, e.g. "openai" or "custom:http://..."
+    name: String,
+    provider: Box<dyn Provider>,
+    /// Model to request; `None` keeps the caller's
+    model: Option<String>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl Link {
+    fn params(&self, params: &GenerateParams) -> GenerateParams {
+        let mut params = params.clone();
+        if let Some(model) = &self.model {
+            params.model = model.clone();
+        }
+        params
+    }
+}
+
+/// Tries the default provider, then each `brain.fallback` entry in order.
+pub struct FallbackProvider {
+    links: Vec<Link>,
+    /// Limit per attempt (for streams: until the first event)
+    timeout: Option<Duration>,
+    threshold: u32,
+    cooldown: Duration,
+}
+
+impl FallbackProvider {
+    /// The chain for `config`: its default provider and model first.
+    pub fn from_config(config: &BizClawConfig) -> Result<Self> {
+        let mut chain = Self::new(config);
+        chain.push(
+            &config.default_provider,
+            None,
+            super::create_single(config)?,
+        );
+        for fallback in &config.brain.fallback {
+            let mut link_config = config.clone();
+            link_config.default_provider = fallback.provider.clone();
+            link_config.default_model = fallback.model.clone();
+            // Another provider must not get the default one's key
+            if !fallback.api_key.is_empty() || fallback.provider != config.default_provider {
+                link_config.api_key = fallback.api_key.clone();
+            }
+            let provider = super::create_single(&link_config)?;
+            let model = (!fallback.model.is_empty()).then(|| fallback.model.clone());
+            chain.push(&fallback.provider, model, provider);
+        }
+        Ok(chain)
+    }
+
+    /// An empty chain with the breaker settings from `config`.
+    fn new(config: &BizClawConfig) -> Self {
+        let brain = &config.brain;
+        Self {
+            links: Vec::new(),
+            timeout: (brain.fallback_timeout_secs > 0)
+                .then(|| Duration::from_secs(brain.fallback_timeout_secs)),
+            threshold: brain.fallback_failures,
+            cooldown: Duration::from_secs(brain.fallback_cooldown_secs),
+        }
+    }
+
+    fn push(&mut self, name: &str, model: Option<String>, provider: Box<dyn Provider>) {
+        let key = breaker_key(name, model.as_deref());
+        self.links.push(Link {
+            name: name.to_string(),
+            provider,
+            model,
+            breaker: breaker(&key, self.threshold, self.cooldown),
+        });
+    }
+
+    /// Run one attempt under the per-attempt time limit.
+    async fn attempt<T>(&self, link: &Link, call: impl Future<Output = Result<T>>) -> Result<T> {
+        let Some(limit) = self.timeout else {
+            return call.await;
+        };
+        tokio::time::timeout(limit, call).await.unwrap_or_else(|_| {
+            Err(BizClawError::Timeout(format!(
+                "{} did not answer within {}s",
+                link.name,
+                limit.as_secs()
+            )))
+        })
+    }
+
+    /// Record a failed attempt. Returns the error back when the chain
+    /// should stop here.
+    fn failed(
+        &self,
+        link: &Link,
+        error: BizClawError,
+    ) -> std::result::Result<BizClawError, BizClawError> {
+        let class = ErrorClass::of(&error);
+        if !class.fails_over() {
+            // The provider is up; the request is the problem
+            link.breaker.record_success();
+            return Err(error);
+        }
+        link.breaker.record_failure(&error.to_string());
+        tracing::warn!(
+            "Provider {} failed ({:?}): {} — trying the next one",
+            link.name,
+            class,
+            error
+        );
+        Ok(error)
+    }
+
+    fn exhausted(&self, last_error: Option<BizClawError>) -> BizClawError {
+        last_error.unwrap_or_else(|| {
+            let names: Vec<&str> = self.links.iter().map(|l| l.name.as_str()).collect();
+            BizClawError::Provider(format!(
+                "No provider available: circuits open for {}",
+                names.join(", ")
+            ))
+        })
+    }
+}
+
+#[async_trait]
+impl Provider for FallbackProvider {
+    fn name(&self) -> &str {
+        self.links.first().map_or("fallback", |l| l.provider.name())
+    }
+
+    async fn chat(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderResponse> {
+        let mut last_error = None;
+        for link in &self.links {
+            if !link.breaker.allow() {
+                tracing::debug!("Provider {} skipped: circuit open", link.name);
+                continue;
+            }
+            let params = link.params(params);
+            match self
+                .attempt(link, link.provider.chat(messages, tools, &params))
+                .await
+            {
+                Ok(mut response) => {
+                    link.breaker.record_success();
+                    response.provider = Some(link.name.clone());
+                    response.model = Some(params.model).filter(|m| !m.is_empty());
+                    return Ok(response);
+                }
+                Err(e) => last_error = Some(self.failed(link, e)?),
+            }
+        }
+        Err(self.exhausted(last_error))
+    }
+
+    /// Falls back only until a provider sends its first event; an error
+    /// after that ends the stream.
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderStream> {
+        let mut last_error = None;
+        for link in &self.links {
+            if !link.breaker.allow() {
+                tracing::debug!("Provider {} skipped: circuit open", link.name);
+                continue;
+            }
+            let params = link.params(params);
+            let started = async {
+                let mut stream = link.provider.chat_stream(messages, tools, &params).await?;
+                match stream.next().await {
+                    Some(Err(e)) => Err(e),
+                    first => Ok((first, stream)),
+                }
+            };
+            match self.attempt(link, started).await {
+                Ok((first, stream)) => {
+                    link.breaker.record_success();
+                    let answered = StreamEvent::Provider {
+                        name: link.name.clone(),
+                        model: params.model,
+                    };
+                    let head = std::iter::once(Ok(answered)).chain(first);
+                    return Ok(Box::pin(futures::stream::iter(head).chain(stream)));
+                }
+                Err(e) => last_error = Some(self.failed(link, e)?),
+            }
+        }
+        Err(self.exhausted(last_error))
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        let mut models = Vec::new();
+        for link in &self.links {
+            if let Ok(list) = link.provider.list_models().await {
+                models.extend(list);
+            }
+        }
+        Ok(models)
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        for link in &self.links {
+            if link.breaker.state() != CircuitState::Open
+                && link.provider.health_check().await.unwrap_or(false)
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bizclaw_core::config::BrainFallback;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers with its name, or fails with the scripted errors first.
+    struct Scripted {
+        name: &'static str,
+        errors: Mutex<Vec<BizClawError>>,
+        calls: Arc<AtomicUsize>,
+        delay: Duration,
+    }
+
+    impl Scripted {
+        fn boxed(
+            name: &'static str,
+            errors: Vec<BizClawError>,
+        ) -> (Box<dyn Provider>, Arc<AtomicUsize>) {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let provider = Self {
+                name,
+                errors: Mutex::new(errors),
+                calls: calls.clone(),
+                delay: Duration::ZERO,
+            };
+            (Box::new(provider), calls)
+        }
+    }
+
+    #[async_trait]
+    impl Provider for Scripted {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn chat(
+            &self,
+            _messages: &[Message],
+            _tools: &[ToolDefinition],
+            params: &GenerateParams,
+        ) -> Result<ProviderResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            let error = {
+                let mut errors = self.errors.lock().unwrap();
+                (!errors.is_empty()).then(|| errors.remove(0))
+            };
+            match error {
+                Some(e) => Err(e),
+                None => Ok(ProviderResponse::text(format!(
+                    "{} via {}",
+                    self.name, params.model
+                ))),
+            }
+        }
+
+        async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+            Ok(vec![])
+        }
+
+        async fn health_check(&self) -> Result<bool> {
+            Ok(true)
+        }
+    }
+
+    fn chain(
+        links: Vec<(&str, Option<&str>, Box<dyn Provider>)>,
+        threshold: u32,
+        cooldown: Duration,
+    ) -> FallbackProvider {
+        let mut chain = FallbackProvider {
+            links: Vec::new(),
+            timeout: Some(Duration::from_millis(200)),
+            threshold,
+            cooldown,
+        };
+        for (name, model, provider) in links {
+            chain.push(name, model.map(String::from), provider);
+        }
+        chain
+    }
+
+    fn params() -> GenerateParams {
+        GenerateParams {
+            model: "gpt-4o".into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_error_classes() {
+        let class = |e: BizClawError| ErrorClass::of(&e);
+        let provider = |m: &str| class(BizClawError::Provider(m.into()));
+        assert_eq!(
+            provider("OpenAI API error 429 Too Many Requests: slow down"),
+            ErrorClass::RateLimited
+        );
+        assert_eq!(
+            provider("Groq API error 401 Unauthorized: bad key"),
+            ErrorClass::Auth
+        );
+        assert_eq!(
+            provider("Anthropic API error 529 <unknown status code>: Overloaded"),
+            ErrorClass::Server
+        );
+        assert_eq!(
+            provider("Gemini API error 503 Service Unavailable: "),
+            ErrorClass::Server
+        );
+        assert_eq!(
+            provider("OpenAI API error 400 Bad Request: context_length_exceeded"),
+            ErrorClass::InvalidRequest
+        );
+        assert_eq!(provider("Anthropic: Overloaded"), ErrorClass::Server);
+        // Numbers elsewhere in the message are not statuses
+        assert_eq!(
+            provider("model llama-3.1-405b not found in 404 of 500 regions"),
+            ErrorClass::Other
+        );
+        assert_eq!(
+            provider("OpenAI API error 5000: model gpt-4-0613"),
+            ErrorClass::Other
+        );
+        assert_eq!(provider("No choices in response"), ErrorClass::Other);
+        assert_eq!(
+            class(BizClawError::Http(
+                "error sending request: operation timed out".into()
+            )),
+            ErrorClass::Timeout
+        );
+        assert_eq!(
+            class(BizClawError::Http("connection refused".into())),
+            ErrorClass::Connection
+        );
+        assert_eq!(
+            class(BizClawError::ApiKeyMissing("openai".into())),
+            ErrorClass::Auth
+        );
+        assert_eq!(
+            class(BizClawError::ContextTooLong("8192 tokens".into())),
+            ErrorClass::InvalidRequest
+        );
+        assert!(!ErrorClass::InvalidRequest.fails_over());
+        assert!(ErrorClass::Auth.fails_over());
+    }
+
+    #[test]
+    fn test_from_config_models() {
+        let mut config = BizClawConfig {
+            default_provider: "ollama".into(),
+            ..Default::default()
+        };
+        config.brain.fallback = vec![
+            BrainFallback {
+                provider: "ollama".into(),
+                model: String::new(),
+                api_key: String::new(),
+            },
+            BrainFallback {
+                provider: "ollama".into(),
+                model: "llama3.1".into(),
+                api_key: String::new(),
+            },
+        ];
+        let chain = FallbackProvider::from_config(&config).unwrap();
+        let models: Vec<Option<&str>> = chain.links.iter().map(|l| l.model.as_deref()).collect();
+        // No model keeps the caller's, like the default link
+        assert_eq!(models, [None, None, Some("llama3.1")]);
+        assert_eq!(chain.links[1].params(&params()).model, "gpt-4o");
+    }
+
+    #[test]
+    fn test_circuit_breaker_half_open() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(30));
+        breaker.record_failure("503");
+        assert!(breaker.allow());
+        breaker.record_failure("503");
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(40));
+        // One probe at a time
+        assert!(breaker.allow());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(!breaker.allow());
+        // A failed probe opens the circuit again straight away
+        breaker.record_failure("503");
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allow());
+        assert_eq!(breaker.status("p").failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_and_records_provider() {
+        let (primary, primary_calls) = Scripted::boxed(
+            "primary",
+            vec![
+                BizClawError::Provider("API error 503 Service Unavailable".into()),
+                BizClawError::Http("operation timed out".into()),
+            ],
+        );
+        let (backup, _) = Scripted::boxed("backup", vec![]);
+        let chain = chain(
+            vec![
+                ("test-fb-primary", None, primary),
+                ("test-fb-backup", Some("haiku"), backup),
+            ],
+            2,
+            Duration::from_secs(60),
+        );
+        let messages = [Message::user("hi")];
+
+        let resp = chain.chat(&messages, &[], &params()).await.unwrap();
+        assert_eq!(resp.content.as_deref(), Some("backup via haiku"));
+        assert_eq!(resp.provider.as_deref(), Some("test-fb-backup"));
+        assert_eq!(resp.model.as_deref(), Some("haiku"));
+
+        // Second failure opens the primary's circuit; it is then skipped
+        chain.chat(&messages, &[], &params()).await.unwrap();
+        assert_eq!(chain.links[0].breaker.state(), CircuitState::Open);
+        chain.chat(&messages, &[], &params()).await.unwrap();
+        assert_eq!(primary_calls.load(Ordering::SeqCst), 2);
+
+        let status = breaker_status();
+        let primary = status.iter().find(|s| s.key == "test-fb-primary").unwrap();
+        assert_eq!(primary.state, CircuitState::Open);
+        assert!(primary.last_error.as_deref().unwrap().contains("timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_breaker_per_model() {
+        let (large, _) = Scripted::boxed(
+            "large",
+            vec![BizClawError::Provider(
+                "OpenAI API error 503 Service Unavailable".into(),
+            )],
+        );
+        let (small, _) = Scripted::boxed("small", vec![]);
+        let chain = chain(
+            vec![
+                ("test-bk-openai", Some("gpt-4o"), large),
+                ("test-bk-openai", Some("gpt-4o-mini"), small),
+            ],
+            1,
+            Duration::from_secs(60),
+        );
+        let resp = chain
+            .chat(&[Message::user("hi")], &[], &params())
+            .await
+            .unwrap();
+        assert_eq!(resp.model.as_deref(), Some("gpt-4o-mini"));
+        // The same provider's other model keeps its own, closed circuit
+        assert_eq!(chain.links[0].breaker.state(), CircuitState::Open);
+        assert_eq!(chain.links[1].breaker.state(), CircuitState::Closed);
+
+        let status = breaker_status();
+        let state = |key: &str| status.iter().find(|s| s.key == key).map(|s| s.state);
+        assert_eq!(state("test-bk-openai/gpt-4o"), Some(CircuitState::Open));
+        assert_eq!(
+            state("test-bk-openai/gpt-4o-mini"),
+            Some(CircuitState::Closed)
+        );
+        assert_eq!(state("test-bk-openai"), None);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_request_not_retried() {
+        let (primary, _) = Scripted::boxed(
+            "primary",
+            vec![BizClawError::Provider(
+                "API error 400 Bad Request: bad schema".into(),
+            )],
+        );
+        let (backup, backup_calls) = Scripted::boxed("backup", vec![]);
+        let chain = chain(
+            vec![
+                ("test-ir-primary", None, primary),
+                ("test-ir-backup", None, backup),
+            ],
+            1,
+            Duration::from_secs(60),
+        );
+        let err = chain
+            .chat(&[Message::user("hi")], &[], &params())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("400"));
+        assert_eq!(backup_calls.load(Ordering::SeqCst), 0);
+        assert_eq!(chain.links[0].breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_and_stream_fallback() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let slow = Box::new(Scripted {
+            name: "slow",
+            errors: Mutex::new(vec![]),
+            calls: calls.clone(),
+            delay: Duration::from_secs(5),
+        });
+        let (backup, _) = Scripted::boxed("backup", vec![]);
+        let chain = chain(
+            vec![
+                ("test-to-slow", None, slow),
+                ("test-to-backup", None, backup),
+            ],
+            3,
+            Duration::from_secs(60),
+        );
+
+        let mut stream = chain
+            .chat_stream(&[Message::user("hi")], &[], &params())
+            .await
+            .unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await {
+            events.push(event.unwrap());
+        }
+        assert_eq!(
+            events[0],
+            StreamEvent::Provider {
+                name: "test-to-backup".into(),
+                model: "gpt-4o".into(),
+            }
+        );
+        assert_eq!(events[1], StreamEvent::Delta("backup via gpt-4o".into()));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let slow = &breaker_status()[..]
+            .iter()
+            .find(|s| s.key == "test-to-slow")
+            .unwrap()
+            .clone();
+        assert!(
+            slow.last_error
+                .as_deref()
+                .unwrap()
+                .contains("did not answer")
+        );
+    }
+}
@@ -3,12 +3,15 @@
 use async_trait::async_trait;
 use bizclaw_core::config::BizClawConfig;
 use bizclaw_core::error::{BizClawError, Result};
-use bizclaw_core::traits::provider::{GenerateParams, Provider};
+use bizclaw_core::traits::provider::{GenerateParams, Provider, ProviderStream};
 use bizclaw_core::types::{Message, ModelInfo, ProviderResponse, ToolDefinition};
 
+use crate::http::HttpClient;
+use crate::stream::{self, OpenAiDecoder};
+
 pub struct GeminiProvider {
     api_key: String,
-    client: reqwest::Client,
+    http: HttpClient,
 }
 
 impl GeminiProvider {
@@ -22,9 +25,27 @@ impl GeminiProvider {
         };
         Ok(Self {
             api_key,
-            client: reqwest::Client::new(),
+            http: HttpClient::new("Gemini", &config.http),
         })
     }
+
+    fn request_body(messages: &[Message], params: &GenerateParams) -> serde_json::Value {
+        serde_json::json!({
+            "model": params.model,
+            "messages": messages,
+            "temperature": params.temperature,
+            "max_tokens": params.max_tokens,
+        })
+    }
+
+    fn request(&self, body: &serde_json::Value) -> reqwest::RequestBuilder {
+        self.http
+            .client()
+            .post("https://generativelanguage.googleapis.com/v1beta/openai/chat/completions")
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .json(body)
+    }
 }
 
 #[async_trait]
@@ -43,37 +64,8 @@ impl Provider for GeminiProvider {
             return Err(BizClawError::ApiKeyMissing("gemini".into()));
         }
 
-        let body = serde_json::json!({
-            "model": params.model,
-            "messages": messages,
-            "temperature": params.temperature,
-            "max_tokens": params.max_tokens,
-        });
-
-        let resp = self
-            .client
-            .post("https://generativelanguage.googleapis.com/v1beta/openai/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| BizClawError::Provider(format!("Gemini error: {e}")))?;
-
-        let status = resp.status();
-        let text = resp
-            .text()
-            .await
-            .map_err(|e| BizClawError::Provider(format!("Read error: {e}")))?;
-
-        if !status.is_success() {
-            return Err(BizClawError::Provider(format!(
-                "Gemini API {status}: {text}"
-            )));
-        }
-
-        let json: serde_json::Value = serde_json::from_str(&text)
-            .map_err(|e| BizClawError::Provider(format!("Invalid JSON: {e}")))?;
+        let body = Self::request_body(messages, params);
+        let json = self.http.json(self.request(&body)).await?;
 
         let content = json["choices"][0]["message"]["content"]
             .as_str()
@@ -84,9 +76,28 @@ impl Provider for GeminiProvider {
             tool_calls: vec![],
             finish_reason: Some("stop".into()),
             usage: None,
+            provider: None,
+            model: None,
         })
     }
 
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        _tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderStream> {
+        if self.api_key.is_empty() {
+            return Err(BizClawError::ApiKeyMissing("gemini".into()));
+        }
+
+        // The OpenAI-compatible endpoint streams the same SSE chunks
+        let mut body = Self::request_body(messages, params);
+        body["stream"] = true.into();
+        body["stream_options"] = serde_json::json!({"include_usage": true});
+        stream::send(&self.http, self.request(&body), OpenAiDecoder::default()).await
+    }
+
     async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         Ok(vec![
             ModelInfo {
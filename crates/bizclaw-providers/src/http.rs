@@ -0,0 +1,427 @@
+//! Shared HTTP layer for the remote providers — timeouts, retries and
+//! typed errors.
+//!
+//! Rate limits (429), server errors (5xx) and connection failures are
+//! retried with exponential backoff: the delay doubles from
+//! `http.backoff_ms` up to `http.max_backoff_secs`, and a random half of it
+//! is jitter so clients that failed together do not retry together. A
+//! `Retry-After` (or `retry-after-ms`) header replaces the computed delay;
+//! one longer than `max_backoff_secs` is not waited out but returned as
+//! [`BizClawError::RateLimited`].
+//!
+//! Error statuses map to typed errors: 429 → `RateLimited`, 401/403 →
+//! `AuthFailed`, a prompt over the model's context → `ContextTooLong`, other
+//! 4xx → `InvalidRequest`, anything else → `Provider`.
+
+use bizclaw_core::config::HttpConfig;
+use bizclaw_core::error::{BizClawError, Result};
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::Duration;
+
+/// A `reqwest::Client` with the configured timeouts, plus the retry policy.
+pub(crate) struct HttpClient {
+    client: reqwest::Client,
+    /// Provider name used in error messages ("OpenAI")
+    label: String,
+    /// Until the response headers arrive
+    request_timeout: Option<Duration>,
+    max_retries: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl HttpClient {
+    pub(crate) fn new(label: impl Into<String>, config: &HttpConfig) -> Self {
+        let request_timeout = (config.request_timeout_secs > 0)
+            .then(|| Duration::from_secs(config.request_timeout_secs));
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs));
+        if let Some(timeout) = request_timeout {
+            // A stalled body, not a long one: streams may run for minutes
+            builder = builder.read_timeout(timeout);
+        }
+        Self {
+            client: builder.build().unwrap_or_default(),
+            label: label.into(),
+            request_timeout,
+            max_retries: config.max_retries,
+            backoff: Duration::from_millis(config.backoff_ms),
+            max_backoff: Duration::from_secs(config.max_backoff_secs),
+        }
+    }
+
+    /// The underlying client, for building requests.
+    pub(crate) fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Send `request`, retrying transient failures. Only a success status
+    /// is returned as a response; anything else is a typed error.
+    pub(crate) async fn send(&self, mut request: RequestBuilder) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            // A request whose body cannot be cloned gets a single attempt
+            let retry = request.try_clone();
+            let (error, retry_after) = match self.send_once(request).await? {
+                Ok(resp) => return Ok(resp),
+                Err(failure) => failure,
+            };
+            let Some(next) = retry.filter(|_| attempt < self.max_retries) else {
+                return Err(error);
+            };
+            let delay = match retry_after {
+                Some(wait) if wait > self.max_backoff => {
+                    let message = match error {
+                        BizClawError::RateLimited(message) => message,
+                        other => other.to_string(),
+                    };
+                    return Err(BizClawError::RateLimited(format!(
+                        "{message} (retry after {}s)",
+                        wait.as_secs()
+                    )));
+                }
+                Some(wait) => wait,
+                None => self.backoff(attempt),
+            };
+            attempt += 1;
+            tracing::warn!(
+                "{} request failed ({error}); retry {attempt}/{} in {delay:?}",
+                self.label,
+                self.max_retries
+            );
+            tokio::time::sleep(delay).await;
+            request = next;
+        }
+    }
+
+    /// Send and parse a JSON response body.
+    pub(crate) async fn json(&self, request: RequestBuilder) -> Result<serde_json::Value> {
+        self.send(request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| BizClawError::Http(format!("{} invalid response: {e}", self.label)))
+    }
+
+    /// One attempt. The outer error ends the request; the inner one is a
+    /// failure worth retrying, with the server's requested delay if any.
+    async fn send_once(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<std::result::Result<Response, (BizClawError, Option<Duration>)>> {
+        let sent = match self.request_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, request.send())
+                .await
+                .map_err(|_| {
+                    BizClawError::Timeout(format!(
+                        "{} did not respond within {}s",
+                        self.label,
+                        timeout.as_secs()
+                    ))
+                })?,
+            None => request.send().await,
+        };
+        let resp = match sent {
+            Ok(resp) => resp,
+            Err(e) if e.is_timeout() => {
+                return Err(BizClawError::Timeout(format!("{}: {e}", self.label)));
+            }
+            Err(e) if e.is_connect() || e.is_request() => {
+                let error = BizClawError::Http(format!("{} connection failed: {e}", self.label));
+                return Ok(Err((error, None)));
+            }
+            Err(e) => return Err(BizClawError::Http(format!("{}: {e}", self.label))),
+        };
+
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(Ok(resp));
+        }
+        let retry_after = retry_after(resp.headers());
+        let text = resp.text().await.unwrap_or_default();
+        let error = status_error(&self.label, status, &text);
+        if is_retryable(status) {
+            Ok(Err((error, retry_after)))
+        } else {
+            Err(error)
+        }
+    }
+
+    /// Delay before retry `attempt` (0-based): half fixed, half jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .backoff
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_backoff);
+        let half = delay / 2;
+        half + half.mul_f64(rand::thread_rng().r#gen::<f64>())
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 429)
+        || (status.is_server_error() && status != StatusCode::NOT_IMPLEMENTED)
+}
+
+/// The delay a server asked for: `retry-after-ms` (OpenAI), or
+/// `Retry-After` as seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name)?.to_str().ok().map(str::trim);
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Some(seconds(ms / 1000.0));
+    }
+    let value = header("retry-after")?;
+    if let Ok(secs) = value.parse::<f64>() {
+        return Some(seconds(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = at.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or_default())
+}
+
+/// `secs` as a delay. Values too large for a `Duration` (`inf`, `1e30`)
+/// saturate, so they count as longer than any backoff limit.
+fn seconds(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs.max(0.0)).unwrap_or(Duration::MAX)
+}
+
+/// The typed error for an error status.
+fn status_error(label: &str, status: StatusCode, text: &str) -> BizClawError {
+    let message = format!("{label} API error {status}: {text}");
+    match status.as_u16() {
+        429 => BizClawError::RateLimited(message),
+        401 | 403 => BizClawError::AuthFailed(message),
+        413 => BizClawError::ContextTooLong(message),
+        400..=499 if is_context_error(text) => BizClawError::ContextTooLong(message),
+        400..=499 if status != StatusCode::REQUEST_TIMEOUT => BizClawError::InvalidRequest(message),
+        _ => BizClawError::Provider(message),
+    }
+}
+
+/// How the providers word "the prompt does not fit the context window".
+fn is_context_error(text: &str) -> bool {
+    let lower = text.to_lowercase();
+    [
+        "context_length_exceeded",
+        "context length",
+        "context window",
+        "prompt is too long",
+        "too many tokens",
+        "input is too long",
+    ]
+    .iter()
+    .any(|needle| lower.contains(needle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A server answering each request with the next scripted response
+    /// (status, extra headers, body). Returns its URL and a hit counter.
+    async fn mock(responses: Vec<(u16, &'static str, &'static str)>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            for (status, headers, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                read_request(&mut socket).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                let reply = format!(
+                    "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n{headers}\r\n{body}",
+                    body.len()
+                );
+                socket.write_all(reply.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+        (url, hits)
+    }
+
+    /// Read headers and a `Content-Length` body.
+    async fn read_request(socket: &mut tokio::net::TcpStream) {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            data.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&data).to_lowercase();
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .find_map(|l| l.strip_prefix("content-length:"))
+                    .and_then(|v| v.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                if data.len() >= end + 4 + length {
+                    return;
+                }
+            }
+            if n == 0 {
+                return;
+            }
+        }
+    }
+
+    fn client(max_retries: u32) -> HttpClient {
+        HttpClient::new(
+            "Mock",
+            &HttpConfig {
+                connect_timeout_secs: 2,
+                request_timeout_secs: 5,
+                max_retries,
+                backoff_ms: 10,
+                max_backoff_secs: 2,
+            },
+        )
+    }
+
+    async fn post(http: &HttpClient, url: &str) -> Result<serde_json::Value> {
+        let request = http.client().post(url).json(&serde_json::json!({"x": 1}));
+        http.json(request).await
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        let (url, hits) = mock(vec![
+            (503, "", "busy"),
+            (529, "", r#"{"error":"Overloaded"}"#),
+            (200, "", r#"{"ok":true}"#),
+        ])
+        .await;
+        let json = post(&client(3), &url).await.unwrap();
+        assert_eq!(json["ok"], true);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // Out of retries: the last error is returned
+        let (url, hits) = mock(vec![(500, "", "boom"), (502, "", "bad gateway")]).await;
+        let err = post(&client(1), &url).await.unwrap_err();
+        assert!(matches!(err, BizClawError::Provider(_)));
+        assert!(err.to_string().contains("bad gateway"));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_honours_retry_after() {
+        let (url, hits) = mock(vec![
+            (429, "Retry-After: 1\r\n", "slow down"),
+            (200, "", "{}"),
+        ])
+        .await;
+        let start = Instant::now();
+        post(&client(3), &url).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // Longer than the backoff limit: fail now rather than wait
+        let (url, hits) = mock(vec![(429, "Retry-After: 3600\r\n", "quota")]).await;
+        let start = Instant::now();
+        let err = post(&client(3), &url).await.unwrap_err();
+        assert!(matches!(err, BizClawError::RateLimited(_)));
+        assert!(err.to_string().contains("retry after 3600s"));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // Delays too large for a Duration fail fast the same way
+        for header in ["Retry-After: inf\r\n", "Retry-After: 1e30\r\n"] {
+            let (url, hits) = mock(vec![(429, header, "quota")]).await;
+            let err = post(&client(3), &url).await.unwrap_err();
+            assert!(matches!(err, BizClawError::RateLimited(_)), "{header}");
+            assert_eq!(hits.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_typed_errors_not_retried() {
+        let (url, hits) = mock(vec![
+            (
+                400,
+                "",
+                r#"{"error":{"code":"context_length_exceeded","message":"This model's maximum context length is 8192 tokens"}}"#,
+            ),
+            (400, "", r#"{"error":"unknown field"}"#),
+            (401, "", "invalid api key"),
+            (429, "", "rate limited"),
+        ])
+        .await;
+        let http = client(0);
+        assert!(matches!(
+            post(&http, &url).await,
+            Err(BizClawError::ContextTooLong(_))
+        ));
+        assert!(matches!(
+            post(&http, &url).await,
+            Err(BizClawError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            post(&http, &url).await,
+            Err(BizClawError::AuthFailed(_))
+        ));
+        let err = post(&http, &url).await.unwrap_err();
+        assert!(matches!(err, BizClawError::RateLimited(_)));
+        assert_eq!(
+            err.to_string(),
+            "Rate limited: Mock API error 429 Too Many Requests: rate limited"
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors() {
+        // Nothing listens on a port we just released
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let err = post(&client(2), &url).await.unwrap_err();
+        assert!(matches!(err, BizClawError::Http(_)));
+        assert!(err.to_string().contains("Mock connection failed"));
+
+        let (url, _) = mock(vec![(200, "", "not json")]).await;
+        let err = post(&client(0), &url).await.unwrap_err();
+        assert!(err.to_string().contains("Mock invalid response"));
+    }
+
+    #[test]
+    fn test_backoff_and_retry_after() {
+        let http = client(5);
+        for attempt in 0..8 {
+            let full = Duration::from_millis(10 << attempt).min(Duration::from_secs(2));
+            let delay = http.backoff(attempt);
+            assert!(delay >= full / 2 && delay <= full, "{attempt}: {delay:?}");
+        }
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert("retry-after", "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+        headers.insert(
+            "retry-after",
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+        headers.insert("retry-after-ms", "250".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(250)));
+        // Too large for a Duration: saturates instead of panicking
+        headers.insert("retry-after-ms", "inf".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::MAX));
+        headers.remove("retry-after-ms");
+        headers.insert("retry-after", "1e30".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::MAX));
+        headers.insert("retry-after", "-5".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        assert!(is_retryable(StatusCode::BAD_GATEWAY));
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_retryable(StatusCode::NOT_IMPLEMENTED));
+        assert!(!is_retryable(StatusCode::BAD_REQUEST));
+    }
+}
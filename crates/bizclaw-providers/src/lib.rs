@@ -6,18 +6,31 @@ pub mod anthropic;
 pub mod brain;
 pub mod custom;
 pub mod deepseek;
+pub mod fallback;
 pub mod gemini;
 pub mod groq;
+mod http;
 pub mod llamacpp;
 pub mod ollama;
 pub mod openai;
+mod stream;
 
 use bizclaw_core::config::BizClawConfig;
 use bizclaw_core::error::Result;
-use bizclaw_core::traits::Provider;
+use bizclaw_core::traits::{Embedder, Provider};
+use std::sync::Arc;
 
-/// Create a provider from configuration.
+/// Create a provider from configuration: the default provider, wrapped in
+/// a fallback chain when `brain.fallback` lists others.
 pub fn create_provider(config: &BizClawConfig) -> Result<Box<dyn Provider>> {
+    if config.brain.fallback.is_empty() {
+        return create_single(config);
+    }
+    Ok(Box::new(fallback::FallbackProvider::from_config(config)?))
+}
+
+/// The provider named by `default_provider`, without fallbacks.
+fn create_single(config: &BizClawConfig) -> Result<Box<dyn Provider>> {
     match config.default_provider.as_str() {
         "openai" | "openrouter" => Ok(Box::new(openai::OpenAiProvider::new(config)?)),
         "anthropic" => Ok(Box::new(anthropic::AnthropicProvider::new(config)?)),
@@ -36,6 +49,18 @@ pub fn create_provider(config: &BizClawConfig) -> Result<Box<dyn Provider>> {
     }
 }
 
+/// Create the embedder named by `memory.embedding_provider` ("none"
+/// disables semantic search).
+pub fn create_embedder(config: &BizClawConfig) -> Result<Option<Arc<dyn Embedder>>> {
+    match config.memory.embedding_provider.as_str() {
+        "" | "none" => Ok(None),
+        "brain" | "local" => Ok(Some(Arc::new(brain::BrainEmbedder::new(config)))),
+        other => Err(bizclaw_core::error::BizClawError::ProviderNotFound(
+            format!("embedding provider {other}"),
+        )),
+    }
+}
+
 /// List all available provider names.
 pub fn available_providers() -> Vec<&'static str> {
     vec![
@@ -1,6 +1,7 @@
 //! llama.cpp server provider implementation.
 //! Connects to a running llama-server via OpenAI-compatible API.
 
+use crate::http::HttpClient;
 use async_trait::async_trait;
 use bizclaw_core::config::BizClawConfig;
 use bizclaw_core::error::{BizClawError, Result};
@@ -9,7 +10,7 @@ use bizclaw_core::types::{Message, ModelInfo, ProviderResponse, ToolDefinition};
 
 pub struct LlamaCppProvider {
     api_url: String,
-    client: reqwest::Client,
+    http: HttpClient,
 }
 
 impl LlamaCppProvider {
@@ -21,7 +22,7 @@ impl LlamaCppProvider {
 
         Ok(Self {
             api_url,
-            client: reqwest::Client::new(),
+            http: HttpClient::new("llama.cpp", &config.http),
         })
     }
 }
@@ -84,32 +85,13 @@ impl Provider for LlamaCppProvider {
             body["tools"] = serde_json::Value::Array(tool_defs);
         }
 
-        let resp = self
-            .client
+        let request = self
+            .http
+            .client()
             .post(format!("{}/v1/chat/completions", self.api_url))
             .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| {
-                BizClawError::Http(format!(
-                    "llama.cpp connection failed ({}): {}",
-                    self.api_url, e
-                ))
-            })?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(BizClawError::Provider(format!(
-                "llama.cpp API error {status}: {text}"
-            )));
-        }
-
-        let json: serde_json::Value = resp
-            .json()
-            .await
-            .map_err(|e| BizClawError::Http(e.to_string()))?;
+            .json(&body);
+        let json = self.http.json(request).await?;
 
         // Parse OpenAI-compatible response format
         let choice = json["choices"]
@@ -145,13 +127,16 @@ impl Provider for LlamaCppProvider {
                     completion_tokens: u["completion_tokens"].as_u64().unwrap_or(0) as u32,
                     total_tokens: u["total_tokens"].as_u64().unwrap_or(0) as u32,
                 }),
+            provider: None,
+            model: None,
         })
     }
 
     async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         // llama-server serves a single model, try /v1/models
         let resp = self
-            .client
+            .http
+            .client()
             .get(format!("{}/v1/models", self.api_url))
             .send()
             .await;
@@ -189,7 +174,8 @@ impl Provider for LlamaCppProvider {
 
     async fn health_check(&self) -> Result<bool> {
         let resp = self
-            .client
+            .http
+            .client()
             .get(format!("{}/health", self.api_url))
             .send()
             .await;
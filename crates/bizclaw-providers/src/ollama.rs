@@ -3,12 +3,15 @@
 use async_trait::async_trait;
 use bizclaw_core::config::BizClawConfig;
 use bizclaw_core::error::{BizClawError, Result};
-use bizclaw_core::traits::provider::{GenerateParams, Provider};
+use bizclaw_core::traits::provider::{GenerateParams, Provider, ProviderStream};
 use bizclaw_core::types::{Message, ModelInfo, ProviderResponse, ToolDefinition};
 
+use crate::http::HttpClient;
+use crate::stream::{self, OllamaDecoder};
+
 pub struct OllamaProvider {
     api_url: String,
-    client: reqwest::Client,
+    http: HttpClient,
 }
 
 impl OllamaProvider {
@@ -20,23 +23,16 @@ impl OllamaProvider {
 
         Ok(Self {
             api_url,
-            client: reqwest::Client::new(),
+            http: HttpClient::new("Ollama", &config.http),
         })
     }
-}
-
-#[async_trait]
-impl Provider for OllamaProvider {
-    fn name(&self) -> &str {
-        "ollama"
-    }
 
-    async fn chat(
-        &self,
+    fn request_body(
         messages: &[Message],
         tools: &[ToolDefinition],
         params: &GenerateParams,
-    ) -> Result<ProviderResponse> {
+        stream: bool,
+    ) -> serde_json::Value {
         // Ollama uses OpenAI-compatible /api/chat endpoint
         let formatted_messages: Vec<serde_json::Value> = messages
             .iter()
@@ -57,7 +53,7 @@ impl Provider for OllamaProvider {
         let mut body = serde_json::json!({
             "model": model,
             "messages": formatted_messages,
-            "stream": false,
+            "stream": stream,
             "options": {
                 "temperature": params.temperature,
                 "top_p": params.top_p,
@@ -81,83 +77,55 @@ impl Provider for OllamaProvider {
                 .collect();
             body["tools"] = serde_json::Value::Array(tool_defs);
         }
+        body
+    }
 
-        let resp = self
-            .client
-            .post(format!("{}/api/chat", self.api_url))
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| {
-                BizClawError::Http(format!(
-                    "Ollama connection failed ({}): {}",
-                    self.api_url, e
-                ))
-            })?;
-
-        // Handle 400 errors — often means model doesn't support tools
-        if resp.status() == reqwest::StatusCode::BAD_REQUEST {
-            let text = resp.text().await.unwrap_or_default();
-            // If error is about tools not supported, retry WITHOUT tools
-            if text.contains("does not support tools") || text.contains("does not support") {
+    /// POST `/api/chat`, failing on an error status. A model that does not
+    /// support tools gets the request again without them.
+    async fn post_chat(&self, mut body: serde_json::Value) -> Result<reqwest::Response> {
+        let request = |body: &serde_json::Value| {
+            self.http
+                .client()
+                .post(format!("{}/api/chat", self.api_url))
+                .header("Content-Type", "application/json")
+                .json(body)
+        };
+        match self.http.send(request(&body)).await {
+            Err(BizClawError::InvalidRequest(text))
+                if body.get("tools").is_some() && text.contains("does not support") =>
+            {
                 tracing::warn!(
                     "⚠️ Ollama model '{}' does not support tools — retrying without tools",
-                    model
+                    body["model"].as_str().unwrap_or_default()
                 );
                 body.as_object_mut().map(|o| o.remove("tools"));
-                let resp2 = self
-                    .client
-                    .post(format!("{}/api/chat", self.api_url))
-                    .header("Content-Type", "application/json")
-                    .json(&body)
-                    .send()
-                    .await
-                    .map_err(|e| BizClawError::Http(format!("Ollama retry failed: {}", e)))?;
-                if !resp2.status().is_success() {
-                    let status = resp2.status();
-                    let text2 = resp2.text().await.unwrap_or_default();
-                    return Err(BizClawError::Provider(format!(
-                        "Ollama API error {status}: {text2}"
-                    )));
-                }
-                // Parse retry response (no tool calls possible)
-                let json: serde_json::Value = resp2
-                    .json()
-                    .await
-                    .map_err(|e| BizClawError::Http(e.to_string()))?;
-                let content = json["message"]["content"].as_str().map(String::from);
-                let usage = Some(bizclaw_core::types::Usage {
-                    prompt_tokens: json["prompt_eval_count"].as_u64().unwrap_or(0) as u32,
-                    completion_tokens: json["eval_count"].as_u64().unwrap_or(0) as u32,
-                    total_tokens: (json["prompt_eval_count"].as_u64().unwrap_or(0)
-                        + json["eval_count"].as_u64().unwrap_or(0))
-                        as u32,
-                });
-                return Ok(ProviderResponse {
-                    content,
-                    tool_calls: vec![],
-                    finish_reason: Some("stop".into()),
-                    usage,
-                });
+                self.http.send(request(&body)).await
             }
-            return Err(BizClawError::Provider(format!(
-                "Ollama API error 400: {text}"
-            )));
+            result => result,
         }
+    }
+}
 
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(BizClawError::Provider(format!(
-                "Ollama API error {status}: {text}"
-            )));
-        }
+#[async_trait]
+impl Provider for OllamaProvider {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    async fn chat(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderResponse> {
+        let resp = self
+            .post_chat(Self::request_body(messages, tools, params, false))
+            .await?;
 
         let json: serde_json::Value = resp
             .json()
             .await
-            .map_err(|e| BizClawError::Http(e.to_string()))?;
+            .map_err(|e| BizClawError::Http(format!("Ollama invalid response: {e}")))?;
 
         let content = json["message"]["content"].as_str().map(String::from);
 
@@ -194,13 +162,31 @@ impl Provider for OllamaProvider {
             tool_calls,
             finish_reason: Some("stop".into()),
             usage,
+            provider: None,
+            model: None,
         })
     }
 
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderStream> {
+        let resp = self
+            .post_chat(Self::request_body(messages, tools, params, true))
+            .await?;
+        Ok(stream::decode(
+            resp.bytes_stream(),
+            OllamaDecoder::default(),
+        ))
+    }
+
     async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         // Call Ollama's /api/tags endpoint to list installed models
         let resp = self
-            .client
+            .http
+            .client()
             .get(format!("{}/api/tags", self.api_url))
             .send()
             .await;
@@ -235,7 +221,8 @@ impl Provider for OllamaProvider {
 
     async fn health_check(&self) -> Result<bool> {
         let resp = self
-            .client
+            .http
+            .client()
             .get(format!("{}/api/tags", self.api_url))
             .send()
             .await;
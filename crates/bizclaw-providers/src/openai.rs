@@ -3,13 +3,16 @@
 use async_trait::async_trait;
 use bizclaw_core::config::BizClawConfig;
 use bizclaw_core::error::{BizClawError, Result};
-use bizclaw_core::traits::provider::{GenerateParams, Provider};
+use bizclaw_core::traits::provider::{GenerateParams, Provider, ProviderStream};
 use bizclaw_core::types::{Message, ModelInfo, ProviderResponse, ToolDefinition};
 
+use crate::http::HttpClient;
+use crate::stream::{self, OpenAiDecoder};
+
 pub struct OpenAiProvider {
     api_key: String,
     api_url: String,
-    client: reqwest::Client,
+    http: HttpClient,
 }
 
 impl OpenAiProvider {
@@ -31,27 +34,15 @@ impl OpenAiProvider {
         Ok(Self {
             api_key,
             api_url,
-            client: reqwest::Client::new(),
+            http: HttpClient::new("OpenAI", &config.http),
         })
     }
-}
 
-#[async_trait]
-impl Provider for OpenAiProvider {
-    fn name(&self) -> &str {
-        "openai"
-    }
-
-    async fn chat(
-        &self,
+    fn request_body(
         messages: &[Message],
         tools: &[ToolDefinition],
         params: &GenerateParams,
-    ) -> Result<ProviderResponse> {
-        if self.api_key.is_empty() {
-            return Err(BizClawError::ApiKeyMissing("openai".into()));
-        }
-
+    ) -> serde_json::Value {
         let mut body = serde_json::json!({
             "model": params.model,
             "messages": messages,
@@ -75,29 +66,37 @@ impl Provider for OpenAiProvider {
                 .collect();
             body["tools"] = serde_json::Value::Array(tool_defs);
         }
+        body
+    }
 
-        let resp = self
-            .client
+    fn request(&self, body: &serde_json::Value) -> reqwest::RequestBuilder {
+        self.http
+            .client()
             .post(format!("{}/chat/completions", self.api_url))
             .header("Authorization", format!("Bearer {}", self.api_key))
             .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| BizClawError::Http(e.to_string()))?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(BizClawError::Provider(format!(
-                "OpenAI API error {status}: {text}"
-            )));
+            .json(body)
+    }
+}
+
+#[async_trait]
+impl Provider for OpenAiProvider {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    async fn chat(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderResponse> {
+        if self.api_key.is_empty() {
+            return Err(BizClawError::ApiKeyMissing("openai".into()));
         }
 
-        let json: serde_json::Value = resp
-            .json()
-            .await
-            .map_err(|e| BizClawError::Http(e.to_string()))?;
+        let body = Self::request_body(messages, tools, params);
+        let json = self.http.json(self.request(&body)).await?;
 
         let choice = json["choices"]
             .get(0)
@@ -132,9 +131,27 @@ impl Provider for OpenAiProvider {
                     completion_tokens: u["completion_tokens"].as_u64().unwrap_or(0) as u32,
                     total_tokens: u["total_tokens"].as_u64().unwrap_or(0) as u32,
                 }),
+            provider: None,
+            model: None,
         })
     }
 
+    async fn chat_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        params: &GenerateParams,
+    ) -> Result<ProviderStream> {
+        if self.api_key.is_empty() {
+            return Err(BizClawError::ApiKeyMissing("openai".into()));
+        }
+
+        let mut body = Self::request_body(messages, tools, params);
+        body["stream"] = true.into();
+        body["stream_options"] = serde_json::json!({"include_usage": true});
+        stream::send(&self.http, self.request(&body), OpenAiDecoder::default()).await
+    }
+
     async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         Ok(vec![
             ModelInfo {
@@ -0,0 +1,670 @@
+//! Streaming response bodies — SSE (OpenAI-compatible, Anthropic) and
+//! NDJSON (Ollama) decoded into [`StreamEvent`]s.
+//!
+//! Bodies arrive in arbitrary chunks: a line, an SSE event or a UTF-8
+//! character can be split across two reads, so decoders buffer bytes and
+//! only parse complete lines.
+
+use bizclaw_core::error::{BizClawError, Result};
+use bizclaw_core::traits::provider::ProviderStream;
+use bizclaw_core::types::{StreamEvent, Usage};
+use futures::{Stream, StreamExt};
+use std::collections::{HashMap, VecDeque};
+
+use crate::http::HttpClient;
+
+/// Turns body chunks into stream events.
+pub(crate) trait Decoder: Send + 'static {
+    /// Events completed by one more chunk of the body.
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<StreamEvent>>;
+
+    /// Events left once the body ends; fails if it ended mid-response.
+    fn finish(&mut self) -> Result<Vec<StreamEvent>>;
+}
+
+/// Send a streaming request and decode its body. Failures before the body
+/// starts are retried and typed like the non-streaming calls.
+pub(crate) async fn send(
+    http: &HttpClient,
+    request: reqwest::RequestBuilder,
+    decoder: impl Decoder,
+) -> Result<ProviderStream> {
+    let resp = http.send(request).await?;
+    Ok(decode(resp.bytes_stream(), decoder))
+}
+
+/// Decode a body stream; ends after the first error.
+pub(crate) fn decode<S, B, E>(body: S, decoder: impl Decoder) -> ProviderStream
+where
+    S: Stream<Item = std::result::Result<B, E>> + Send + 'static,
+    B: AsRef<[u8]>,
+    E: std::fmt::Display,
+{
+    let state = (Box::pin(body), decoder, VecDeque::new(), false);
+    Box::pin(futures::stream::unfold(
+        state,
+        |(mut body, mut decoder, mut pending, mut ended)| async move {
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some((Ok(event), (body, decoder, pending, ended)));
+                }
+                if ended {
+                    return None;
+                }
+                let events = match body.next().await {
+                    Some(Ok(bytes)) => decoder.push(bytes.as_ref()),
+                    Some(Err(e)) => Err(BizClawError::Http(format!("Stream read failed: {e}"))),
+                    None => {
+                        ended = true;
+                        decoder.finish()
+                    }
+                };
+                match events {
+                    Ok(events) => pending.extend(events),
+                    Err(e) => return Some((Err(e), (body, decoder, pending, true))),
+                }
+            }
+        },
+    ))
+}
+
+/// Splits bytes into lines without `\r\n`, holding back a partial last line.
+#[derive(Default)]
+struct LineBuffer {
+    buf: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(bytes);
+        let Some(end) = self.buf.iter().rposition(|&b| b == b'\n') else {
+            return vec![];
+        };
+        let rest = self.buf.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.buf, rest);
+        complete[..end]
+            .split(|&b| b == b'\n')
+            .map(|line| String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)).into())
+            .collect()
+    }
+
+    /// The unterminated last line, if any.
+    fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.buf);
+        let text = String::from_utf8_lossy(&rest).trim().to_string();
+        (!text.is_empty()).then_some(text)
+    }
+}
+
+/// One server-sent event.
+#[derive(Debug, PartialEq)]
+struct SseEvent {
+    event: Option<String>,
+    data: String,
+}
+
+/// Server-sent events: `event:` and `data:` fields, dispatched at a blank
+/// line. Comments and other fields are ignored.
+#[derive(Default)]
+struct SseParser {
+    lines: LineBuffer,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    fn push(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        let lines = self.lines.push(bytes);
+        lines.into_iter().filter_map(|l| self.line(&l)).collect()
+    }
+
+    fn finish(&mut self) -> Vec<SseEvent> {
+        let mut events: Vec<SseEvent> = self
+            .lines
+            .finish()
+            .and_then(|line| self.line(&line))
+            .into_iter()
+            .collect();
+        events.extend(self.line(""));
+        events
+    }
+
+    fn line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            if self.data.is_empty() {
+                self.event = None;
+                return None;
+            }
+            return Some(SseEvent {
+                event: self.event.take(),
+                data: std::mem::take(&mut self.data).join("\n"),
+            });
+        }
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            _ => {}
+        }
+        None
+    }
+}
+
+fn parse_json(data: &str) -> Result<serde_json::Value> {
+    serde_json::from_str(data)
+        .map_err(|e| BizClawError::Provider(format!("Invalid stream chunk: {e}: {data}")))
+}
+
+fn error_message(json: &serde_json::Value) -> Option<String> {
+    let error = json.get("error")?;
+    Some(
+        error["message"]
+            .as_str()
+            .or(error.as_str())
+            .map(String::from)
+            .unwrap_or_else(|| error.to_string()),
+    )
+}
+
+/// OpenAI chat completion chunks (`data: {...}` … `data: [DONE]`), as sent
+/// by OpenAI, OpenRouter, Groq, DeepSeek, Gemini and compatible servers.
+#[derive(Default)]
+pub(crate) struct OpenAiDecoder {
+    sse: SseParser,
+    /// Held back so usage (sent after it) comes first
+    finish_reason: Option<String>,
+    done: bool,
+}
+
+impl OpenAiDecoder {
+    fn event(&mut self, data: &str) -> Result<Vec<StreamEvent>> {
+        if data == "[DONE]" {
+            return Ok(self.complete());
+        }
+        let json = parse_json(data)?;
+        if let Some(message) = error_message(&json) {
+            return Err(BizClawError::Provider(message));
+        }
+
+        let mut events = Vec::new();
+        let choice = &json["choices"][0];
+        let delta = &choice["delta"];
+        if let Some(text) = delta["content"].as_str()
+            && !text.is_empty()
+        {
+            events.push(StreamEvent::Delta(text.to_string()));
+        }
+        for (position, call) in delta["tool_calls"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            events.push(StreamEvent::ToolCallDelta {
+                index: call["index"].as_u64().map_or(position, |i| i as usize),
+                id: call["id"].as_str().map(String::from),
+                name: call["function"]["name"].as_str().map(String::from),
+                arguments: call["function"]["arguments"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            });
+        }
+        if let Some(reason) = choice["finish_reason"].as_str() {
+            self.finish_reason = Some(reason.to_string());
+        }
+        if let Some(usage) = json["usage"].as_object() {
+            let count = |key: &str| usage.get(key).and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+            events.push(StreamEvent::Usage(Usage {
+                prompt_tokens: count("prompt_tokens"),
+                completion_tokens: count("completion_tokens"),
+                total_tokens: count("total_tokens"),
+            }));
+        }
+        Ok(events)
+    }
+
+    fn complete(&mut self) -> Vec<StreamEvent> {
+        if self.done {
+            return vec![];
+        }
+        self.done = true;
+        let reason = self.finish_reason.take().unwrap_or_else(|| "stop".into());
+        vec![StreamEvent::Finish(reason)]
+    }
+}
+
+impl Decoder for OpenAiDecoder {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<StreamEvent>> {
+        let mut events = Vec::new();
+        for sse in self.sse.push(bytes) {
+            events.extend(self.event(&sse.data)?);
+        }
+        Ok(events)
+    }
+
+    fn finish(&mut self) -> Result<Vec<StreamEvent>> {
+        let mut events = Vec::new();
+        for sse in self.sse.finish() {
+            events.extend(self.event(&sse.data)?);
+        }
+        // Some servers close without [DONE] after the finish reason
+        if !self.done && self.finish_reason.is_none() {
+            return Err(BizClawError::Provider(
+                "Stream ended before the response finished".into(),
+            ));
+        }
+        events.extend(self.complete());
+        Ok(events)
+    }
+}
+
+/// Anthropic Messages API events (`message_start`, `content_block_*`,
+/// `message_delta`, `message_stop`).
+#[derive(Default)]
+pub(crate) struct AnthropicDecoder {
+    sse: SseParser,
+    /// Content block index → tool call index
+    tool_blocks: HashMap<u64, usize>,
+    input_tokens: u32,
+    output_tokens: u32,
+    stop_reason: Option<String>,
+    done: bool,
+}
+
+impl AnthropicDecoder {
+    fn event(&mut self, data: &str) -> Result<Vec<StreamEvent>> {
+        let json = parse_json(data)?;
+        let mut events = Vec::new();
+        match json["type"].as_str() {
+            Some("message_start") => {
+                let usage = &json["message"]["usage"];
+                self.input_tokens = usage["input_tokens"].as_u64().unwrap_or(0) as u32;
+                self.output_tokens = usage["output_tokens"].as_u64().unwrap_or(0) as u32;
+            }
+            Some("content_block_start") => {
+                let block = &json["content_block"];
+                if block["type"] == "tool_use" {
+                    let index = self.tool_blocks.len();
+                    let block_index = json["index"].as_u64().unwrap_or(0);
+                    self.tool_blocks.insert(block_index, index);
+                    events.push(StreamEvent::ToolCallDelta {
+                        index,
+                        id: block["id"].as_str().map(String::from),
+                        name: block["name"].as_str().map(String::from),
+                        arguments: String::new(),
+                    });
+                } else if let Some(text) = block["text"].as_str()
+                    && !text.is_empty()
+                {
+                    events.push(StreamEvent::Delta(text.to_string()));
+                }
+            }
+            Some("content_block_delta") => {
+                let delta = &json["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => {
+                        if let Some(text) = delta["text"].as_str() {
+                            events.push(StreamEvent::Delta(text.to_string()));
+                        }
+                    }
+                    Some("input_json_delta") => {
+                        let block_index = json["index"].as_u64().unwrap_or(0);
+                        if let Some(&index) = self.tool_blocks.get(&block_index) {
+                            events.push(StreamEvent::ToolCallDelta {
+                                index,
+                                id: None,
+                                name: None,
+                                arguments: delta["partial_json"]
+                                    .as_str()
+                                    .unwrap_or_default()
+                                    .to_string(),
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Some("message_delta") => {
+                if let Some(reason) = json["delta"]["stop_reason"].as_str() {
+                    self.stop_reason = Some(reason.to_string());
+                }
+                if let Some(tokens) = json["usage"]["output_tokens"].as_u64() {
+                    self.output_tokens = tokens as u32;
+                }
+            }
+            Some("message_stop") => events.extend(self.complete()),
+            Some("error") => {
+                let message = error_message(&json).unwrap_or_else(|| data.to_string());
+                return Err(BizClawError::Provider(format!("Anthropic: {message}")));
+            }
+            _ => {}
+        }
+        Ok(events)
+    }
+
+    fn complete(&mut self) -> Vec<StreamEvent> {
+        if self.done {
+            return vec![];
+        }
+        self.done = true;
+        let reason = self.stop_reason.take().unwrap_or_else(|| "end_turn".into());
+        vec![
+            StreamEvent::Usage(Usage {
+                prompt_tokens: self.input_tokens,
+                completion_tokens: self.output_tokens,
+                total_tokens: self.input_tokens + self.output_tokens,
+            }),
+            StreamEvent::Finish(reason),
+        ]
+    }
+}
+
+impl Decoder for AnthropicDecoder {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<StreamEvent>> {
+        let mut events = Vec::new();
+        for sse in self.sse.push(bytes) {
+            events.extend(self.event(&sse.data)?);
+        }
+        Ok(events)
+    }
+
+    fn finish(&mut self) -> Result<Vec<StreamEvent>> {
+        let mut events = Vec::new();
+        for sse in self.sse.finish() {
+            events.extend(self.event(&sse.data)?);
+        }
+        if !self.done && self.stop_reason.is_none() {
+            return Err(BizClawError::Provider(
+                "Stream ended before the response finished".into(),
+            ));
+        }
+        events.extend(self.complete());
+        Ok(events)
+    }
+}
+
+/// Ollama `/api/chat` lines: one JSON object per line, the last with
+/// `"done": true` and token counts.
+#[derive(Default)]
+pub(crate) struct OllamaDecoder {
+    lines: LineBuffer,
+    tool_calls: usize,
+    done: bool,
+}
+
+impl OllamaDecoder {
+    fn line(&mut self, line: &str) -> Result<Vec<StreamEvent>> {
+        if line.trim().is_empty() || self.done {
+            return Ok(vec![]);
+        }
+        let json = parse_json(line)?;
+        if let Some(message) = error_message(&json) {
+            return Err(BizClawError::Provider(format!("Ollama: {message}")));
+        }
+
+        let mut events = Vec::new();
+        if let Some(text) = json["message"]["content"].as_str()
+            && !text.is_empty()
+        {
+            events.push(StreamEvent::Delta(text.to_string()));
+        }
+        // Ollama sends each tool call whole, without an id
+        for call in json["message"]["tool_calls"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            let function = &call["function"];
+            events.push(StreamEvent::ToolCallDelta {
+                index: self.tool_calls,
+                id: None,
+                name: function["name"].as_str().map(String::from),
+                arguments: function["arguments"].to_string(),
+            });
+            self.tool_calls += 1;
+        }
+        if json["done"].as_bool() == Some(true) {
+            self.done = true;
+            let prompt = json["prompt_eval_count"].as_u64().unwrap_or(0) as u32;
+            let completion = json["eval_count"].as_u64().unwrap_or(0) as u32;
+            events.push(StreamEvent::Usage(Usage {
+                prompt_tokens: prompt,
+                completion_tokens: completion,
+                total_tokens: prompt + completion,
+            }));
+            let reason = json["done_reason"].as_str().unwrap_or("stop");
+            events.push(StreamEvent::Finish(reason.to_string()));
+        }
+        Ok(events)
+    }
+}
+
+impl Decoder for OllamaDecoder {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<StreamEvent>> {
+        let mut events = Vec::new();
+        for line in self.lines.push(bytes) {
+            events.extend(self.line(&line)?);
+        }
+        Ok(events)
+    }
+
+    fn finish(&mut self) -> Result<Vec<StreamEvent>> {
+        let events = match self.lines.finish() {
+            Some(line) => self.line(&line)?,
+            None => vec![],
+        };
+        if !self.done {
+            return Err(BizClawError::Provider(
+                "Stream ended before the response finished".into(),
+            ));
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bizclaw_core::types::StreamAccumulator;
+
+    /// Decode `body` fed in `chunk`-byte pieces.
+    async fn run(body: &str, chunk: usize, decoder: impl Decoder) -> Vec<Result<StreamEvent>> {
+        let chunks: Vec<std::result::Result<Vec<u8>, String>> = body
+            .as_bytes()
+            .chunks(chunk)
+            .map(|c| Ok(c.to_vec()))
+            .collect();
+        decode(futures::stream::iter(chunks), decoder)
+            .collect()
+            .await
+    }
+
+    fn collect(events: Vec<Result<StreamEvent>>) -> bizclaw_core::types::ProviderResponse {
+        let mut acc = StreamAccumulator::new();
+        for event in events {
+            acc.push(event.unwrap());
+        }
+        acc.finish()
+    }
+
+    #[test]
+    fn test_sse_parser_split_fields() {
+        let mut sse = SseParser::default();
+        assert!(sse.push(b": keep-alive\n\nevent: ping\nda").is_empty());
+        let events = sse.push("ta: {\"a\":1}\r\n\r\ndata: x\ndata: y\n\ndata: é".as_bytes());
+        assert_eq!(
+            events,
+            [
+                SseEvent {
+                    event: Some("ping".into()),
+                    data: "{\"a\":1}".into(),
+                },
+                SseEvent {
+                    event: None,
+                    data: "x\ny".into(),
+                },
+            ]
+        );
+        assert_eq!(sse.finish()[0].data, "é");
+    }
+
+    #[tokio::test]
+    async fn test_openai_text_and_tool_calls() {
+        let body = concat!(
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"Xin \"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"chào\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",",
+            "\"type\":\"function\",\"function\":{\"name\":\"weather\",\"arguments\":\"\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,",
+            "\"function\":{\"arguments\":\"{\\\"city\\\":\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,",
+            "\"function\":{\"arguments\":\"\\\"Hà Nội\\\"}\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":9,\"completion_tokens\":4,\"total_tokens\":13}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        // Every chunking gives the same result, including splits inside
+        // multi-byte characters
+        for chunk in [1, 7, body.len()] {
+            let events = run(body, chunk, OpenAiDecoder::default()).await;
+            assert_eq!(
+                events.last().unwrap().as_ref().unwrap(),
+                &StreamEvent::Finish("tool_calls".into())
+            );
+            let resp = collect(events);
+            assert_eq!(resp.content.as_deref(), Some("Xin chào"));
+            assert_eq!(resp.tool_calls[0].id, "call_1");
+            assert_eq!(resp.tool_calls[0].function.name, "weather");
+            assert_eq!(
+                resp.tool_calls[0].function.arguments,
+                "{\"city\":\"Hà Nội\"}"
+            );
+            assert_eq!(resp.usage.unwrap().total_tokens, 13);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_openai_errors_and_truncation() {
+        let events = run(
+            "data: {\"error\":{\"message\":\"Rate limit reached\"}}\n\n",
+            64,
+            OpenAiDecoder::default(),
+        )
+        .await;
+        assert_eq!(events.len(), 1);
+        assert!(
+            events[0]
+                .as_ref()
+                .unwrap_err()
+                .to_string()
+                .contains("Rate limit")
+        );
+
+        let events = run(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+            64,
+            OpenAiDecoder::default(),
+        )
+        .await;
+        assert_eq!(
+            events[0].as_ref().unwrap(),
+            &StreamEvent::Delta("Hi".into())
+        );
+        assert!(events[1].is_err());
+
+        // A finish reason without [DONE] still completes
+        let events = run(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"},\"finish_reason\":\"length\"}]}",
+            64,
+            OpenAiDecoder::default(),
+        )
+        .await;
+        assert_eq!(collect(events).finish_reason.as_deref(), Some("length"));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_events() {
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":25,\"output_tokens\":1}}}\n\n",
+            "event: content_block_start\n",
+            "data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: ping\ndata: {\"type\":\"ping\"}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Let me check.\"}}\n\n",
+            "event: content_block_start\n",
+            "data: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"weather\",\"input\":{}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"city\\\": \"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"\\\"Huế\\\"}\"}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":1}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\"},\"usage\":{\"output_tokens\":30}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+        for chunk in [3, body.len()] {
+            let resp = collect(run(body, chunk, AnthropicDecoder::default()).await);
+            assert_eq!(resp.content.as_deref(), Some("Let me check."));
+            assert_eq!(resp.tool_calls[0].id, "toolu_1");
+            assert_eq!(resp.tool_calls[0].function.arguments, "{\"city\": \"Huế\"}");
+            assert_eq!(resp.finish_reason.as_deref(), Some("tool_use"));
+            let usage = resp.usage.unwrap();
+            assert_eq!((usage.prompt_tokens, usage.completion_tokens), (25, 30));
+        }
+
+        let events = run(
+            "event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n",
+            64,
+            AnthropicDecoder::default(),
+        )
+        .await;
+        assert!(
+            events[0]
+                .as_ref()
+                .unwrap_err()
+                .to_string()
+                .contains("Overloaded")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ollama_lines() {
+        let body = concat!(
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"Đang \"},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"xem\"},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"\",\"tool_calls\":[",
+            "{\"function\":{\"name\":\"weather\",\"arguments\":{\"city\":\"Đà Nẵng\"}}}]},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,",
+            "\"done_reason\":\"stop\",\"prompt_eval_count\":12,\"eval_count\":5}",
+        );
+        for chunk in [5, body.len()] {
+            let resp = collect(run(body, chunk, OllamaDecoder::default()).await);
+            assert_eq!(resp.content.as_deref(), Some("Đang xem"));
+            assert_eq!(resp.tool_calls[0].function.name, "weather");
+            assert_eq!(
+                resp.tool_calls[0].function.arguments,
+                "{\"city\":\"Đà Nẵng\"}"
+            );
+            assert!(!resp.tool_calls[0].id.is_empty());
+            assert_eq!(resp.usage.unwrap().total_tokens, 17);
+            assert_eq!(resp.finish_reason.as_deref(), Some("stop"));
+        }
+
+        let events = run(
+            "{\"error\":\"model not found\"}\n",
+            64,
+            OllamaDecoder::default(),
+        )
+        .await;
+        assert!(
+            events[0]
+                .as_ref()
+                .unwrap_err()
+                .to_string()
+                .contains("model not found")
+        );
+    }
+}
@@ -222,7 +222,7 @@ impl Tool for ExecuteCodeTool {
 
             let _ = tokio::fs::remove_file(&file_path).await;
             let _ = tokio::fs::remove_file(&out_path).await;
-            run.map(|r| r.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?)
+            run.map(|r| r.map_err(std::io::Error::other)?)
         } else {
             // Interpreted — just run
             let mut cmd_args: Vec<String> = config.args;
@@ -240,7 +240,7 @@ impl Tool for ExecuteCodeTool {
             .await;
 
             let _ = tokio::fs::remove_file(&file_path).await;
-            run.map(|r| r.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?)
+            run.map(|r| r.map_err(std::io::Error::other)?)
         };
 
         let elapsed = start.elapsed();
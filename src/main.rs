@@ -9,6 +9,7 @@
 //!   bizclaw channel start              # Start channel listener
 //!   bizclaw onboard                    # First-time setup
 //!   bizclaw brain download             # Download local model
+//!   bizclaw brain inspect <model.gguf> # Metadata, tensors, RAM estimate
 //!   bizclaw config show                # Show configuration
 
 use anyhow::Result;
@@ -134,6 +135,77 @@ enum BrainAction {
         #[arg(default_value = "Hello, who are you?")]
         prompt: String,
     },
+    /// Requantize a GGUF model (e.g. F16 → Q4_K)
+    Quantize {
+        /// Input GGUF file
+        input: std::path::PathBuf,
+        /// Output GGUF file
+        output: std::path::PathBuf,
+        /// Type for weight matrices: q4_0, q8_0 or q4_k
+        #[arg(long = "type", default_value = "q4_k")]
+        ty: String,
+        /// Type for embedding tables: f32, f16, q8_0, ...
+        #[arg(long, default_value = "q8_0")]
+        embedding_type: String,
+        /// Type for norms and biases
+        #[arg(long, default_value = "f32")]
+        norm_type: String,
+    },
+    /// Measure prefill/decode speed, peak RSS and KV usage (JSON)
+    Bench {
+        /// GGUF model (default: brain.model_path or the first installed model)
+        #[arg(long)]
+        model: Option<std::path::PathBuf>,
+        #[arg(long, default_value_t = 128)]
+        prompt_tokens: usize,
+        #[arg(long, default_value_t = 64)]
+        gen_tokens: usize,
+        #[arg(long, default_value_t = 3)]
+        repetitions: usize,
+        /// Worker threads (default: brain.threads)
+        #[arg(long)]
+        threads: Option<usize>,
+        /// Prefill batch size (default: brain.batch_size)
+        #[arg(long)]
+        batch_size: Option<usize>,
+        /// KV cache precision: f32, f16 or q8_0 (default: brain.kv_precision)
+        #[arg(long)]
+        kv: Option<String>,
+    },
+    /// Show a GGUF file's metadata, tensors and estimated RAM
+    Inspect {
+        /// GGUF model
+        model: std::path::PathBuf,
+        /// Context to size the KV cache for (default: the model's)
+        #[arg(long)]
+        context: Option<usize>,
+        /// KV cache precision: f32, f16 or q8_0 (default: brain.kv_precision)
+        #[arg(long)]
+        kv: Option<String>,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Compute perplexity over a text file with a sliding window (JSON)
+    Perplexity {
+        /// Text corpus
+        file: std::path::PathBuf,
+        /// GGUF model (default: brain.model_path or the first installed model)
+        #[arg(long)]
+        model: Option<std::path::PathBuf>,
+        /// Window length in tokens (0 = the model's context length)
+        #[arg(long, default_value_t = 512)]
+        context: usize,
+        /// Tokens the window advances by (0 = half the window)
+        #[arg(long, default_value_t = 0)]
+        stride: usize,
+        /// Worker threads (default: brain.threads)
+        #[arg(long)]
+        threads: Option<usize>,
+        /// Batch size (default: brain.batch_size)
+        #[arg(long)]
+        batch_size: Option<usize>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -460,6 +532,121 @@ async fn async_main() -> Result<()> {
                         }
                     }
                 }
+                BrainAction::Quantize {
+                    input,
+                    output,
+                    ty,
+                    embedding_type,
+                    norm_type,
+                } => {
+                    use bizclaw_brain::quantize;
+                    let parse = |name: &str| {
+                        quantize::parse_type(name).ok_or_else(|| {
+                            anyhow::anyhow!("Unknown type {name:?} (f32, f16, q4_0, q8_0, q4_k)")
+                        })
+                    };
+                    let options = quantize::QuantizeOptions {
+                        ty: parse(&ty)?,
+                        embedding_type: parse(&embedding_type)?,
+                        norm_type: parse(&norm_type)?,
+                    };
+
+                    println!("🧠 Quantizing {} → {}", input.display(), output.display());
+                    let start = std::time::Instant::now();
+                    let report = quantize::quantize_model(&input, &output, &options)?;
+                    println!(
+                        "✅ {} tensors, {:.1} MB → {:.1} MB in {:.1}s",
+                        report.tensors,
+                        report.input_bytes as f64 / 1024.0 / 1024.0,
+                        report.output_bytes as f64 / 1024.0 / 1024.0,
+                        start.elapsed().as_secs_f64()
+                    );
+                    if report.fallbacks > 0 {
+                        println!(
+                            "   {} tensor(s) kept a wider type: rows not divisible by the block size",
+                            report.fallbacks
+                        );
+                    }
+                }
+                BrainAction::Bench {
+                    model,
+                    prompt_tokens,
+                    gen_tokens,
+                    repetitions,
+                    threads,
+                    batch_size,
+                    kv,
+                } => {
+                    let kv = kv.unwrap_or_else(|| config.brain.kv_precision.clone());
+                    let options = bizclaw_brain::bench::BenchOptions {
+                        prompt_tokens,
+                        gen_tokens,
+                        repetitions,
+                        batch_size: batch_size.unwrap_or(config.brain.batch_size as usize),
+                        threads: threads.unwrap_or(config.brain.threads as usize),
+                        kv_precision: bizclaw_brain::kv_cache::KvPrecision::parse(&kv).ok_or_else(
+                            || anyhow::anyhow!("Unknown KV precision {kv:?} (f32, f16, q8_0)"),
+                        )?,
+                    };
+                    let path = brain_model_path(model, &config)?;
+                    let report = bizclaw_brain::bench::run_bench(&path, &options)?;
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                }
+                BrainAction::Inspect {
+                    model,
+                    context,
+                    kv,
+                    json,
+                } => {
+                    use bizclaw_brain::inspect;
+                    let kv = kv.unwrap_or_else(|| config.brain.kv_precision.clone());
+                    let options = inspect::InspectOptions {
+                        context: context.unwrap_or(0),
+                        batch_size: config.brain.batch_size as usize,
+                        kv_precision: bizclaw_brain::kv_cache::KvPrecision::parse(&kv).ok_or_else(
+                            || anyhow::anyhow!("Unknown KV precision {kv:?} (f32, f16, q8_0)"),
+                        )?,
+                    };
+                    let report = inspect::inspect(&model, &options)?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&report)?);
+                    } else {
+                        println!("{report}");
+                        if let Some(host) = inspect::host_memory() {
+                            let verdict = if report.ram.fits(&host) {
+                                "✅ fits"
+                            } else {
+                                "❌ does not fit"
+                            };
+                            println!(
+                                "  {verdict} in {:.1} MB available ({:.1} MB total)",
+                                host.available_bytes as f64 / 1024.0 / 1024.0,
+                                host.total_bytes as f64 / 1024.0 / 1024.0
+                            );
+                        }
+                    }
+                }
+                BrainAction::Perplexity {
+                    file,
+                    model,
+                    context,
+                    stride,
+                    threads,
+                    batch_size,
+                } => {
+                    let text = std::fs::read_to_string(&file)
+                        .map_err(|e| anyhow::anyhow!("Cannot read {}: {e}", file.display()))?;
+                    let options = bizclaw_brain::bench::PerplexityOptions {
+                        context,
+                        stride,
+                        batch_size: batch_size.unwrap_or(config.brain.batch_size as usize),
+                        threads: threads.unwrap_or(config.brain.threads as usize),
+                        kv_precision: bizclaw_brain::kv_cache::KvPrecision::F32,
+                    };
+                    let path = brain_model_path(model, &config)?;
+                    let report = bizclaw_brain::bench::perplexity(&path, &text, &options)?;
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                }
             }
         }
 
@@ -926,3 +1113,32 @@ async fn wait_for_shutdown() {
     // Sleep indefinitely - Docker will send SIGTERM/SIGKILL to stop the container
     tokio::time::sleep(tokio::time::Duration::from_secs(u64::MAX)).await;
 }
+
+/// Model for brain subcommands: `--model`, else `brain.model_path`, else the
+/// first `.gguf` in the models directory.
+fn brain_model_path(
+    model: Option<std::path::PathBuf>,
+    config: &bizclaw_core::BizClawConfig,
+) -> Result<std::path::PathBuf> {
+    if let Some(path) = model {
+        return Ok(path);
+    }
+    if !config.brain.model_path.is_empty() {
+        return Ok(std::path::PathBuf::from(&config.brain.model_path));
+    }
+    let model_dir = bizclaw_core::BizClawConfig::home_dir().join("models");
+    std::fs::read_dir(&model_dir)
+        .ok()
+        .and_then(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .find(|p| p.extension().and_then(|ext| ext.to_str()) == Some("gguf"))
+        })
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "No model found in {}; pass --model or run: bizclaw brain download tinyllama-1.1b",
+                model_dir.display()
+            )
+        })
+}